@@ -1,183 +1,2088 @@
-use crate::window::{Modal, Renderable};
-use byteorder::{LittleEndian, ReadBytesExt};
-use crossbeam::channel::{unbounded, Receiver, Sender};
-use glium::Display;
-use glium::{
-    backend::Facade,
-    texture::{ClientFormat, RawImage2d},
-    Texture2d,
-};
-use image::jpeg::JpegDecoder;
-use image::ImageDecoder;
+use crate::capture::CaptureReader;
+use crate::channel::{ChannelConfig, OverflowPolicy, SensorSender};
+use crate::connection;
+use crate::diagnostics::{self, SensorStats, ThroughputHistory};
+use crate::endian::{byte_order_combo, Endianness};
+use crate::framing::FrameSync;
+use crate::notifications::Notification;
+use crate::playback::PlaybackControls;
+use crate::server::{self, RestartableSensor};
+use crate::session::SensorSpec;
+use crate::settings::Settings;
+use crate::texture;
+use crate::tls::{self, ServerTlsConfig};
+use crate::window::{self, parse_listen_addr, Modal, Place, Placement, Renderable};
+use crate::yuv::{self, YuvConverter, YuvError, YuvLayout};
+use byteorder::{LittleEndian, WriteBytesExt};
+use crossbeam::channel::Receiver;
+use glium::{Display, Texture2d};
+use image::jpeg::{JpegDecoder, JpegEncoder};
+use image::png::PngDecoder;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageResult, Rgb, RgbImage};
+use imageproc::drawing::draw_text_mut;
 use imgui::TextureId;
-use imgui::{self, im_str, ImString, Image, Ui, Window, WindowFlags};
+use imgui::{
+    self, im_str, ColorEdit, Condition, ImStr, ImString, Image, MouseButton, Slider, Ui, Window,
+    WindowFlags,
+};
 use imgui_glium_renderer::Renderer;
+use jpeg_decoder::ColorTransform;
+use log::{info, warn};
+use openh264::decoder::{DecodedYUV, Decoder};
+use rustls::ServerConfig;
+use rusttype::{point, Font, Scale};
+use serde::Serialize;
 use std::borrow::Cow;
-use std::io::{self, Cursor, Read};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
+use std::mem;
 use std::net::SocketAddr;
-use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumIter, EnumString};
+use thiserror::Error;
+
+/// The default capacity of the buffered reader wrapped around each camera connection.
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The default limit on the size of a single frame. The length prefix is checked against this
+/// before anything is allocated, so a corrupt prefix can't exhaust memory.
+const DEFAULT_MAX_FRAME_BYTES: usize = 32 * 1024 * 1024;
+
+/// How many decoded frames can wait for the camera window. Frames arriving while it's full push
+/// out the oldest waiting frame, since only the newest frame matters for a live feed.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 4;
+pub const DEFAULT_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::DropOldest;
+
+/// The number of most recent frames considered when computing the decode failure rate.
+const DECODE_HEALTH_WINDOW: usize = 50;
+
+/// The default decode failure rate above which the camera is reported as unhealthy.
+const DEFAULT_UNHEALTHY_THRESHOLD: f32 = 0.2;
+
+/// The longest side a frame is scaled down to before it's uploaded as a texture, when the camera
+/// window limits the texture size, and the range that limit can be set to.
+const DEFAULT_MAX_TEXTURE_DIM: u32 = 1920;
+const MIN_TEXTURE_DIM: u32 = 64;
+const MAX_TEXTURE_DIM: u32 = 16384;
+
+/// The number of frames in a row that can fail to decode before the connection is dropped. A
+/// stream this broken is most likely not sending the configured video format at all.
+const MAX_CONSECUTIVE_DECODE_FAILURES: u32 = 100;
 
 #[derive(AsRefStr, EnumIter, EnumString, Clone, Copy, Debug)]
-/// A list of allowed formats for the camera. Currently we only support MJPEG, but the boilerplate
-/// for allowing the user to select different formats is set up.
+/// A list of allowed formats for the camera. `MJPEG` streams may also send PNG frames, see
+/// `decode_image`, and `RAW` frames are uncompressed pixels, see `decode_raw`.
+#[allow(clippy::upper_case_acronyms)]
 pub enum VideoFormat {
     MJPEG,
     H264,
+    RAW,
+}
+
+/// How MJPEG frames are delimited on the wire. `LengthPrefixed` frames start with a u32 length,
+/// like the other video formats, while `SoiEoiMarkers` frames are bare JPEGs found by their start
+/// (FFD8) and end (FFD9) markers, as sent by many IP cameras.
+#[derive(AsRefStr, EnumIter, EnumString, Clone, Copy, Debug)]
+pub enum FrameFraming {
+    LengthPrefixed,
+    SoiEoiMarkers,
+}
+
+/// The JPEG start and end of image markers.
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+
+/// The bytes every PNG image starts with.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// How many bytes are read from the stream at a time while looking for JPEG markers.
+const MARKER_READ_SIZE: usize = 16 * 1024;
+
+/// How much one notch of the mouse wheel zooms the camera image by.
+const ZOOM_STEP: f32 = 1.25;
+
+/// The fraction of values at each end of a frame's histogram that auto levels ignores when
+/// finding the frame's range.
+const AUTO_LEVELS_CLIP: f32 = 0.005;
+
+/// How far the camera image can be zoomed in.
+const MAX_ZOOM: f32 = 16.0;
+
+/// The width of the overview inset shown while zoomed in, as a fraction of the image's width, and
+/// its distance from the image's top right corner in logical pixels.
+const MINIMAP_FRACTION: f32 = 0.25;
+const MINIMAP_MARGIN: f32 = 8.0;
+
+/// The height of timestamp overlays as a fraction of the frame's height, and the smallest height
+/// in pixels they're drawn at so they stay legible on small frames.
+const TIMESTAMP_SCALE: f32 = 1.0 / 24.0;
+const MIN_TIMESTAMP_HEIGHT: f32 = 12.0;
+
+/// The span of recent frames the displayed frame rate is averaged over. A feed that hasn't
+/// delivered a frame for this long shows 0 FPS.
+const FPS_WINDOW: Duration = Duration::from_secs(2);
+
+/// Tracks the outcome of the most recently decoded frames. A few corrupt frames here and there
+/// are normal, so the camera window uses the failure rate over this sliding window to decide
+/// whether the feed is genuinely broken.
+pub struct DecodeHealth {
+    failures: VecDeque<bool>,
+}
+
+impl DecodeHealth {
+    pub fn new() -> Self {
+        Self {
+            failures: VecDeque::with_capacity(DECODE_HEALTH_WINDOW),
+        }
+    }
+
+    fn record(&mut self, success: bool) {
+        if self.failures.len() == DECODE_HEALTH_WINDOW {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(!success);
+    }
+
+    /// Returns the fraction of recent frames that failed to decode.
+    pub fn failure_rate(&self) -> f32 {
+        if self.failures.is_empty() {
+            return 0.0;
+        }
+        let failures = self.failures.iter().filter(|failed| **failed).count();
+        failures as f32 / self.failures.len() as f32
+    }
 }
 
+#[derive(Clone)]
 pub struct Camera {
-    sender: Sender<CameraData>,
+    sender: SensorSender<CameraData>,
+    pub health: Arc<Mutex<DecodeHealth>>,
+    pub stats: Arc<SensorStats>,
+    pub capture_path: Option<PathBuf>,
+    pub buffer_size: usize,
+    /// How long a connection can go without sending anything before it's dropped, if at all.
+    pub read_timeout: Option<Duration>,
+    /// The most devices that can be connected at once. Further connections are refused.
+    pub max_connections: usize,
+    pub endianness: Endianness,
+    pub max_frame_bytes: usize,
+    /// How MJPEG frames are delimited. The other video formats are always length-prefixed.
+    pub framing: FrameFraming,
+    /// Whether length-prefixed frames are sent with a checksum, as read by `FrameSync`, so that
+    /// corrupted frames are skipped rather than decoded.
+    pub checksummed: bool,
+    /// If set, connections are only read over TLS with this configuration.
+    pub tls: Option<Arc<ServerConfig>>,
+    /// Whether H264 and JPEG frames are sent to the window as they're decoded, in I420 and YCbCr
+    /// respectively, so the window can convert them to RGB on the GPU. Otherwise they're converted
+    /// here.
+    pub yuv_frames: bool,
+}
+
+/// Why a camera connection failed or a frame couldn't be decoded.
+#[derive(Debug, Error)]
+pub enum CameraError {
+    /// Reading from the connection failed, or the window's channel was disconnected.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("couldn't decode the image: {0}")]
+    Decode(#[from] ImageError),
+    #[error("couldn't decode the JPEG: {0}")]
+    Jpeg(#[from] jpeg_decoder::Error),
+    #[error("couldn't create the H264 decoder: {0}")]
+    H264Decoder(String),
+    #[error("unknown pixel format {0}")]
+    UnknownPixelFormat(u32),
+    #[error("{width}x{height} {pixel_format:?} image should be {expected} bytes but is {actual}")]
+    SizeMismatch {
+        width: u32,
+        height: u32,
+        pixel_format: PixelFormat,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("frame of {size} bytes exceeds the maximum of {max} bytes")]
+    FrameTooLarge { size: usize, max: usize },
+    #[error("{0} frames in a row failed to decode")]
+    TooManyDecodeFailures(u32),
+}
+
+/// The sensor threads hand their errors to `server::serve`, which tells a disconnect from a
+/// closed window by the error kind, so I/O errors are passed through as they are.
+impl From<CameraError> for io::Error {
+    fn from(e: CameraError) -> Self {
+        match e {
+            CameraError::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
 }
 
 pub struct CameraData {
     pub image_bytes: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    pub pixel_format: PixelFormat,
+}
+
+impl CameraData {
+    /// Checks that the frame holds as many bytes as its dimensions and pixel format call for.
+    pub fn check_size(&self) -> Result<(), CameraError> {
+        check_frame_size(
+            self.image_bytes.len(),
+            self.width,
+            self.height,
+            self.pixel_format,
+        )
+    }
+
+    /// Converts the frame to RGB, which is what the window, recordings and snapshots work with.
+    pub fn into_rgb(self) -> Result<CameraData, CameraError> {
+        Ok(CameraData {
+            image_bytes: to_rgb(self.image_bytes, self.width, self.height, self.pixel_format)?,
+            width: self.width,
+            height: self.height,
+            pixel_format: PixelFormat::Rgb,
+        })
+    }
+}
+
+/// The layout of the pixels in a decoded image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    Rgb,
+    Rgba,
+    Bgr,
+    /// A full size luma plane followed by the two chroma planes at half the width and height, as
+    /// H264 decodes to.
+    I420,
+    /// A Y, Cb and Cr byte for each pixel, as JPEG decodes to before its color transform.
+    Ycbcr,
+}
+
+impl PixelFormat {
+    /// Looks up the pixel format a raw frame's header refers to.
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(PixelFormat::Rgb),
+            1 => Some(PixelFormat::Rgba),
+            2 => Some(PixelFormat::Bgr),
+            3 => Some(PixelFormat::I420),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes an image of the given size takes up.
+    pub fn frame_bytes(self, width: u32, height: u32) -> usize {
+        let pixels = width as usize * height as usize;
+        match self {
+            PixelFormat::Rgb | PixelFormat::Bgr | PixelFormat::Ycbcr => pixels * 3,
+            PixelFormat::Rgba => pixels * 4,
+            PixelFormat::I420 => yuv::i420_bytes(width, height),
+        }
+    }
+}
+
+fn check_frame_size(
+    actual: usize,
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+) -> Result<(), CameraError> {
+    let expected = pixel_format.frame_bytes(width, height);
+    if actual != expected {
+        return Err(CameraError::SizeMismatch {
+            width,
+            height,
+            pixel_format,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Converts an image in the given pixel format to RGB, dropping any alpha. Fails if the number of
+/// bytes doesn't match the image's dimensions, e.g. because the image was truncated.
+pub fn to_rgb(
+    mut bytes: Vec<u8>,
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+) -> Result<Vec<u8>, CameraError> {
+    check_frame_size(bytes.len(), width, height, pixel_format)?;
+    Ok(match pixel_format {
+        PixelFormat::Rgb => bytes,
+        PixelFormat::Rgba => bytes
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].iter().cloned())
+            .collect(),
+        PixelFormat::Bgr => {
+            for pixel in bytes.chunks_exact_mut(3) {
+                pixel.swap(0, 2);
+            }
+            bytes
+        }
+        PixelFormat::I420 => yuv::i420_to_rgb(&bytes, width, height),
+        PixelFormat::Ycbcr => {
+            yuv::interleaved_to_rgb(&mut bytes);
+            bytes
+        }
+    })
 }
 
 impl Camera {
-    pub fn new(sender: Sender<CameraData>) -> Self {
-        Self { sender }
+    pub fn new(sender: SensorSender<CameraData>) -> Self {
+        Self {
+            sender,
+            health: Arc::new(Mutex::new(DecodeHealth::new())),
+            stats: Arc::new(SensorStats::new()),
+            capture_path: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            read_timeout: None,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS,
+            endianness: Endianness::Little,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            framing: FrameFraming::LengthPrefixed,
+            checksummed: false,
+            tls: None,
+            yuv_frames: false,
+        }
     }
 
-    /// Starts a TCP listener to receive data from the camera. This supports multiple connections,
-    /// though multiple connections aren't handled correctly at the moment.
+    /// Starts a TCP listener to receive data from the camera. Each connection is handled on its own
+    /// thread, so multiple devices can stream to the same listener at once. Fails if the port can't
+    /// be bound.
     pub fn start(
-        mut self,
+        self,
         ip: SocketAddr,
         video_format: VideoFormat,
-    ) -> JoinHandle<io::Result<()>> {
-        println!("Starting a camera on {} with format {:?}", ip, video_format);
-        thread::spawn(move || {
-            let listener = TcpListener::bind(&ip).unwrap();
-            for stream in listener.incoming() {
-                self.handle_image_stream(stream?, video_format)?;
-            }
-            Ok(())
-        })
+        shutdown: Arc<AtomicBool>,
+    ) -> io::Result<JoinHandle<io::Result<()>>> {
+        info!("camera: listening on {} with format {:?}", ip, video_format);
+        let listener = server::bind(ip)?;
+        Ok(thread::spawn(move || {
+            let capture_path = self.capture_path.clone();
+            let buffer_size = self.buffer_size;
+            let read_timeout = self.read_timeout;
+            let max_connections = self.max_connections;
+            let stats = self.stats.clone();
+            server::serve(
+                "camera",
+                &listener,
+                &shutdown,
+                &stats,
+                read_timeout,
+                max_connections,
+                move |stream| {
+                    let stream = tls::accept(stream, self.tls.as_ref());
+                    let stream: Box<dyn Read> = match capture_path {
+                        Some(ref path) => Box::new(CaptureReader::new(stream, path)?),
+                        None => Box::new(stream),
+                    };
+                    let mut camera = self.clone();
+                    camera
+                        .handle_image_stream(
+                            BufReader::with_capacity(buffer_size, stream),
+                            video_format,
+                        )
+                        .map_err(io::Error::from)
+                },
+            )
+        }))
     }
 
-    /// Receives bytes and decodes them to bytes. Currently only supports
-    /// MJPEG, though the boilerplate for H264 exists.
-    pub fn handle_image_stream(
+    /// Receives bytes and decodes them to RGB frames using the given video format.
+    pub fn handle_image_stream<R: Read>(
         &mut self,
-        stream: TcpStream,
+        stream: R,
         video_format: VideoFormat,
-    ) -> io::Result<()> {
+    ) -> Result<(), CameraError> {
         match video_format {
             VideoFormat::MJPEG => self.handle_mjpeg(stream),
-            VideoFormat::H264 => Ok(()),
+            VideoFormat::H264 => self.handle_h264(stream),
+            VideoFormat::RAW => self.handle_raw(stream),
         }
     }
 
-    /// Handles receiving MJPEG data and sending frames to the camera window. This function assumes
-    /// a data format that consists is a u32 containing the data length n followed by n bytes.
-    fn handle_mjpeg(&mut self, mut stream: TcpStream) -> io::Result<()> {
+    /// Handles receiving MJPEG data and sending frames to the camera window. Frames are delimited
+    /// according to `framing`. Length prefixed frames can also be PNG images, which are decoded
+    /// as such. JPEGs are left in YCbCr for the window to convert if `yuv_frames` is set.
+    fn handle_mjpeg<R: Read>(&mut self, mut stream: R) -> Result<(), CameraError> {
+        let mut consecutive_failures = 0;
+        let mut scanner = JpegScanner::new();
+        let mut sync = FrameSync::new("camera");
         loop {
-            let size = stream.read_u32::<LittleEndian>()? as usize;
-            let mut bytes = vec![0; size];
-            stream.read_exact(&mut bytes[..])?;
-
-            let bytes = Cursor::new(bytes);
-            if let Ok(decoder) = JpegDecoder::new(bytes) {
-                let (width, height) = decoder.dimensions();
-                let mut image_bytes: Vec<u8> = vec![0; decoder.total_bytes() as usize];
-                match decoder.read_image(&mut image_bytes[..]) {
-                    Ok(()) => {
-                        let camera_data = CameraData {
-                            image_bytes,
-                            width: width as u32,
-                            height: height as u32,
-                        };
-                        self.sender.send(camera_data).map_err(|_| {
-                            io::Error::new(
-                                io::ErrorKind::ConnectionAborted,
-                                "camera channel disconnected",
-                            )
-                        })?;
-                    }
-                    Err(e) => {
-                        println!("Error decoding the image: {:?}", e);
-                    }
+            let bytes = match self.framing {
+                FrameFraming::LengthPrefixed => self.read_frame(&mut stream, &mut sync)?,
+                FrameFraming::SoiEoiMarkers => {
+                    scanner.next_frame(&mut stream, self.max_frame_bytes)?
+                }
+            };
+
+            let decoded = if self.yuv_frames && !bytes.starts_with(&PNG_SIGNATURE) {
+                decode_jpeg_ycbcr(bytes)
+            } else {
+                decode_image(bytes)
+            };
+            match decoded {
+                Ok(camera_data) => {
+                    consecutive_failures = 0;
+                    self.record_decode(true);
+                    self.sender.send(camera_data, &self.stats)?;
+                }
+                Err(e) => {
+                    warn!("camera: {}", e);
+                    self.record_decode(false);
+                    consecutive_failures += 1;
+                    check_decode_failures(consecutive_failures)?;
+                }
+            }
+        }
+    }
+
+    /// Handles receiving H264 data and sending frames to the camera window. This uses the same
+    /// framing as MJPEG, a u32 length n followed by n bytes of NAL units. A message doesn't have to
+    /// hold a complete access unit: the decoder keeps partial units buffered and only yields a
+    /// picture once the whole frame has arrived.
+    fn handle_h264<R: Read>(&mut self, mut stream: R) -> Result<(), CameraError> {
+        let mut decoder =
+            Decoder::new().map_err(|e| CameraError::H264Decoder(format!("{:?}", e)))?;
+        let mut consecutive_failures = 0;
+        let mut sync = FrameSync::new("camera");
+        loop {
+            let bytes = self.read_frame(&mut stream, &mut sync)?;
+
+            let yuv = match decoder.decode(&bytes) {
+                Ok(Some(yuv)) => yuv,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("camera: error decoding the H264 frame: {:?}", e);
+                    self.record_decode(false);
+                    consecutive_failures += 1;
+                    check_decode_failures(consecutive_failures)?;
+                    continue;
+                }
+            };
+            consecutive_failures = 0;
+            let (width, height) = yuv.dimension_rgb();
+            let camera_data = if self.yuv_frames {
+                CameraData {
+                    image_bytes: pack_i420(&yuv, width, height),
+                    width: width as u32,
+                    height: height as u32,
+                    pixel_format: PixelFormat::I420,
+                }
+            } else {
+                let mut image_bytes = vec![0; width * height * 3];
+                yuv.write_rgb8(&mut image_bytes);
+                CameraData {
+                    image_bytes,
+                    width: width as u32,
+                    height: height as u32,
+                    pixel_format: PixelFormat::Rgb,
+                }
+            };
+            self.record_decode(true);
+            self.sender.send(camera_data, &self.stats)?;
+        }
+    }
+
+    /// Handles receiving uncompressed frames, using the same framing as MJPEG.
+    fn handle_raw<R: Read>(&mut self, mut stream: R) -> Result<(), CameraError> {
+        let mut consecutive_failures = 0;
+        let mut sync = FrameSync::new("camera");
+        loop {
+            let bytes = self.read_frame(&mut stream, &mut sync)?;
+
+            match decode_raw(bytes, self.endianness) {
+                Ok(camera_data) => {
+                    consecutive_failures = 0;
+                    self.record_decode(true);
+                    self.sender.send(camera_data, &self.stats)?;
+                }
+                Err(e) => {
+                    warn!("camera: error decoding the raw frame: {}", e);
+                    self.record_decode(false);
+                    consecutive_failures += 1;
+                    check_decode_failures(consecutive_failures)?;
                 }
             }
         }
     }
+
+    /// Reads a u32 length n followed by n bytes, refusing frames larger than `max_frame_bytes`.
+    /// Checksummed frames are read through `sync` instead, which skips frames that are too large.
+    fn read_frame<R: Read>(
+        &self,
+        stream: &mut R,
+        sync: &mut FrameSync,
+    ) -> Result<Vec<u8>, CameraError> {
+        if self.checksummed {
+            return Ok(sync.next_frame(stream, self.endianness, self.max_frame_bytes)?);
+        }
+        let size = self.endianness.read_u32(stream)? as usize;
+        if size > self.max_frame_bytes {
+            return Err(CameraError::FrameTooLarge {
+                size,
+                max: self.max_frame_bytes,
+            });
+        }
+        let mut bytes = vec![0; size];
+        stream.read_exact(&mut bytes[..])?;
+        Ok(bytes)
+    }
+
+    fn record_decode(&self, success: bool) {
+        if let Ok(mut health) = self.health.lock() {
+            health.record(success);
+        }
+    }
+}
+
+/// Extracts JPEGs from a stream by their start and end markers. Bytes read past the end of a frame
+/// are kept for the next one, so markers split across reads are still found. Anything between
+/// frames is skipped.
+///
+/// This relies on FFD9 only appearing at the end of the image, which holds for the entropy-coded
+/// data since any FF byte in it is followed by 00. JPEGs carrying an EXIF thumbnail would be cut
+/// short at the end of the thumbnail, but cameras streaming MJPEG don't send those.
+struct JpegScanner {
+    buffer: Vec<u8>,
+    /// How far into `buffer` the end marker has already been searched for.
+    scanned: usize,
+}
+
+impl JpegScanner {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            scanned: 0,
+        }
+    }
+
+    /// Reads from the stream until a whole JPEG has arrived and returns it, refusing frames larger
+    /// than `max_frame_bytes`.
+    fn next_frame<R: Read>(
+        &mut self,
+        stream: &mut R,
+        max_frame_bytes: usize,
+    ) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(frame) = self.take_frame() {
+                return Ok(frame);
+            }
+            if self.buffer.len() > max_frame_bytes {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "no end of image marker within the maximum of {} bytes",
+                        max_frame_bytes
+                    ),
+                ));
+            }
+            let mut chunk = [0; MARKER_READ_SIZE];
+            let size = stream.read(&mut chunk)?;
+            if size == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buffer.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    /// Takes the first complete JPEG out of the buffer, if there is one.
+    fn take_frame(&mut self) -> Option<Vec<u8>> {
+        // Until a frame has started, everything before the last byte can be thrown away. The last
+        // byte is kept in case it's the first half of the start marker.
+        if self.scanned == 0 {
+            match find_marker(&self.buffer, JPEG_SOI) {
+                Some(start) => {
+                    self.buffer.drain(..start);
+                    self.scanned = JPEG_SOI.len();
+                }
+                None => {
+                    let keep = self.buffer.len().min(1);
+                    self.buffer.drain(..self.buffer.len() - keep);
+                    return None;
+                }
+            }
+        }
+
+        // The search resumes one byte early in case the end marker was split across reads.
+        let from = self.scanned - 1;
+        match find_marker(&self.buffer[from..], JPEG_EOI) {
+            Some(end) => {
+                let end = from + end + JPEG_EOI.len();
+                self.scanned = 0;
+                Some(self.buffer.drain(..end).collect())
+            }
+            None => {
+                self.scanned = self.buffer.len().max(JPEG_SOI.len());
+                None
+            }
+        }
+    }
+}
+
+/// Finds the first occurrence of a two byte marker.
+fn find_marker(bytes: &[u8], marker: [u8; 2]) -> Option<usize> {
+    bytes.windows(2).position(|window| window == marker)
+}
+
+/// Gives up on a connection once too many frames in a row have failed to decode. Occasional
+/// corrupt frames are only skipped.
+fn check_decode_failures(consecutive_failures: u32) -> Result<(), CameraError> {
+    if consecutive_failures >= MAX_CONSECUTIVE_DECODE_FAILURES {
+        return Err(CameraError::TooManyDecodeFailures(consecutive_failures));
+    }
+    Ok(())
 }
 
 pub struct CameraWindow {
+    /// The name shown in the window's title bar and in the list of active sensors.
+    pub name: String,
+    /// Keeps the window apart from other windows with the same name.
+    window_id: usize,
+    /// Where the main window has asked this window to move on its next frame.
+    placement: Option<Placement>,
+    /// Where the window was drawn on its last frame.
+    shown_at: Option<Placement>,
+    /// The number of clockwise quarter turns applied to each frame before it's displayed.
     pub rotation: u8,
+    /// Whether each frame is mirrored left to right and top to bottom. Flips undo how the camera
+    /// is mounted, so they're applied before the rotation.
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// The longest side a frame's texture can have, if it's limited. Larger frames are scaled
+    /// down before they're uploaded, while snapshots and recordings keep the full resolution.
+    pub max_texture_dim: Option<u32>,
     pub window_width: f32,
     pub window_height: f32,
     pub texture_id: Option<TextureId>,
     pub receiver: Receiver<CameraData>,
+    pub health: Arc<Mutex<DecodeHealth>>,
+    pub unhealthy_threshold: f32,
+    pub gamma: f32,
+    /// Whether each frame's levels are stretched so its darkest and brightest pixels span the
+    /// full range, for cameras whose exposure is off.
+    pub auto_levels: bool,
+    /// A manual brightness adjustment applied after the auto levels: each channel is multiplied
+    /// by the gain and then has the offset added.
+    pub gain: f32,
+    pub offset: f32,
+    pub spec: Option<SensorSpec>,
+    /// Lets the sensor feeding the window be restarted from it, when the window started the
+    /// sensor itself.
+    pub restartable: Option<RestartableSensor>,
+    pub stats: Arc<SensorStats>,
+    /// The bytes per second received recently, for the throughput graph.
+    throughput: ThroughputHistory,
+    /// How long the camera can go without sending a frame before it's shown as stale.
+    pub stale_after: Duration,
+    /// Whether frames are corrected for lens distortion before they're displayed, and how.
+    pub undistort: bool,
+    pub distortion: Distortion,
+    /// Whether the frame on screen is frozen. Frames keep arriving and being recorded while
+    /// paused, they just aren't shown.
+    pub paused: bool,
+    /// The part of the frame shown in the window.
+    pub viewport: Viewport,
+    /// Whether snapshots and recordings get the time they were captured drawn onto them, and
+    /// whether the frame on screen gets it too.
+    pub burn_timestamp: bool,
+    pub live_timestamp: bool,
+    pub timestamp_overlay: TimestampOverlay,
+    /// The controls of the file playback shown in the window, if it's showing one.
+    pub playback: Option<PlaybackControls>,
+    frame_times: VecDeque<Instant>,
+    /// The height of the controls below the image as of the last frame, which the image leaves
+    /// room for when it's scaled to the window.
+    controls_height: f32,
+    /// The frame currently on screen, after flipping, rotation and gamma correction. Frames whose
+    /// colors were converted on the GPU are kept in I420 or YCbCr, as they were sent.
+    last_frame: Option<CameraData>,
+    snapshot_status: Option<String>,
+    record_path: ImString,
+    /// The recording received frames are appended to, while one is running.
+    recording: Option<Recording>,
+    record_status: Option<String>,
+    /// Whether the decode failure rate was over the threshold as of the last frame, so only
+    /// crossing it raises a notification.
+    was_unhealthy: bool,
+    /// Whether the last frame couldn't be uploaded, so only the first of a run of failures
+    /// raises a notification.
+    texture_failing: bool,
+    /// Converts I420 and YCbCr frames to RGB on the GPU, created along with the first frame it
+    /// converts.
+    yuv: Option<YuvConverter>,
+    /// Whether converting a frame on the GPU failed, after which frames are converted on the CPU.
+    gpu_colors_failed: bool,
+    notifications: Vec<Notification>,
 }
 
 impl CameraWindow {
     pub fn new(receiver: Receiver<CameraData>) -> Self {
+        let mut record_path = ImString::new("camera_recording.mjpeg");
+        record_path.reserve_exact(256);
         Self {
+            name: "Camera".to_string(),
+            window_id: window::next_window_id(),
+            placement: None,
+            shown_at: None,
             rotation: 0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            max_texture_dim: None,
             window_width: 0.0,
             window_height: 0.0,
             texture_id: None,
             receiver,
+            health: Arc::new(Mutex::new(DecodeHealth::new())),
+            unhealthy_threshold: DEFAULT_UNHEALTHY_THRESHOLD,
+            gamma: 1.0,
+            auto_levels: false,
+            gain: 1.0,
+            offset: 0.0,
+            spec: None,
+            restartable: None,
+            stats: Arc::new(SensorStats::new()),
+            throughput: ThroughputHistory::new(),
+            stale_after: diagnostics::DEFAULT_STALE_AFTER,
+            undistort: false,
+            distortion: Distortion::none(),
+            paused: false,
+            viewport: Viewport::new(),
+            burn_timestamp: false,
+            live_timestamp: false,
+            timestamp_overlay: TimestampOverlay::new(),
+            playback: None,
+            frame_times: VecDeque::new(),
+            controls_height: 0.0,
+            last_frame: None,
+            snapshot_status: None,
+            record_path,
+            recording: None,
+            record_status: None,
+            was_unhealthy: false,
+            texture_failing: false,
+            yuv: None,
+            gpu_colors_failed: false,
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Whether a frame can be shown by converting its colors on the GPU. That shows the frame as
+    /// it was sent, so anything that changes the pixels, and recordings, which are encoded from
+    /// RGB, need the frame converted on the CPU instead.
+    fn gpu_colors_apply(&self, frame: &CameraData) -> bool {
+        yuv_layout(frame.pixel_format).is_some()
+            && !self.gpu_colors_failed
+            && self.recording.is_none()
+            && !self.undistort
+            && !self.flip_horizontal
+            && !self.flip_vertical
+            && self.rotation == 0
+            && !self.auto_levels
+            && self.gain == 1.0
+            && self.offset == 0.0
+            && self.gamma == 1.0
+            && !self.live_timestamp
+            && self.max_texture_dim.is_none()
+            && frame.width.max(frame.height) <= texture::max_size()
+    }
+
+    /// Shows an I420 or YCbCr frame by converting its colors on the GPU. If that fails, the GPU
+    /// isn't tried again and the frame is handed back converted to RGB, to be shown like any other.
+    fn show_on_gpu(
+        &mut self,
+        display: &Display,
+        renderer: &mut Renderer,
+        frame: CameraData,
+    ) -> Option<CameraData> {
+        let layout = match yuv_layout(frame.pixel_format) {
+            Some(layout) => layout,
+            None => return Some(frame),
+        };
+        match self.convert_on_gpu(display, layout, &frame) {
+            Ok(gl_texture) => {
+                window::store_texture(renderer.textures(), &mut self.texture_id, gl_texture);
+                self.texture_failing = false;
+                self.window_width = frame.width as f32;
+                self.window_height = frame.height as f32;
+                self.last_frame = Some(frame);
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "{}: couldn't convert the frame's colors on the GPU: {}",
+                    self.name, e
+                );
+                self.notifications.push(Notification::error(format!(
+                    "{}: converting colors on the CPU, the GPU couldn't: {}",
+                    self.name, e
+                )));
+                self.gpu_colors_failed = true;
+                match frame.into_rgb() {
+                    Ok(frame) => Some(frame),
+                    Err(e) => {
+                        warn!("camera: dropping frame: {}", e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn convert_on_gpu(
+        &mut self,
+        display: &Display,
+        layout: YuvLayout,
+        frame: &CameraData,
+    ) -> Result<Rc<Texture2d>, YuvError> {
+        let mut converter = match self.yuv.take() {
+            Some(converter) => converter,
+            None => YuvConverter::new(display)?,
+        };
+        let converted = converter.convert(
+            display,
+            layout,
+            &frame.image_bytes,
+            frame.width,
+            frame.height,
+        );
+        self.yuv = Some(converter);
+        converted
+    }
+
+    /// Starts recording received frames to the configured file, or stops and closes the file if a
+    /// recording is already running.
+    fn toggle_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            let path = recording.path.clone();
+            self.record_status = match self.finish_recording(recording) {
+                Ok(()) => Some(format!("Saved {}", path.display())),
+                Err(e) => Some(format!("Couldn't finish the recording: {}", e)),
+            };
+            return;
+        }
+        let path = PathBuf::from(self.record_path.to_str());
+        match File::create(&path) {
+            Ok(file) => {
+                let (listen_address, video_format, framing) = match &self.spec {
+                    Some(SensorSpec::Camera {
+                        address,
+                        video_format,
+                        framing,
+                        ..
+                    }) => (
+                        Some(*address),
+                        Some(video_format.clone()),
+                        Some(framing.clone()),
+                    ),
+                    _ => (None, None, None),
+                };
+                self.recording = Some(Recording {
+                    writer: BufWriter::new(file),
+                    path,
+                    metadata: RecordingMetadata {
+                        started: format_timestamp(SystemTime::now()),
+                        stopped: None,
+                        width: 0,
+                        height: 0,
+                        frames: 0,
+                        listen_address,
+                        source: None,
+                        video_format,
+                        framing,
+                    },
+                });
+                self.record_status = None;
+            }
+            Err(e) => self.record_status = Some(format!("Couldn't start recording: {}", e)),
+        }
+    }
+
+    /// Flushes a recording that's being stopped and writes its metadata next to it.
+    fn finish_recording(&self, mut recording: Recording) -> io::Result<()> {
+        recording.writer.flush()?;
+        recording.metadata.stopped = Some(format_timestamp(SystemTime::now()));
+        recording.metadata.source = self.stats.peer();
+        let file = File::create(metadata_path(&recording.path))?;
+        serde_json::to_writer_pretty(file, &recording.metadata).map_err(io::Error::other)
+    }
+
+    /// Saves a frame to a PNG file, with the timestamp drawn on it if snapshots are timestamped.
+    fn save_frame(&self, frame: &CameraData, path: &Path) -> ImageResult<()> {
+        let converted;
+        let frame = match frame.pixel_format {
+            PixelFormat::Rgb => frame,
+            _ => {
+                let image_bytes = to_rgb(
+                    frame.image_bytes.clone(),
+                    frame.width,
+                    frame.height,
+                    frame.pixel_format,
+                )
+                .map_err(|e| ImageError::IoError(e.into()))?;
+                converted = CameraData {
+                    image_bytes,
+                    width: frame.width,
+                    height: frame.height,
+                    pixel_format: PixelFormat::Rgb,
+                };
+                &converted
+            }
+        };
+        if self.burn_timestamp {
+            let stamped = self
+                .timestamp_overlay
+                .stamp(frame, &format_timestamp(SystemTime::now()));
+            save_snapshot(&stamped, path)
+        } else {
+            save_snapshot(frame, path)
+        }
+    }
+
+    /// Appends a received frame to the recording, if one is running. Zero-sized frames can't be
+    /// encoded and aren't displayed either, so they're left out. A failed write stops the
+    /// recording.
+    fn record_frame(&mut self, frame: &CameraData) {
+        let recording = match &mut self.recording {
+            Some(recording) if frame.width > 0 && frame.height > 0 => recording,
+            _ => return,
+        };
+        let result = if self.burn_timestamp {
+            let stamped = self
+                .timestamp_overlay
+                .stamp(frame, &format_timestamp(SystemTime::now()));
+            write_frame(&mut recording.writer, &stamped)
+        } else {
+            write_frame(&mut recording.writer, frame)
+        };
+        match result {
+            Ok(()) => {
+                recording.metadata.frames += 1;
+                recording.metadata.width = frame.width;
+                recording.metadata.height = frame.height;
+            }
+            Err(e) => {
+                // The frames written so far are still worth describing.
+                if let Some(recording) = self.recording.take() {
+                    let _ = self.finish_recording(recording);
+                }
+                self.record_status = Some(format!("Recording stopped: {}", e));
+            }
+        }
+    }
+}
+
+/// A camera recording in progress.
+struct Recording {
+    writer: BufWriter<File>,
+    /// Where the recording is written. The path in the window can change while recording, so the
+    /// metadata goes next to this one.
+    path: PathBuf,
+    metadata: RecordingMetadata,
+}
+
+/// Describes a recording, written as JSON next to it once it stops so it can be told apart from
+/// other recordings later.
+#[derive(Serialize)]
+struct RecordingMetadata {
+    /// When the recording was started and stopped, in UTC.
+    started: String,
+    stopped: Option<String>,
+    /// The size of the most recent frame recorded, in pixels.
+    width: u32,
+    height: u32,
+    frames: u64,
+    /// The address the camera listened on and the peer that last sent it frames, when known.
+    /// Recordings of played back files have neither.
+    listen_address: Option<SocketAddr>,
+    source: Option<SocketAddr>,
+    video_format: Option<String>,
+    framing: Option<String>,
+}
+
+/// The path of the metadata written for the recording at `path`, which is the recording's own
+/// path with `.json` added.
+fn metadata_path(path: &Path) -> PathBuf {
+    let mut metadata_path = path.as_os_str().to_owned();
+    metadata_path.push(".json");
+    PathBuf::from(metadata_path)
+}
+
+/// Decodes a single JPEG or PNG image into an RGB frame, telling them apart by the PNG signature
+/// so a stream can mix the two.
+pub fn decode_image(bytes: Vec<u8>) -> Result<CameraData, CameraError> {
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        Ok(decode_png(bytes)?)
+    } else {
+        Ok(decode_jpeg(bytes)?)
+    }
+}
+
+/// Decodes a single JPEG image into a YCbCr frame, leaving out the decoder's conversion to RGB so
+/// the window can do it on the GPU. JPEGs whose components aren't YCbCr, e.g. grayscale, CMYK or
+/// ones coded in RGB, are decoded to RGB as usual.
+pub fn decode_jpeg_ycbcr(bytes: Vec<u8>) -> Result<CameraData, CameraError> {
+    if !jpeg_is_ycbcr(&bytes) {
+        return Ok(decode_jpeg(bytes)?);
+    }
+    let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(&bytes));
+    decoder.read_info()?;
+    let info = match decoder.info() {
+        Some(info) if info.pixel_format == jpeg_decoder::PixelFormat::RGB24 => info,
+        _ => return Ok(decode_jpeg(bytes)?),
+    };
+    decoder.set_color_transform(ColorTransform::None);
+    Ok(CameraData {
+        image_bytes: decoder.decode()?,
+        width: u32::from(info.width),
+        height: u32::from(info.height),
+        pixel_format: PixelFormat::Ycbcr,
+    })
+}
+
+/// Whether a JPEG's three components are coded as YCbCr, going by its headers up to the first
+/// scan. Decoders take three components to be YCbCr unless an Adobe segment says they aren't, or
+/// the components are named R, G and B.
+fn jpeg_is_ycbcr(bytes: &[u8]) -> bool {
+    const SOF_MARKERS: [u8; 13] = [
+        0xC0, 0xC1, 0xC2, 0xC3, 0xC5, 0xC6, 0xC7, 0xC9, 0xCA, 0xCB, 0xCD, 0xCE, 0xCF,
+    ];
+    const APP14: u8 = 0xEE;
+    const SOS: u8 = 0xDA;
+    let mut components = 0;
+    let mut rgb_named = false;
+    let mut adobe_transform = None;
+    // Skip the SOI marker, then walk the segments, each a marker and a big-endian length that
+    // includes the length itself.
+    let mut offset = 2;
+    while offset + 4 <= bytes.len() && bytes[offset] == 0xFF {
+        let marker = bytes[offset + 1];
+        if marker == SOS {
+            break;
+        }
+        let length = usize::from(bytes[offset + 2]) << 8 | usize::from(bytes[offset + 3]);
+        let end = (offset + 2 + length).min(bytes.len());
+        let segment = &bytes[(offset + 4).min(end)..end];
+        if SOF_MARKERS.contains(&marker) && segment.len() >= 6 {
+            components = segment[5];
+            let ids: Vec<u8> = segment[6..].iter().step_by(3).cloned().collect();
+            rgb_named = ids.starts_with(b"RGB");
+        } else if marker == APP14 && segment.starts_with(b"Adobe") && segment.len() >= 12 {
+            adobe_transform = Some(segment[11]);
+        }
+        offset += 2 + length;
+    }
+    components == 3 && !rgb_named && adobe_transform != Some(0)
+}
+
+/// Decodes a single PNG image into an RGB frame. PNGs come in more color types and bit depths
+/// than JPEGs, so every one of them is converted to 8-bit RGB, dropping any alpha.
+pub fn decode_png(bytes: Vec<u8>) -> ImageResult<CameraData> {
+    let decoder = PngDecoder::new(Cursor::new(bytes))?;
+    let image = DynamicImage::from_decoder(decoder)?.into_rgb8();
+    let (width, height) = image.dimensions();
+    Ok(CameraData {
+        image_bytes: image.into_raw(),
+        width,
+        height,
+        pixel_format: PixelFormat::Rgb,
+    })
+}
+
+/// Decodes a single JPEG image into an RGB frame. The decoder yields either RGB or, for grayscale
+/// JPEGs, luma, which is expanded to RGB here so everything downstream only has to handle RGB.
+pub fn decode_jpeg(bytes: Vec<u8>) -> ImageResult<CameraData> {
+    let decoder = JpegDecoder::new(Cursor::new(bytes))?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    let mut image_bytes: Vec<u8> = vec![0; decoder.total_bytes() as usize];
+    decoder.read_image(&mut image_bytes[..])?;
+    if color_type == ColorType::L8 {
+        image_bytes = image_bytes
+            .iter()
+            .flat_map(|&luma| std::iter::repeat_n(luma, 3))
+            .collect();
+    }
+    Ok(CameraData {
+        image_bytes,
+        width,
+        height,
+        pixel_format: PixelFormat::Rgb,
+    })
+}
+
+/// The layout a frame in the given pixel format is converted on the GPU from, if it's one of the
+/// YUV formats.
+fn yuv_layout(pixel_format: PixelFormat) -> Option<YuvLayout> {
+    match pixel_format {
+        PixelFormat::I420 => Some(YuvLayout::I420),
+        PixelFormat::Ycbcr => Some(YuvLayout::Interleaved),
+        _ => None,
+    }
+}
+
+/// Copies the planes of a decoded H264 picture into an I420 frame. The decoder pads each row of
+/// its planes out to a stride, which the frame leaves out.
+fn pack_i420(yuv: &DecodedYUV, width: usize, height: usize) -> Vec<u8> {
+    let (chroma_width, chroma_height) = yuv::chroma_size(width as u32, height as u32);
+    let (chroma_width, chroma_height) = (chroma_width as usize, chroma_height as usize);
+    let (y_stride, u_stride, v_stride) = yuv.strides_yuv();
+    let mut bytes = Vec::with_capacity(yuv::i420_bytes(width as u32, height as u32));
+    let planes = [
+        (yuv.y_with_stride(), y_stride, width, height),
+        (yuv.u_with_stride(), u_stride, chroma_width, chroma_height),
+        (yuv.v_with_stride(), v_stride, chroma_width, chroma_height),
+    ];
+    for (plane, stride, plane_width, plane_height) in planes.iter() {
+        for row in plane.chunks(*stride).take(*plane_height) {
+            bytes.extend_from_slice(&row[..*plane_width]);
+        }
+    }
+    bytes
+}
+
+/// Decodes an uncompressed frame: a u32 width, a u32 height and a u32 pixel format (0 for RGB, 1
+/// for RGBA, 2 for BGR, 3 for I420) followed by the pixels, row by row, or by the planes for I420.
+/// Frames whose pixels don't match the header are rejected here rather than handed to the window.
+pub fn decode_raw(bytes: Vec<u8>, endianness: Endianness) -> Result<CameraData, CameraError> {
+    let mut header = Cursor::new(&bytes[..]);
+    let width = endianness.read_u32(&mut header)?;
+    let height = endianness.read_u32(&mut header)?;
+    let code = endianness.read_u32(&mut header)?;
+    let pixel_format = PixelFormat::from_code(code).ok_or(CameraError::UnknownPixelFormat(code))?;
+    let image_bytes = bytes[header.position() as usize..].to_vec();
+    check_frame_size(image_bytes.len(), width, height, pixel_format)?;
+    Ok(CameraData {
+        image_bytes,
+        width,
+        height,
+        pixel_format,
+    })
+}
+
+/// Writes a frame as JPEG using the same framing as the network protocol, a u32 length n followed
+/// by n bytes, so recordings can be played back like a live MJPEG feed.
+fn write_frame<W: Write>(writer: &mut W, frame: &CameraData) -> io::Result<()> {
+    let mut jpeg = Vec::new();
+    JpegEncoder::new(&mut jpeg)
+        .encode(
+            &frame.image_bytes,
+            frame.width,
+            frame.height,
+            ColorType::Rgb8,
+        )
+        .map_err(io::Error::other)?;
+    writer.write_u32::<LittleEndian>(jpeg.len() as u32)?;
+    writer.write_all(&jpeg)
+}
+
+/// Stretches the levels of an 8-bit image buffer in place so that its darkest values become 0 and
+/// its brightest 255. The darkest and brightest `AUTO_LEVELS_CLIP` of the values are left out when
+/// finding the range, so a few stray pixels don't keep a dim image dim. An image of a single
+/// level is left as it is.
+fn stretch_levels(bytes: &mut [u8]) {
+    let mut histogram = [0usize; 256];
+    for byte in bytes.iter() {
+        histogram[*byte as usize] += 1;
+    }
+    let clip = (bytes.len() as f32 * AUTO_LEVELS_CLIP) as usize;
+    let level_at = |count: usize| {
+        let mut total = 0;
+        histogram
+            .iter()
+            .position(|levels| {
+                total += levels;
+                total > count
+            })
+            .unwrap_or(255)
+    };
+    let low = level_at(clip);
+    let high = level_at(bytes.len().saturating_sub(clip + 1));
+    if high <= low {
+        return;
+    }
+    let scale = 255.0 / (high - low) as f32;
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let stretched = (value as f32 - low as f32) * scale;
+        *entry = stretched.round().clamp(0.0, 255.0) as u8;
+    }
+    for byte in bytes.iter_mut() {
+        *byte = table[*byte as usize];
+    }
+}
+
+/// Multiplies every value of an 8-bit image buffer by `gain` and adds `offset`, clamping the
+/// results to [0, 255]. A gain of 1.0 and an offset of 0.0 leave the image untouched.
+fn apply_gain_offset(bytes: &mut [u8], gain: f32, offset: f32) {
+    if (gain - 1.0).abs() < f32::EPSILON && offset.abs() < f32::EPSILON {
+        return;
+    }
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = (value as f32 * gain + offset).round().clamp(0.0, 255.0) as u8;
+    }
+    for byte in bytes.iter_mut() {
+        *byte = table[*byte as usize];
+    }
+}
+
+/// Applies gamma correction to an 8-bit image buffer in place. A gamma of 1.0 leaves the image
+/// untouched, larger values brighten the mid-tones and smaller values darken them.
+fn apply_gamma(bytes: &mut [u8], gamma: f32) {
+    if gamma <= 0.0 || (gamma - 1.0).abs() < f32::EPSILON {
+        return;
+    }
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = (255.0 * (value as f32 / 255.0).powf(1.0 / gamma)).round() as u8;
+    }
+    for byte in bytes.iter_mut() {
+        *byte = table[*byte as usize];
+    }
+}
+
+/// Drops the frame times that fall outside of `FPS_WINDOW` and averages the rate of the rest.
+fn frames_per_second(frame_times: &mut VecDeque<Instant>, now: Instant) -> f32 {
+    while frame_times
+        .front()
+        .is_some_and(|time| now.duration_since(*time) > FPS_WINDOW)
+    {
+        frame_times.pop_front();
+    }
+    match (frame_times.front(), frame_times.back()) {
+        (Some(first), Some(last)) if frame_times.len() > 1 => {
+            let span = last.duration_since(*first).as_secs_f32();
+            if span > 0.0 {
+                (frame_times.len() - 1) as f32 / span
+            } else {
+                0.0
+            }
+        }
+        _ => 0.0,
+    }
+}
+
+/// Writes a frame to a PNG file.
+fn save_snapshot(frame: &CameraData, path: &Path) -> ImageResult<()> {
+    image::save_buffer(
+        path,
+        &frame.image_bytes,
+        frame.width,
+        frame.height,
+        ColorType::Rgb8,
+    )
+}
+
+/// Returns a file name starting with `prefix` for a snapshot taken now, so that snapshots don't
+/// overwrite each other.
+pub fn snapshot_path(prefix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.png", timestamped_name(prefix)))
+}
+
+/// Appends the current time to `prefix`, e.g. "snapshot_1585744205_250".
+pub fn timestamped_name(prefix: &str) -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "{}_{}_{:03}",
+        prefix,
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    )
+}
+
+/// A corner of the frame the timestamp overlay can be drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+const CORNERS: [Corner; 4] = [
+    Corner::TopLeft,
+    Corner::TopRight,
+    Corner::BottomLeft,
+    Corner::BottomRight,
+];
+
+/// Where and in which color the capture time is drawn onto a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimestampOverlay {
+    pub corner: Corner,
+    /// The color of the text, as RGB from 0 to 1.
+    pub color: [f32; 3],
+}
+
+impl TimestampOverlay {
+    pub fn new() -> Self {
+        Self {
+            corner: Corner::BottomRight,
+            color: [1.0, 1.0, 0.0],
+        }
+    }
+
+    /// Returns a copy of the RGB frame with `text` drawn into the overlay's corner.
+    pub fn stamp(&self, frame: &CameraData, text: &str) -> CameraData {
+        let mut image_bytes = frame.image_bytes.clone();
+        self.draw(&mut image_bytes, frame.width, frame.height, text);
+        CameraData {
+            image_bytes,
+            width: frame.width,
+            height: frame.height,
+            pixel_format: PixelFormat::Rgb,
+        }
+    }
+
+    /// Draws `text` into the overlay's corner of an RGB image buffer, scaled to the image's
+    /// height. Text that doesn't fit is clipped.
+    pub fn draw(&self, image_bytes: &mut Vec<u8>, width: u32, height: u32, text: &str) {
+        let font = match Font::from_bytes(ttf_noto_sans::REGULAR) {
+            Ok(font) => font,
+            Err(e) => {
+                warn!("camera: couldn't load the timestamp font: {}", e);
+                return;
+            }
+        };
+        let mut image = match RgbImage::from_raw(width, height, mem::take(image_bytes)) {
+            Some(image) => image,
+            None => return,
+        };
+        let size = (height as f32 * TIMESTAMP_SCALE).max(MIN_TIMESTAMP_HEIGHT);
+        let scale = Scale::uniform(size);
+        let v_metrics = font.v_metrics(scale);
+        let text_height = (v_metrics.ascent - v_metrics.descent).ceil() as u32;
+        let text_width = font
+            .layout(text, scale, point(0.0, 0.0))
+            .filter_map(|glyph| glyph.pixel_bounding_box())
+            .map(|bounds| bounds.max.x.max(0) as u32)
+            .max()
+            .unwrap_or(0);
+        let margin = (size / 4.0) as u32;
+        let right = width.saturating_sub(text_width + margin);
+        let bottom = height.saturating_sub(text_height + margin);
+        let (x, y) = match self.corner {
+            Corner::TopLeft => (margin, margin),
+            Corner::TopRight => (right, margin),
+            Corner::BottomLeft => (margin, bottom),
+            Corner::BottomRight => (right, bottom),
+        };
+        let color = Rgb([
+            (self.color[0] * 255.0) as u8,
+            (self.color[1] * 255.0) as u8,
+            (self.color[2] * 255.0) as u8,
+        ]);
+        draw_text_mut(&mut image, color, x, y, scale, &font, text);
+        *image_bytes = image.into_raw();
+    }
+
+    /// Draws the corner and color controls.
+    fn render(&mut self, ui: &Ui) {
+        let labels: [&ImStr; 4] = [
+            im_str!("Top Left"),
+            im_str!("Top Right"),
+            im_str!("Bottom Left"),
+            im_str!("Bottom Right"),
+        ];
+        let mut corner_item = CORNERS
+            .iter()
+            .position(|corner| *corner == self.corner)
+            .unwrap_or(0);
+        if imgui::ComboBox::new(im_str!("Timestamp Corner")).build_simple_string(
+            ui,
+            &mut corner_item,
+            &labels,
+        ) {
+            self.corner = CORNERS[corner_item];
+        }
+        ColorEdit::new(im_str!("Timestamp Color"), &mut self.color).build(ui);
+    }
+}
+
+/// Formats a time as a UTC date and time with milliseconds, e.g. "2020-04-01 12:30:05.250 UTC".
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts a number of days since 1970-01-01 into a year, month and day of the proleptic
+/// Gregorian calendar, using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// A radial lens distortion model, used to correct the barrel distortion of wide angle lenses.
+/// The center is given as a fraction of the frame's width and height, and the focal length as a
+/// fraction of its width, so the same settings work at any resolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+    pub focal: f32,
+    pub center: [f32; 2],
+}
+
+impl Distortion {
+    /// A model that leaves frames as they are.
+    pub fn none() -> Self {
+        Self {
+            k1: 0.0,
+            k2: 0.0,
+            focal: 1.0,
+            center: [0.5, 0.5],
+        }
+    }
+
+    /// Where the pixel at (x, y) of the corrected frame comes from in the distorted frame.
+    fn source(&self, x: f32, y: f32, width: u32, height: u32) -> (f32, f32) {
+        let focal = self.focal.max(f32::EPSILON) * width as f32;
+        let cx = self.center[0] * width as f32;
+        let cy = self.center[1] * height as f32;
+        let (nx, ny) = ((x - cx) / focal, (y - cy) / focal);
+        let r2 = nx * nx + ny * ny;
+        let factor = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+        (cx + nx * factor * focal, cy + ny * factor * focal)
+    }
+}
+
+/// Corrects the lens distortion of an RGB image buffer, sampling the distorted frame bilinearly.
+/// Pixels whose source falls outside of the frame are left black. A model without any distortion
+/// returns the frame as it is.
+fn undistort_rgb(bytes: Vec<u8>, width: u32, height: u32, distortion: &Distortion) -> Vec<u8> {
+    if distortion.k1 == 0.0 && distortion.k2 == 0.0 {
+        return bytes;
+    }
+    let (w, h) = (width as usize, height as usize);
+    let mut corrected = vec![0; bytes.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let (sx, sy) = distortion.source(x as f32, y as f32, width, height);
+            if sx < 0.0 || sy < 0.0 || sx > (w - 1) as f32 || sy > (h - 1) as f32 {
+                continue;
+            }
+            let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+            let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+            let dst = (y * w + x) * 3;
+            for channel in 0..3 {
+                let sample = |px: usize, py: usize| bytes[(py * w + px) * 3 + channel] as f32;
+                let top = sample(x0, y0) * (1.0 - fx) + sample(x1, y0) * fx;
+                let bottom = sample(x0, y1) * (1.0 - fx) + sample(x1, y1) * fx;
+                corrected[dst + channel] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+            }
+        }
+    }
+    corrected
+}
+
+/// Mirrors an RGB image buffer left to right and/or top to bottom.
+fn flip_rgb(
+    mut bytes: Vec<u8>,
+    width: u32,
+    height: u32,
+    horizontal: bool,
+    vertical: bool,
+) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    if horizontal {
+        for row in bytes.chunks_exact_mut(w * 3) {
+            for x in 0..w / 2 {
+                let (left, right) = row.split_at_mut((w - 1 - x) * 3);
+                left[x * 3..x * 3 + 3].swap_with_slice(&mut right[..3]);
+            }
+        }
+    }
+    if vertical {
+        for y in 0..h / 2 {
+            let (top, bottom) = bytes.split_at_mut((h - 1 - y) * w * 3);
+            top[y * w * 3..(y + 1) * w * 3].swap_with_slice(&mut bottom[..w * 3]);
+        }
+    }
+    bytes
+}
+
+/// Rotates an RGB image buffer clockwise by the given number of quarter turns, returning the
+/// rotated bytes along with the new width and height.
+fn rotate_rgb(bytes: Vec<u8>, width: u32, height: u32, rotation: u8) -> (Vec<u8>, u32, u32) {
+    let rotation = rotation % 4;
+    if rotation == 0 {
+        return (bytes, width, height);
+    }
+    let (w, h) = (width as usize, height as usize);
+    let mut rotated = vec![0; bytes.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let (new_x, new_y, new_w) = match rotation {
+                1 => (h - 1 - y, x, h),
+                2 => (w - 1 - x, h - 1 - y, w),
+                _ => (y, w - 1 - x, h),
+            };
+            let src = (y * w + x) * 3;
+            let dst = (new_y * new_w + new_x) * 3;
+            rotated[dst..dst + 3].copy_from_slice(&bytes[src..src + 3]);
+        }
+    }
+    if rotation == 2 {
+        (rotated, width, height)
+    } else {
+        (rotated, height, width)
+    }
+}
+
+/// The part of the frame shown in the camera window, as a zoom factor and the texture coordinates
+/// of the center of the visible region. The visible region always stays inside the frame, so at a
+/// zoom of 1 the whole frame is shown. Rotation is applied to the pixels before they're uploaded,
+/// so the viewport is in the coordinates of the frame as it's displayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub zoom: f32,
+    pub center: [f32; 2],
+}
+
+impl Viewport {
+    pub fn new() -> Self {
+        Self {
+            zoom: 1.0,
+            center: [0.5, 0.5],
         }
     }
+
+    /// The texture coordinates of the top left and bottom right corners of the visible region.
+    pub fn uv_bounds(&self) -> ([f32; 2], [f32; 2]) {
+        let half = 0.5 / self.zoom;
+        (
+            [self.center[0] - half, self.center[1] - half],
+            [self.center[0] + half, self.center[1] + half],
+        )
+    }
+
+    /// Maps the visible region onto an overview of the whole frame drawn at `origin` with the given
+    /// `size`, returning the top left and bottom right corners of the region within it.
+    pub fn inset_rect(&self, origin: [f32; 2], size: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        let (uv0, uv1) = self.uv_bounds();
+        (
+            [origin[0] + uv0[0] * size[0], origin[1] + uv0[1] * size[1]],
+            [origin[0] + uv1[0] * size[0], origin[1] + uv1[1] * size[1]],
+        )
+    }
+
+    /// Zooms in by the given number of mouse wheel notches, or out for negative notches, keeping
+    /// the point at `anchor` where it is. `anchor` is a position within the displayed image, from
+    /// (0, 0) in the top left corner to (1, 1) in the bottom right.
+    pub fn zoom_by(&mut self, notches: f32, anchor: [f32; 2]) {
+        let (uv0, uv1) = self.uv_bounds();
+        let zoom = (self.zoom * ZOOM_STEP.powf(notches)).clamp(1.0, MAX_ZOOM);
+        let size = 1.0 / zoom;
+        for axis in 0..2 {
+            let target = uv0[axis] + anchor[axis] * (uv1[axis] - uv0[axis]);
+            self.center[axis] = target + (0.5 - anchor[axis]) * size;
+        }
+        self.zoom = zoom;
+        self.clamp();
+    }
+
+    /// Moves the visible region along with a drag of `delta`, given as a fraction of the displayed
+    /// image's size.
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        for (center, delta) in self.center.iter_mut().zip(delta.iter()) {
+            *center -= delta / self.zoom;
+        }
+        self.clamp();
+    }
+
+    /// Moves the center back far enough that the visible region is inside the frame.
+    fn clamp(&mut self) {
+        let half = 0.5 / self.zoom;
+        for center in self.center.iter_mut() {
+            *center = center.max(half).min(1.0 - half);
+        }
+    }
+}
+
+/// Scales an image to the largest size that fits within `available` without changing its aspect
+/// ratio.
+fn aspect_fit(image: [f32; 2], available: [f32; 2]) -> [f32; 2] {
+    if image[0] <= 0.0 || image[1] <= 0.0 {
+        return [0.0, 0.0];
+    }
+    let scale = (available[0] / image[0])
+        .min(available[1] / image[1])
+        .max(0.0);
+    [image[0] * scale, image[1] * scale]
 }
 
 impl Renderable for CameraWindow {
-    /// Renders the data received from the camera sensor. This currently
-    /// assumes RGB data format.
-    fn render(&mut self, ui: &Ui, display: &Display, renderer: &mut Renderer) {
+    /// Renders the data received from the camera sensor.
+    fn render(
+        &mut self,
+        ui: &Ui,
+        display: &Display,
+        renderer: &mut Renderer,
+        visible: bool,
+        all_paused: bool,
+    ) -> bool {
+        let mut opened = true;
+        self.throughput.sample(&self.stats);
+
         // If we've received new camera data, update the texture. We also need to check if there is
         // an existing texture ahead of time so we can reuse the texture instead of creating a new
-        // one each time.
-        if let Ok(camera_data) = self.receiver.try_recv() {
-            let image_frame = Some(RawImage2d {
-                data: Cow::Owned(camera_data.image_bytes.clone()),
-                width: camera_data.width as u32,
-                height: camera_data.height as u32,
-                format: ClientFormat::U8U8U8,
-            })
-            .unwrap();
-            self.window_width = camera_data.width as f32;
-            self.window_height = camera_data.height as f32;
-            let gl_texture = Texture2d::new(display.get_context(), image_frame)
-                .expect("Couldn't create new texture");
-            if let Some(tex_id) = self.texture_id {
-                renderer.textures().replace(tex_id, Rc::new(gl_texture));
+        // one each time. Zero-sized frames can't be turned into a texture, so they're skipped and
+        // the previous frame stays on screen. If the UI has fallen behind, every queued frame is
+        // counted and recorded but only the newest one is displayed. Frames are converted to RGB
+        // first, unless their colors can be converted on the GPU, and frames whose length doesn't
+        // match their dimensions are dropped.
+        let mut newest = None;
+        for data in self.receiver.try_iter().collect::<Vec<_>>() {
+            let converted = if self.gpu_colors_apply(&data) {
+                data.check_size().map(|()| data)
             } else {
-                self.texture_id = Some(renderer.textures().insert(Rc::new(gl_texture)));
+                data.into_rgb()
+            };
+            let data = match converted {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("camera: dropping frame: {}", e);
+                    continue;
+                }
+            };
+            self.record_frame(&data);
+            if data.width > 0 && data.height > 0 {
+                self.frame_times.push_back(Instant::now());
+                newest = Some(data);
+            }
+        }
+        let paused = self.paused || all_paused;
+        let newest = newest
+            .filter(|_| !paused)
+            .and_then(|data| self.show_on_gpu(display, renderer, data));
+        let frame = newest.map(|data| {
+            // The distortion is a property of the lens, so it's corrected before the frame is
+            // rotated.
+            let image_bytes = if self.undistort {
+                undistort_rgb(data.image_bytes, data.width, data.height, &self.distortion)
+            } else {
+                data.image_bytes
+            };
+            let image_bytes = flip_rgb(
+                image_bytes,
+                data.width,
+                data.height,
+                self.flip_horizontal,
+                self.flip_vertical,
+            );
+            // Rotating the pixels themselves keeps the window's aspect ratio right for quarter
+            // turns, since the width and height swap along with the image.
+            let (mut image_bytes, width, height) =
+                rotate_rgb(image_bytes, data.width, data.height, self.rotation);
+            if self.auto_levels {
+                stretch_levels(&mut image_bytes);
+            }
+            apply_gain_offset(&mut image_bytes, self.gain, self.offset);
+            apply_gamma(&mut image_bytes, self.gamma);
+            CameraData {
+                image_bytes,
+                width,
+                height,
+                pixel_format: PixelFormat::Rgb,
             }
+        });
+        // The live overlay only goes on the texture, so snapshots of the frame don't end up with
+        // two timestamps.
+        let stamped = frame.as_ref().filter(|_| self.live_timestamp).map(|frame| {
+            let mut stamped = frame.image_bytes.clone();
+            self.timestamp_overlay.draw(
+                &mut stamped,
+                frame.width,
+                frame.height,
+                &format_timestamp(SystemTime::now()),
+            );
+            stamped
+        });
+        if let Some(frame) = frame {
+            let display_bytes = Cow::Borrowed(stamped.as_deref().unwrap_or(&frame.image_bytes[..]));
+            // The window is still sized for the full frame, so a scaled down texture is just
+            // stretched back over it.
+            let (display_bytes, texture_width, texture_height) = match self.max_texture_dim {
+                Some(max_dim) => {
+                    texture::downscale_rgb(display_bytes, frame.width, frame.height, max_dim)
+                }
+                None => (display_bytes, frame.width, frame.height),
+            };
+            self.window_width = frame.width as f32;
+            self.window_height = frame.height as f32;
+            let uploaded = texture::upload_rgb(
+                display,
+                renderer,
+                &mut self.texture_id,
+                display_bytes,
+                texture_width,
+                texture_height,
+            );
+            self.notifications.extend(texture::report_upload(
+                uploaded,
+                &mut self.texture_failing,
+                &self.name,
+            ));
+            self.last_frame = Some(frame);
+        }
+
+        let failure_rate = self
+            .health
+            .lock()
+            .map(|health| health.failure_rate())
+            .unwrap_or(0.0);
+        let unhealthy = failure_rate > self.unhealthy_threshold;
+        if unhealthy && !self.was_unhealthy {
+            self.notifications.push(Notification::warn(format!(
+                "Camera: {:.0}% of recent frames failed to decode",
+                failure_rate * 100.0
+            )));
+        }
+        self.was_unhealthy = unhealthy;
+
+        if !visible {
+            return true;
         }
 
+        let show_health = || {
+            if unhealthy {
+                ui.text_colored(
+                    [1.0, 0.2, 0.2, 1.0],
+                    &im_str!(
+                        "Unhealthy: {:.0}% of recent frames failed to decode",
+                        failure_rate * 100.0
+                    ),
+                );
+            }
+        };
+
         // We call this each iteration of the CameraWindow, so we need to make sure we draw the
         // window even if we didn't receive camera data on this iteration. However, we currently
         // do not draw a window unless we've received our first sample from the camera.
+        let fps = frames_per_second(&mut self.frame_times, Instant::now());
+        let title = window::window_title(&self.name, self.window_id);
+        let mut restarted = None;
         if let Some(tex_id) = self.texture_id {
-            let camera_dims = [self.window_width, self.window_height];
-            Window::new(im_str!("Camera"))
-                .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+            let frame_dims = [self.window_width, self.window_height];
+            let camera_dims = window::logical_size(frame_dims, window::scale_factor(ui));
+            Window::new(&title)
+                .opened(&mut opened)
+                .size(
+                    [camera_dims[0], camera_dims[1] + self.controls_height],
+                    Condition::FirstUseEver,
+                )
+                .place(self.placement.take())
                 .build(ui, || {
-                    Image::new(tex_id, camera_dims).build(&ui);
+                    self.shown_at = Some(window::current_placement(ui));
+                    // The image is scaled to whatever space the controls leave, and centered in
+                    // it when the window's aspect ratio doesn't match the frame's.
+                    let start = ui.cursor_pos();
+                    let available = ui.content_region_avail();
+                    let area = [available[0], (available[1] - self.controls_height).max(0.0)];
+                    let image_size = aspect_fit(camera_dims, area);
+                    ui.set_cursor_pos([
+                        start[0] + (area[0] - image_size[0]) / 2.0,
+                        start[1] + (area[1] - image_size[1]) / 2.0,
+                    ]);
+                    // An invisible button over the image takes the mouse input, so dragging pans
+                    // the image instead of moving the window.
+                    let image_pos = ui.cursor_pos();
+                    let origin = ui.cursor_screen_pos();
+                    let (uv0, uv1) = self.viewport.uv_bounds();
+                    Image::new(tex_id, image_size).uv0(uv0).uv1(uv1).build(ui);
+                    ui.set_cursor_pos(image_pos);
+                    ui.invisible_button(im_str!("camera_viewport"), image_size);
+                    if image_size[0] > 0.0 && image_size[1] > 0.0 {
+                        if ui.is_item_hovered() {
+                            let io = ui.io();
+                            if io.mouse_wheel != 0.0 {
+                                let anchor = [
+                                    (io.mouse_pos[0] - origin[0]) / image_size[0],
+                                    (io.mouse_pos[1] - origin[1]) / image_size[1],
+                                ];
+                                self.viewport.zoom_by(io.mouse_wheel, anchor);
+                            }
+                            if ui.is_mouse_double_clicked(MouseButton::Left) {
+                                self.viewport = Viewport::new();
+                            }
+                        }
+                        if ui.is_item_active() {
+                            let delta = ui.io().mouse_delta;
+                            self.viewport
+                                .pan([delta[0] / image_size[0], delta[1] / image_size[1]]);
+                        }
+                    }
+                    // While zoomed in, an overview of the whole frame in the top right corner
+                    // outlines the part of it that's shown.
+                    if self.viewport.zoom > 1.0 {
+                        let inset_size = [
+                            image_size[0] * MINIMAP_FRACTION,
+                            image_size[1] * MINIMAP_FRACTION,
+                        ];
+                        let inset_pos = [
+                            image_pos[0] + image_size[0] - inset_size[0] - MINIMAP_MARGIN,
+                            image_pos[1] + MINIMAP_MARGIN,
+                        ];
+                        ui.set_cursor_pos(inset_pos);
+                        let inset_origin = ui.cursor_screen_pos();
+                        Image::new(tex_id, inset_size)
+                            .border_col([1.0, 1.0, 1.0, 0.8])
+                            .build(ui);
+                        let (min, max) = self.viewport.inset_rect(inset_origin, inset_size);
+                        ui.get_window_draw_list()
+                            .add_rect(min, max, [1.0, 0.8, 0.0, 1.0])
+                            .thickness(2.0)
+                            .build();
+                    }
+                    ui.set_cursor_pos([start[0], start[1] + area[1]]);
+                    let controls_start = ui.cursor_pos()[1];
+
+                    ui.text(format!(
+                        "{:.1} FPS, {}x{}, {:.1}x zoom",
+                        fps, frame_dims[0], frame_dims[1], self.viewport.zoom
+                    ));
+                    diagnostics::connection_status(ui, &self.stats, self.stale_after);
+                    diagnostics::dropped_frames(ui, &self.stats);
+                    diagnostics::throughput_graph(ui, &self.throughput);
+                    show_health();
+                    ui.checkbox(im_str!("Pause"), &mut self.paused);
+                    if let Some(playback) = &mut self.playback {
+                        playback.render(ui);
+                    }
+                    Slider::new(im_str!("Gamma"), 0.1..=3.0).build(ui, &mut self.gamma);
+                    ui.checkbox(im_str!("Auto Levels"), &mut self.auto_levels);
+                    Slider::new(im_str!("Gain"), 0.1..=4.0).build(ui, &mut self.gain);
+                    Slider::new(im_str!("Offset"), -128.0..=128.0).build(ui, &mut self.offset);
+                    ui.checkbox(im_str!("Correct Lens Distortion"), &mut self.undistort);
+                    if self.undistort {
+                        let distortion = &mut self.distortion;
+                        Slider::new(im_str!("k1"), -1.0..=1.0).build(ui, &mut distortion.k1);
+                        Slider::new(im_str!("k2"), -1.0..=1.0).build(ui, &mut distortion.k2);
+                        Slider::new(im_str!("Focal Length"), 0.1..=2.0)
+                            .build(ui, &mut distortion.focal);
+                        Slider::new(im_str!("Center X"), 0.0..=1.0)
+                            .build(ui, &mut distortion.center[0]);
+                        Slider::new(im_str!("Center Y"), 0.0..=1.0)
+                            .build(ui, &mut distortion.center[1]);
+                    }
+                    // The viewport is in displayed coordinates, so it's reset along with the
+                    // rotation rather than ending up on a different part of the frame.
+                    if ui.button(im_str!("Rotate Left"), [0.0, 0.0]) {
+                        self.rotation = (self.rotation + 3) % 4;
+                        self.viewport = Viewport::new();
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Rotate Right"), [0.0, 0.0]) {
+                        self.rotation = (self.rotation + 1) % 4;
+                        self.viewport = Viewport::new();
+                    }
+                    ui.same_line(0.0);
+                    ui.text(format!("{} degrees", self.rotation as u32 * 90));
+                    if ui.checkbox(im_str!("Flip Horizontally"), &mut self.flip_horizontal) {
+                        self.viewport = Viewport::new();
+                    }
+                    ui.same_line(0.0);
+                    if ui.checkbox(im_str!("Flip Vertically"), &mut self.flip_vertical) {
+                        self.viewport = Viewport::new();
+                    }
+                    let mut limit_texture = self.max_texture_dim.is_some();
+                    if ui.checkbox(im_str!("Limit Texture Size"), &mut limit_texture) {
+                        self.max_texture_dim = if limit_texture {
+                            Some(DEFAULT_MAX_TEXTURE_DIM)
+                        } else {
+                            None
+                        };
+                    }
+                    if let Some(max_dim) = &mut self.max_texture_dim {
+                        let mut value = *max_dim as i32;
+                        if ui
+                            .input_int(im_str!("Max Texture Size"), &mut value)
+                            .build()
+                        {
+                            *max_dim = value
+                                .max(MIN_TEXTURE_DIM as i32)
+                                .min(MAX_TEXTURE_DIM as i32)
+                                as u32;
+                        }
+                    }
+                    if ui.button(im_str!("Save Snapshot"), [0.0, 0.0]) {
+                        if let Some(frame) = &self.last_frame {
+                            let path = snapshot_path("snapshot");
+                            self.snapshot_status = Some(match self.save_frame(frame, &path) {
+                                Ok(()) => format!("Saved {}", path.display()),
+                                Err(e) => format!("Couldn't save snapshot: {}", e),
+                            });
+                        }
+                    }
+                    if let Some(status) = &self.snapshot_status {
+                        ui.same_line(0.0);
+                        ui.text(status);
+                    }
+
+                    ui.checkbox(
+                        im_str!("Timestamp Snapshots and Recordings"),
+                        &mut self.burn_timestamp,
+                    );
+                    ui.checkbox(im_str!("Show Timestamp Live"), &mut self.live_timestamp);
+                    if self.burn_timestamp || self.live_timestamp {
+                        self.timestamp_overlay.render(ui);
+                    }
+
+                    ui.input_text(im_str!("Recording File"), &mut self.record_path)
+                        .build();
+                    let record_label = if self.recording.is_some() {
+                        im_str!("Stop Recording")
+                    } else {
+                        im_str!("Record")
+                    };
+                    if ui.button(record_label, [0.0, 0.0]) {
+                        self.toggle_recording();
+                    }
+                    if let Some(status) = &self.record_status {
+                        ui.same_line(0.0);
+                        ui.text(status);
+                    }
+                    connection::connection_help(ui, self.spec.as_ref());
+                    if let Some(sensor) = &mut self.restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
+                    self.controls_height = ui.cursor_pos()[1] - controls_start;
                 });
         } else {
-            Window::new(im_str!("Camera")).build(ui, || {
-                ui.text(im_str!("Waiting for camera data..."));
-            });
+            Window::new(&title)
+                .opened(&mut opened)
+                .place(self.placement.take())
+                .build(ui, || {
+                    self.shown_at = Some(window::current_placement(ui));
+                    ui.text(im_str!("Waiting for camera data..."));
+                    diagnostics::connection_status(ui, &self.stats, self.stale_after);
+                    diagnostics::dropped_frames(ui, &self.stats);
+                    diagnostics::throughput_graph(ui, &self.throughput);
+                    show_health();
+                    connection::connection_help(ui, self.spec.as_ref());
+                    if let Some(sensor) = &mut self.restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
+                });
+        }
+        if let Some(address) = restarted {
+            if let Some(spec) = &mut self.spec {
+                spec.set_address(address);
+            }
         }
+        opened
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reset_texture(&mut self) {
+        self.texture_id = None;
+        // The converter's textures and shader belong to the display they were created on.
+        self.yuv = None;
+    }
+
+    fn has_pending_data(&self) -> bool {
+        !self.receiver.is_empty()
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        self.spec.clone()
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        Some(self.stats.clone())
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        mem::take(&mut self.notifications)
+    }
+
+    fn capture(&self, path: &Path) -> Option<ImageResult<()>> {
+        self.last_frame
+            .as_ref()
+            .map(|frame| self.save_frame(frame, path))
+    }
+
+    fn set_placement(&mut self, placement: Placement) {
+        self.placement = Some(placement);
+    }
+
+    fn take_join_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        self.restartable
+            .as_mut()
+            .map_or_else(Vec::new, RestartableSensor::take_handles)
+    }
+
+    fn current_placement(&self) -> Option<Placement> {
+        self.shown_at
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
     }
 }
 
 pub struct CameraConfig {
+    name: ImString,
     camera_port: ImString,
+    /// Why the last attempt to create the sensor failed, if it did.
+    address_error: Option<String>,
     video_format_list: Vec<ImString>,
     video_format_item: usize,
+    framing_list: Vec<ImString>,
+    framing_item: usize,
+    checksummed: bool,
+    gpu_colors: bool,
+    capture_raw: bool,
+    capture_path: ImString,
+    buffer_kib: i32,
+    read_timeout_secs: f32,
+    max_connections: i32,
+    stale_after_secs: f32,
+    channel: ChannelConfig,
+    endianness: Endianness,
+    max_frame_mib: i32,
+    unhealthy_threshold: f32,
+    tls: ServerTlsConfig,
 }
 
 impl CameraConfig {
@@ -189,57 +2094,481 @@ impl CameraConfig {
                 ImString::new(vf_str)
             })
             .collect();
-        camera_port.reserve_exact(10);
+        camera_port.reserve_exact(256);
+        let framing_list: Vec<ImString> = FrameFraming::iter()
+            .map(|framing| {
+                let framing_str: &str = framing.as_ref();
+                ImString::new(framing_str)
+            })
+            .collect();
+        let mut capture_path = ImString::new("camera_capture.bin");
+        capture_path.reserve_exact(256);
+        let mut name = ImString::new("Camera");
+        name.reserve_exact(64);
         Self {
+            name,
             camera_port,
+            address_error: None,
             video_format_item: 0,
             video_format_list,
+            framing_list,
+            framing_item: 0,
+            checksummed: false,
+            gpu_colors: false,
+            capture_raw: false,
+            capture_path,
+            buffer_kib: (DEFAULT_BUFFER_SIZE / 1024) as i32,
+            read_timeout_secs: 0.0,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS as i32,
+            stale_after_secs: diagnostics::DEFAULT_STALE_AFTER.as_secs_f32(),
+            channel: ChannelConfig::new(DEFAULT_CHANNEL_CAPACITY, DEFAULT_OVERFLOW_POLICY),
+            endianness: Endianness::Little,
+            max_frame_mib: (DEFAULT_MAX_FRAME_BYTES >> 20) as i32,
+            unhealthy_threshold: DEFAULT_UNHEALTHY_THRESHOLD,
+            tls: ServerTlsConfig::new(),
         }
     }
 }
 
 impl Modal for CameraConfig {
+    fn sensor_name(&self) -> &'static ImStr {
+        im_str!("Camera")
+    }
+
+    fn popup_name(&self) -> &'static ImStr {
+        im_str!("Camera Configuration")
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        self.camera_port = ImString::new(settings.camera_port.to_string());
+        self.camera_port.reserve_exact(256);
+        self.channel.capacity = settings.camera_channel_capacity as i32;
+    }
+
     fn render_modal(
         &mut self,
         ui: &Ui,
         join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
         sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        _shutdown: &Arc<AtomicBool>,
     ) {
-        ui.popup_modal(im_str!("Camera Configuration"))
+        ui.popup_modal(self.popup_name())
             .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
             .build(|| {
-                ui.input_text(im_str!("Listen Port"), &mut self.camera_port)
+                ui.input_text(im_str!("Window Name"), &mut self.name)
                     .build();
+                ui.input_text(im_str!("Listen Port or Address"), &mut self.camera_port)
+                    .build();
+                if let Some(e) = &self.address_error {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], e);
+                }
 
                 // For some reason, the combo box takes a slice of references, so we need to make
                 // a new Vec of references.
-                let video_slices: Vec<&ImString> =
-                    self.video_format_list.iter().map(|vf| vf).collect();
+                let video_slices: Vec<&ImString> = self.video_format_list.iter().collect();
                 imgui::ComboBox::new(im_str!("Video Format")).build_simple_string(
                     ui,
                     &mut self.video_format_item,
                     &video_slices,
                 );
 
-                let video_format: VideoFormat = VideoFormat::from_str(
-                    &self.video_format_list[self.video_format_item].to_string(),
+                let video_format: VideoFormat =
+                    VideoFormat::from_str(self.video_format_list[self.video_format_item].as_ref())
+                        .unwrap();
+
+                // Only MJPEG frames can be found without a length prefix.
+                if let VideoFormat::MJPEG = video_format {
+                    let framing_slices: Vec<&ImString> = self.framing_list.iter().collect();
+                    imgui::ComboBox::new(im_str!("Frame Framing")).build_simple_string(
+                        ui,
+                        &mut self.framing_item,
+                        &framing_slices,
+                    );
+                }
+                let framing =
+                    FrameFraming::from_str(self.framing_list[self.framing_item].as_ref()).unwrap();
+                // JPEG markers delimit frames by themselves, so there's no header to checksum.
+                let marker_framed = matches!(
+                    (&video_format, &framing),
+                    (VideoFormat::MJPEG, FrameFraming::SoiEoiMarkers)
+                );
+                if !marker_framed {
+                    ui.checkbox(im_str!("Checksummed Frames (CRC32)"), &mut self.checksummed);
+                }
+                // Only H264 and MJPEG decode to YUV. Raw frames are sent as RGB already.
+                let gpu_colors = match video_format {
+                    VideoFormat::H264 | VideoFormat::MJPEG => {
+                        ui.checkbox(im_str!("Convert Colors on the GPU"), &mut self.gpu_colors);
+                        self.gpu_colors
+                    }
+                    _ => false,
+                };
+
+                ui.input_int(im_str!("Read Buffer (KiB)"), &mut self.buffer_kib)
+                    .build();
+                ui.input_float(
+                    im_str!("Read Timeout (s, 0 = off)"),
+                    &mut self.read_timeout_secs,
                 )
-                .unwrap();
+                .build();
+                ui.input_int(im_str!("Max Connections"), &mut self.max_connections)
+                    .build();
+                self.tls.render(ui);
+                ui.input_float(im_str!("Stale After (s)"), &mut self.stale_after_secs)
+                    .build();
+                self.channel.render(ui);
+                byte_order_combo(ui, &mut self.endianness);
+                ui.input_int(im_str!("Max Frame Size (MiB)"), &mut self.max_frame_mib)
+                    .build();
+                Slider::new(im_str!("Unhealthy Decode Failure Rate"), 0.0..=1.0)
+                    .build(ui, &mut self.unhealthy_threshold);
+                ui.checkbox(im_str!("Capture Raw Bytes"), &mut self.capture_raw);
+                if self.capture_raw {
+                    ui.input_text(im_str!("Capture File"), &mut self.capture_path)
+                        .build();
+                }
 
                 if ui.button(im_str!("Create Sensor Window"), [0.0, 0.0]) {
-                    let (camera_tx, camera_rx) = unbounded();
-                    let camera = Camera::new(camera_tx);
-                    join_handles.push(
-                        camera.start(
-                            format!("0.0.0.0:{}", self.camera_port.to_string())
-                                .parse()
-                                .expect("couldn't parse IP address"),
-                            video_format,
-                        ),
-                    );
-                    sensor_windows.push(Box::new(CameraWindow::new(camera_rx)));
+                    let address = match parse_listen_addr(self.camera_port.as_ref()) {
+                        Ok(address) => address,
+                        Err(e) => {
+                            self.address_error = Some(format!("Invalid listen address: {}", e));
+                            return;
+                        }
+                    };
+                    let tls_files = self.tls.files();
+                    let tls = match tls_files.as_ref().map(|files| files.load()).transpose() {
+                        Ok(tls) => tls,
+                        Err(e) => {
+                            self.address_error = Some(format!("Couldn't set up TLS: {}", e));
+                            return;
+                        }
+                    };
+                    self.address_error = None;
+                    let (camera_tx, camera_rx) = self.channel.channel();
+                    let mut camera = Camera::new(camera_tx);
+                    camera.buffer_size = self.buffer_kib.max(1) as usize * 1024;
+                    camera.read_timeout = server::read_timeout_from_secs(self.read_timeout_secs);
+                    camera.max_connections = self.max_connections.max(1) as usize;
+                    camera.max_frame_bytes = (self.max_frame_mib.max(1) as usize) << 20;
+                    camera.endianness = self.endianness;
+                    camera.framing = framing;
+                    camera.checksummed = self.checksummed && !marker_framed;
+                    camera.tls = tls;
+                    camera.yuv_frames = gpu_colors;
+                    if self.capture_raw {
+                        camera.capture_path = Some(PathBuf::from(self.capture_path.to_string()));
+                    }
+                    let mut camera_window = CameraWindow::new(camera_rx);
+                    camera_window.name = window::window_name(&self.name, "Camera");
+                    camera_window.health = camera.health.clone();
+                    camera_window.stats = camera.stats.clone();
+                    camera_window.unhealthy_threshold = self.unhealthy_threshold;
+                    camera_window.stale_after =
+                        diagnostics::stale_after_from_secs(self.stale_after_secs);
+                    camera_window.spec = Some(SensorSpec::Camera {
+                        address,
+                        video_format: video_format.as_ref().to_string(),
+                        framing: framing.as_ref().to_string(),
+                        endianness: self.endianness,
+                        checksummed: self.checksummed && !marker_framed,
+                        tls: tls_files,
+                        gpu_colors,
+                    });
+                    let started = RestartableSensor::start(address, move |address, stop| {
+                        camera.clone().start(address, video_format, stop)
+                    });
+                    match started {
+                        Ok((restartable, handle)) => {
+                            camera_window.restartable = Some(restartable);
+                            join_handles.push(handle);
+                        }
+                        Err(e) => {
+                            self.address_error = Some(e.to_string());
+                            return;
+                        }
+                    }
+                    sensor_windows.push(Box::new(camera_window));
                     ui.close_current_popup();
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::channel;
+    use image::png::PngEncoder;
+    use openh264::encoder::{Encoder, EncoderConfig};
+    use openh264::formats::YUVBuffer;
+
+    #[test]
+    fn h264_frames_decode_at_the_encoded_resolution() {
+        let (width, height) = (64, 48);
+        let mut encoder = Encoder::with_config(EncoderConfig::new(width, height)).unwrap();
+        let rgb = vec![128; (width * height * 3) as usize];
+        let yuv = YUVBuffer::with_rgb(width as usize, height as usize, &rgb);
+        let nal_units = encoder.encode(&yuv).unwrap().to_vec();
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&(nal_units.len() as u32).to_le_bytes());
+        stream.extend_from_slice(&nal_units);
+
+        let (sender, receiver) = channel(4, OverflowPolicy::Block);
+        let mut camera = Camera::new(sender);
+        // The stream ends after the one frame, which ends the handler with an EOF error.
+        assert!(camera.handle_h264(Cursor::new(stream)).is_err());
+
+        let frame = receiver
+            .try_recv()
+            .expect("the frame should have been decoded");
+        assert_eq!((frame.width, frame.height), (width, height));
+        assert_eq!(frame.image_bytes.len(), (width * height * 3) as usize);
+    }
+
+    #[test]
+    fn the_frame_rate_averages_recent_frames() {
+        let start = Instant::now();
+        let mut frame_times: VecDeque<Instant> = (0..11)
+            .map(|frame| start + Duration::from_millis(frame * 100))
+            .collect();
+        let now = start + Duration::from_millis(1000);
+        assert!((frames_per_second(&mut frame_times, now) - 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_stalled_feed_reads_zero_frames_per_second() {
+        let start = Instant::now();
+        let mut frame_times: VecDeque<Instant> = (0..10)
+            .map(|frame| start + Duration::from_millis(frame * 100))
+            .collect();
+        let now = start + Duration::from_secs(5);
+        assert_eq!(frames_per_second(&mut frame_times, now), 0.0);
+        assert!(frame_times.is_empty());
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_png() {
+        let (width, height) = (4, 3);
+        let mut image_bytes = vec![0; (width * height * 3) as usize];
+        // Make the pixel at (1, 2) stand out.
+        let pixel = (2 * width + 1) as usize * 3;
+        image_bytes[pixel..pixel + 3].copy_from_slice(&[10, 20, 30]);
+        let frame = CameraData {
+            image_bytes,
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+        };
+
+        let path = std::env::temp_dir().join(format!("sensorview-{}.png", std::process::id()));
+        save_snapshot(&frame, &path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.dimensions(), (width, height));
+        assert_eq!(saved.get_pixel(1, 2).0, [10, 20, 30]);
+    }
+
+    #[test]
+    fn recordings_use_the_network_framing() {
+        let frames = [(8, 8), (16, 4)];
+        let mut recording = Vec::new();
+        for &(width, height) in frames.iter() {
+            let frame = CameraData {
+                image_bytes: vec![200; (width * height * 3) as usize],
+                width,
+                height,
+                pixel_format: PixelFormat::Rgb,
+            };
+            write_frame(&mut recording, &frame).unwrap();
+        }
+
+        let mut stream = Cursor::new(recording);
+        for &dims in frames.iter() {
+            let size = Endianness::Little.read_u32(&mut stream).unwrap() as usize;
+            let mut jpeg = vec![0; size];
+            stream.read_exact(&mut jpeg).unwrap();
+            let decoder = JpegDecoder::new(Cursor::new(jpeg)).unwrap();
+            assert_eq!(decoder.dimensions(), dims);
+        }
+        assert_eq!(stream.position() as usize, stream.get_ref().len());
+    }
+
+    #[test]
+    fn undistorting_keeps_the_center_and_moves_the_edges() {
+        let (width, height) = (9, 9);
+        let bytes: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            undistort_rgb(bytes.clone(), width, height, &Distortion::none()),
+            bytes
+        );
+
+        let barrel = Distortion {
+            k1: 0.5,
+            ..Distortion::none()
+        };
+        assert_eq!(barrel.source(4.5, 4.5, width, height), (4.5, 4.5));
+        let (sx, sy) = barrel.source(0.0, 0.0, width, height);
+        assert!(sx < 0.0 && sy < 0.0);
+
+        let corrected = undistort_rgb(bytes, width, height, &barrel);
+        assert_eq!(corrected.len(), (width * height * 3) as usize);
+        // The corner samples from outside of the frame, so it's left black.
+        assert_eq!(&corrected[..3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn oversized_frames_are_refused_before_reading() {
+        let (sender, _receiver) = channel(4, OverflowPolicy::Block);
+        let mut camera = Camera::new(sender);
+        // Only the length prefix is sent. Running out of data would be an UnexpectedEof, so
+        // InvalidData shows the size was refused before the frame was allocated and read.
+        let stream = u32::MAX.to_le_bytes();
+        let error = camera.handle_mjpeg(&stream[..]).unwrap_err();
+        assert!(
+            matches!(error, CameraError::FrameTooLarge { size, .. } if size == u32::MAX as usize)
+        );
+    }
+
+    #[test]
+    fn corrupt_jpeg_frames_are_skipped() {
+        let (width, height) = (16, 8);
+        let mut jpeg = Vec::new();
+        JpegEncoder::new(&mut jpeg)
+            .encode(
+                &vec![200; width * height * 3],
+                width as u32,
+                height as u32,
+                ColorType::Rgb8,
+            )
+            .unwrap();
+
+        let mut stream = Vec::new();
+        for frame in [&jpeg[..], b"not a jpeg", &jpeg[..]].iter() {
+            stream.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            stream.extend_from_slice(frame);
+        }
+
+        let (sender, receiver) = channel(4, OverflowPolicy::Block);
+        let mut camera = Camera::new(sender);
+        // The stream ends after the last frame, which is reported as an EOF rather than a decode
+        // failure.
+        let error = camera.handle_mjpeg(&stream[..]).unwrap_err();
+        assert!(
+            matches!(error, CameraError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+
+        let frames: Vec<CameraData> = receiver.try_iter().collect();
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            assert_eq!((frame.width, frame.height), (width as u32, height as u32));
+        }
+    }
+
+    #[test]
+    fn grayscale_jpegs_are_expanded_to_rgb() {
+        let (width, height) = (8, 4);
+        let mut jpeg = Vec::new();
+        JpegEncoder::new(&mut jpeg)
+            .encode(
+                &vec![90; width * height],
+                width as u32,
+                height as u32,
+                ColorType::L8,
+            )
+            .unwrap();
+
+        let frame = decode_jpeg(jpeg).unwrap();
+        assert_eq!((frame.width, frame.height), (width as u32, height as u32));
+        assert_eq!(frame.image_bytes.len(), width * height * 3);
+        for pixel in frame.image_bytes.chunks(3) {
+            assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+        }
+    }
+
+    #[test]
+    fn png_frames_are_decoded_to_rgb() {
+        let (width, height) = (4, 2);
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| [i as u8, 10, 20, 128])
+            .collect();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .encode(&rgba, width as u32, height as u32, ColorType::Rgba8)
+            .unwrap();
+        assert!(png.starts_with(&PNG_SIGNATURE));
+
+        // The alpha channel is dropped rather than blended.
+        let frame = decode_image(png).unwrap();
+        assert_eq!((frame.width, frame.height), (width as u32, height as u32));
+        let expected: Vec<u8> = (0..width * height)
+            .flat_map(|i| [i as u8, 10, 20])
+            .collect();
+        assert_eq!(frame.image_bytes, expected);
+
+        // Anything that isn't a PNG is still decoded as a JPEG.
+        assert!(decode_image(vec![0; 16]).is_err());
+    }
+
+    #[test]
+    fn large_frames_are_downscaled_to_the_max_texture_dim() {
+        let (width, height) = (400, 100);
+        let bytes = vec![200; (width * height * 3) as usize];
+        let (scaled, scaled_width, scaled_height) =
+            texture::downscale_rgb(Cow::Borrowed(&bytes[..]), width, height, 100);
+        assert_eq!((scaled_width, scaled_height), (100, 25));
+        assert_eq!(scaled.len(), 100 * 25 * 3);
+        assert!(scaled.iter().all(|&value| value == 200));
+
+        // Frames that already fit aren't copied.
+        let (kept, kept_width, kept_height) =
+            texture::downscale_rgb(Cow::Borrowed(&bytes[..]), width, height, 400);
+        assert!(matches!(kept, Cow::Borrowed(_)));
+        assert_eq!((kept_width, kept_height), (width, height));
+
+        // A very thin frame keeps at least one pixel along its short side.
+        let thin = vec![0; 1000 * 3];
+        let (_, thin_width, thin_height) =
+            texture::downscale_rgb(Cow::Borrowed(&thin[..]), 1000, 1, 10);
+        assert_eq!((thin_width, thin_height), (10, 1));
+    }
+
+    /// Hands out a few bytes per read, so markers get split across reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = buf.len().min(self.0.len()).min(3);
+            buf[..size].copy_from_slice(&self.0[..size]);
+            self.0 = &self.0[size..];
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn jpegs_are_found_by_their_markers() {
+        let first = [0xFF, 0xD8, 1, 2, 0xFF, 0x00, 3, 0xFF, 0xD9];
+        let second = [0xFF, 0xD8, 4, 0xFF, 0xD9];
+        let mut stream = b"junk".to_vec();
+        stream.extend_from_slice(&first);
+        stream.extend_from_slice(b"\xFF between frames");
+        stream.extend_from_slice(&second);
+
+        let mut scanner = JpegScanner::new();
+        let mut stream = Trickle(&stream);
+        assert_eq!(scanner.next_frame(&mut stream, 1024).unwrap(), first);
+        assert_eq!(scanner.next_frame(&mut stream, 1024).unwrap(), second);
+        let error = scanner.next_frame(&mut stream, 1024).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn jpegs_without_an_end_marker_are_refused() {
+        let mut stream = vec![0xFF, 0xD8];
+        stream.extend(std::iter::repeat_n(0, 64));
+        let mut scanner = JpegScanner::new();
+        let error = scanner.next_frame(&mut &stream[..], 16).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,29 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// A reader that copies every byte read from the inner reader into a capture file before it gets
+/// decoded. Since nothing is parsed here, the capture contains exactly what arrived on the wire,
+/// including any malformed frames.
+pub struct CaptureReader<R> {
+    inner: R,
+    capture: File,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Wraps the reader, appending captured bytes to the file at `path` so that multiple
+    /// connections to the same sensor end up in a single capture. Connections that are open at the
+    /// same time interleave their bytes, so such a capture can't be replayed as is.
+    pub fn new(inner: R, path: &Path) -> io::Result<Self> {
+        let capture = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { inner, capture })
+    }
+}
+
+impl<R: Read> Read for CaptureReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.capture.write_all(&buf[..size])?;
+        Ok(size)
+    }
+}
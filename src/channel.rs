@@ -0,0 +1,196 @@
+use crate::diagnostics::SensorStats;
+use crossbeam::channel::{bounded, Receiver, Sender, TrySendError};
+use imgui::{im_str, ImStr, Ui};
+use std::io;
+
+/// What a sensor thread does with new data while the window's channel is full, i.e. while the UI
+/// has fallen behind the sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    /// Throw the new data away. The sensor thread keeps reading so the connection never backs up,
+    /// at the cost of gaps in what the window shows.
+    Drop,
+    /// Throw the oldest waiting data away to make room for the new data, so the window always
+    /// gets the latest. Like `Drop`, the sensor thread never waits for the window.
+    DropOldest,
+    /// Wait for the window to catch up. Nothing is lost, but the sensor stops reading from the
+    /// connection in the meantime.
+    Block,
+}
+
+/// The policies in the order they're listed in the config modals.
+const POLICIES: [OverflowPolicy; 3] = [
+    OverflowPolicy::Drop,
+    OverflowPolicy::DropOldest,
+    OverflowPolicy::Block,
+];
+
+/// The sending half of a bounded channel from a sensor thread to its window.
+pub struct SensorSender<T> {
+    sender: Sender<T>,
+    /// A receiver on the same channel, which `DropOldest` takes the oldest item off with. Holding
+    /// it keeps the channel from ever disconnecting, so a sensor with this policy has to be
+    /// stopped through its shutdown flag instead, as `RestartableSensor` does when the window
+    /// goes away.
+    oldest: Option<Receiver<T>>,
+    policy: OverflowPolicy,
+}
+
+impl<T> Clone for SensorSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            oldest: self.oldest.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T> SensorSender<T> {
+    /// The number of items waiting in the channel for the window to take them.
+    pub fn backlog(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Sends data to the window, applying the overflow policy if the channel is full, and records
+    /// in the sensor's stats whether it was passed on or dropped. Fails with `ConnectionAborted`
+    /// once the window has been closed, which tells the sensor to stop.
+    pub fn send(&self, data: T, stats: &SensorStats) -> io::Result<()> {
+        let result = match self.policy {
+            OverflowPolicy::Drop => match self.sender.try_send(data) {
+                Err(TrySendError::Full(_)) => {
+                    stats.record_drop();
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+                Ok(()) => {
+                    stats.record_frame(self.backlog());
+                    Ok(())
+                }
+            },
+            OverflowPolicy::DropOldest => self.send_replacing_oldest(data, stats),
+            OverflowPolicy::Block => self
+                .sender
+                .send(data)
+                .map(|()| stats.record_frame(self.backlog()))
+                .map_err(|_| ()),
+        };
+        result.map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "sensor window channel disconnected",
+            )
+        })
+    }
+
+    /// Sends data to the window, taking the oldest item off the channel for every time it's
+    /// full. The window may take an item itself in between, in which case nothing is dropped.
+    fn send_replacing_oldest(&self, mut data: T, stats: &SensorStats) -> Result<(), ()> {
+        loop {
+            match self.sender.try_send(data) {
+                Ok(()) => {
+                    stats.record_frame(self.backlog());
+                    return Ok(());
+                }
+                Err(TrySendError::Disconnected(_)) => return Err(()),
+                Err(TrySendError::Full(rejected)) => {
+                    if let Some(oldest) = &self.oldest {
+                        if oldest.try_recv().is_ok() {
+                            stats.record_drop();
+                        }
+                    }
+                    data = rejected;
+                }
+            }
+        }
+    }
+}
+
+/// Creates a channel from a sensor thread to its window that holds at most `capacity` items.
+pub fn channel<T>(capacity: usize, policy: OverflowPolicy) -> (SensorSender<T>, Receiver<T>) {
+    let (sender, receiver) = bounded(capacity.max(1));
+    let oldest = match policy {
+        OverflowPolicy::DropOldest => Some(receiver.clone()),
+        OverflowPolicy::Drop | OverflowPolicy::Block => None,
+    };
+    let sender = SensorSender {
+        sender,
+        oldest,
+        policy,
+    };
+    (sender, receiver)
+}
+
+/// The channel settings shown in a sensor's config modal.
+pub struct ChannelConfig {
+    pub capacity: i32,
+    policy_item: usize,
+}
+
+impl ChannelConfig {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity as i32,
+            policy_item: POLICIES.iter().position(|p| *p == policy).unwrap_or(0),
+        }
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        POLICIES[self.policy_item]
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        ui.input_int(im_str!("Channel Capacity"), &mut self.capacity)
+            .build();
+        let labels: [&ImStr; 3] = [
+            im_str!("Drop New Data"),
+            im_str!("Drop Oldest Data"),
+            im_str!("Block Sensor"),
+        ];
+        imgui::ComboBox::new(im_str!("When Full")).build_simple_string(
+            ui,
+            &mut self.policy_item,
+            &labels,
+        );
+    }
+
+    /// Creates a channel with these settings.
+    pub fn channel<T>(&self) -> (SensorSender<T>, Receiver<T>) {
+        channel(self.capacity.max(1) as usize, self.policy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends more items than the channel holds without the window taking any, returning what the
+    /// window then finds in the channel and how many items were counted as dropped.
+    fn overfill(policy: OverflowPolicy) -> (Vec<u32>, u64) {
+        let (sender, receiver) = channel(3, policy);
+        let stats = SensorStats::new();
+        for item in 0..10 {
+            sender.send(item, &stats).unwrap();
+        }
+        assert_eq!(receiver.len(), 3);
+        (receiver.try_iter().collect(), stats.dropped())
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_items() {
+        assert_eq!(overfill(OverflowPolicy::DropOldest), (vec![7, 8, 9], 7));
+    }
+
+    #[test]
+    fn drop_keeps_the_oldest_items() {
+        assert_eq!(overfill(OverflowPolicy::Drop), (vec![0, 1, 2], 7));
+    }
+
+    #[test]
+    fn closing_the_window_aborts_the_sensor() {
+        let (sender, receiver) = channel(1, OverflowPolicy::Drop);
+        drop(receiver);
+        let e = sender.send(0, &SensorStats::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::camera::{FrameFraming, VideoFormat};
+use crate::endian::Endianness;
+use crate::gps::GpsFormat;
+use crate::lidar::{DistanceUnit, LidarFormat};
+use crate::server::Transport;
+use crate::session::SensorSpec;
+use crate::window::{parse_listen_addr, resolve_addr};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: sensorview [OPTIONS]
+
+Options:
+    --camera ADDR:PORT[,FORMAT]  Listen for a camera, FORMAT being MJPEG (default, also takes
+                                 PNG frames), H264 or RAW
+    --lidar ADDR:PORT[,FORMAT[,UNIT]]
+                                 Listen for a LIDAR, FORMAT being Basic (default) or Intensity
+                                 and UNIT the unit of its distances, Raw (default), Millimeters,
+                                 Centimeters or Meters
+    --gps PORT|ADDR:PORT         Listen for a GPS, on all interfaces if only a port is given
+    --controller ADDR:PORT       Send the gamepad state to ADDR:PORT
+    --selftest                   Render a single frame with a synthetic LIDAR scan and exit
+    --headless                   Write what the sensors receive to files instead of showing them
+    --output DIR                 The directory headless mode writes to (default: .)
+    --health PORT|ADDR:PORT      Serve a JSON report of each sensor's state over HTTP
+    --help                       Show this message
+
+Sensor options can be repeated to start several sensors of the same kind. ADDR can be a
+hostname or a bracketed IPv6 address like [::1]. Adding ,CRC to a --camera or --lidar value
+reads its frames wrapped in checksummed SVFR headers, e.g. --lidar 0.0.0.0:8002,Basic,CRC.";
+
+/// What sensorview was asked to do on the command line.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub selftest: bool,
+    pub help: bool,
+    /// Whether to capture the sensors to files without opening a window.
+    pub headless: bool,
+    pub output_dir: Option<PathBuf>,
+    /// Where to serve the health report, if anywhere.
+    pub health_address: Option<SocketAddr>,
+    /// Sensors to start right away, in the order they were given.
+    pub sensors: Vec<SensorSpec>,
+}
+
+/// Parses the command line arguments, not including the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--selftest" => parsed.selftest = true,
+            "--help" | "-h" => parsed.help = true,
+            "--headless" => parsed.headless = true,
+            "--output" => {
+                let dir = args.next().ok_or("--output needs a value")?;
+                parsed.output_dir = Some(PathBuf::from(dir));
+            }
+            "--health" => {
+                let value = args.next().ok_or("--health needs a value")?;
+                let address = parse_listen_addr(&value)
+                    .map_err(|e| format!("Invalid --health value {:?}: {}", value, e))?;
+                parsed.health_address = Some(address);
+            }
+            "--camera" | "--lidar" | "--gps" | "--controller" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                let spec = parse_sensor(&arg, &value)
+                    .map_err(|e| format!("Invalid {} value {:?}: {}", arg, value, e))?;
+                parsed.sensors.push(spec);
+            }
+            _ => return Err(format!("Unknown argument {:?}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parses the value given to one of the sensor flags.
+fn parse_sensor(flag: &str, value: &str) -> Result<SensorSpec, String> {
+    let endianness = Endianness::Little;
+    match flag {
+        "--camera" => {
+            let (address, options, checksummed) = split_sensor_value(value)?;
+            if let Some(option) = options.get(1) {
+                return Err(format!("unexpected option {:?}", option));
+            }
+            let video_format = match options.first() {
+                Some(format) => VideoFormat::from_str(format)
+                    .map_err(|_| format!("unknown video format {:?}", format))?,
+                None => VideoFormat::MJPEG,
+            };
+            Ok(SensorSpec::Camera {
+                address,
+                video_format: video_format.as_ref().to_string(),
+                framing: FrameFraming::LengthPrefixed.as_ref().to_string(),
+                endianness,
+                checksummed,
+                tls: None,
+                gpu_colors: false,
+            })
+        }
+        "--lidar" => {
+            let (address, options, checksummed) = split_sensor_value(value)?;
+            if let Some(option) = options.get(2) {
+                return Err(format!("unexpected option {:?}", option));
+            }
+            let format = match options.first() {
+                Some(format) => LidarFormat::from_str(format)
+                    .map_err(|_| format!("unknown LIDAR format {:?}", format))?,
+                None => LidarFormat::Basic,
+            };
+            let distance_unit = match options.get(1) {
+                Some(unit) => DistanceUnit::from_str(unit)
+                    .map_err(|_| format!("unknown distance unit {:?}", unit))?,
+                None => DistanceUnit::Raw,
+            };
+            Ok(SensorSpec::Lidar {
+                address,
+                format: format.as_ref().to_string(),
+                endianness,
+                transport: Transport::Tcp,
+                checksummed,
+                distance_unit: distance_unit.as_ref().to_string(),
+                tls: None,
+            })
+        }
+        "--gps" => Ok(SensorSpec::Gps {
+            address: parse_listen_addr(value).map_err(|e| e.to_string())?,
+            format: GpsFormat::Single.as_ref().to_string(),
+            endianness,
+            transport: Transport::Tcp,
+            tls: None,
+        }),
+        "--controller" => Ok(SensorSpec::Controller {
+            address: parse_address(value)?,
+            endianness,
+            tls: None,
+        }),
+        _ => Err(format!("unknown sensor flag {}", flag)),
+    }
+}
+
+/// Splits a --camera or --lidar value into its address, the options that follow it in order and
+/// whether it ends in ,CRC.
+fn split_sensor_value(value: &str) -> Result<(SocketAddr, Vec<&str>, bool), String> {
+    let mut parts = value.split(',').map(str::trim);
+    let address = parse_address(parts.next().unwrap_or(""))?;
+    let mut options = Vec::new();
+    let mut checksummed = false;
+    for part in parts {
+        if part.eq_ignore_ascii_case("crc") {
+            checksummed = true;
+        } else {
+            options.push(part);
+        }
+    }
+    Ok((address, options, checksummed))
+}
+
+fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    resolve_addr(address).map_err(|e| format!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn sensor_flags_are_parsed_in_order() {
+        let args = parse(&[
+            "--camera",
+            "0.0.0.0:8001,H264",
+            "--lidar",
+            "0.0.0.0:8002,Intensity,Millimeters,CRC",
+            "--gps",
+            "8003",
+            "--controller",
+            "192.168.1.20:8004",
+            "--camera",
+            "[::1]:8005",
+        ])
+        .unwrap();
+        assert!(!args.selftest && !args.help);
+        let endianness = Endianness::Little;
+        assert_eq!(
+            args.sensors,
+            vec![
+                SensorSpec::Camera {
+                    address: "0.0.0.0:8001".parse().unwrap(),
+                    video_format: "H264".to_string(),
+                    framing: "LengthPrefixed".to_string(),
+                    endianness,
+                    checksummed: false,
+                    tls: None,
+                    gpu_colors: false,
+                },
+                SensorSpec::Lidar {
+                    address: "0.0.0.0:8002".parse().unwrap(),
+                    format: "Intensity".to_string(),
+                    transport: Transport::Tcp,
+                    endianness,
+                    checksummed: true,
+                    distance_unit: "Millimeters".to_string(),
+                    tls: None,
+                },
+                SensorSpec::Gps {
+                    address: "0.0.0.0:8003".parse().unwrap(),
+                    format: GpsFormat::Single.as_ref().to_string(),
+                    transport: Transport::Tcp,
+                    endianness,
+                    tls: None,
+                },
+                SensorSpec::Controller {
+                    address: "192.168.1.20:8004".parse().unwrap(),
+                    endianness,
+                    tls: None,
+                },
+                SensorSpec::Camera {
+                    address: "[::1]:8005".parse().unwrap(),
+                    video_format: "MJPEG".to_string(),
+                    framing: "LengthPrefixed".to_string(),
+                    endianness,
+                    checksummed: false,
+                    tls: None,
+                    gpu_colors: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn other_flags_are_parsed() {
+        let args = parse(&["--selftest", "-h"]).unwrap();
+        assert!(args.selftest && args.help);
+        assert!(args.sensors.is_empty());
+    }
+
+    #[test]
+    fn malformed_arguments_are_rejected() {
+        assert!(parse(&["--camera"]).is_err());
+        assert!(parse(&["--camera", "0.0.0.0"]).is_err());
+        assert!(parse(&["--camera", "0.0.0.0:8001,VP9"]).is_err());
+        assert!(parse(&["--camera", "0.0.0.0:8001,MJPEG,Basic"]).is_err());
+        assert!(parse(&["--lidar", "not an address"]).is_err());
+        assert!(parse(&["--lidar", "0.0.0.0:8002,Sonar"]).is_err());
+        assert!(parse(&["--lidar", "0.0.0.0:8002,Basic,Furlongs"]).is_err());
+        assert!(parse(&["--gps", "0.0.0.0"]).is_err());
+        assert!(parse(&["--gps", "70000"]).is_err());
+        assert!(parse(&["--controller", ""]).is_err());
+        assert!(parse(&["--radar", "0.0.0.0:8006"]).is_err());
+    }
+}
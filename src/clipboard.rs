@@ -0,0 +1,28 @@
+use arboard::Clipboard;
+use imgui::{ClipboardBackend, Context, ImStr, ImString};
+use log::warn;
+
+/// Hands imgui's copy and paste to the system clipboard. Without it, text copied in sensorview
+/// could only be pasted back into sensorview.
+struct SystemClipboard(Clipboard);
+
+impl ClipboardBackend for SystemClipboard {
+    fn get(&mut self) -> Option<ImString> {
+        self.0.get_text().ok().map(ImString::new)
+    }
+
+    fn set(&mut self, value: &ImStr) {
+        if let Err(e) = self.0.set_text(value.to_str().to_string()) {
+            warn!("clipboard: couldn't copy the text: {}", e);
+        }
+    }
+}
+
+/// Connects an imgui context to the system clipboard, leaving it with imgui's own clipboard if
+/// the system's can't be opened.
+pub fn install(imgui: &mut Context) {
+    match Clipboard::new() {
+        Ok(clipboard) => imgui.set_clipboard_backend(Box::new(SystemClipboard(clipboard))),
+        Err(e) => warn!("clipboard: couldn't open the system clipboard: {}", e),
+    }
+}
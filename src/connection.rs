@@ -0,0 +1,280 @@
+use crate::camera::{FrameFraming, VideoFormat};
+use crate::framing::FRAME_MAGIC;
+use crate::gps::GpsFormat;
+use crate::lidar::{DistanceUnit, LidarFormat};
+use crate::server::Transport;
+use crate::session::SensorSpec;
+use imgui::{im_str, ImString, Ui};
+use std::str::FromStr;
+
+/// Describes what a device has to do to stream to a sensor, tailored to the sensor's format
+/// options, e.g. "send u32 LE length + JPEG bytes to 0.0.0.0:8001 over TCP". For a controller,
+/// it describes what sensorview sends to the receiver instead.
+pub fn connection_command(spec: &SensorSpec) -> String {
+    match spec {
+        SensorSpec::Camera {
+            address,
+            video_format,
+            framing,
+            endianness,
+            tls,
+            checksummed,
+            ..
+        } => {
+            let video_format = VideoFormat::from_str(video_format).unwrap_or(VideoFormat::MJPEG);
+            let framing = FrameFraming::from_str(framing).unwrap_or(FrameFraming::LengthPrefixed);
+            let length = frame_header(endianness.short_name(), *checksummed);
+            let frame = match (video_format, framing) {
+                (VideoFormat::MJPEG, FrameFraming::SoiEoiMarkers) => {
+                    "bare JPEG bytes (FFD8 to FFD9)".to_string()
+                }
+                (VideoFormat::MJPEG, FrameFraming::LengthPrefixed) => {
+                    format!("{} + JPEG bytes", length)
+                }
+                (VideoFormat::H264, _) => format!("{} + H264 NAL units", length),
+                (VideoFormat::RAW, _) => format!(
+                    "{} + u32 width, u32 height, u32 pixel format (0 RGB, 1 RGBA, 2 BGR, \
+                     3 I420) + pixels",
+                    length
+                ),
+            };
+            format!(
+                "send {} to {} over {}",
+                frame,
+                address,
+                stream_protocol(tls.is_some())
+            )
+        }
+        SensorSpec::Lidar {
+            address,
+            format,
+            endianness,
+            transport,
+            tls,
+            checksummed,
+            distance_unit,
+        } => {
+            let format = LidarFormat::from_str(format).unwrap_or(LidarFormat::Basic);
+            let order = endianness.short_name();
+            let distance = match DistanceUnit::from_str(distance_unit) {
+                Ok(DistanceUnit::Raw) | Err(_) => "distance".to_string(),
+                Ok(unit) => format!("distance in {}", unit.as_ref().to_lowercase()),
+            };
+            let point = match format {
+                LidarFormat::Basic => format!("f32 {0} angle, f32 {0} {1}", order, distance),
+                LidarFormat::Intensity => format!(
+                    "f32 {0} angle, f32 {0} {1}, f32 {0} intensity",
+                    order, distance
+                ),
+            };
+            let scan = format!("u32 {} return count + ({}) per return", order, point);
+            let scan = if *checksummed {
+                format!("{} + {}", frame_header(order, true), scan)
+            } else {
+                scan
+            };
+            format!(
+                "send {} to {} over {}",
+                scan,
+                address,
+                protocol(*transport, tls.is_some())
+            )
+        }
+        SensorSpec::Gps {
+            address,
+            format,
+            endianness,
+            transport,
+            tls,
+        } => {
+            let format = GpsFormat::from_str(format).unwrap_or(GpsFormat::Single);
+            let order = endianness.short_name();
+            let fix = format!("f32 {0} lat, f32 {0} lon", order);
+            let message = match format {
+                GpsFormat::Single => fix,
+                GpsFormat::Batched => format!("u32 {} count + ({}) per fix", order, fix),
+                GpsFormat::Tagged => format!("u32 {} track id, {}", order, fix),
+            };
+            format!(
+                "send {} to {} over {}",
+                message,
+                address,
+                protocol(*transport, tls.is_some())
+            )
+        }
+        SensorSpec::Controller {
+            address,
+            endianness,
+            tls,
+        } => format!(
+            "listen on {} over {} for u32 {} length + CBOR gamepad event",
+            address,
+            stream_protocol(tls.is_some()),
+            endianness.short_name()
+        ),
+    }
+}
+
+/// The header in front of each length-prefixed frame in the given byte order: the payload's
+/// length, preceded by the magic and followed by the payload's CRC32 for checksummed frames.
+fn frame_header(order: &str, checksummed: bool) -> String {
+    if checksummed {
+        format!(
+            "{} + u32 {1} length + u32 {1} CRC32",
+            String::from_utf8_lossy(&FRAME_MAGIC),
+            order
+        )
+    } else {
+        format!("u32 {} length", order)
+    }
+}
+
+fn protocol(transport: Transport, tls: bool) -> &'static str {
+    match transport {
+        Transport::Tcp => stream_protocol(tls),
+        Transport::Udp => "UDP",
+    }
+}
+
+fn stream_protocol(tls: bool) -> &'static str {
+    if tls {
+        "TLS"
+    } else {
+        "TCP"
+    }
+}
+
+/// Shows how to point a device at the sensor, with a button that copies it to the clipboard.
+pub fn connection_help(ui: &Ui, spec: Option<&SensorSpec>) {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return,
+    };
+    let command = ImString::new(connection_command(spec));
+    ui.text_wrapped(&command);
+    if ui.button(im_str!("Copy Connection Command"), [0.0, 0.0]) {
+        ui.set_clipboard_text(&command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endian::Endianness;
+    use crate::tls::{ClientTlsFiles, ServerTlsFiles};
+    use std::path::PathBuf;
+
+    fn server_tls() -> Option<ServerTlsFiles> {
+        Some(ServerTlsFiles {
+            certificate: PathBuf::from("cert.pem"),
+            private_key: PathBuf::from("key.pem"),
+        })
+    }
+
+    fn camera(video_format: &str, framing: &str, checksummed: bool) -> SensorSpec {
+        SensorSpec::Camera {
+            address: "127.0.0.1:8001".parse().unwrap(),
+            video_format: video_format.to_string(),
+            framing: framing.to_string(),
+            endianness: Endianness::Little,
+            tls: None,
+            checksummed,
+            gpu_colors: false,
+        }
+    }
+
+    fn lidar(format: &str, transport: Transport, checksummed: bool, unit: &str) -> SensorSpec {
+        SensorSpec::Lidar {
+            address: "0.0.0.0:8002".parse().unwrap(),
+            format: format.to_string(),
+            endianness: Endianness::Little,
+            transport,
+            tls: None,
+            checksummed,
+            distance_unit: unit.to_string(),
+        }
+    }
+
+    fn gps(format: &str, transport: Transport) -> SensorSpec {
+        SensorSpec::Gps {
+            address: "0.0.0.0:8003".parse().unwrap(),
+            format: format.to_string(),
+            endianness: Endianness::Little,
+            transport,
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn connection_commands_describe_each_sensor() {
+        let cases = vec![
+            (
+                camera("MJPEG", "LengthPrefixed", false),
+                "send u32 LE length + JPEG bytes to 127.0.0.1:8001 over TCP",
+            ),
+            (
+                camera("MJPEG", "SoiEoiMarkers", false),
+                "send bare JPEG bytes (FFD8 to FFD9) to 127.0.0.1:8001 over TCP",
+            ),
+            (
+                camera("MJPEG", "LengthPrefixed", true),
+                "send SVFR + u32 LE length + u32 LE CRC32 + JPEG bytes to 127.0.0.1:8001 over TCP",
+            ),
+            (
+                SensorSpec::Camera {
+                    address: "127.0.0.1:8001".parse().unwrap(),
+                    video_format: "H264".to_string(),
+                    framing: "LengthPrefixed".to_string(),
+                    endianness: Endianness::Big,
+                    tls: server_tls(),
+                    checksummed: true,
+                    gpu_colors: true,
+                },
+                "send SVFR + u32 BE length + u32 BE CRC32 + H264 NAL units to 127.0.0.1:8001 \
+                 over TLS",
+            ),
+            (
+                camera("RAW", "LengthPrefixed", false),
+                "send u32 LE length + u32 width, u32 height, u32 pixel format (0 RGB, 1 RGBA, \
+                 2 BGR, 3 I420) + pixels to 127.0.0.1:8001 over TCP",
+            ),
+            (
+                lidar("Basic", Transport::Tcp, false, "Raw"),
+                "send u32 LE return count + (f32 LE angle, f32 LE distance) per return to \
+                 0.0.0.0:8002 over TCP",
+            ),
+            (
+                lidar("Intensity", Transport::Udp, true, "Millimeters"),
+                "send SVFR + u32 LE length + u32 LE CRC32 + u32 LE return count + (f32 LE angle, \
+                 f32 LE distance in millimeters, f32 LE intensity) per return to 0.0.0.0:8002 \
+                 over UDP",
+            ),
+            (
+                gps("Single", Transport::Tcp),
+                "send f32 LE lat, f32 LE lon to 0.0.0.0:8003 over TCP",
+            ),
+            (
+                gps("Batched", Transport::Udp),
+                "send u32 LE count + (f32 LE lat, f32 LE lon) per fix to 0.0.0.0:8003 over UDP",
+            ),
+            (
+                gps("Tagged", Transport::Tcp),
+                "send u32 LE track id, f32 LE lat, f32 LE lon to 0.0.0.0:8003 over TCP",
+            ),
+            (
+                SensorSpec::Controller {
+                    address: "192.168.1.10:9000".parse().unwrap(),
+                    endianness: Endianness::Little,
+                    tls: Some(ClientTlsFiles {
+                        ca_certificate: PathBuf::from("ca.pem"),
+                        server_name: "robot".to_string(),
+                    }),
+                },
+                "listen on 192.168.1.10:9000 over TLS for u32 LE length + CBOR gamepad event",
+            ),
+        ];
+        for (spec, expected) in cases {
+            assert_eq!(connection_command(&spec), expected, "for {:?}", spec);
+        }
+    }
+}
@@ -1,17 +1,33 @@
-use crate::window::{Modal, Renderable};
-use byteorder::{LittleEndian, WriteBytesExt};
-use gilrs::{Axis, Button, Event, EventType, Gilrs};
-use imgui::{self, im_str, ImString, Ui, WindowFlags};
-use serde::Serialize;
-use std::io::{self, Write};
-use std::net::SocketAddr;
-use std::net::TcpStream;
+use crate::connection;
+use crate::diagnostics::SensorStats;
+use crate::endian::{byte_order_combo, Endianness};
+use crate::notifications::Notification;
+use crate::session::SensorSpec;
+use crate::shutdown::{self, ShutdownReader};
+use crate::tls::{ClientStream, ClientTls, ClientTlsConfig};
+use crate::window::{self, resolve_addr, Modal, Place, Placement, Renderable};
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks};
+use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use glium::Display;
+use image::ImageResult;
+use imgui::{self, im_str, ImStr, ImString, Slider, Ui, Window, WindowFlags};
+use imgui_glium_renderer::Renderer;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// A gamepad event enumeration identical to the EventType enumeration in `gilrs` except with the
 /// Code field removed.
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum GpEvent {
     ButtonPressed(Button),
     ButtonRepeated(Button),
@@ -23,20 +39,263 @@ pub enum GpEvent {
     Dropped,
 }
 
-pub struct Controller;
+/// A request from the receiver to rumble the gamepad, sent back over the same connection and
+/// framed the same way as the gamepad events. Magnitudes range from 0 to 1.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RumbleCommand {
+    pub strong: f32,
+    pub weak: f32,
+    pub duration_ms: u32,
+}
+
+/// Every button listed in the controller legend, in display order.
+const LEGEND_BUTTONS: [Button; 19] = [
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::C,
+    Button::Z,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Every axis listed in the controller legend, in display order.
+const LEGEND_AXES: [Axis; 8] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::LeftZ,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::RightZ,
+    Axis::DPadX,
+    Axis::DPadY,
+];
+
+/// The default radius around the center of each stick, as a fraction of full deflection, within
+/// which the stick counts as centered.
+const DEFAULT_DEADZONE: f32 = 0.1;
+
+/// The largest deadzone allowed. Anything close to full deflection would leave no usable range
+/// outside of it.
+const MAX_DEADZONE: f32 = 0.9;
+
+/// The default shortest time between two sends of analog updates.
+const DEFAULT_SEND_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait between polls of the gamepad. gilrs can only be polled, so this keeps the
+/// controller thread from spinning a core.
+const GAMEPAD_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The default delays between attempts to connect to the receiver. The first retry comes soon
+/// in case the receiver was only restarting, and later ones back off so an absent receiver isn't
+/// hammered.
+const DEFAULT_INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_BACKOFF_MULTIPLIER: f32 = 2.0;
+
+/// The largest rumble command accepted from the receiver. Real commands are a few dozen bytes, so
+/// anything bigger means the stream is out of sync.
+const MAX_RUMBLE_COMMAND_BYTES: usize = 1024;
+
+/// The largest event accepted from an event log. Real events are a few dozen bytes, so anything
+/// bigger means the log is corrupt.
+const MAX_LOGGED_EVENT_BYTES: usize = 1024;
+
+/// How often a replay checks whether the application is shutting down while it waits for the
+/// next event.
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The state of the connection to the receiver, reported by the controller thread so the window
+/// can show what it's up to while the receiver is unreachable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkStatus {
+    /// Trying to connect, counting attempts since the last time the connection was up.
+    Connecting {
+        attempt: u32,
+    },
+    Connected,
+    /// The last attempt to connect failed, and the next is `retry_in` away.
+    ConnectFailed {
+        attempt: u32,
+        error: String,
+        retry_in: Duration,
+    },
+    /// Sending to the receiver failed, which closes the connection.
+    SendFailed(String),
+}
+
+impl LinkStatus {
+    fn color(&self) -> [f32; 4] {
+        match self {
+            LinkStatus::Connecting { .. } => [1.0, 0.8, 0.2, 1.0],
+            LinkStatus::Connected => [0.2, 0.9, 0.2, 1.0],
+            LinkStatus::ConnectFailed { .. } | LinkStatus::SendFailed(_) => [1.0, 0.2, 0.2, 1.0],
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            LinkStatus::Connecting { attempt } if *attempt > 1 => {
+                format!("Connecting to the receiver (attempt {})", attempt)
+            }
+            LinkStatus::Connecting { .. } => "Connecting to the receiver".to_string(),
+            LinkStatus::Connected => "Connected to the receiver".to_string(),
+            LinkStatus::ConnectFailed {
+                attempt,
+                error,
+                retry_in,
+            } => format!(
+                "Attempt {} failed, retrying in {:.1}s: {}",
+                attempt,
+                retry_in.as_secs_f32(),
+                error
+            ),
+            LinkStatus::SendFailed(error) => format!("Send failed, reconnecting: {}", error),
+        }
+    }
+}
+
+/// How long to wait between attempts to connect to the receiver. The delay starts at `initial`
+/// and grows by `multiplier` with each failed attempt, up to `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: DEFAULT_INITIAL_RETRY_DELAY,
+            max: DEFAULT_MAX_RETRY_DELAY,
+            multiplier: DEFAULT_BACKOFF_MULTIPLIER,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay before the next attempt after `attempt` attempts in a row have failed, counting
+    /// from 1. A multiplier below 1 is treated as 1, so the delay never shrinks.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let secs = self.initial.as_secs_f64() * f64::from(self.multiplier.max(1.0)).powi(exponent);
+        let max = self.max.max(self.initial);
+        if secs.is_finite() && secs < max.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            max
+        }
+    }
+
+    /// Converts a retry delay typed into the config modal, in seconds, into a duration between
+    /// 0.1 seconds and an hour. `f32::max` maps NaN to the minimum, where `clamp` would pass it
+    /// through and make the conversion panic.
+    #[allow(clippy::manual_clamp)]
+    fn delay_from_secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs.max(0.1).min(3600.0))
+    }
+}
+
+pub struct Controller {
+    /// Stick positions closer to the center than this are sent as 0. Stick jitter around the
+    /// center would otherwise produce a constant stream of axis events.
+    pub deadzone: f32,
+    /// Analog updates are sent at most this often, with only the latest value of each axis or
+    /// analog button sent. Button presses and releases are sent right away.
+    pub send_interval: Duration,
+    /// Whether rumble commands sent back by the receiver are played on the gamepad.
+    pub rumble: bool,
+    /// Counts the events and bytes sent to the receiver.
+    pub stats: Arc<SensorStats>,
+    /// The byte order of the length prefixes, both of the events and of the rumble commands.
+    pub endianness: Endianness,
+    /// How long to wait between failed attempts to connect to the receiver.
+    pub backoff: Backoff,
+    /// If set, every event sent to the receiver is also written to this log so it can be
+    /// replayed later.
+    pub event_log: Option<EventLog>,
+    /// If set, the receiver is connected to over TLS with this configuration.
+    pub tls: Option<ClientTls>,
+}
 
 impl Controller {
-    pub fn start(ip: SocketAddr) -> JoinHandle<io::Result<()>> {
+    pub fn new() -> Self {
+        Self {
+            deadzone: DEFAULT_DEADZONE,
+            send_interval: DEFAULT_SEND_INTERVAL,
+            rumble: false,
+            stats: Arc::new(SensorStats::new()),
+            endianness: Endianness::Little,
+            backoff: Backoff::default(),
+            event_log: None,
+            tls: None,
+        }
+    }
+
+    /// Starts polling the gamepad and sending its events to the given address. Each event is
+    /// also passed to `ui_sender` so the controller window can show the live state. If the
+    /// connection drops, the gamepad keeps being polled while the thread reconnects, and events
+    /// from while it was disconnected aren't sent. With `rumble` set, rumble commands read back
+    /// from the connection are played on the gamepad that was last used. Every change in the state
+    /// of the connection is passed to `status_sender`.
+    pub fn start(
+        mut self,
+        ip: SocketAddr,
+        ui_sender: Sender<GpEvent>,
+        status_sender: Sender<LinkStatus>,
+        shutdown: Arc<AtomicBool>,
+    ) -> JoinHandle<io::Result<()>> {
         thread::spawn(move || {
-            let mut gilrs = Gilrs::new().unwrap();
-            let mut stream = loop {
-                match TcpStream::connect(ip) {
-                    Ok(conn) => break conn,
-                    Err(_) => thread::sleep(Duration::from_secs(10)),
+            let mut gilrs = Gilrs::new().map_err(|e| {
+                io::Error::other(format!("couldn't initialize gamepad input: {}", e))
+            })?;
+            let mut link = Link::new(
+                ip,
+                self.tls.take(),
+                self.endianness,
+                self.backoff,
+                self.stats.clone(),
+                status_sender,
+            );
+            let (rumble_tx, rumble_rx) = unbounded();
+            // Dropping an effect stops it, so the effect of the last command has to be held here
+            // to keep the gamepad rumbling until the next command replaces it.
+            let mut rumble_effect: Option<Effect> = None;
+            let mut active_gamepad: Option<GamepadId> = None;
+            // The raw position of every axis, since a stick's deadzone depends on both of its
+            // axes, and the last value sent for each axis so that unchanged values are skipped.
+            let mut raw_axes = HashMap::new();
+            let mut sent_axes = HashMap::new();
+            let mut batcher = EventBatcher::new(self.send_interval);
+            while !shutdown.load(Ordering::Relaxed) {
+                if let Some(conn) = link.connect() {
+                    if self.rumble {
+                        if let Err(e) = spawn_rumble_reader(
+                            conn,
+                            self.endianness,
+                            rumble_tx.clone(),
+                            shutdown.clone(),
+                        ) {
+                            warn!("controller: can't read rumble commands: {}", e);
+                        }
+                    }
                 }
-            };
-            loop {
-                while let Some(Event { event, .. }) = gilrs.next_event() {
+
+                while let Some(Event { id, event, .. }) = gilrs.next_event() {
+                    active_gamepad = Some(id);
                     // Most of the fields in gilrs are serializable except for the Code on each
                     // event. Since we don't need it and we want to send events directly, we
                     // translate between gilrs's events to custom events without the code so we
@@ -46,57 +305,1058 @@ impl Controller {
                         EventType::ButtonRepeated(btn, ..) => GpEvent::ButtonRepeated(btn),
                         EventType::ButtonReleased(btn, ..) => GpEvent::ButtonReleased(btn),
                         EventType::ButtonChanged(btn, val, ..) => GpEvent::ButtonChanged(btn, val),
-                        EventType::AxisChanged(axis, val, ..) => GpEvent::AxisChanged(axis, val),
+                        EventType::AxisChanged(axis, val, ..) => {
+                            raw_axes.insert(axis, val);
+                            let partner = stick_partner(axis)
+                                .and_then(|partner| raw_axes.get(&partner))
+                                .cloned()
+                                .unwrap_or(0.0);
+                            let magnitude = val.hypot(partner);
+                            let val = apply_deadzone(val, magnitude, self.deadzone);
+                            if sent_axes.insert(axis, val) == Some(val) {
+                                continue;
+                            }
+                            GpEvent::AxisChanged(axis, val)
+                        }
                         EventType::Connected => GpEvent::Connected,
                         EventType::Disconnected => GpEvent::Disconnected,
                         EventType::Dropped => GpEvent::Dropped,
                     };
-                    let data = serde_cbor::to_vec(&gp_event).unwrap();
-                    stream.write_u32::<LittleEndian>(data.len() as u32)?;
-                    stream.write_all(&data)?;
-                    stream.flush()?;
+                    // Closing the controller window stops sending the controller state.
+                    ui_sender.send(gp_event.clone()).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::ConnectionAborted,
+                            "controller channel disconnected",
+                        )
+                    })?;
+                    batcher.push(gp_event);
+                }
+
+                while let Ok(command) = rumble_rx.try_recv() {
+                    // Without any events yet, fall back to the first gamepad that can rumble.
+                    let gamepad = active_gamepad.or_else(|| {
+                        gilrs
+                            .gamepads()
+                            .find(|(_, gamepad)| gamepad.is_ff_supported())
+                            .map(|(id, _)| id)
+                    });
+                    if let Some(effect) = rumble_effect.take() {
+                        let _ = effect.stop();
+                    }
+                    if let Some(gamepad) = gamepad {
+                        match play_rumble(&mut gilrs, gamepad, &command) {
+                            Ok(effect) => rumble_effect = effect,
+                            Err(e) => warn!("controller: couldn't rumble the gamepad: {}", e),
+                        }
+                    }
                 }
+
+                let due = batcher.take_due(Instant::now());
+                // Events are logged whether or not the receiver is connected, since the log is
+                // for replaying what the operator did.
+                if let Some(event_log) = &mut self.event_log {
+                    if let Err(e) = event_log.write(&due) {
+                        warn!("controller: stopped logging events: {}", e);
+                        self.event_log = None;
+                    }
+                }
+                link.send(&due);
+                thread::sleep(GAMEPAD_POLL_INTERVAL);
             }
+            Ok(())
         })
     }
 }
 
+/// The connection to the receiver of the controller state, which is reopened whenever it drops.
+struct Link {
+    ip: SocketAddr,
+    /// If set, the receiver is connected to over TLS with this configuration.
+    tls: Option<ClientTls>,
+    stream: Option<ClientStream>,
+    next_connect: Instant,
+    /// The number of attempts to connect since the connection was last up.
+    attempt: u32,
+    backoff: Backoff,
+    endianness: Endianness,
+    stats: Arc<SensorStats>,
+    status_sender: Sender<LinkStatus>,
+}
+
+impl Link {
+    fn new(
+        ip: SocketAddr,
+        tls: Option<ClientTls>,
+        endianness: Endianness,
+        backoff: Backoff,
+        stats: Arc<SensorStats>,
+        status_sender: Sender<LinkStatus>,
+    ) -> Self {
+        Self {
+            ip,
+            tls,
+            stream: None,
+            next_connect: Instant::now(),
+            attempt: 0,
+            backoff,
+            endianness,
+            stats,
+            status_sender,
+        }
+    }
+
+    /// Passes a change in the state of the connection on to the window. The window only reads
+    /// the status while it's open, so a failed send is fine.
+    fn report(&self, status: LinkStatus) {
+        let _ = self.status_sender.send(status);
+    }
+
+    /// Connects to the receiver if there's no connection and the last attempt was long enough
+    /// ago. Returns the connection if a new one was opened.
+    fn connect(&mut self) -> Option<&ClientStream> {
+        if self.stream.is_some() || Instant::now() < self.next_connect {
+            return None;
+        }
+        self.attempt += 1;
+        self.report(LinkStatus::Connecting {
+            attempt: self.attempt,
+        });
+        match ClientStream::connect(self.ip, self.tls.as_ref()) {
+            Ok(conn) => {
+                info!("controller: connected to {}", self.ip);
+                self.stats.connection_opened();
+                self.report(LinkStatus::Connected);
+                self.attempt = 0;
+                self.stream = Some(conn);
+                self.stream.as_ref()
+            }
+            Err(e) => {
+                let retry_in = self.backoff.delay(self.attempt);
+                warn!(
+                    "controller: couldn't connect to {}, retrying in {:.1}s: {}",
+                    self.ip,
+                    retry_in.as_secs_f32(),
+                    e
+                );
+                self.report(LinkStatus::ConnectFailed {
+                    attempt: self.attempt,
+                    error: e.to_string(),
+                    retry_in,
+                });
+                self.next_connect = Instant::now() + retry_in;
+                None
+            }
+        }
+    }
+
+    /// Sends the events if connected. If that fails, the connection is dropped and the next call
+    /// to `connect` opens a new one.
+    fn send(&mut self, gp_events: &[GpEvent]) {
+        if let Some(conn) = &mut self.stream {
+            match write_events(conn, self.endianness, gp_events) {
+                Ok(bytes) => {
+                    self.stats.add_bytes(bytes);
+                    for _ in gp_events {
+                        self.stats.record_frame(0);
+                    }
+                }
+                Err(e) => {
+                    // Try again right away, since the receiver may only have restarted.
+                    warn!("controller: lost the connection to {}: {}", self.ip, e);
+                    let _ = self
+                        .status_sender
+                        .send(LinkStatus::SendFailed(e.to_string()));
+                    // Wakes up the rumble reader, which is still blocked on this connection.
+                    let _ = conn.shutdown(Shutdown::Both);
+                    self.stats.connection_closed();
+                    self.stream = None;
+                    self.next_connect = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+/// Writes each event as a u32 length n followed by n bytes of CBOR, returning the number of bytes
+/// written.
+fn write_events<W: Write>(
+    stream: &mut W,
+    endianness: Endianness,
+    gp_events: &[GpEvent],
+) -> io::Result<usize> {
+    if gp_events.is_empty() {
+        return Ok(0);
+    }
+    let mut written = 0;
+    for gp_event in gp_events {
+        let data = serde_cbor::to_vec(gp_event)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        endianness.write_u32(stream, data.len() as u32)?;
+        stream.write_all(&data)?;
+        written += 4 + data.len();
+    }
+    stream.flush()?;
+    Ok(written)
+}
+
+/// A gamepad event as stored in an event log, along with when it was sent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    /// Milliseconds since the log was created.
+    pub offset_ms: u64,
+    pub event: GpEvent,
+}
+
+/// Records the events sent to the receiver so they can be replayed without a gamepad. Each event
+/// is written as a little-endian u32 length n followed by n bytes of CBOR, the same framing as
+/// on the wire.
+pub struct EventLog {
+    writer: BufWriter<File>,
+    created: Instant,
+}
+
+impl EventLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            created: Instant::now(),
+        })
+    }
+
+    /// Appends events sent together, all stamped with the current time.
+    fn write(&mut self, gp_events: &[GpEvent]) -> io::Result<()> {
+        if gp_events.is_empty() {
+            return Ok(());
+        }
+        let offset_ms = self.created.elapsed().as_millis() as u64;
+        for gp_event in gp_events {
+            let logged = LoggedEvent {
+                offset_ms,
+                event: gp_event.clone(),
+            };
+            let data = serde_cbor::to_vec(&logged)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Endianness::Little.write_u32(&mut self.writer, data.len() as u32)?;
+            self.writer.write_all(&data)?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// Reads every event of a log written by `EventLog`. A log cut off partway through an event, as
+/// happens when sensorview is killed while logging, ends at the last whole event.
+pub fn read_event_log<R: Read>(mut reader: R) -> io::Result<Vec<LoggedEvent>> {
+    let mut events = Vec::new();
+    loop {
+        let size = match Endianness::Little.read_u32(&mut reader) {
+            Ok(size) => size as usize,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        if size > MAX_LOGGED_EVENT_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("logged event of {} bytes is too large", size),
+            ));
+        }
+        let mut data = vec![0; size];
+        match reader.read_exact(&mut data) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let event = serde_cbor::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Sends the events of a log to the receiver at `address`, over TLS if `tls` is set, waiting
+/// between them as long as the operator did when they were recorded. Stops early once `shutdown`
+/// is set.
+pub fn replay_event_log(
+    path: &Path,
+    address: SocketAddr,
+    endianness: Endianness,
+    tls: Option<&ClientTls>,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let events = read_event_log(BufReader::new(File::open(path)?))?;
+    let mut stream = ClientStream::connect(address, tls)?;
+    info!(
+        "controller: replaying {} events from {} to {}",
+        events.len(),
+        path.display(),
+        address
+    );
+    let started = Instant::now();
+    for logged in events {
+        let due = started + Duration::from_millis(logged.offset_ms);
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            thread::sleep((due - now).min(REPLAY_POLL_INTERVAL));
+        }
+        write_events(&mut stream, endianness, &[logged.event])?;
+    }
+    info!("controller: finished replaying {}", path.display());
+    Ok(())
+}
+
+/// Reads rumble commands from a clone of the connection on a new thread, passing them to `sender`
+/// until the connection closes.
+fn spawn_rumble_reader(
+    conn: &ClientStream,
+    endianness: Endianness,
+    sender: Sender<RumbleCommand>,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let stream = conn.try_clone()?;
+    stream.set_read_timeout(Some(shutdown::POLL_INTERVAL))?;
+    thread::spawn(move || {
+        let mut reader = ShutdownReader::new(stream, shutdown);
+        loop {
+            match read_rumble_command(&mut reader, endianness) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        return;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => return,
+                Err(e) => {
+                    warn!("controller: stopped reading rumble commands: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Reads a single rumble command, framed as a u32 length n followed by n bytes of CBOR.
+fn read_rumble_command<R: Read>(
+    reader: &mut R,
+    endianness: Endianness,
+) -> io::Result<RumbleCommand> {
+    let size = endianness.read_u32(reader)? as usize;
+    if size > MAX_RUMBLE_COMMAND_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("rumble command of {} bytes is too large", size),
+        ));
+    }
+    let mut data = vec![0; size];
+    reader.read_exact(&mut data)?;
+    serde_cbor::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Converts a magnitude from 0 to 1 to the range gilrs uses, clamping anything outside of it.
+fn rumble_magnitude(value: f32) -> u16 {
+    // A NaN magnitude stays NaN through the clamp, and the cast turns it into 0.
+    (value.clamp(0.0, 1.0) * f32::from(u16::MAX)) as u16
+}
+
+/// Returns the gilrs effects for the strong and weak motors of a rumble command.
+fn rumble_effects(command: &RumbleCommand) -> [BaseEffect; 2] {
+    let scheduling = Replay {
+        play_for: Ticks::from_ms(command.duration_ms),
+        ..Default::default()
+    };
+    [
+        BaseEffect {
+            kind: BaseEffectType::Strong {
+                magnitude: rumble_magnitude(command.strong),
+            },
+            scheduling,
+            ..Default::default()
+        },
+        BaseEffect {
+            kind: BaseEffectType::Weak {
+                magnitude: rumble_magnitude(command.weak),
+            },
+            scheduling,
+            ..Default::default()
+        },
+    ]
+}
+
+/// Plays a rumble command on the gamepad. A command without a duration just stops the current
+/// rumble, so no effect is returned for it.
+fn play_rumble(
+    gilrs: &mut Gilrs,
+    gamepad: GamepadId,
+    command: &RumbleCommand,
+) -> Result<Option<Effect>, gilrs::ff::Error> {
+    if command.duration_ms == 0 {
+        return Ok(None);
+    }
+    let [strong, weak] = rumble_effects(command);
+    let effect = EffectBuilder::new()
+        .add_effect(strong)
+        .add_effect(weak)
+        .gamepads(&[gamepad])
+        .repeat(Repeat::For(Ticks::from_ms(command.duration_ms)))
+        .finish(gilrs)?;
+    effect.play()?;
+    Ok(Some(effect))
+}
+
+/// Holds gamepad events until they're due to be sent, so that rapid analog motion doesn't flood
+/// the link. Analog updates for the same axis or button replace each other while queued; every
+/// other event is kept.
+struct EventBatcher {
+    interval: Duration,
+    pending: Vec<GpEvent>,
+    last_send: Option<Instant>,
+}
+
+impl EventBatcher {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: Vec::new(),
+            last_send: None,
+        }
+    }
+
+    fn push(&mut self, gp_event: GpEvent) {
+        match self
+            .pending
+            .iter_mut()
+            .find(|queued| same_analog_input(queued, &gp_event))
+        {
+            Some(queued) => *queued = gp_event,
+            None => self.pending.push(gp_event),
+        }
+    }
+
+    /// Takes the queued events if they should be sent now. That's the case once the send
+    /// interval has passed, or right away if a non-analog event like a button press is queued.
+    fn take_due(&mut self, now: Instant) -> Vec<GpEvent> {
+        let interval_passed = self
+            .last_send
+            .is_none_or(|last_send| now.duration_since(last_send) >= self.interval);
+        let has_edge = self.pending.iter().any(|queued| {
+            !matches!(
+                queued,
+                GpEvent::AxisChanged(..) | GpEvent::ButtonChanged(..)
+            )
+        });
+        if self.pending.is_empty() || !(interval_passed || has_edge) {
+            return Vec::new();
+        }
+        self.last_send = Some(now);
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Checks whether two events are analog updates of the same axis or button.
+fn same_analog_input(a: &GpEvent, b: &GpEvent) -> bool {
+    match (a, b) {
+        (GpEvent::AxisChanged(a, _), GpEvent::AxisChanged(b, _)) => a == b,
+        (GpEvent::ButtonChanged(a, _), GpEvent::ButtonChanged(b, _)) => a == b,
+        _ => false,
+    }
+}
+
+/// Returns the other axis of the stick an axis belongs to. Triggers and the d-pad aren't sticks, so
+/// their deadzone only depends on the axis itself.
+fn stick_partner(axis: Axis) -> Option<Axis> {
+    match axis {
+        Axis::LeftStickX => Some(Axis::LeftStickY),
+        Axis::LeftStickY => Some(Axis::LeftStickX),
+        Axis::RightStickX => Some(Axis::RightStickY),
+        Axis::RightStickY => Some(Axis::RightStickX),
+        _ => None,
+    }
+}
+
+/// Applies a radial deadzone to an axis value, given the magnitude of the stick's deflection.
+/// Inside the deadzone the value is 0. Outside it the value is rescaled so that the edge of the
+/// deadzone maps to 0 and full deflection still maps to 1, avoiding a jump at the edge. The
+/// deadzone is clamped to `[0, MAX_DEADZONE]`.
+#[allow(clippy::manual_clamp)]
+fn apply_deadzone(value: f32, magnitude: f32, deadzone: f32) -> f32 {
+    // A NaN deadzone becomes 0, i.e. no deadzone at all.
+    let deadzone = deadzone.max(0.0).min(MAX_DEADZONE);
+    if magnitude <= deadzone || magnitude == 0.0 {
+        return 0.0;
+    }
+    let scaled = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0);
+    value / magnitude * scaled
+}
+
+/// Shows a live legend of the gamepad's buttons and axes along with what each is sent as,
+/// highlighting the inputs that are currently active.
+pub struct ControllerWindow {
+    receiver: Receiver<GpEvent>,
+    status_receiver: Receiver<LinkStatus>,
+    buttons: HashMap<Button, f32>,
+    axes: HashMap<Axis, f32>,
+    connected: bool,
+    /// The latest state of the connection to the receiver, if the thread has reported one yet.
+    link_status: Option<LinkStatus>,
+    pub spec: Option<SensorSpec>,
+    pub stats: Arc<SensorStats>,
+    /// Where the main window has asked this window to move on its next frame.
+    placement: Option<Placement>,
+    /// Where the window was drawn on its last frame.
+    shown_at: Option<Placement>,
+}
+
+impl ControllerWindow {
+    pub fn new(receiver: Receiver<GpEvent>, status_receiver: Receiver<LinkStatus>) -> Self {
+        Self {
+            receiver,
+            status_receiver,
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            connected: false,
+            link_status: None,
+            spec: None,
+            stats: Arc::new(SensorStats::new()),
+            placement: None,
+            shown_at: None,
+        }
+    }
+
+    fn update(&mut self, event: GpEvent) {
+        match event {
+            GpEvent::ButtonPressed(btn) | GpEvent::ButtonRepeated(btn) => {
+                self.buttons.insert(btn, 1.0);
+            }
+            GpEvent::ButtonReleased(btn) => {
+                self.buttons.insert(btn, 0.0);
+            }
+            GpEvent::ButtonChanged(btn, val) => {
+                self.buttons.insert(btn, val);
+            }
+            GpEvent::AxisChanged(axis, val) => {
+                self.axes.insert(axis, val);
+            }
+            GpEvent::Connected => self.connected = true,
+            GpEvent::Disconnected => {
+                self.connected = false;
+                self.buttons.clear();
+                self.axes.clear();
+            }
+            GpEvent::Dropped => (),
+        }
+    }
+}
+
+impl Renderable for ControllerWindow {
+    fn render(
+        &mut self,
+        ui: &Ui,
+        _display: &Display,
+        _renderer: &mut Renderer,
+        visible: bool,
+        _all_paused: bool,
+    ) -> bool {
+        let mut opened = true;
+
+        while let Ok(event) = self.receiver.try_recv() {
+            self.update(event);
+        }
+        if let Some(status) = self.status_receiver.try_iter().last() {
+            self.link_status = Some(status);
+        }
+        if !visible {
+            return true;
+        }
+
+        let active_color = [0.2, 1.0, 0.2, 1.0];
+        let buttons = &self.buttons;
+        let axes = &self.axes;
+        let connected = self.connected;
+        let link_status = &self.link_status;
+        let spec = self.spec.as_ref();
+        let shown_at = &mut self.shown_at;
+        Window::new(im_str!("Controller"))
+            .opened(&mut opened)
+            .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+            .place(self.placement.take())
+            .build(ui, || {
+                *shown_at = Some(window::current_placement(ui));
+                match link_status {
+                    Some(status) => ui.text_colored(status.color(), status.describe()),
+                    None => ui.text(im_str!("Starting...")),
+                }
+                if connected {
+                    ui.text(im_str!("Gamepad connected"));
+                } else {
+                    ui.text(im_str!("Waiting for a gamepad event..."));
+                }
+                connection::connection_help(ui, spec);
+                ui.separator();
+
+                // Events are forwarded unchanged, so each input is sent under its own name.
+                ui.columns(3, im_str!("legend"), true);
+                ui.text(im_str!("Input"));
+                ui.next_column();
+                ui.text(im_str!("Sent As"));
+                ui.next_column();
+                ui.text(im_str!("Value"));
+                ui.next_column();
+                ui.separator();
+                let rows = LEGEND_BUTTONS
+                    .iter()
+                    .map(|btn| {
+                        let value = buttons.get(btn).cloned().unwrap_or(0.0);
+                        (format!("{:?}", btn), value, value > 0.5)
+                    })
+                    .chain(LEGEND_AXES.iter().map(|axis| {
+                        let value = axes.get(axis).cloned().unwrap_or(0.0);
+                        (format!("{:?}", axis), value, value.abs() > 0.1)
+                    }));
+                for (name, value, active) in rows {
+                    let cells = [name.clone(), name, format!("{:.2}", value)];
+                    for cell in cells.iter() {
+                        if active {
+                            ui.text_colored(active_color, cell);
+                        } else {
+                            ui.text(cell);
+                        }
+                        ui.next_column();
+                    }
+                }
+                ui.columns(1, im_str!(""), false);
+            });
+        opened
+    }
+
+    fn name(&self) -> &str {
+        "Controller"
+    }
+
+    fn reset_texture(&mut self) {}
+
+    fn has_pending_data(&self) -> bool {
+        !self.receiver.is_empty() || !self.status_receiver.is_empty()
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        self.spec.clone()
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        Some(self.stats.clone())
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        Vec::new()
+    }
+
+    fn capture(&self, _path: &Path) -> Option<ImageResult<()>> {
+        None
+    }
+
+    fn set_placement(&mut self, placement: Placement) {
+        self.placement = Some(placement);
+    }
+
+    fn current_placement(&self) -> Option<Placement> {
+        self.shown_at
+    }
+}
+
 pub struct ControllerConfig {
     send_ip: ImString,
+    deadzone: f32,
+    send_interval_ms: i32,
+    rumble: bool,
+    endianness: Endianness,
+    /// The connection retry delays, in seconds, and the factor the delay grows by.
+    initial_retry_secs: f32,
+    max_retry_secs: f32,
+    backoff_multiplier: f32,
+    /// Why the last attempt to start sending failed, if it did.
+    address_error: Option<String>,
+    log_events: bool,
+    /// The event log written while sending, and the one replayed by "Replay Log".
+    log_path: ImString,
+    replay_status: Option<String>,
+    tls: ClientTlsConfig,
 }
 
 impl ControllerConfig {
     pub fn new() -> Self {
         let mut send_ip = ImString::new("");
-        send_ip.reserve_exact(21);
-        Self { send_ip }
+        send_ip.reserve_exact(256);
+        let mut log_path = ImString::new("controller_events.log");
+        log_path.reserve_exact(256);
+        Self {
+            send_ip,
+            deadzone: DEFAULT_DEADZONE,
+            send_interval_ms: DEFAULT_SEND_INTERVAL.as_millis() as i32,
+            rumble: false,
+            endianness: Endianness::Little,
+            initial_retry_secs: DEFAULT_INITIAL_RETRY_DELAY.as_secs_f32(),
+            max_retry_secs: DEFAULT_MAX_RETRY_DELAY.as_secs_f32(),
+            backoff_multiplier: DEFAULT_BACKOFF_MULTIPLIER,
+            address_error: None,
+            log_events: false,
+            log_path,
+            replay_status: None,
+            tls: ClientTlsConfig::new(),
+        }
     }
 }
 
 impl Modal for ControllerConfig {
+    fn sensor_name(&self) -> &'static ImStr {
+        im_str!("Controller")
+    }
+
+    fn popup_name(&self) -> &'static ImStr {
+        im_str!("Controller Configuration")
+    }
+
     fn render_modal(
         &mut self,
         ui: &Ui,
         join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
-        _sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        shutdown: &Arc<AtomicBool>,
     ) {
-        // TODO: right now this just creates the modal and then just silently sends controller
-        // events out. It'd be nice to have a window maybe representing the controller?
-        ui.popup_modal(im_str!("Controller Configuration"))
+        ui.popup_modal(self.popup_name())
             .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
             .build(|| {
                 ui.input_text(im_str!("Send Address"), &mut self.send_ip)
                     .build();
+                if let Some(e) = &self.address_error {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], e);
+                }
+                Slider::new(im_str!("Stick Deadzone"), 0.0..=MAX_DEADZONE)
+                    .build(ui, &mut self.deadzone);
+                ui.input_int(
+                    im_str!("Min Send Interval (ms)"),
+                    &mut self.send_interval_ms,
+                )
+                .build();
+                ui.checkbox(im_str!("Accept Rumble Commands"), &mut self.rumble);
+                byte_order_combo(ui, &mut self.endianness);
+                ui.input_float(
+                    im_str!("Initial Retry Delay (s)"),
+                    &mut self.initial_retry_secs,
+                )
+                .build();
+                ui.input_float(im_str!("Max Retry Delay (s)"), &mut self.max_retry_secs)
+                    .build();
+                Slider::new(im_str!("Backoff Multiplier"), 1.0..=4.0)
+                    .build(ui, &mut self.backoff_multiplier);
+                self.tls.render(ui);
+                ui.checkbox(im_str!("Log Sent Events"), &mut self.log_events);
+                ui.input_text(im_str!("Event Log"), &mut self.log_path)
+                    .build();
+
+                // Replaying needs no gamepad, so it's started on its own rather than along with
+                // a controller window.
+                if ui.button(im_str!("Replay Log"), [0.0, 0.0]) {
+                    let tls = self.tls.files().map(|files| files.load()).transpose();
+                    match (resolve_addr(self.send_ip.as_ref()), tls) {
+                        (Ok(address), Ok(tls)) => {
+                            let path = PathBuf::from(self.log_path.to_str());
+                            let endianness = self.endianness;
+                            let shutdown = shutdown.clone();
+                            self.replay_status =
+                                Some(format!("Replaying {} to {}", path.display(), address));
+                            join_handles.push(thread::spawn(move || {
+                                replay_event_log(&path, address, endianness, tls.as_ref(), shutdown)
+                                    .map_err(|e| {
+                                        warn!("controller: replay failed: {}", e);
+                                        e
+                                    })
+                            }));
+                        }
+                        (Err(e), _) => {
+                            self.replay_status = Some(format!("Invalid send address: {}", e))
+                        }
+                        (_, Err(e)) => {
+                            self.replay_status = Some(format!("Couldn't set up TLS: {}", e))
+                        }
+                    }
+                }
+                if let Some(status) = &self.replay_status {
+                    ui.same_line(0.0);
+                    ui.text(status);
+                }
 
                 if ui.button(im_str!("Send Controller State"), [0.0, 0.0]) {
-                    join_handles.push(Controller::start(
-                        self.send_ip
-                            .to_string()
-                            .parse()
-                            .expect("couldn't parse IP address"),
+                    let address = match resolve_addr(self.send_ip.as_ref()) {
+                        Ok(address) => address,
+                        Err(e) => {
+                            self.address_error = Some(format!("Invalid send address: {}", e));
+                            return;
+                        }
+                    };
+                    let event_log = if self.log_events {
+                        match EventLog::create(Path::new(self.log_path.to_str())) {
+                            Ok(event_log) => Some(event_log),
+                            Err(e) => {
+                                self.address_error =
+                                    Some(format!("Couldn't create the event log: {}", e));
+                                return;
+                            }
+                        }
+                    } else {
+                        None
+                    };
+                    let tls_files = self.tls.files();
+                    let tls = match tls_files.as_ref().map(|files| files.load()).transpose() {
+                        Ok(tls) => tls,
+                        Err(e) => {
+                            self.address_error = Some(format!("Couldn't set up TLS: {}", e));
+                            return;
+                        }
+                    };
+                    self.address_error = None;
+                    let (ui_tx, ui_rx) = unbounded();
+                    let (status_tx, status_rx) = unbounded();
+                    let mut controller = Controller::new();
+                    controller.deadzone = self.deadzone;
+                    controller.send_interval =
+                        Duration::from_millis(self.send_interval_ms.max(0) as u64);
+                    controller.rumble = self.rumble;
+                    controller.endianness = self.endianness;
+                    let initial = Backoff::delay_from_secs(self.initial_retry_secs);
+                    controller.backoff = Backoff {
+                        initial,
+                        max: Backoff::delay_from_secs(self.max_retry_secs).max(initial),
+                        multiplier: self.backoff_multiplier.max(1.0),
+                    };
+                    controller.event_log = event_log;
+                    controller.tls = tls;
+                    let mut controller_window = ControllerWindow::new(ui_rx, status_rx);
+                    controller_window.stats = controller.stats.clone();
+                    join_handles.push(controller.start(
+                        address,
+                        ui_tx,
+                        status_tx,
+                        shutdown.clone(),
                     ));
+                    controller_window.spec = Some(SensorSpec::Controller {
+                        address,
+                        endianness: self.endianness,
+                        tls: tls_files,
+                    });
+                    sensor_windows.push(Box::new(controller_window));
                     ui.close_current_popup();
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn values_inside_the_deadzone_are_zero() {
+        assert_eq!(apply_deadzone(0.05, 0.05, 0.1), 0.0);
+        assert_eq!(apply_deadzone(-0.1, 0.1, 0.1), 0.0);
+        // A stick pushed diagonally is outside the deadzone even if each axis alone isn't.
+        let magnitude = 0.08f32.hypot(0.08);
+        assert!(apply_deadzone(0.08, magnitude, 0.1) > 0.0);
+    }
+
+    #[test]
+    fn values_outside_the_deadzone_are_rescaled() {
+        assert_eq!(apply_deadzone(1.0, 1.0, 0.1), 1.0);
+        assert_eq!(apply_deadzone(-1.0, 1.0, 0.1), -1.0);
+        assert!((apply_deadzone(0.55, 0.55, 0.1) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deadzones_are_clamped() {
+        // A deadzone of 1 or more would otherwise divide by zero or flip the sign.
+        assert_eq!(apply_deadzone(1.0, 1.0, 1.5), 1.0);
+        assert_eq!(apply_deadzone(0.5, 0.5, 1.5), 0.0);
+        assert_eq!(apply_deadzone(0.5, 0.5, -1.0), 0.5);
+        assert_eq!(apply_deadzone(0.5, 0.5, f32::NAN), 0.5);
+    }
+
+    #[test]
+    fn rapid_axis_updates_are_coalesced() {
+        let start = Instant::now();
+        let mut batcher = EventBatcher::new(Duration::from_millis(20));
+        batcher.push(GpEvent::AxisChanged(Axis::LeftStickX, 0.1));
+        assert_eq!(batcher.take_due(start).len(), 1);
+
+        for i in 1..=10 {
+            batcher.push(GpEvent::AxisChanged(Axis::LeftStickX, i as f32 / 10.0));
+        }
+        assert!(batcher
+            .take_due(start + Duration::from_millis(5))
+            .is_empty());
+        let due = batcher.take_due(start + Duration::from_millis(20));
+        assert_eq!(due.len(), 1);
+        assert!(matches!(due[0], GpEvent::AxisChanged(Axis::LeftStickX, value) if value == 1.0));
+    }
+
+    #[test]
+    fn button_presses_are_sent_right_away() {
+        let start = Instant::now();
+        let mut batcher = EventBatcher::new(Duration::from_millis(20));
+        batcher.push(GpEvent::AxisChanged(Axis::LeftStickX, 0.1));
+        batcher.take_due(start);
+
+        batcher.push(GpEvent::AxisChanged(Axis::LeftStickX, 0.2));
+        batcher.push(GpEvent::ButtonPressed(Button::South));
+        batcher.push(GpEvent::AxisChanged(Axis::LeftStickX, 0.3));
+        let due = batcher.take_due(start + Duration::from_millis(1));
+        assert_eq!(due.len(), 2);
+        assert!(matches!(due[0], GpEvent::AxisChanged(Axis::LeftStickX, value) if value == 0.3));
+        assert!(matches!(due[1], GpEvent::ButtonPressed(Button::South)));
+    }
+
+    #[test]
+    fn dropped_connections_are_reopened() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stats = Arc::new(SensorStats::new());
+        let (status_sender, status_receiver) = unbounded();
+        let mut link = Link::new(
+            listener.local_addr().unwrap(),
+            None,
+            Endianness::Little,
+            Backoff::default(),
+            stats.clone(),
+            status_sender,
+        );
+        link.connect();
+        assert!(link.stream.is_some());
+        let statuses: Vec<LinkStatus> = status_receiver.try_iter().collect();
+        assert_eq!(
+            statuses,
+            vec![LinkStatus::Connecting { attempt: 1 }, LinkStatus::Connected]
+        );
+
+        // The receiver going away only shows up as a write error once the local side has found
+        // out that the connection was reset.
+        drop(listener.accept().unwrap());
+        for _ in 0..100 {
+            link.send(&[GpEvent::Connected]);
+            if link.stream.is_none() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(link.stream.is_none());
+        let statuses: Vec<LinkStatus> = status_receiver.try_iter().collect();
+        assert!(matches!(statuses[..], [LinkStatus::SendFailed(_)]));
+
+        link.connect();
+        assert!(link.stream.is_some());
+        listener.accept().unwrap();
+        assert_eq!(stats.connections(), 1);
+        // The attempts are counted from the last time the connection was up.
+        let statuses: Vec<LinkStatus> = status_receiver.try_iter().collect();
+        assert_eq!(
+            statuses,
+            vec![LinkStatus::Connecting { attempt: 1 }, LinkStatus::Connected]
+        );
+    }
+
+    #[test]
+    fn rumble_commands_are_clamped_to_the_gilrs_range() {
+        let command = RumbleCommand {
+            strong: 1.5,
+            weak: -0.5,
+            duration_ms: 250,
+        };
+        let data = serde_cbor::to_vec(&command).unwrap();
+        let mut framed = (data.len() as u32).to_le_bytes().to_vec();
+        framed.extend_from_slice(&data);
+        let command = read_rumble_command(&mut &framed[..], Endianness::Little).unwrap();
+
+        let [strong, weak] = rumble_effects(&command);
+        assert_eq!(
+            strong.kind,
+            BaseEffectType::Strong {
+                magnitude: u16::MAX
+            }
+        );
+        assert_eq!(weak.kind, BaseEffectType::Weak { magnitude: 0 });
+        assert_eq!(strong.scheduling.play_for, Ticks::from_ms(250));
+        assert_eq!(rumble_magnitude(0.5), u16::MAX / 2);
+        assert_eq!(rumble_magnitude(f32::NAN), 0);
+    }
+
+    #[test]
+    fn event_logs_are_read_back_up_to_the_last_whole_event() {
+        let path =
+            std::env::temp_dir().join(format!("sensorview-events-{}.log", std::process::id()));
+        let mut event_log = EventLog::create(&path).unwrap();
+        event_log
+            .write(&[
+                GpEvent::ButtonPressed(Button::South),
+                GpEvent::AxisChanged(Axis::LeftStickX, 0.5),
+            ])
+            .unwrap();
+        event_log.write(&[]).unwrap();
+        event_log
+            .write(&[GpEvent::ButtonReleased(Button::South)])
+            .unwrap();
+        drop(event_log);
+        let mut bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let logged = read_event_log(&bytes[..]).unwrap();
+        assert_eq!(logged.len(), 3);
+        assert!(matches!(
+            logged[0].event,
+            GpEvent::ButtonPressed(Button::South)
+        ));
+        assert!(
+            matches!(logged[1].event, GpEvent::AxisChanged(Axis::LeftStickX, value) if value == 0.5)
+        );
+        assert!(matches!(
+            logged[2].event,
+            GpEvent::ButtonReleased(Button::South)
+        ));
+        assert_eq!(logged[0].offset_ms, logged[1].offset_ms);
+        assert!(logged[1].offset_ms <= logged[2].offset_ms);
+
+        // A log cut off partway through the last event ends at the event before it.
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(read_event_log(&bytes[..]).unwrap().len(), 2);
+
+        let oversized = ((MAX_LOGGED_EVENT_BYTES + 1) as u32).to_le_bytes();
+        let err = read_event_log(&oversized[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn retry_delays_back_off_up_to_the_max() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            multiplier: 2.0,
+        };
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(2), Duration::from_secs(2));
+        assert_eq!(backoff.delay(4), Duration::from_secs(8));
+        assert_eq!(backoff.delay(5), Duration::from_secs(10));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(10));
+
+        // A multiplier below 1 would make the delay shrink with every attempt.
+        let flat = Backoff {
+            multiplier: 0.5,
+            ..backoff
+        };
+        assert_eq!(flat.delay(3), Duration::from_secs(1));
+
+        let min = Duration::from_secs_f32(0.1);
+        assert_eq!(Backoff::delay_from_secs(f32::NAN), min);
+        assert_eq!(Backoff::delay_from_secs(-5.0), min);
+        assert_eq!(Backoff::delay_from_secs(1e9), Duration::from_secs(3600));
+    }
+}
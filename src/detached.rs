@@ -0,0 +1,197 @@
+use crate::clipboard;
+use crate::diagnostics::SensorStats;
+use crate::notifications::Notification;
+use crate::session::{DetachedLayout, SensorSpec};
+use crate::window::Renderable;
+use glium::glutin::dpi::{LogicalPosition, LogicalSize};
+use glium::glutin::{self, Event, WindowEvent, WindowId};
+use glium::{Display, Surface};
+use imgui::{Context, SuspendedContext};
+use imgui_glium_renderer::Renderer;
+use imgui_winit_support::{HiDpiMode, WinitPlatform};
+use std::io;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// A sensor window popped out of the main window into its own OS window, e.g. to put a feed on a
+/// second monitor. Every OS window needs its own display, imgui context and renderer. Only one
+/// imgui context can be active at a time, so the detached window keeps its context suspended
+/// between frames and the main window has to suspend its own context while detached windows
+/// render.
+pub struct DetachedWindow {
+    display: Display,
+    platform: WinitPlatform,
+    renderer: Renderer,
+    context: Option<SuspendedContext>,
+    window_id: WindowId,
+    pending_events: Vec<Event>,
+    renderable: Box<dyn Renderable>,
+    pub closed: bool,
+    /// Set once the sensor window itself has been closed, as opposed to the OS window. The sensor
+    /// window is then dropped rather than docked back into the main window.
+    pub removed: bool,
+}
+
+impl DetachedWindow {
+    /// Opens a new OS window for the given sensor window, where `layout` says if it's given.
+    /// This creates a new imgui context, so it must only be called while no other imgui context
+    /// is active.
+    pub fn new(
+        events_loop: &glutin::EventsLoop,
+        mut renderable: Box<dyn Renderable>,
+        layout: Option<&DetachedLayout>,
+    ) -> Self {
+        let [width, height] = layout.map_or([800.0, 600.0], |layout| layout.size);
+        let context = glutin::ContextBuilder::new().with_vsync(false);
+        let builder = glutin::WindowBuilder::new()
+            .with_title(renderable.name())
+            .with_dimensions(LogicalSize::new(width, height));
+        let display =
+            Display::new(builder, context, events_loop).expect("Could not create display.");
+        let window_id = {
+            let gl_window = display.gl_window();
+            let window = gl_window.window();
+            if let Some([x, y]) = layout.and_then(|layout| layout.position) {
+                window.set_position(LogicalPosition::new(x, y));
+            }
+            window.id()
+        };
+
+        let mut imgui = Context::create();
+        imgui.set_ini_filename(None);
+        clipboard::install(&mut imgui);
+        let mut platform = WinitPlatform::init(&mut imgui);
+        {
+            let gl_window = display.gl_window();
+            let window = gl_window.window();
+            platform.attach_window(imgui.io_mut(), window, HiDpiMode::Rounded);
+        }
+        let renderer = Renderer::init(&mut imgui, &display).expect("Failed to initialize renderer");
+
+        // Any texture the window already had lives in the main window's renderer, so it has to
+        // be uploaded again to this window's renderer.
+        renderable.reset_texture();
+
+        Self {
+            display,
+            platform,
+            renderer,
+            context: Some(imgui.suspend()),
+            window_id,
+            pending_events: Vec::new(),
+            renderable,
+            closed: false,
+            removed: false,
+        }
+    }
+
+    /// Queues an event for this window if it belongs to it. Events are held until the window's
+    /// imgui context is active during `render_frame`.
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Event::WindowEvent { window_id, event } = event {
+            if *window_id != self.window_id {
+                return;
+            }
+            if let WindowEvent::CloseRequested = event {
+                self.closed = true;
+            }
+            self.pending_events.push(Event::WindowEvent {
+                window_id: *window_id,
+                event: event.clone(),
+            });
+        }
+    }
+
+    /// Draws a single frame of the detached sensor window. This activates the window's own imgui
+    /// context, so it must only be called while no other imgui context is active. `all_paused` is
+    /// the main window's Pause All toggle, which applies to detached windows as well.
+    pub fn render_frame(&mut self, all_paused: bool) {
+        let mut imgui = match self.context.take().map(SuspendedContext::activate) {
+            Some(Ok(imgui)) => imgui,
+            _ => panic!("Couldn't activate the detached window's imgui context"),
+        };
+
+        {
+            let gl_window = self.display.gl_window();
+            let window = gl_window.window();
+            for event in self.pending_events.drain(..) {
+                self.platform.handle_event(imgui.io_mut(), window, &event);
+            }
+            self.platform
+                .prepare_frame(imgui.io_mut(), window)
+                .expect("Failed to start frame.");
+            let ui = imgui.frame();
+
+            if !self
+                .renderable
+                .render(&ui, &self.display, &mut self.renderer, true, all_paused)
+            {
+                self.removed = true;
+            }
+
+            let mut target = self.display.draw();
+            target.clear_color_srgb(0.211, 0.223, 0.243, 1.0);
+            self.platform.prepare_render(&ui, window);
+            let draw_data = ui.render();
+            self.renderer
+                .render(&mut target, draw_data)
+                .expect("Couldn't render");
+            target.finish().expect("Failed to swap buffers");
+        }
+
+        self.context = Some(imgui.suspend());
+    }
+
+    /// Whether the sensor in the detached window has data waiting to be shown.
+    pub fn has_pending_data(&self) -> bool {
+        self.renderable.has_pending_data()
+    }
+
+    /// Describes the sensor feeding the detached window.
+    pub fn sensor_spec(&self) -> Option<SensorSpec> {
+        self.renderable.sensor_spec()
+    }
+
+    /// Takes the notifications raised by the sensor window since this was last called.
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        self.renderable.take_notifications()
+    }
+
+    /// Takes the sensor threads started by the sensor window since this was last called.
+    pub fn take_join_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        self.renderable.take_join_handles()
+    }
+
+    /// The name and stats of the sensor shown in this window, for the diagnostics window.
+    pub fn stats(&self) -> Option<(String, Arc<SensorStats>)> {
+        let name = self.renderable.name().to_string();
+        self.renderable.stats().map(|stats| (name, stats))
+    }
+
+    /// Where the OS window is, to be saved with the session as the sensor at index `sensor`.
+    pub fn layout(&self, sensor: usize) -> DetachedLayout {
+        let gl_window = self.display.gl_window();
+        let window = gl_window.window();
+        let size = window
+            .get_inner_size()
+            .map_or([800.0, 600.0], |size| [size.width, size.height]);
+        DetachedLayout {
+            sensor,
+            position: window
+                .get_position()
+                .map(|position| [position.x, position.y]),
+            size,
+        }
+    }
+
+    /// The sensor window shown in this window.
+    pub fn renderable(&self) -> &(dyn Renderable + 'static) {
+        self.renderable.as_ref()
+    }
+
+    /// Closes the OS window and hands the sensor window back so it can be docked again.
+    pub fn into_renderable(mut self) -> Box<dyn Renderable> {
+        self.renderable.reset_texture();
+        self.renderable
+    }
+}
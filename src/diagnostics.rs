@@ -0,0 +1,614 @@
+use crate::notifications::Notification;
+use crate::session::SensorSpec;
+use crate::window::Renderable;
+use glium::Display;
+use image::ImageResult;
+use imgui::{im_str, Ui, Window, WindowFlags};
+use imgui_glium_renderer::Renderer;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a connected sensor can go without sending anything before its window reports it as
+/// stale, unless the window is configured otherwise.
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(2);
+
+/// The longest a config modal can set a sensor to go without data before it's stale, in seconds.
+const MAX_STALE_AFTER_SECS: f32 = 3600.0;
+
+/// Converts the time before a sensor is stale typed into a config modal, in seconds, into a
+/// duration, clamped to what `Duration::from_secs_f32` can take.
+#[allow(clippy::manual_clamp)]
+pub fn stale_after_from_secs(secs: f32) -> Duration {
+    // Unlike `clamp`, `max` turns NaN into the lower bound.
+    Duration::from_secs_f32(secs.max(0.0).min(MAX_STALE_AFTER_SECS))
+}
+
+/// The number of most recent frames the drop rate is computed over.
+const DROP_RATE_WINDOW: usize = 100;
+
+/// The drop rate above which a sensor window warns that the UI isn't keeping up.
+const DROP_WARNING_RATE: f32 = 0.1;
+
+/// How often a sensor window samples its byte counter for the throughput graph, and how many
+/// samples the graph shows.
+const THROUGHPUT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const THROUGHPUT_SAMPLES: usize = 60;
+
+/// The state of a sensor's connections, as shown by the status dot in its window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionState {
+    /// Nothing is connected and the last connection, if any, closed cleanly.
+    Listening,
+    /// A device is connected and sending data.
+    Connected,
+    /// A device is connected but hasn't sent anything for a while.
+    Stale,
+    /// Nothing is connected and the last connection closed with an error.
+    Error,
+}
+
+impl ConnectionState {
+    /// Works out the state from the number of open connections, how long ago anything was last
+    /// heard from the sensor, and whether the last connection failed.
+    pub fn from_stats(
+        connections: usize,
+        since_last_activity: Option<Duration>,
+        failed: bool,
+        stale_after: Duration,
+    ) -> Self {
+        if connections == 0 {
+            if failed {
+                ConnectionState::Error
+            } else {
+                ConnectionState::Listening
+            }
+        } else if since_last_activity.is_none_or(|since| since > stale_after) {
+            ConnectionState::Stale
+        } else {
+            ConnectionState::Connected
+        }
+    }
+
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            ConnectionState::Listening => [0.6, 0.6, 0.6, 1.0],
+            ConnectionState::Connected => [0.2, 0.9, 0.2, 1.0],
+            ConnectionState::Stale => [1.0, 0.8, 0.2, 1.0],
+            ConnectionState::Error => [1.0, 0.2, 0.2, 1.0],
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionState::Listening => "Listening",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::Stale => "Stale",
+            ConnectionState::Error => "Error",
+        }
+    }
+}
+
+/// Counters describing how a sensor is doing, updated by the sensor's threads and read by the
+/// diagnostics window. The sensor and its window each hold a clone of the same `Arc`.
+pub struct SensorStats {
+    bytes: AtomicU64,
+    frames: AtomicU64,
+    /// Frames thrown away because the window's channel was full.
+    dropped: AtomicU64,
+    /// Whether each of the most recent frames was dropped, for the drop rate.
+    recent_drops: Mutex<VecDeque<bool>>,
+    connections: AtomicUsize,
+    /// The most connections the sensor serves at once, or 0 if it doesn't take connections.
+    max_connections: AtomicUsize,
+    /// The peers turned away for being over `max_connections` since the window last looked.
+    refused: Mutex<Vec<SocketAddr>>,
+    /// The peer the sensor most recently started receiving from.
+    peer: Mutex<Option<SocketAddr>>,
+    backlog: AtomicUsize,
+    last_update: Mutex<Option<Instant>>,
+    /// When a connection was last opened or a frame last recorded, for telling stale connections
+    /// apart from ones that have only just been opened.
+    last_activity: Mutex<Option<Instant>>,
+    /// Why the last connection closed, if it closed with an error. Cleared by the next
+    /// connection.
+    last_error: Mutex<Option<String>>,
+}
+
+impl SensorStats {
+    pub fn new() -> Self {
+        Self {
+            bytes: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            recent_drops: Mutex::new(VecDeque::with_capacity(DROP_RATE_WINDOW)),
+            connections: AtomicUsize::new(0),
+            max_connections: AtomicUsize::new(0),
+            refused: Mutex::new(Vec::new()),
+            peer: Mutex::new(None),
+            backlog: AtomicUsize::new(0),
+            last_update: Mutex::new(None),
+            last_activity: Mutex::new(None),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records a frame passed on to the window, along with how many frames are waiting in the
+    /// window's channel afterwards.
+    pub fn record_frame(&self, backlog: usize) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.backlog.store(backlog, Ordering::Relaxed);
+        if let Ok(mut last_update) = self.last_update.lock() {
+            *last_update = Some(Instant::now());
+        }
+        self.record_outcome(false);
+        self.touch();
+    }
+
+    /// Records a frame thrown away because the window's channel was full.
+    pub fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.record_outcome(true);
+        self.touch();
+    }
+
+    fn record_outcome(&self, dropped: bool) {
+        if let Ok(mut recent_drops) = self.recent_drops.lock() {
+            if recent_drops.len() == DROP_RATE_WINDOW {
+                recent_drops.pop_front();
+            }
+            recent_drops.push_back(dropped);
+        }
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = None;
+        }
+    }
+
+    /// Records why a connection closed with an error. Call before `connection_closed` so the
+    /// window never sees the connection gone without the error.
+    pub fn record_error(&self, error: String) {
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(error);
+        }
+    }
+
+    fn touch(&self) {
+        if let Ok(mut last_activity) = self.last_activity.lock() {
+            *last_activity = Some(Instant::now());
+        }
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set_max_connections(&self, max_connections: usize) {
+        self.max_connections
+            .store(max_connections, Ordering::Relaxed);
+    }
+
+    /// Records a connection closed because the sensor was already serving as many as it's allowed.
+    pub fn record_refused(&self, peer: SocketAddr) {
+        if let Ok(mut refused) = self.refused.lock() {
+            refused.push(peer);
+        }
+    }
+
+    /// Records the peer the sensor has started receiving from.
+    pub fn set_peer(&self, peer: SocketAddr) {
+        if let Ok(mut last_peer) = self.peer.lock() {
+            *last_peer = Some(peer);
+        }
+    }
+
+    /// The peer the sensor most recently started receiving from, if it's received from any.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer.lock().ok().and_then(|peer| *peer)
+    }
+
+    /// The peers refused since the last call, oldest first.
+    pub fn take_refused(&self) -> Vec<SocketAddr> {
+        self.refused
+            .lock()
+            .map(|mut refused| refused.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The fraction of the most recent frames that were dropped.
+    pub fn drop_rate(&self) -> f32 {
+        match self.recent_drops.lock() {
+            Ok(recent_drops) if !recent_drops.is_empty() => {
+                let drops = recent_drops.iter().filter(|dropped| **dropped).count();
+                drops as f32 / recent_drops.len() as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.max_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn backlog(&self) -> usize {
+        self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// How long ago the last frame was recorded, if there's been one.
+    pub fn since_last_update(&self) -> Option<Duration> {
+        self.last_update
+            .lock()
+            .ok()
+            .and_then(|last_update| *last_update)
+            .map(|last_update| last_update.elapsed())
+    }
+
+    /// Why the last connection closed, if it closed with an error and nothing has connected
+    /// since.
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error
+            .lock()
+            .ok()
+            .and_then(|last_error| last_error.clone())
+    }
+
+    /// The state of the sensor's connections, counting it as stale once it's been quiet for
+    /// longer than `stale_after`.
+    pub fn connection_state(&self, stale_after: Duration) -> ConnectionState {
+        let since_last_activity = self
+            .last_activity
+            .lock()
+            .ok()
+            .and_then(|last_activity| *last_activity)
+            .map(|last_activity| last_activity.elapsed());
+        ConnectionState::from_stats(
+            self.connections(),
+            since_last_activity,
+            self.last_error().is_some(),
+            stale_after,
+        )
+    }
+}
+
+/// Draws a dot colored by the state of the sensor's connections followed by the state's name and
+/// how many connections are open, and the error that closed the last connection, if there was
+/// one.
+pub fn connection_status(ui: &Ui, stats: &SensorStats, stale_after: Duration) {
+    let state = stats.connection_state(stale_after);
+    let size = ui.current_font_size();
+    let pos = ui.cursor_screen_pos();
+    ui.get_window_draw_list()
+        .add_circle(
+            [pos[0] + size / 2.0, pos[1] + size / 2.0],
+            size / 3.0,
+            state.color(),
+        )
+        .filled(true)
+        .build();
+    let cursor = ui.cursor_pos();
+    ui.set_cursor_pos([cursor[0] + size, cursor[1]]);
+    let connections = stats.connections();
+    match (state, stats.last_error()) {
+        (ConnectionState::Error, Some(e)) => ui.text(format!("{}: {}", state.label(), e)),
+        _ if connections == 0 => ui.text(state.label()),
+        _ => match stats.max_connections() {
+            0 => ui.text(format!("{} ({} open)", state.label(), connections)),
+            max => ui.text(format!(
+                "{} ({} of {} connections)",
+                state.label(),
+                connections,
+                max
+            )),
+        },
+    }
+}
+
+/// Shows how many frames the sensor has dropped because its window fell behind, if any, with a
+/// warning once recent frames are being dropped at more than `DROP_WARNING_RATE`.
+pub fn dropped_frames(ui: &Ui, stats: &SensorStats) {
+    let dropped = stats.dropped();
+    if dropped == 0 {
+        return;
+    }
+    let rate = stats.drop_rate();
+    if rate > DROP_WARNING_RATE {
+        ui.text_colored(
+            [1.0, 0.2, 0.2, 1.0],
+            format!(
+                "Dropping {:.0}% of frames, the UI can't keep up ({} dropped)",
+                rate * 100.0,
+                dropped
+            ),
+        );
+    } else {
+        ui.text(format!("{} frames dropped", dropped));
+    }
+}
+
+/// The rate in bytes per second received between two samples of a sensor's byte counter, each
+/// taken along with the time it was taken at. A counter that went backwards has started over,
+/// e.g. because the sensor was restarted with new stats, so everything it counts was received
+/// since the previous sample.
+pub fn interval_rate(previous: (Instant, u64), current: (Instant, u64)) -> f32 {
+    let elapsed = current
+        .0
+        .saturating_duration_since(previous.0)
+        .as_secs_f32();
+    if elapsed <= 0.0 {
+        return 0.0;
+    }
+    let bytes = if current.1 < previous.1 {
+        current.1
+    } else {
+        current.1 - previous.1
+    };
+    bytes as f32 / elapsed
+}
+
+/// The bytes per second a sensor received over each of the last `THROUGHPUT_SAMPLES` sampling
+/// intervals, oldest first, for the throughput graph in its window.
+pub struct ThroughputHistory {
+    rates: VecDeque<f32>,
+    last_sample: Option<(Instant, u64)>,
+}
+
+impl ThroughputHistory {
+    pub fn new() -> Self {
+        Self {
+            rates: VecDeque::with_capacity(THROUGHPUT_SAMPLES),
+            last_sample: None,
+        }
+    }
+
+    /// Samples the sensor's byte counter if `THROUGHPUT_SAMPLE_INTERVAL` has passed since the last
+    /// sample. Windows call this every frame. A sensor whose connection dropped stops counting,
+    /// so its rate falls to zero until something connects again.
+    pub fn sample(&mut self, stats: &SensorStats) {
+        let now = Instant::now();
+        let current = (now, stats.bytes());
+        match self.last_sample {
+            Some((at, _)) if now.duration_since(at) < THROUGHPUT_SAMPLE_INTERVAL => return,
+            Some(previous) => {
+                if self.rates.len() == THROUGHPUT_SAMPLES {
+                    self.rates.pop_front();
+                }
+                self.rates.push_back(interval_rate(previous, current));
+            }
+            None => (),
+        }
+        self.last_sample = Some(current);
+    }
+}
+
+/// Plots the sensor's recent throughput, labelled with the latest rate. Nothing is shown until
+/// the first interval has been sampled.
+pub fn throughput_graph(ui: &Ui, history: &ThroughputHistory) {
+    let latest = match history.rates.back() {
+        Some(latest) => *latest,
+        None => return,
+    };
+    let rates: Vec<f32> = history.rates.iter().copied().collect();
+    let overlay = im_str!("{}/s", format_bytes(latest as u64));
+    ui.plot_lines(im_str!("Throughput"), &rates)
+        .overlay_text(&overlay)
+        .scale_min(0.0)
+        .graph_size([0.0, 40.0])
+        .build();
+}
+
+/// A reader that counts every byte read from the inner reader towards a sensor's stats.
+pub struct StatsReader<R> {
+    inner: R,
+    stats: Arc<SensorStats>,
+}
+
+impl<R: Read> StatsReader<R> {
+    pub fn new(inner: R, stats: Arc<SensorStats>) -> Self {
+        Self { inner, stats }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for StatsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.stats.add_bytes(size);
+        Ok(size)
+    }
+}
+
+/// Only received bytes are counted, so what a TLS session sends back doesn't show up in the stats.
+impl<R: Write> Write for StatsReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Shows the stats of every active sensor side by side. The main window passes in the stats of
+/// the open sensor windows before each render, so closed sensors drop out of the table.
+pub struct DiagnosticsWindow {
+    pub sensors: Vec<(String, Arc<SensorStats>)>,
+}
+
+impl DiagnosticsWindow {
+    pub fn new() -> Self {
+        Self {
+            sensors: Vec::new(),
+        }
+    }
+}
+
+/// The column headers of the diagnostics table.
+const HEADERS: [&str; 7] = [
+    "Sensor",
+    "Connections",
+    "Frames",
+    "Dropped",
+    "Bytes",
+    "Last Update",
+    "Backlog",
+];
+
+/// Formats a byte count with a binary unit suffix.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl Renderable for DiagnosticsWindow {
+    fn render(
+        &mut self,
+        ui: &Ui,
+        _display: &Display,
+        _renderer: &mut Renderer,
+        visible: bool,
+        _all_paused: bool,
+    ) -> bool {
+        if !visible {
+            return true;
+        }
+        let mut opened = true;
+        let sensors = &self.sensors;
+        Window::new(im_str!("Diagnostics"))
+            .opened(&mut opened)
+            .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+            .build(ui, || {
+                if sensors.is_empty() {
+                    ui.text(im_str!("No active sensors"));
+                    return;
+                }
+                ui.columns(HEADERS.len() as i32, im_str!("diagnostics"), true);
+                for header in HEADERS.iter() {
+                    ui.text(header);
+                    ui.next_column();
+                }
+                ui.separator();
+                for (name, stats) in sensors {
+                    let last_update = match stats.since_last_update() {
+                        Some(age) => format!("{:.1}s ago", age.as_secs_f32()),
+                        None => "never".to_string(),
+                    };
+                    let cells = [
+                        name.clone(),
+                        stats.connections().to_string(),
+                        stats.frames().to_string(),
+                        stats.dropped().to_string(),
+                        format_bytes(stats.bytes()),
+                        last_update,
+                        stats.backlog().to_string(),
+                    ];
+                    for cell in cells.iter() {
+                        ui.text(cell);
+                        ui.next_column();
+                    }
+                }
+                ui.columns(1, im_str!(""), false);
+            });
+        opened
+    }
+
+    fn name(&self) -> &str {
+        "Diagnostics"
+    }
+
+    fn reset_texture(&mut self) {}
+
+    fn has_pending_data(&self) -> bool {
+        false
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        None
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        None
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        Vec::new()
+    }
+
+    fn capture(&self, _path: &Path) -> Option<ImageResult<()>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_after_is_clamped() {
+        assert_eq!(stale_after_from_secs(-1.0), Duration::from_secs(0));
+        assert_eq!(stale_after_from_secs(f32::NAN), Duration::from_secs(0));
+        assert_eq!(stale_after_from_secs(2.0), Duration::from_secs(2));
+        assert_eq!(
+            stale_after_from_secs(f32::INFINITY),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(stale_after_from_secs(1e30), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn quiet_connections_go_stale() {
+        let stale_after = Duration::from_secs(2);
+        let state = |connections, since, failed| {
+            ConnectionState::from_stats(connections, since, failed, stale_after)
+        };
+        assert_eq!(state(0, None, false), ConnectionState::Listening);
+        assert_eq!(state(0, None, true), ConnectionState::Error);
+        let recent = Some(Duration::from_secs(1));
+        assert_eq!(state(1, recent, false), ConnectionState::Connected);
+        assert_eq!(
+            state(1, Some(Duration::from_secs(3)), false),
+            ConnectionState::Stale
+        );
+        assert_eq!(state(1, None, false), ConnectionState::Stale);
+    }
+}
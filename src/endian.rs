@@ -0,0 +1,89 @@
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use imgui::{im_str, ImStr, Ui};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// The byte order of the numbers in a sensor's wire format.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+/// The byte orders in the order they're listed in the config modals.
+const BYTE_ORDERS: [Endianness; 2] = [Endianness::Little, Endianness::Big];
+
+impl Endianness {
+    pub fn read_u32<R: Read>(self, reader: &mut R) -> io::Result<u32> {
+        match self {
+            Endianness::Little => reader.read_u32::<LittleEndian>(),
+            Endianness::Big => reader.read_u32::<BigEndian>(),
+        }
+    }
+
+    pub fn read_f32<R: Read>(self, reader: &mut R) -> io::Result<f32> {
+        match self {
+            Endianness::Little => reader.read_f32::<LittleEndian>(),
+            Endianness::Big => reader.read_f32::<BigEndian>(),
+        }
+    }
+
+    pub fn write_u32<W: Write>(self, writer: &mut W, value: u32) -> io::Result<()> {
+        match self {
+            Endianness::Little => writer.write_u32::<LittleEndian>(value),
+            Endianness::Big => writer.write_u32::<BigEndian>(value),
+        }
+    }
+
+    /// The abbreviation used when describing a wire format, e.g. "u32 LE".
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Endianness::Little => "LE",
+            Endianness::Big => "BE",
+        }
+    }
+}
+
+/// Shows a combo box for picking the byte order in a sensor's config modal.
+pub fn byte_order_combo(ui: &Ui, endianness: &mut Endianness) {
+    let labels: [&ImStr; 2] = [im_str!("Little Endian"), im_str!("Big Endian")];
+    let mut item = BYTE_ORDERS
+        .iter()
+        .position(|order| order == endianness)
+        .unwrap_or(0);
+    if imgui::ComboBox::new(im_str!("Byte Order")).build_simple_string(ui, &mut item, &labels) {
+        *endianness = BYTE_ORDERS[item];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_orders_read_the_same_bytes_differently() {
+        let bytes = [0x01, 0x02, 0x03, 0x04];
+        assert_eq!(
+            Endianness::Little.read_u32(&mut &bytes[..]).unwrap(),
+            0x0403_0201
+        );
+        assert_eq!(
+            Endianness::Big.read_u32(&mut &bytes[..]).unwrap(),
+            0x0102_0304
+        );
+
+        let bytes = 1.5f32.to_be_bytes();
+        assert_eq!(Endianness::Big.read_f32(&mut &bytes[..]).unwrap(), 1.5);
+        assert_ne!(Endianness::Little.read_f32(&mut &bytes[..]).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn values_round_trip_in_either_byte_order() {
+        for &endianness in BYTE_ORDERS.iter() {
+            let mut bytes = Vec::new();
+            endianness.write_u32(&mut bytes, 0xdead_beef).unwrap();
+            assert_eq!(endianness.read_u32(&mut &bytes[..]).unwrap(), 0xdead_beef);
+        }
+    }
+}
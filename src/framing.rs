@@ -0,0 +1,161 @@
+use crate::endian::Endianness;
+use log::warn;
+use std::io::{self, Read};
+
+/// The bytes every checksummed frame starts with.
+pub const FRAME_MAGIC: [u8; 4] = *b"SVFR";
+
+/// The size of a checksummed frame's header: the magic, a u32 payload length and a u32 CRC32 of
+/// the payload.
+const HEADER_SIZE: usize = 12;
+
+/// How many bytes are read from the stream at a time while looking for a frame.
+const SYNC_READ_SIZE: usize = 16 * 1024;
+
+/// Reads checksummed frames from a stream. Each frame is `FRAME_MAGIC`, the payload's length and
+/// its CRC32 as u32s in the sensor's byte order, and then the payload itself.
+///
+/// A frame with a length over the maximum or a payload that doesn't match its checksum is
+/// skipped by scanning for the next magic, so a stream that got out of step recovers at the next
+/// intact frame instead of the connection being dropped. Bytes read past the end of a frame are
+/// kept for the next one.
+pub struct FrameSync {
+    /// The kind of sensor the frames are for, to name it in log messages.
+    sensor: &'static str,
+    buffer: Vec<u8>,
+}
+
+impl FrameSync {
+    pub fn new(sensor: &'static str) -> Self {
+        Self {
+            sensor,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Reads from the stream until an intact frame has arrived and returns its payload.
+    pub fn next_frame<R: Read>(
+        &mut self,
+        stream: &mut R,
+        endianness: Endianness,
+        max_frame_bytes: usize,
+    ) -> io::Result<Vec<u8>> {
+        loop {
+            let start = match find_magic(&self.buffer) {
+                Some(start) => start,
+                None => {
+                    // The end of the buffer could be the start of a magic split across reads.
+                    let keep = self.buffer.len().min(FRAME_MAGIC.len() - 1);
+                    self.buffer.drain(..self.buffer.len() - keep);
+                    self.fill(stream)?;
+                    continue;
+                }
+            };
+            self.buffer.drain(..start);
+            if self.buffer.len() < HEADER_SIZE {
+                self.fill(stream)?;
+                continue;
+            }
+
+            let mut header = &self.buffer[FRAME_MAGIC.len()..HEADER_SIZE];
+            let size = endianness.read_u32(&mut header)? as usize;
+            let checksum = endianness.read_u32(&mut header)?;
+            if size > max_frame_bytes {
+                warn!(
+                    "{}: skipping a frame of {} bytes, over the maximum of {} bytes",
+                    self.sensor, size, max_frame_bytes
+                );
+                self.buffer.drain(..1);
+                continue;
+            }
+            if self.buffer.len() < HEADER_SIZE + size {
+                self.fill(stream)?;
+                continue;
+            }
+
+            let payload = &self.buffer[HEADER_SIZE..HEADER_SIZE + size];
+            if crc32fast::hash(payload) != checksum {
+                warn!(
+                    "{}: skipping a frame of {} bytes with a bad checksum",
+                    self.sensor, size
+                );
+                self.buffer.drain(..1);
+                continue;
+            }
+            let payload = payload.to_vec();
+            self.buffer.drain(..HEADER_SIZE + size);
+            return Ok(payload);
+        }
+    }
+
+    fn fill<R: Read>(&mut self, stream: &mut R) -> io::Result<()> {
+        let mut chunk = [0; SYNC_READ_SIZE];
+        let size = stream.read(&mut chunk)?;
+        if size == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.buffer.extend_from_slice(&chunk[..size]);
+        Ok(())
+    }
+}
+
+fn find_magic(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(FRAME_MAGIC.len())
+        .position(|window| window == FRAME_MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Wraps a payload in a checksummed frame in little-endian byte order.
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = FRAME_MAGIC.to_vec();
+        let order = Endianness::Little;
+        order.write_u32(&mut bytes, payload.len() as u32).unwrap();
+        order
+            .write_u32(&mut bytes, crc32fast::hash(payload))
+            .unwrap();
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    fn next_frame(stream: &mut Cursor<Vec<u8>>, sync: &mut FrameSync) -> io::Result<Vec<u8>> {
+        sync.next_frame(stream, Endianness::Little, 1024)
+    }
+
+    #[test]
+    fn a_corrupted_length_is_skipped_for_the_next_valid_frame() {
+        let mut bytes = frame(b"lost");
+        bytes[FRAME_MAGIC.len()] ^= 0x10;
+        bytes.extend(frame(b"valid"));
+        let mut stream = Cursor::new(bytes);
+        let mut sync = FrameSync::new("test");
+        assert_eq!(next_frame(&mut stream, &mut sync).unwrap(), b"valid");
+    }
+
+    #[test]
+    fn a_length_over_the_maximum_is_skipped_for_the_next_valid_frame() {
+        let mut bytes = frame(b"lost");
+        bytes[FRAME_MAGIC.len() + 3] = 0xff;
+        bytes.extend(frame(b"valid"));
+        let mut stream = Cursor::new(bytes);
+        let mut sync = FrameSync::new("test");
+        assert_eq!(next_frame(&mut stream, &mut sync).unwrap(), b"valid");
+    }
+
+    #[test]
+    fn garbage_between_frames_is_skipped() {
+        let mut bytes = frame(b"first");
+        bytes.extend_from_slice(b"SVgarbage");
+        bytes.extend(frame(b"second"));
+        let mut stream = Cursor::new(bytes);
+        let mut sync = FrameSync::new("test");
+        assert_eq!(next_frame(&mut stream, &mut sync).unwrap(), b"first");
+        assert_eq!(next_frame(&mut stream, &mut sync).unwrap(), b"second");
+        let end = next_frame(&mut stream, &mut sync).unwrap_err();
+        assert_eq!(end.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
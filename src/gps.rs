@@ -1,27 +1,179 @@
-use crate::window::{Modal, Renderable};
-use byteorder::{LittleEndian, ReadBytesExt};
-use crossbeam::channel::{unbounded, Receiver, Sender};
+use crate::camera::{self, CameraError, PixelFormat};
+use crate::capture::CaptureReader;
+use crate::channel::{ChannelConfig, OverflowPolicy, SensorSender};
+use crate::connection;
+use crate::diagnostics::{self, SensorStats, ThroughputHistory};
+use crate::endian::{byte_order_combo, Endianness};
+use crate::notifications::Notification;
+use crate::server::{self, RestartableSensor, Transport};
+use crate::session::SensorSpec;
+use crate::settings::Settings;
+use crate::texture;
+use crate::tls::{self, ServerTlsConfig};
+use crate::window::{self, parse_listen_addr, Modal, Place, Placement, Renderable};
+use crossbeam::channel::{Receiver, TryRecvError};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use glium::texture::{ClientFormat, RawImage2d};
 use glium::Display;
-use glium::{
-    backend::Facade,
-    texture::{ClientFormat, RawImage2d},
-    Texture2d,
-};
 use image::png::PngDecoder;
-use image::ImageDecoder;
+use image::{ColorType, ImageDecoder, ImageError, ImageResult};
 use image::{Rgb, RgbImage};
-use imageproc::drawing::draw_filled_circle_mut;
+use imageproc::drawing::{
+    draw_antialiased_line_segment_mut, draw_filled_circle_mut, draw_filled_rect_mut,
+    draw_hollow_circle_mut, draw_line_segment_mut, draw_text_mut,
+};
+use imageproc::pixelops::interpolate;
+use imageproc::rect::Rect;
 use imgui::TextureId;
-use imgui::{self, im_str, ImString, Image, Ui, Window, WindowFlags};
+use imgui::{self, im_str, ColorEdit, ImStr, ImString, Image, Slider, Ui, Window, WindowFlags};
 use imgui_glium_renderer::Renderer;
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, USER_AGENT};
+use rustls::ServerConfig;
+use rusttype::{Font, Scale};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
 use std::f32::consts::PI;
-use std::io::{self, Cursor};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
+use std::mem;
 use std::net::SocketAddr;
-use std::net::{TcpListener, TcpStream};
-use std::rc::Rc;
+use std::num::ParseFloatError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::string::FromUtf8Error;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter, EnumString};
+use thiserror::Error;
+
+/// The default capacity of the buffered reader wrapped around each GPS connection.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// How many fixes can wait for the GPS window. Every fix is part of the track, so by default the
+/// sensor waits for the window rather than dropping fixes.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+pub const DEFAULT_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Block;
+
+/// The default distance in degrees, roughly a meter, that a fix has to move from the previous
+/// point to be drawn as a new point.
+const DEFAULT_STATIONARY_EPSILON: f32 = 1e-5;
+
+/// The zoom levels the zoom buttons move between. Below `MIN_GRID_ZOOM` the world is less than
+/// three tiles across, which is too small for even the default grid of tiles drawn around the
+/// track. `MAX_ZOOM` is the last level in `METERS_PER_PIXEL`.
+const MIN_GRID_ZOOM: u32 = 2;
+const MAX_ZOOM: u32 = 20;
+
+/// The zoom level the map jumps to when the first fix arrives, unless another one is configured.
+const DEFAULT_INITIAL_ZOOM: u32 = 16;
+
+/// The default number of tiles along each side of the grid drawn around the track.
+const DEFAULT_GRID_SIZE: u32 = 3;
+
+/// The default and largest number of tiles downloaded at once. OpenStreetMap's tile usage policy
+/// asks for no more than two; other tile servers may allow more.
+const DEFAULT_TILE_FETCH_CONCURRENCY: u32 = 2;
+const MAX_TILE_FETCH_CONCURRENCY: u32 = 8;
+
+/// The longest the scale bar in the corner of the map gets, in pixels. The bar is shortened from
+/// this to the nearest round distance.
+const SCALE_BAR_MAX_PIXELS: f32 = 120.0;
+
+/// The tile server queried unless another one is configured. `{z}`, `{x}` and `{y}` are replaced
+/// with the zoom level and tile indices, and `{s}` with one of the server's subdomains.
+pub const DEFAULT_TILE_URL: &str = "http://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png";
+
+/// The subdomains `{s}` in a tile URL template is replaced with. Requests are spread across them.
+const TILE_SUBDOMAINS: [&str; 3] = ["a", "b", "c"];
+
+/// OpenStreetMap's tile usage policy requires requests to identify the application making them.
+const TILE_USER_AGENT: &str = concat!(
+    "sensorview/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/ostrosco/sensorview)"
+);
+
+/// The compression the tile client asks for. The client decodes responses itself, see
+/// `read_body`.
+const TILE_ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// How long to wait on a single tile request before giving up on it.
+const TILE_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The Nominatim endpoint place names typed into the search box are looked up with. Its usage
+/// policy asks for the same identifying user agent as the tile servers, and for no more than one
+/// request a second, which searching only on request keeps well within.
+const GEOCODE_URL: &str = "https://nominatim.openstreetmap.org/search";
+
+/// How many recent searches are remembered, so searching for the same place again doesn't make
+/// another request.
+const GEOCODE_CACHE_SIZE: usize = 32;
+
+/// How long tiles cached on disk are used before they're fetched again, in days.
+const DEFAULT_TILE_MAX_AGE_DAYS: i32 = 7;
+
+/// How far the latest fix can get from the center of the map, in tiles, before following it
+/// re-centers the map. Tiles are cached, but re-centering still rebuilds and redraws the map.
+const FOLLOW_RECENTER_TILES: f32 = 0.5;
+
+/// The round intervals graticule lines are drawn at, in degrees: seconds, then minutes, then whole
+/// degrees. The smallest one that keeps lines at least `GRATICULE_MIN_SPACING` pixels apart is
+/// used.
+const GRATICULE_INTERVALS: [f32; 18] = [
+    1.0 / 3600.0,
+    2.0 / 3600.0,
+    5.0 / 3600.0,
+    10.0 / 3600.0,
+    20.0 / 3600.0,
+    30.0 / 3600.0,
+    1.0 / 60.0,
+    2.0 / 60.0,
+    5.0 / 60.0,
+    10.0 / 60.0,
+    20.0 / 60.0,
+    30.0 / 60.0,
+    1.0,
+    2.0,
+    5.0,
+    10.0,
+    20.0,
+    30.0,
+];
+const GRATICULE_MIN_SPACING: f32 = 100.0;
+
+/// How strongly graticule lines show over the map, from 0 to 1, and the height of their labels
+/// in pixels.
+const GRATICULE_ALPHA: f32 = 0.35;
+const GRATICULE_LABEL_SIZE: f32 = 12.0;
+
+/// The neutral color the map tiles fade towards as their opacity is turned down, and that the
+/// tracks are drawn on in track only mode.
+const MAP_BACKGROUND: [u8; 3] = [224, 224, 224];
+
+/// How far the markers drawn at the ends of a track reach from the fix they mark, in pixels.
+const MARKER_EXTENT: i32 = 15;
+
+/// How long the track is considered live after the latest fix. Live tracks mark the latest
+/// position with an arrow, otherwise it's marked as the end of the track.
+const TRACK_LIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the JSON lines file fixes are logged to is flushed, so at most this much is lost
+/// if sensorview crashes.
+const JSONL_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The mean radius of the Earth in meters, used for great-circle distances between fixes.
+const EARTH_RADIUS_METERS: f32 = 6_371_000.0;
+
+/// How many of the latest fixes a track's speed is averaged over by default, and at most. Longer
+/// windows smooth out more of the jitter in the fixes but lag behind changes in speed.
+const DEFAULT_SPEED_WINDOW: usize = 5;
+const MAX_SPEED_WINDOW: usize = 30;
 
 // Defines the meters per pixel by zoom level from 0 to 20.
 static METERS_PER_PIXEL: [f32; 21] = [
@@ -29,48 +181,454 @@ static METERS_PER_PIXEL: [f32; 21] = [
     152.746, 76.373, 38.187, 19.093, 9.547, 4.773, 2.387, 1.193, 0.596, 0.298, 0.149,
 ];
 
+/// The largest number of fixes accepted in a single batch. Anything larger is treated as a
+/// corrupt stream rather than trusted.
+const MAX_BATCH_SIZE: u32 = 4096;
+
+/// The default radius and color of the dots the track is drawn with.
+const DEFAULT_POINT_RADIUS: i32 = 3;
+const DEFAULT_POINT_COLOR: [f32; 3] = [0.0, 0.0, 1.0];
+
+/// By default, consecutive fixes landing on the same pixel or one next to it are drawn once.
+const DEFAULT_MERGE_RADIUS: i32 = 1;
+const MAX_MERGE_RADIUS: i32 = 10;
+
+/// The radius in pixels each fix spreads its visit over in the heatmap, falling off towards the
+/// edge, so that nearby fixes add up to a hot spot.
+const HEATMAP_RADIUS: i32 = 8;
+
+/// How opaque the faintest and the hottest parts of the heatmap are drawn over the map.
+const HEATMAP_MIN_ALPHA: f32 = 0.3;
+const HEATMAP_MAX_ALPHA: f32 = 0.8;
+
+/// The colors tracks with an id are drawn in, picked by the id. Blue is left out so they don't
+/// get mixed up with the default track.
+const TRACK_COLORS: [[f32; 3]; 6] = [
+    [0.9, 0.1, 0.1],
+    [0.1, 0.6, 0.1],
+    [1.0, 0.5, 0.0],
+    [0.6, 0.2, 0.8],
+    [0.0, 0.7, 0.7],
+    [0.9, 0.2, 0.6],
+];
+
+/// Why a GPS connection failed, or why the map tiles or a place search couldn't be loaded.
+#[derive(Debug, Error)]
+pub enum GpsError {
+    /// Reading from the connection or decompressing a response failed, or the window's channel
+    /// was disconnected.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("GPS batch of {0} fixes exceeds the maximum")]
+    BatchTooLarge(u32),
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("couldn't decode tile: {0}")]
+    TileDecode(#[from] ImageError),
+    #[error("unsupported tile color type {0:?}")]
+    UnsupportedColorType(ColorType),
+    #[error("{0}")]
+    TilePixels(#[from] CameraError),
+    #[error("tiles of different sizes, {0}x{1} and {2}x{3}")]
+    TileSizeMismatch(u32, u32, u32, u32),
+    #[error("map of {width}x{height} pixels should be {expected} bytes but is {actual}")]
+    MapSizeMismatch {
+        width: u32,
+        height: u32,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("map image too large")]
+    MapTooLarge,
+    #[error("the tile download thread panicked")]
+    DownloadPanicked,
+    #[error("the search stopped")]
+    SearchStopped,
+    #[error("search response isn't UTF-8: {0}")]
+    GeocodeEncoding(#[from] FromUtf8Error),
+    #[error("invalid search response: {0}")]
+    GeocodeResponse(#[from] serde_json::Error),
+    #[error("invalid coordinates in search response: {0}")]
+    GeocodeCoordinates(#[from] ParseFloatError),
+    #[error("no places found for {0:?}")]
+    NoPlaces(String),
+}
+
+/// The sensor threads hand their errors to `server::serve`, which tells a disconnect from a
+/// closed window by the error kind, so I/O errors are passed through as they are.
+impl From<GpsError> for io::Error {
+    fn from(e: GpsError) -> Self {
+        match e {
+            GpsError::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
+
+#[derive(AsRefStr, EnumIter, EnumString, Clone, Copy, Debug)]
+/// The wire formats the GPS can send. `Single` sends one lat/lon pair per message while `Batched`
+/// prefixes each message with a u32 count of the lat/lon pairs that follow. `Tagged` prefixes
+/// each lat/lon pair with a u32 track id, so several objects can report to the same listener.
+pub enum GpsFormat {
+    Single,
+    Batched,
+    Tagged,
+}
+
+#[derive(Clone)]
 pub struct Gps {
-    sender: Sender<GpsData>,
+    sender: SensorSender<GpsData>,
+    pub stats: Arc<SensorStats>,
+    pub format: GpsFormat,
+    pub capture_path: Option<PathBuf>,
+    pub buffer_size: usize,
+    /// How long a connection can go without sending anything before it's dropped, if at all.
+    pub read_timeout: Option<Duration>,
+    /// The most devices that can be connected at once. Further connections are refused.
+    pub max_connections: usize,
+    pub endianness: Endianness,
+    pub transport: Transport,
+    /// If set, TCP connections are only read over TLS with this configuration.
+    pub tls: Option<Arc<ServerConfig>>,
 }
 
 impl Gps {
-    pub fn new(sender: Sender<GpsData>) -> Self {
-        Self { sender }
+    pub fn new(sender: SensorSender<GpsData>) -> Self {
+        Self {
+            sender,
+            stats: Arc::new(SensorStats::new()),
+            format: GpsFormat::Single,
+            capture_path: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            read_timeout: None,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS,
+            endianness: Endianness::Little,
+            transport: Transport::Tcp,
+            tls: None,
+        }
     }
 
-    /// Starts a TCP listener to receive data from the GPS. This supports multiple connections,
-    /// though multiple connections aren't handled correctly at the moment.
-    pub fn start(mut self, ip: SocketAddr) -> JoinHandle<io::Result<()>> {
-        thread::spawn(move || {
-            let listener = TcpListener::bind(&ip).unwrap();
-            for stream in listener.incoming() {
-                self.handle_gps(stream?)?;
+    /// Starts listening for data from the GPS over the configured transport. Over TCP each
+    /// connection is handled on its own thread, so multiple devices can stream to the same
+    /// listener at once. Over UDP every datagram is decoded as it arrives. Fails if the port can't
+    /// be bound.
+    pub fn start(
+        self,
+        ip: SocketAddr,
+        shutdown: Arc<AtomicBool>,
+    ) -> io::Result<JoinHandle<io::Result<()>>> {
+        let stats = self.stats.clone();
+        match self.transport {
+            Transport::Tcp => {
+                let listener = server::bind(ip)?;
+                info!("GPS: listening on {} with format {:?}", ip, self.format);
+                Ok(thread::spawn(move || {
+                    let buffer_size = self.buffer_size;
+                    let read_timeout = self.read_timeout;
+                    let max_connections = self.max_connections;
+                    server::serve(
+                        "GPS",
+                        &listener,
+                        &shutdown,
+                        &stats,
+                        read_timeout,
+                        max_connections,
+                        move |stream| {
+                            let stream = tls::accept(stream, self.tls.as_ref());
+                            self.receive(BufReader::with_capacity(buffer_size, stream))
+                        },
+                    )
+                }))
             }
-            Ok(())
-        })
+            Transport::Udp => {
+                let socket = server::bind_udp(ip)?;
+                info!(
+                    "GPS: receiving datagrams on {} with format {:?}",
+                    ip, self.format
+                );
+                if self.tls.is_some() {
+                    warn!("GPS: TLS only applies over TCP, so datagrams are read in plaintext");
+                }
+                Ok(thread::spawn(move || {
+                    server::serve_udp("GPS", &socket, &shutdown, &stats, |datagram| {
+                        self.receive(datagram)
+                    })
+                }))
+            }
+        }
+    }
+
+    /// Decodes the fixes arriving on a connection or in a datagram, copying the raw bytes to the
+    /// capture file first if there is one.
+    fn receive<R: Read + 'static>(&self, stream: R) -> io::Result<()> {
+        let stream: Box<dyn Read> = match self.capture_path {
+            Some(ref path) => Box::new(CaptureReader::new(stream, path)?),
+            None => Box::new(stream),
+        };
+        Ok(self.clone().handle_gps(stream)?)
     }
 
-    pub fn handle_gps(&mut self, mut stream: TcpStream) -> io::Result<()> {
+    pub fn handle_gps<R: Read>(&mut self, mut stream: R) -> Result<(), GpsError> {
         loop {
-            let lat = stream.read_f32::<LittleEndian>()?;
-            let lon = stream.read_f32::<LittleEndian>()?;
-            let data = GpsData { lat, lon };
-            self.sender.send(data).unwrap();
+            let count = match self.format {
+                GpsFormat::Single | GpsFormat::Tagged => 1,
+                GpsFormat::Batched => {
+                    let count = self.endianness.read_u32(&mut stream)?;
+                    if count > MAX_BATCH_SIZE {
+                        return Err(GpsError::BatchTooLarge(count));
+                    }
+                    count
+                }
+            };
+            for _ in 0..count {
+                let track = match self.format {
+                    GpsFormat::Tagged => Some(self.endianness.read_u32(&mut stream)?),
+                    GpsFormat::Single | GpsFormat::Batched => None,
+                };
+                let lat = self.endianness.read_f32(&mut stream)?;
+                let lon = self.endianness.read_f32(&mut stream)?;
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let data = GpsData {
+                    lat,
+                    lon,
+                    track,
+                    timestamp,
+                };
+                self.sender.send(data, &self.stats)?;
+            }
         }
     }
 }
 
-#[derive(Clone)]
+/// How the fixes of a track are drawn on the map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackStyle {
+    /// A dot for each fix.
+    Dots,
+    /// A line through the fixes in the order they arrived.
+    Lines,
+    /// A line through the fixes with a dot on each of them.
+    LinesAndDots,
+}
+
+const TRACK_STYLES: [TrackStyle; 3] = [
+    TrackStyle::Dots,
+    TrackStyle::Lines,
+    TrackStyle::LinesAndDots,
+];
+
+/// A rectangle of the map in pixels, from `left` and `top` up to but not including `right` and
+/// `bottom`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PixelRegion {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl PixelRegion {
+    /// The region covering every point along with `margin` pixels around each, clipped to a map
+    /// of the given size. There's no region if it lies entirely off the map.
+    fn around(points: &[(i32, i32)], margin: i32, width: u32, height: u32) -> Option<Self> {
+        let left = points.iter().map(|(x, _)| x - margin).min()?.max(0);
+        let top = points.iter().map(|(_, y)| y - margin).min()?.max(0);
+        let right = points.iter().map(|(x, _)| x + margin + 1).max()?;
+        let bottom = points.iter().map(|(_, y)| y + margin + 1).max()?;
+        let right = (right.max(0) as u32).min(width);
+        let bottom = (bottom.max(0) as u32).min(height);
+        if left as u32 >= right || top as u32 >= bottom {
+            return None;
+        }
+        Some(Self {
+            left: left as u32,
+            top: top as u32,
+            right,
+            bottom,
+        })
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    fn height(&self) -> u32 {
+        self.bottom - self.top
+    }
+}
+
+/// Which part of the map changed since it was last uploaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MapDamage {
+    Clean,
+    /// Only this region changed, so only it needs uploading.
+    Region(PixelRegion),
+    /// The whole map changed, e.g. because it was redrawn.
+    Full,
+}
+
+impl MapDamage {
+    fn add(self, region: PixelRegion) -> Self {
+        match self {
+            MapDamage::Clean => MapDamage::Region(region),
+            MapDamage::Region(damaged) => MapDamage::Region(damaged.union(region)),
+            MapDamage::Full => MapDamage::Full,
+        }
+    }
+}
+
+/// Identifies which object a fix belongs to when several report to the same GPS listener.
+pub type TrackId = u32;
+
+#[derive(Clone, Serialize)]
 pub struct GpsData {
-    lat: f32,
-    lon: f32,
+    pub lat: f32,
+    pub lon: f32,
+    /// The track the fix belongs to. Fixes sent without an id make up the default track.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<TrackId>,
+    /// When the fix was received, in seconds since the Unix epoch. Coordinates that aren't fixes,
+    /// like the map's center, leave this at 0.
+    pub timestamp: f64,
+}
+
+/// A spot on the map standing in for one or more consecutive fixes of a track that landed on
+/// top of each other at the current zoom.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MergedPoint {
+    pub point: (i32, i32),
+    /// How many fixes landed here.
+    pub count: u32,
+}
+
+/// Collapses runs of consecutive points within `radius` pixels of where the run started into a
+/// single point each. Only consecutive points are merged so that the track's lines still follow
+/// the order the fixes arrived in.
+fn merge_points(points: &[(i32, i32)], radius: i32) -> Vec<MergedPoint> {
+    let mut merged = Vec::new();
+    for point in points {
+        merge_point(&mut merged, *point, radius);
+    }
+    merged
+}
+
+/// Adds a point to the end of a merged track, returning whether it landed on the last spot.
+fn merge_point(merged: &mut Vec<MergedPoint>, point: (i32, i32), radius: i32) -> bool {
+    if let Some(last) = merged.last_mut() {
+        let (dx, dy) = (point.0 - last.point.0, point.1 - last.point.1);
+        if dx.abs() <= radius && dy.abs() <= radius {
+            last.count += 1;
+            return true;
+        }
+    }
+    merged.push(MergedPoint { point, count: 1 });
+    false
+}
+
+/// The fixes of a single track along with where they're drawn on the map.
+#[derive(Default)]
+pub struct Track {
+    /// Every fix drawn on the map. `points` holds the same track in pixels, which has to be
+    /// recomputed from these whenever the map moves.
+    pub fixes: Vec<GpsData>,
+    pub points: Vec<(i32, i32)>,
+    /// `points` with the fixes that overlap at the current zoom merged, which is what's drawn.
+    /// It's rebuilt along with `points`.
+    pub merged: Vec<MergedPoint>,
+    /// The latest fixes, oldest first, including the stationary ones that aren't added to
+    /// `fixes`, so that the speed drops to 0 once the object stops.
+    recent: Vec<GpsData>,
+}
+
+impl Track {
+    /// Adds a fix to the end of the track. Once the track holds more than `max_points` fixes, the
+    /// oldest ones are dropped. A limit of 0 keeps every fix. Returns whether any were dropped.
+    fn push(
+        &mut self,
+        fix: GpsData,
+        point: (i32, i32),
+        max_points: usize,
+        merge_radius: i32,
+    ) -> bool {
+        self.fixes.push(fix);
+        self.points.push(point);
+        if max_points > 0 && self.fixes.len() > max_points {
+            let excess = self.fixes.len() - max_points;
+            self.fixes.drain(..excess);
+            self.points.drain(..excess);
+            // Where the runs of merged fixes start depends on the first fix, so they're redone.
+            self.remerge(merge_radius);
+            return true;
+        }
+        merge_point(&mut self.merged, point, merge_radius);
+        false
+    }
+
+    /// Merges the track's points again, e.g. after they were placed on a new map.
+    fn remerge(&mut self, merge_radius: i32) {
+        self.merged = merge_points(&self.points, merge_radius);
+    }
+
+    /// The bearing of the latest leg of the track in degrees clockwise from north, i.e. the
+    /// direction from the last fix at a different position to the latest one. A track with a
+    /// single position has no bearing yet.
+    pub fn heading(&self) -> Option<f32> {
+        let last = self.fixes.last()?;
+        let previous = self
+            .fixes
+            .iter()
+            .rev()
+            .find(|fix| fix.lat != last.lat || fix.lon != last.lon)?;
+        Some(bearing_degrees(previous, last))
+    }
+
+    /// Remembers a fix for working out the speed, keeping only the latest `window` of them.
+    fn record_recent(&mut self, fix: GpsData, window: usize) {
+        self.recent.push(fix);
+        let window = window.max(2);
+        if self.recent.len() > window {
+            let excess = self.recent.len() - window;
+            self.recent.drain(..excess);
+        }
+    }
+
+    /// The speed in meters per second averaged over the latest fixes.
+    pub fn speed(&self) -> Option<f32> {
+        average_speed(&self.recent)
+    }
 }
 
 pub struct GpsWindow {
+    /// The name shown in the window's title bar and in the list of active sensors.
+    pub name: String,
+    /// Keeps the window apart from other windows with the same name.
+    window_id: usize,
+    /// Where the main window has asked this window to move on its next frame.
+    placement: Option<Placement>,
+    /// Where the window was drawn on its last frame.
+    shown_at: Option<Placement>,
     pub texture_id: Option<TextureId>,
     pub image: RgbImage,
+    pub map_image: RgbImage,
+    /// The map with the graticule and the tracks drawn on it, but not the markers, scale bar or
+    /// center, which move around. New fixes are drawn onto this and copied over to `image`
+    /// around where they changed it, so the rest of the map isn't redrawn.
+    track_image: RgbImage,
     pub receiver: Receiver<GpsData>,
-    pub points: Vec<(i32, i32)>,
+    /// The tracks drawn on the map, keyed by their id. The default track sorts first.
+    pub tracks: BTreeMap<Option<TrackId>, Track>,
     pub query_lat: f32,
     pub query_lon: f32,
     pub lat_meters: f32,
@@ -80,21 +638,281 @@ pub struct GpsWindow {
     pub x_tile: u32,
     pub y_tile: u32,
     pub zoom: u32,
+    /// The zoom level the map jumps to from the world view when the first fix arrives.
+    pub initial_zoom: u32,
+    pub has_fix: bool,
     pub width: u32,
     pub height: u32,
+    pub tile_dim: f32,
+    /// The URL tiles are fetched from. See `DEFAULT_TILE_URL` for the placeholders it contains.
+    pub tile_url_template: String,
+    /// The number of tiles along each side of the map. This is odd so that the tile being
+    /// queried sits in the center.
+    pub grid_size: u32,
+    /// The most tiles downloaded at once when the map is queried.
+    pub tile_fetch_concurrency: u32,
+    pub tile_cache: TileMemoryCache,
+    pub tile_disk_cache: Option<TileDiskCache>,
+    pub show_markers: bool,
+    /// Whether lines of latitude and longitude are drawn over the map at round intervals.
+    pub show_graticule: bool,
+    /// Whether the tracks are drawn as dots, lines connecting them, or both. Lines keep a
+    /// fast-moving object's path continuous when its fixes are far apart.
+    pub track_style: TrackStyle,
+    /// How much of the map tiles shows through under the tracks, from 0 to 1. Lower values blend
+    /// the tiles towards `MAP_BACKGROUND` so a dense track stands out.
+    pub map_opacity: f32,
+    /// Whether the tracks are drawn on a blank background instead of the map tiles. The tracks
+    /// stay where they'd be on the map.
+    pub track_only: bool,
+    /// The radius in pixels of the dot drawn for each fix.
+    pub point_radius: i32,
+    /// The color of the dots drawn for each fix of the default track, as RGB from 0 to 1. Tracks
+    /// with an id get a color from `TRACK_COLORS`.
+    pub point_color: [f32; 3],
+    /// Whether the tracks are drawn as a heatmap of how often each spot was visited instead of
+    /// as dots.
+    pub heatmap: bool,
+    /// The visit density of each pixel of the map, rebuilt from the tracks whenever the heatmap
+    /// is redrawn.
+    density: Vec<f32>,
+    pub max_jump_meters: f32,
+    /// The most fixes kept and drawn per track, dropping the oldest first. 0 keeps every fix.
+    pub max_points: usize,
+    /// Fixes closer than this many degrees to the previous point are treated as the same position.
+    pub stationary_epsilon: f32,
+    /// How many of the latest fixes each track's speed is averaged over.
+    pub speed_window: usize,
+    /// Consecutive fixes landing within this many pixels of each other are drawn as one.
+    pub merge_radius: i32,
+    pub spec: Option<SensorSpec>,
+    /// Lets the sensor feeding the window be restarted from it, when the window started the
+    /// sensor itself.
+    pub restartable: Option<RestartableSensor>,
+    pub stats: Arc<SensorStats>,
+    /// The last fix received on each track, whether or not it was accepted.
+    last_coords: HashMap<Option<TrackId>, GpsData>,
+    /// The bytes per second received recently, for the throughput graph.
+    throughput: ThroughputHistory,
+    /// How long the GPS can go without sending a fix before it's shown as stale.
+    pub stale_after: Duration,
+    /// Whether the map on screen is frozen. Fixes that arrive while paused are still added to
+    /// the tracks, and show up once the map is unpaused.
+    pub paused: bool,
+    /// What changed on the map since it was last uploaded. Uploads wait while the map is paused,
+    /// so this carries them over until the pause lifts.
+    damage: MapDamage,
+    /// Whether the map is re-centered on the latest fix once it's moved away from the center,
+    /// rather than only once it leaves the map.
+    pub follow: bool,
+    last_fix: Option<Instant>,
+    track_live: bool,
+    tile_error: Option<String>,
+    gpx_path: ImString,
+    gpx_status: Option<String>,
+    /// The file every accepted fix is appended to as a line of JSON while logging, and when it
+    /// was last flushed.
+    jsonl_path: ImString,
+    jsonl: Option<BufWriter<File>>,
+    jsonl_flushed: Instant,
+    jsonl_status: Option<String>,
+    snapshot_status: Option<String>,
+    /// The coordinate and zoom typed in to jump the map to, independent of incoming fixes. The
+    /// coordinate can be typed in decimal degrees or in degrees, minutes and seconds.
+    goto_lat: ImString,
+    goto_lon: ImString,
+    goto_zoom: i32,
+    /// Whether coordinates are shown in degrees, minutes and seconds rather than decimal degrees.
+    pub dms_readouts: bool,
+    goto_error: Option<String>,
+    /// The place name typed in to search for, and why the last search failed, if it did.
+    search_query: ImString,
+    search_error: Option<String>,
+    /// The most recent searches and the coordinates they found, oldest first.
+    geocode_cache: VecDeque<(String, (f32, f32))>,
+    /// The search being looked up on its own thread, if there is one.
+    pending_search: Option<PendingSearch>,
+    /// The coordinates under the mouse cursor, if it's over the map.
+    cursor_coords: Option<GpsData>,
+    http_client: reqwest::Client,
+    /// Whether the last upload of the map failed, so only the first of a run of failures raises
+    /// a notification.
+    texture_failing: bool,
+    notifications: Vec<Notification>,
+}
+
+/// A place search running on its own thread, polled by the window each frame.
+struct PendingSearch {
+    /// The search, keyed like the cache of recent searches.
+    key: String,
+    /// Where the coordinates found, or why the search failed, will arrive.
+    receiver: Receiver<Result<(f32, f32), GpsError>>,
 }
 
+#[derive(Clone)]
 struct OsmTile {
     data: Vec<u8>,
     width: u32,
     height: u32,
 }
 
+/// How much memory decoded tiles are cached in by default, in MiB.
+const DEFAULT_TILE_CACHE_MIB: i32 = 64;
+
+/// Tiles are keyed by their zoom level followed by the x and y tile indices.
+type TileKey = (u32, u32, u32);
+
+/// A tile's PNG bytes, or why they couldn't be downloaded.
+type TileDownload = Result<Vec<u8>, GpsError>;
+
+/// An in-memory cache of decoded map tiles. The cache is bounded by a total byte budget shared
+/// across all zoom levels; once the budget is exceeded the least recently used tiles are evicted.
+/// A budget of zero disables the cache entirely.
+pub struct TileMemoryCache {
+    tiles: HashMap<TileKey, OsmTile>,
+    recency: VecDeque<TileKey>,
+    used_bytes: usize,
+    pub max_bytes: usize,
+}
+
+impl TileMemoryCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            recency: VecDeque::new(),
+            used_bytes: 0,
+            max_bytes,
+        }
+    }
+
+    fn get(&mut self, key: TileKey) -> Option<OsmTile> {
+        let tile = self.tiles.get(&key)?.clone();
+        self.touch(key);
+        Some(tile)
+    }
+
+    fn insert(&mut self, key: TileKey, tile: OsmTile) {
+        if tile.data.len() > self.max_bytes {
+            return;
+        }
+        self.used_bytes += tile.data.len();
+        if let Some(old) = self.tiles.insert(key, tile) {
+            self.used_bytes -= old.data.len();
+        }
+        self.touch(key);
+
+        while self.used_bytes > self.max_bytes {
+            let oldest = match self.recency.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some(evicted) = self.tiles.remove(&oldest) {
+                self.used_bytes -= evicted.data.len();
+            }
+        }
+    }
+
+    /// Marks the key as the most recently used entry.
+    fn touch(&mut self, key: TileKey) {
+        self.recency.retain(|k| *k != key);
+        self.recency.push_back(key);
+    }
+}
+
+/// A cache of the raw PNG bytes of map tiles stored on disk, so tiles survive restarts and the
+/// same area isn't fetched from OpenStreetMap over and over. Tiles older than `max_age` are
+/// treated as missing so that they eventually get refreshed.
+pub struct TileDiskCache {
+    pub dir: PathBuf,
+    pub max_age: Duration,
+}
+
+impl TileDiskCache {
+    pub fn new(dir: PathBuf, max_age: Duration) -> Self {
+        Self { dir, max_age }
+    }
+
+    /// Returns the directory tiles from the given tile server are cached in. Tiles from the
+    /// default server are kept directly in `default_dir`, others in a subdirectory named after
+    /// their URL template so that different servers' tiles don't mix.
+    pub fn dir_for(tile_url_template: &str) -> PathBuf {
+        let dir = Self::default_dir();
+        if tile_url_template == DEFAULT_TILE_URL {
+            return dir;
+        }
+        let name: String = tile_url_template
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        dir.join(name)
+    }
+
+    /// Returns the directory tiles are cached in unless another one is configured.
+    pub fn default_dir() -> PathBuf {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sensorview")
+            .join("tiles")
+    }
+
+    fn path(&self, (zoom, x_tile, y_tile): TileKey) -> PathBuf {
+        self.dir
+            .join(zoom.to_string())
+            .join(x_tile.to_string())
+            .join(format!("{}.png", y_tile))
+    }
+
+    fn get(&self, key: TileKey) -> Option<Vec<u8>> {
+        let path = self.path(key);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > self.max_age {
+            return None;
+        }
+        fs::read(path).ok()
+    }
+
+    fn insert(&self, key: TileKey, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, bytes)
+    }
+}
+
+/// Converts a cache size in MiB, as the config modal takes it, to bytes.
+fn mib_to_bytes(mib: i32) -> usize {
+    (mib.max(0) as usize) << 20
+}
+
+/// Converts a tile age in days, as the config modal takes it, to a duration.
+fn days_to_duration(days: i32) -> Duration {
+    Duration::from_secs(days.max(0) as u64 * 24 * 60 * 60)
+}
+
 impl GpsWindow {
     pub fn new(receiver: Receiver<GpsData>) -> Self {
+        let mut gpx_path = ImString::new("track.gpx");
+        gpx_path.reserve_exact(256);
+        let mut jsonl_path = ImString::new("fixes.jsonl");
+        jsonl_path.reserve_exact(256);
+        let mut search_query = ImString::new("");
+        search_query.reserve_exact(256);
+        let mut goto_lat = ImString::new("0");
+        goto_lat.reserve_exact(32);
+        let mut goto_lon = ImString::new("0");
+        goto_lon.reserve_exact(32);
         Self {
+            name: "GPS".to_string(),
+            window_id: window::next_window_id(),
+            placement: None,
+            shown_at: None,
             texture_id: None,
             image: RgbImage::from_raw(0, 0, Vec::new()).unwrap(),
+            map_image: RgbImage::from_raw(0, 0, Vec::new()).unwrap(),
+            track_image: RgbImage::from_raw(0, 0, Vec::new()).unwrap(),
             receiver,
             x_tile: 0,
             y_tile: 0,
@@ -105,10 +923,99 @@ impl GpsWindow {
             nw_lat: 0.0,
             nw_lon: 0.0,
             zoom: 0,
-            points: Vec::new(),
+            initial_zoom: DEFAULT_INITIAL_ZOOM,
+            has_fix: false,
+            tracks: BTreeMap::new(),
             width: 0,
             height: 0,
+            tile_dim: 0.0,
+            tile_url_template: DEFAULT_TILE_URL.to_string(),
+            grid_size: DEFAULT_GRID_SIZE,
+            tile_fetch_concurrency: DEFAULT_TILE_FETCH_CONCURRENCY,
+            tile_cache: TileMemoryCache::new(mib_to_bytes(DEFAULT_TILE_CACHE_MIB)),
+            tile_disk_cache: Some(TileDiskCache::new(
+                TileDiskCache::default_dir(),
+                days_to_duration(DEFAULT_TILE_MAX_AGE_DAYS),
+            )),
+            show_markers: true,
+            show_graticule: false,
+            map_opacity: 1.0,
+            track_only: false,
+            track_style: TrackStyle::LinesAndDots,
+            point_radius: DEFAULT_POINT_RADIUS,
+            point_color: DEFAULT_POINT_COLOR,
+            heatmap: false,
+            density: Vec::new(),
+            max_jump_meters: 0.0,
+            max_points: 0,
+            stationary_epsilon: DEFAULT_STATIONARY_EPSILON,
+            speed_window: DEFAULT_SPEED_WINDOW,
+            merge_radius: DEFAULT_MERGE_RADIUS,
+            spec: None,
+            restartable: None,
+            stats: Arc::new(SensorStats::new()),
+            last_coords: HashMap::new(),
+            throughput: ThroughputHistory::new(),
+            stale_after: diagnostics::DEFAULT_STALE_AFTER,
+            paused: false,
+            damage: MapDamage::Clean,
+            follow: false,
+            last_fix: None,
+            track_live: false,
+            tile_error: None,
+            gpx_path,
+            gpx_status: None,
+            jsonl_path,
+            jsonl: None,
+            jsonl_flushed: Instant::now(),
+            jsonl_status: None,
+            snapshot_status: None,
+            goto_lat,
+            goto_lon,
+            dms_readouts: false,
+            goto_zoom: 16,
+            goto_error: None,
+            search_query,
+            search_error: None,
+            geocode_cache: VecDeque::new(),
+            pending_search: None,
+            cursor_coords: None,
+            http_client: tile_client(),
+            texture_failing: false,
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Moves the map to the initial zoom if it's still at the world view when the first fix
+    /// arrives; otherwise whatever zoom level has already been chosen is kept.
+    fn zoom_to_first_fix(&mut self) {
+        if !self.has_fix && self.zoom == 0 {
+            self.zoom = self.initial_zoom;
+        }
+    }
+
+    /// Checks a fix for obvious glitches: coordinates outside the valid range, the (0, 0) "null
+    /// island" many receivers report before they have a lock, or a jump from the previous fix
+    /// larger than `max_jump_meters` (if set). Jumps are measured from the previous fix received
+    /// on the same track whether or not it was accepted, so after a genuine relocation only the
+    /// first fix at the new position is rejected rather than every fix from then on.
+    fn validate_fix(&mut self, fix: &GpsData) -> Result<(), String> {
+        check_coords(fix.lat, fix.lon)?;
+        if fix.lat == 0.0 && fix.lon == 0.0 {
+            return Err("null island fix".to_string());
+        }
+        if let Some(ref last) = self.last_coords.insert(fix.track, fix.clone()) {
+            let jump = distance_meters(last, fix);
+            if self.max_jump_meters > 0.0 && jump > self.max_jump_meters {
+                return Err(format!("jumped {:.0} m from the previous fix", jump));
+            }
         }
+        Ok(())
+    }
+
+    /// Returns the latest fix drawn for the given track, if it has any.
+    fn last_fix_of(&self, track: Option<TrackId>) -> Option<&GpsData> {
+        self.tracks.get(&track).and_then(|track| track.fixes.last())
     }
 
     fn meters_per_pixel(&self) -> f32 {
@@ -116,42 +1023,259 @@ impl GpsWindow {
     }
 
     /// Converts a set of GPS coordinates to pixel coordinates relative to the northwestern
-    /// coordinates of the tiles being drawn.
+    /// coordinates of the tiles being drawn. Coordinates west or north of the tiles come out
+    /// negative.
     fn coords_to_pixel(&self, coords: &GpsData) -> (i32, i32) {
         let meters_per_pixel = self.meters_per_pixel();
-        let lon_diff = self.lon_meters * (coords.lon - self.nw_lon).abs() / meters_per_pixel;
-        let lat_diff = self.lat_meters * (coords.lat - self.nw_lat).abs() / meters_per_pixel;
+        let lon_diff = self.lon_meters * (coords.lon - self.nw_lon) / meters_per_pixel;
+        let lat_diff = self.lat_meters * (self.nw_lat - coords.lat) / meters_per_pixel;
         (lon_diff.floor() as i32, lat_diff.floor() as i32)
     }
 
+    /// Converts pixel coordinates relative to the northwestern corner of the tiles back to GPS
+    /// coordinates. This is the inverse of `coords_to_pixel`, apart from its rounding.
+    fn pixel_to_coords(&self, (x, y): (f32, f32)) -> GpsData {
+        let meters_per_pixel = self.meters_per_pixel();
+        GpsData {
+            lat: self.nw_lat - y * meters_per_pixel / self.lat_meters,
+            lon: self.nw_lon + x * meters_per_pixel / self.lon_meters,
+            track: None,
+            timestamp: 0.0,
+        }
+    }
+
+    /// Changes the zoom level, clamped to the levels the map supports, and reloads the map around
+    /// the current center. The track is placed again at the new scale.
+    fn set_zoom(&mut self, zoom: u32) {
+        let zoom = zoom.clamp(MIN_GRID_ZOOM, MAX_ZOOM);
+        if zoom == self.zoom {
+            return;
+        }
+        let previous = self.zoom;
+        self.zoom = zoom;
+        if !self.try_query_osm(self.query_lat, self.query_lon) {
+            self.zoom = previous;
+        }
+    }
+
+    /// Centers the map on a coordinate typed in by the user rather than one received from the
+    /// GPS. Fixes that arrive afterwards are drawn on top of this map, and the map only moves
+    /// again once the track leaves it.
+    fn go_to(&mut self, lat: f32, lon: f32, zoom: i32) {
+        if let Err(e) = check_coords(lat, lon) {
+            self.goto_error = Some(e);
+            return;
+        }
+        self.goto_error = None;
+        let previous = self.zoom;
+        self.zoom = (zoom.max(0) as u32).clamp(MIN_GRID_ZOOM, MAX_ZOOM);
+        if self.try_query_osm(lat, lon) {
+            self.has_fix = true;
+        } else {
+            self.zoom = previous;
+        }
+    }
+
+    /// Goes to the coordinate typed into the go-to controls, if it's a valid one.
+    fn go_to_entered(&mut self) {
+        let coords = parse_coordinate(self.goto_lat.to_str(), Axis::Latitude).and_then(|lat| {
+            parse_coordinate(self.goto_lon.to_str(), Axis::Longitude).map(|lon| (lat, lon))
+        });
+        match coords {
+            Ok((lat, lon)) => self.go_to(lat, lon, self.goto_zoom),
+            Err(e) => self.goto_error = Some(e),
+        }
+    }
+
+    /// Shows a latitude or longitude in the format the readouts are set to.
+    fn format_coordinate(&self, value: f32, axis: Axis) -> String {
+        if self.dms_readouts {
+            format_dms(value, axis)
+        } else {
+            format!("{:.6}", value)
+        }
+    }
+
+    /// Looks up the place name in the search box. Places searched for recently are taken from the
+    /// cache right away, returning true since the map moved. Anything else is looked up on its own
+    /// thread so the UI doesn't stall on Nominatim, and the result is picked up by `poll_search`.
+    fn search(&mut self) -> bool {
+        let query = self.search_query.to_str().trim().to_string();
+        if query.is_empty() {
+            return false;
+        }
+        let key = query.to_lowercase();
+        if let Some(position) = self.geocode_cache.iter().position(|(q, _)| *q == key) {
+            let entry = self.geocode_cache.remove(position).unwrap();
+            let coords = entry.1;
+            self.geocode_cache.push_back(entry);
+            self.go_to_place(coords);
+            return true;
+        }
+
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+        let client = self.http_client.clone();
+        thread::spawn(move || {
+            let result = geocode(&client, &query);
+            // The window may have been closed, or a newer search started, in the meantime.
+            let _ = sender.send(result);
+        });
+        self.search_error = None;
+        self.pending_search = Some(PendingSearch { key, receiver });
+        false
+    }
+
+    /// Takes the result of the running search once it arrives, caching it and centering the map
+    /// on the place found. Returns whether the map moved.
+    fn poll_search(&mut self) -> bool {
+        let result = match &self.pending_search {
+            Some(pending) => match pending.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => Err(GpsError::SearchStopped),
+            },
+            None => return false,
+        };
+        let key = self.pending_search.take().unwrap().key;
+        match result {
+            Ok(coords) => {
+                if self.geocode_cache.len() >= GEOCODE_CACHE_SIZE {
+                    self.geocode_cache.pop_front();
+                }
+                self.geocode_cache.push_back((key, coords));
+                self.go_to_place(coords);
+                true
+            }
+            Err(e) => {
+                self.search_error = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// Centers the map on a place that was searched for, at the zoom level from the go-to
+    /// controls, which are updated to the place's coordinates.
+    fn go_to_place(&mut self, (lat, lon): (f32, f32)) {
+        self.search_error = None;
+        self.goto_lat = ImString::new(self.format_coordinate(lat, Axis::Latitude));
+        self.goto_lat.reserve_exact(32);
+        self.goto_lon = ImString::new(self.format_coordinate(lon, Axis::Longitude));
+        self.goto_lon.reserve_exact(32);
+        self.go_to(lat, lon, self.goto_zoom);
+    }
+
+    /// Starts appending fixes to the configured JSON lines file, or stops and closes the file if
+    /// fixes are already being logged. An existing file is appended to rather than replaced.
+    fn toggle_jsonl(&mut self) {
+        if let Some(mut jsonl) = self.jsonl.take() {
+            self.jsonl_status = match jsonl.flush() {
+                Ok(()) => Some(format!("Saved {}", self.jsonl_path.to_str())),
+                Err(e) => Some(format!("Couldn't finish the log: {}", e)),
+            };
+            return;
+        }
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.jsonl_path.to_str())
+        {
+            Ok(file) => {
+                self.jsonl = Some(BufWriter::new(file));
+                self.jsonl_flushed = Instant::now();
+                self.jsonl_status = None;
+            }
+            Err(e) => self.jsonl_status = Some(format!("Couldn't start logging: {}", e)),
+        }
+    }
+
+    /// Appends a fix to the JSON lines file, if fixes are being logged. A failed write stops
+    /// logging.
+    fn log_fix(&mut self, fix: &GpsData) {
+        let jsonl = match &mut self.jsonl {
+            Some(jsonl) => jsonl,
+            None => return,
+        };
+        let mut result = write_json_line(jsonl, fix);
+        if result.is_ok() && self.jsonl_flushed.elapsed() >= JSONL_FLUSH_INTERVAL {
+            self.jsonl_flushed = Instant::now();
+            result = jsonl.flush();
+        }
+        if let Err(e) = result {
+            self.jsonl = None;
+            self.jsonl_status = Some(format!("Logging stopped: {}", e));
+        }
+    }
+
+    /// Writes every fix of every track to a GPX file at the given path.
+    fn export_gpx(&self, path: &str) -> io::Result<()> {
+        fs::write(path, tracks_to_gpx(&self.tracks))
+    }
+
+    /// Saves the map with the tracks and markers drawn on it to a timestamped PNG file,
+    /// remembering how it went so it can be shown in the window.
+    fn save_image(&mut self) {
+        let path = camera::snapshot_path("gps");
+        self.snapshot_status = Some(match self.image.save(&path) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(e) => format!("Couldn't save image: {}", e),
+        });
+    }
+
+    /// Forgets every track drawn so far and redraws the map without them. The tiles and zoom are
+    /// left as they are, so the next fix starts a fresh track on the current map.
+    fn clear_tracks(&mut self) {
+        self.tracks.clear();
+        self.last_coords.clear();
+        self.last_fix = None;
+        self.track_live = false;
+        self.redraw_track();
+    }
+
+    /// The number of fixes across all tracks.
+    fn fix_count(&self) -> usize {
+        self.tracks.values().map(|track| track.fixes.len()).sum()
+    }
+
+    /// Checks whether a pixel lies within the current map.
+    fn in_bounds(&self, (x, y): (i32, i32)) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
     /// Gathers tiles that contain and surround the given latitude and longitude. Also calculates
     /// the most northwestern coordinate and the number of meters per degree for latitude and
     /// longitude at this given latitude.
-    fn query_osm(&mut self, lat: f32, lon: f32) -> Result<(), Box<dyn Error>> {
+    fn query_osm(&mut self, lat: f32, lon: f32) -> Result<(), GpsError> {
         self.query_lat = lat;
         self.query_lon = lon;
         let n = (1 << self.zoom) as f32;
 
         // First, calculate the right tile to query that contains this input coordinate. Taken from:
         // https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames
-        self.x_tile = ((lon + 180.0) / 360.0 * n as f32).floor() as u32;
+        self.x_tile = ((lon + 180.0) / 360.0 * n).floor() as u32;
         let lat_rad = lat * PI / 180.0;
         self.y_tile = ((1.0 - (lat_rad.tan().asinh()) / PI) / 2.0 * n).floor() as u32;
 
         let (nw_xtile, nw_ytile) = if self.zoom > 0 {
-            let image_bytes = self.query_tiles()?;
-            self.image = RgbImage::from_raw(self.width, self.height, image_bytes).unwrap();
-            // TODO: for the moment, the map is hardcoded to query a 3x3 grid for the map, so we
-            // know for certain which tile is the northwestern tile. In theory though, this
-            // shouldn't be hardcoded.
-            (self.x_tile - 1, self.y_tile - 1)
+            // The grid is centered on the queried tile, but it can't extend past the top or
+            // bottom of the world, so near the poles it's shifted to stay within it; east and
+            // west wrap around. At low zoom levels the grid is limited to the size of the world.
+            let grid_size = self.grid_size.min(1 << self.zoom);
+            let nw_xtile = self.x_tile as i32 - (grid_size / 2) as i32;
+            let nw_ytile = self
+                .y_tile
+                .saturating_sub(grid_size / 2)
+                .min((1 << self.zoom) - grid_size);
+            let image_bytes = self.query_tiles(nw_xtile, nw_ytile, grid_size)?;
+            self.map_image = map_image_from_raw(self.width, self.height, image_bytes)?;
+            (nw_xtile, nw_ytile)
         } else {
             let tile = self.query_tile(self.x_tile, self.y_tile)?;
             self.width = tile.width;
             self.height = tile.height;
-            self.image = RgbImage::from_raw(self.width, self.height, tile.data).unwrap();
-            (self.x_tile, self.y_tile)
+            self.map_image = map_image_from_raw(self.width, self.height, tile.data)?;
+            (self.x_tile as i32, self.y_tile)
         };
+        dim_image(&mut self.map_image, self.tile_dim);
 
         // Now, work backwards to calculate the lat/lon of the northwestern corner of the tile.
         // Taken from: https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames
@@ -167,184 +1291,2201 @@ impl GpsWindow {
             - 0.0023 * (6.0 * query_lat_rad).cos();
         self.lon_meters = 111_412.84 * query_lat_rad.cos() - 93.5 * (3.0 * query_lat_rad).cos()
             + 0.118 * (5.0 * query_lat_rad).cos();
+
+        // The tracks' pixels are relative to the old corner, so they have to be placed again.
+        let mut tracks = mem::take(&mut self.tracks);
+        for track in tracks.values_mut() {
+            track.points = track
+                .fixes
+                .iter()
+                .map(|coords| self.coords_to_pixel(coords))
+                .collect();
+            track.remerge(self.merge_radius);
+        }
+        self.tracks = tracks;
+        self.redraw_track();
         Ok(())
     }
 
-    /// Queries nine tiles used for drawing data onto the map.
-    fn query_tiles(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
-        // First off, we need to query nine tiles. The tile for our starting point will be the
-        // center tile and we'll query all the other tiles around it.
-        let mut image_bytes = Vec::new();
-        for y in -1_i32..=1 {
-            let mut row =
-                self.query_map_row(self.x_tile - 1, (self.y_tile as i32 + y) as u32, 3)?;
-            image_bytes.append(&mut row);
+    /// Queries the map around the given coordinates, remembering the error if it fails so it can
+    /// be shown in the window. A failed query can leave the map dimensions half-updated, so they're
+    /// restored to match the map that's still displayed. Queries are retried on every fix, so only
+    /// the first of a run of failures raises a notification.
+    fn try_query_osm(&mut self, lat: f32, lon: f32) -> bool {
+        let (width, height) = (self.width, self.height);
+        match self.query_osm(lat, lon) {
+            Ok(()) => {
+                self.tile_error = None;
+                true
+            }
+            Err(e) => {
+                self.width = width;
+                self.height = height;
+                if self.tile_error.is_none() {
+                    self.notifications.push(Notification::error(format!(
+                        "GPS: failed to load tiles: {}",
+                        e
+                    )));
+                }
+                self.tile_error = Some(e.to_string());
+                false
+            }
         }
-        self.height *= 3;
-        Ok(image_bytes)
     }
 
-    /// Queries a row of tiles and stitches them together.
-    fn query_map_row(
-        &mut self,
-        x_tile: u32,
-        y_tile: u32,
-        row_length: u32,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut tiles = Vec::new();
-        for ix in 0..row_length {
-            tiles.push(self.query_tile(x_tile + ix, y_tile)?);
+    /// Rebuilds the displayed image from the map tiles, drawing the tracks and their markers on
+    /// top.
+    fn redraw_track(&mut self) {
+        self.image = self.map_image.clone();
+        let opacity = if self.track_only {
+            0.0
+        } else {
+            self.map_opacity
+        };
+        fade_to_background(&mut self.image, opacity, Rgb(MAP_BACKGROUND));
+        if self.show_graticule {
+            self.draw_graticule();
         }
-
-        let mut map_row = Vec::new();
-        for row_num in 0..256 {
-            for tile in &tiles {
-                let start_byte = row_num * 256 * 3;
-                let end_byte = start_byte + 256 * 3;
-                map_row.extend_from_slice(&tile.data[start_byte..end_byte]);
+        if self.heatmap {
+            // The density is rebuilt from scratch so it follows the map when it moves and drops
+            // the fixes trimmed by `max_points`.
+            let mut density = mem::take(&mut self.density);
+            density.clear();
+            density.resize((self.width * self.height) as usize, 0.0);
+            for track in self.tracks.values() {
+                for point in &track.points {
+                    add_visit(&mut density, self.width, self.height, *point);
+                }
+            }
+            draw_heatmap(&mut self.image, &density);
+            self.density = density;
+        } else {
+            for (id, track) in &self.tracks {
+                let color = rgb_from_floats(track_color(*id, self.point_color));
+                let points: Vec<(i32, i32)> = track.merged.iter().map(|m| m.point).collect();
+                if self.track_style != TrackStyle::Dots {
+                    draw_lines(&mut self.image, &points, color);
+                }
+                if self.track_style != TrackStyle::Lines {
+                    draw_points(&mut self.image, &points, self.point_radius, color);
+                }
             }
         }
-
-        self.width = tiles[0].width * row_length;
-        self.height = tiles[0].height;
-        Ok(map_row)
+        self.track_image = self.image.clone();
+        self.draw_overlays();
     }
 
-    /// Queries a single tile from OpenStreetMap.
-    fn query_tile(&self, x_tile: u32, y_tile: u32) -> Result<OsmTile, Box<dyn Error>> {
-        let mut resp = reqwest::get(&format!(
-            "http://a.tile.openstreetmap.org/{}/{}/{}.png",
-            self.zoom, x_tile, y_tile,
-        ))?;
-        let mut bytes: Vec<u8> = Vec::new();
-        resp.copy_to(&mut bytes)?;
-        let bytes = Cursor::new(bytes);
-        let decoder = PngDecoder::new(bytes).expect("couldn't make decoder");
-        let (width, height) = decoder.dimensions();
-        let mut data: Vec<u8> = vec![0; decoder.total_bytes() as usize];
-        decoder.read_image(&mut data).expect("couldn't parse image");
-        Ok(OsmTile {
-            data,
-            width: width as u32,
-            height: height as u32,
-        })
+    /// Draws the markers, scale bar and center over the tracks. They're all drawn in solid
+    /// colors, so drawing them again over themselves leaves the image as it was.
+    fn draw_overlays(&mut self) {
+        if self.show_markers {
+            self.draw_markers();
+        }
+        self.draw_scale_bar();
+        self.draw_center();
     }
-}
 
-impl Renderable for GpsWindow {
-    /// Renders the data received from the gps sensor. This currently assumes RGB data format.
-    fn render(&mut self, ui: &Ui, display: &Display, renderer: &mut Renderer) {
-        if self.image.is_empty() {
-            self.query_osm(self.query_lat, self.query_lon)
-                .expect("Couldn't get tiles");
-
-            let image_frame = Some(RawImage2d {
-                data: Cow::Owned(self.image.to_vec()),
-                width: self.width as u32,
-                height: self.height as u32,
-                format: ClientFormat::U8U8U8,
-            })
-            .unwrap();
-            let gl_texture = Texture2d::new(display.get_context(), image_frame)
-                .expect("Couldn't create new texture");
-            if let Some(tex_id) = self.texture_id {
-                renderer.textures().replace(tex_id, Rc::new(gl_texture));
-            } else {
-                self.texture_id = Some(renderer.textures().insert(Rc::new(gl_texture)));
+    /// Draws the latest fix of a track without redrawing the rest of the map, returning the
+    /// region of the map that changed. The fix and the segment leading to it are drawn onto
+    /// `track_image`, which is copied to `image` around them and around the markers that moved
+    /// along with the fix.
+    ///
+    /// There's no region if the whole map has to be redrawn instead: the heatmap is normalized
+    /// over every visit, so one more changes all of it, and there may not be a `track_image` to
+    /// draw onto yet.
+    fn draw_new_point(&mut self, id: Option<TrackId>) -> Option<PixelRegion> {
+        if self.heatmap || self.image.is_empty() {
+            return None;
+        }
+        if self.track_image.dimensions() != self.image.dimensions() {
+            return None;
+        }
+        let track = self.tracks.get(&id)?;
+        // The markers sit on the fixes themselves rather than on the merged points, so the
+        // region covers where the end of the track was as well as where it is now.
+        let mut changed: Vec<(i32, i32)> = track.points.iter().rev().take(2).cloned().collect();
+        let latest = track.merged.last()?;
+        // A fix merged into the last point only moves the markers.
+        if latest.count == 1 {
+            let color = rgb_from_floats(track_color(id, self.point_color));
+            let mut drawn = vec![latest.point];
+            if let Some(previous) = track.merged.len().checked_sub(2).map(|ix| track.merged[ix]) {
+                drawn.push(previous.point);
+                if self.track_style != TrackStyle::Dots {
+                    draw_lines(&mut self.track_image, &drawn, color);
+                }
+            }
+            // The previous dot is drawn again so that it stays on top of the new segment.
+            if self.track_style != TrackStyle::Lines {
+                draw_points(&mut self.track_image, &drawn, self.point_radius, color);
             }
+            changed.extend(drawn);
         }
+        let margin = self.point_radius.max(MARKER_EXTENT) + 1;
+        let region = PixelRegion::around(&changed, margin, self.width, self.height)?;
+        copy_region(&self.track_image, &mut self.image, region);
+        self.draw_overlays();
+        Some(region)
+    }
 
-        if let Ok(gps_data) = self.receiver.try_recv() {
-            // The zoom is zero until we receive our first point. Once the first point comes in,
-            // query OSM for the tiles for this point.
-            if self.zoom == 0 {
-                self.zoom = 16;
-                self.query_osm(gps_data.lat, gps_data.lon).unwrap();
+    /// Draws faint lines of latitude and longitude at an interval that suits the zoom level, each
+    /// labeled along the top or left edge of the map.
+    fn draw_graticule(&mut self) {
+        let font = match Font::from_bytes(ttf_noto_sans::REGULAR) {
+            Ok(font) => font,
+            Err(e) => {
+                warn!("gps: couldn't load the graticule font: {}", e);
+                return;
             }
-
-            // TODO: consider _not_ adding the point if the point hasn't changed between
-            // measurements. A stationary object shouldn't overwrite the entire track of points
-            // thus far.
-            let pixel_coords = self.coords_to_pixel(&gps_data);
-            self.points.push(pixel_coords);
-            let color = Rgb([0u8, 0u8, 255u8]);
-            draw_filled_circle_mut(&mut self.image, pixel_coords, 3, color);
-
-            // TODO: this needs to be filled in to do the following things:
-            //
-            // Check if the current tile will fit all of the current points.  If not, get a new
-            // tile and re-draw the points on top.
-            let image_frame = Some(RawImage2d {
-                data: Cow::Owned(self.image.to_vec()),
-                width: self.width as u32,
-                height: self.height as u32,
-                format: ClientFormat::U8U8U8,
+        };
+        let scale = Scale::uniform(GRATICULE_LABEL_SIZE);
+        let line_color = Rgb([60u8, 60u8, 60u8]);
+        let label_color = Rgb([30u8, 30u8, 30u8]);
+        let (width, height) = self.image.dimensions();
+        let meters_per_pixel = self.meters_per_pixel();
+        let corner = |lat, lon| GpsData {
+            lat,
+            lon,
+            track: None,
+            timestamp: 0.0,
+        };
+
+        // Lines are counted in whole intervals, which keeps rounding from building up along the
+        // way.
+        let lon_interval =
+            graticule_interval(meters_per_pixel / self.lon_meters, GRATICULE_MIN_SPACING);
+        let mut step = (self.nw_lon / lon_interval).ceil() as i64;
+        loop {
+            let lon = step as f32 * lon_interval;
+            let (x, _) = self.coords_to_pixel(&corner(self.nw_lat, lon));
+            if x >= width as i32 {
+                break;
+            }
+            if x >= 0 {
+                for y in 0..height {
+                    blend_pixel(self.image.get_pixel_mut(x as u32, y), line_color);
+                }
+                let label = format_graticule(lon, lon_interval, 'E', 'W');
+                draw_text_mut(
+                    &mut self.image,
+                    label_color,
+                    x as u32 + 2,
+                    2,
+                    scale,
+                    &font,
+                    &label,
+                );
+            }
+            step += 1;
+        }
+
+        let lat_interval =
+            graticule_interval(meters_per_pixel / self.lat_meters, GRATICULE_MIN_SPACING);
+        let mut step = (self.nw_lat / lat_interval).floor() as i64;
+        loop {
+            let lat = step as f32 * lat_interval;
+            let (_, y) = self.coords_to_pixel(&corner(lat, self.nw_lon));
+            if y >= height as i32 {
+                break;
+            }
+            if y >= 0 {
+                for x in 0..width {
+                    blend_pixel(self.image.get_pixel_mut(x, y as u32), line_color);
+                }
+                let label = format_graticule(lat, lat_interval, 'N', 'S');
+                draw_text_mut(
+                    &mut self.image,
+                    label_color,
+                    2,
+                    y as u32 + 2,
+                    scale,
+                    &font,
+                    &label,
+                );
+            }
+            step -= 1;
+        }
+    }
+
+    /// Draws a crosshair over the coordinates the map was last centered on. The map's corner
+    /// moves whenever it's queried again, so this has to be redrawn along with the track.
+    fn draw_center(&mut self) {
+        let center = self.center_pixel();
+        draw_crosshair(&mut self.image, center, Rgb([255u8, 0u8, 255u8]));
+    }
+
+    /// The pixel of the coordinates the map was last centered on.
+    fn center_pixel(&self) -> (i32, i32) {
+        let center = GpsData {
+            lat: self.query_lat,
+            lon: self.query_lon,
+            track: None,
+            timestamp: 0.0,
+        };
+        self.coords_to_pixel(&center)
+    }
+
+    /// The width of a single tile of the map in pixels.
+    fn tile_size(&self) -> f32 {
+        let grid_size = if self.zoom > 0 {
+            self.grid_size.min(1 << self.zoom)
+        } else {
+            1
+        };
+        self.width as f32 / grid_size as f32
+    }
+
+    /// Draws a bar in the bottom left corner of the map whose length is the distance returned by
+    /// `scale_bar`.
+    fn draw_scale_bar(&mut self) {
+        let (_, pixels) = scale_bar(self.meters_per_pixel(), SCALE_BAR_MAX_PIXELS);
+        let (x, y) = (10, self.height as i32 - 14);
+        let length = pixels.round() as u32;
+        if length == 0 || y < 0 {
+            return;
+        }
+        let color = Rgb([0u8, 0u8, 0u8]);
+        draw_filled_rect_mut(&mut self.image, Rect::at(x, y).of_size(length, 3), color);
+        draw_filled_rect_mut(&mut self.image, Rect::at(x, y - 5).of_size(2, 8), color);
+        draw_filled_rect_mut(
+            &mut self.image,
+            Rect::at(x + length as i32 - 2, y - 5).of_size(2, 8),
+            color,
+        );
+    }
+
+    /// Draws a flag at the start of each track. The end of each track gets an arrow pointing in
+    /// the direction of travel while fixes are still arriving, and a flag once the tracks have
+    /// gone quiet. A live track that hasn't moved yet has no direction, so it gets a dot instead.
+    fn draw_markers(&mut self) {
+        for track in self.tracks.values() {
+            let (first, last) = match (track.points.first(), track.points.last()) {
+                (Some(first), Some(last)) => (*first, *last),
+                _ => continue,
+            };
+            draw_flag(&mut self.image, first, Rgb([0u8, 160u8, 0u8]));
+            if self.track_live {
+                let color = Rgb([255u8, 140u8, 0u8]);
+                match track.heading() {
+                    Some(heading) => draw_arrow(&mut self.image, last, heading, color),
+                    None => draw_filled_circle_mut(&mut self.image, last, 5, color),
+                }
+            } else {
+                draw_flag(&mut self.image, last, Rgb([200u8, 0u8, 0u8]));
+            }
+        }
+    }
+
+    /// Queries a square grid of tiles used for drawing data onto the map, starting from the
+    /// northwestern tile. Tiles that aren't cached are downloaded `tile_fetch_concurrency` at a
+    /// time.
+    fn query_tiles(
+        &mut self,
+        nw_xtile: i32,
+        nw_ytile: u32,
+        grid_size: u32,
+    ) -> Result<Vec<u8>, GpsError> {
+        // The tiles are listed row by row from west to east, the order they're stitched in. East
+        // and west wrap around.
+        let zoom = self.zoom;
+        let n = 1 << zoom;
+        let keys: Vec<TileKey> = (0..grid_size)
+            .flat_map(|y| {
+                (0..grid_size as i32)
+                    .map(move |x| (zoom, (nw_xtile + x).rem_euclid(n) as u32, nw_ytile + y))
             })
-            .unwrap();
-            let gl_texture = Texture2d::new(display.get_context(), image_frame)
-                .expect("Couldn't create new texture");
-            if let Some(tex_id) = self.texture_id {
-                renderer.textures().replace(tex_id, Rc::new(gl_texture));
+            .collect();
+        let mut tiles: Vec<Option<OsmTile>> =
+            keys.iter().map(|key| self.cached_tile(*key)).collect();
+        let missing: Vec<usize> = (0..keys.len()).filter(|ix| tiles[*ix].is_none()).collect();
+        let missing_keys: Vec<TileKey> = missing.iter().map(|ix| keys[*ix]).collect();
+        let downloads = fetch_tiles(
+            &self.http_client,
+            &self.tile_url_template,
+            &missing_keys,
+            self.tile_fetch_concurrency as usize,
+        );
+        for ((ix, key), bytes) in missing.into_iter().zip(missing_keys).zip(downloads) {
+            tiles[ix] = Some(self.store_tile(key, bytes?)?);
+        }
+        let tiles: Vec<OsmTile> = tiles.into_iter().flatten().collect();
+
+        let mut image_bytes = Vec::new();
+        for row in tiles.chunks(grid_size as usize) {
+            let mut row = self.stitch_row(row)?;
+            image_bytes.append(&mut row);
+        }
+        self.height *= grid_size;
+        Ok(image_bytes)
+    }
+
+    /// Stitches a row of tiles together from west to east.
+    fn stitch_row(&mut self, tiles: &[OsmTile]) -> Result<Vec<u8>, GpsError> {
+        // Tiles are stitched row by row, which only lines up if they're all the same size.
+        let (width, height) = (tiles[0].width, tiles[0].height);
+        if let Some(tile) = tiles
+            .iter()
+            .find(|tile| tile.width != width || tile.height != height)
+        {
+            return Err(GpsError::TileSizeMismatch(
+                width,
+                height,
+                tile.width,
+                tile.height,
+            ));
+        }
+
+        // Each pixel row of the stitched image is made of the same pixel row from every tile.
+        let row_bytes = tiles[0].width as usize * 3;
+        let mut map_row = Vec::with_capacity(row_bytes * tiles.len() * tiles[0].height as usize);
+        for row_num in 0..tiles[0].height as usize {
+            for tile in tiles {
+                let start_byte = row_num * row_bytes;
+                map_row.extend_from_slice(&tile.data[start_byte..start_byte + row_bytes]);
+            }
+        }
+
+        self.width = tiles[0].width * tiles.len() as u32;
+        self.height = tiles[0].height;
+        Ok(map_row)
+    }
+
+    /// Queries a single tile from the tile server, consulting the in-memory cache and then the disk
+    /// cache first.
+    fn query_tile(&mut self, x_tile: u32, y_tile: u32) -> Result<OsmTile, GpsError> {
+        let key = (self.zoom, x_tile, y_tile);
+        if let Some(tile) = self.cached_tile(key) {
+            return Ok(tile);
+        }
+        let bytes = fetch_tile(&self.http_client, &self.tile_url_template, key)?;
+        self.store_tile(key, bytes)
+    }
+
+    /// Looks a tile up in the in-memory cache and then the disk cache, copying tiles found on
+    /// disk into memory.
+    fn cached_tile(&mut self, key: TileKey) -> Option<OsmTile> {
+        if let Some(tile) = self.tile_cache.get(key) {
+            return Some(tile);
+        }
+
+        // A cached tile that fails to decode is most likely a partial write, so it's fetched
+        // again rather than treated as an error.
+        let cached = self
+            .tile_disk_cache
+            .as_ref()
+            .and_then(|cache| cache.get(key))
+            .and_then(|bytes| decode_tile(bytes).ok());
+        if let Some(tile) = &cached {
+            self.tile_cache.insert(key, tile.clone());
+        }
+        cached
+    }
+
+    /// Writes a downloaded tile to the disk cache and decodes it into the in-memory cache.
+    fn store_tile(&mut self, key: TileKey, bytes: Vec<u8>) -> Result<OsmTile, GpsError> {
+        if let Some(cache) = &self.tile_disk_cache {
+            if let Err(e) = cache.insert(key, &bytes) {
+                warn!("GPS: couldn't write tile to the disk cache: {}", e);
+            }
+        }
+        let tile = decode_tile(bytes)?;
+        self.tile_cache.insert(key, tile.clone());
+        Ok(tile)
+    }
+
+    /// Uploads the current map image as a texture, replacing the previous texture if one exists.
+    /// A zero-sized image can't be turned into a texture, so in that case the previous texture is
+    /// left in place.
+    fn upload_image(&mut self, display: &Display, renderer: &mut Renderer) {
+        let uploaded = texture::upload_rgb(
+            display,
+            renderer,
+            &mut self.texture_id,
+            Cow::Borrowed(self.image.as_raw()),
+            self.width,
+            self.height,
+        );
+        self.notifications.extend(texture::report_upload(
+            uploaded,
+            &mut self.texture_failing,
+            &self.name,
+        ));
+        self.damage = MapDamage::Clean;
+    }
+
+    /// Uploads whatever changed on the map since the last upload. A changed region is written
+    /// into the existing texture, so only its pixels are sent to the GPU rather than the whole
+    /// map. Without a texture of the map's size to write into, the whole map is uploaded.
+    fn upload_damage(&mut self, display: &Display, renderer: &mut Renderer) {
+        let region = match self.damage {
+            MapDamage::Clean => return,
+            MapDamage::Region(region) => region,
+            MapDamage::Full => return self.upload_image(display, renderer),
+        };
+        let texture = self
+            .texture_id
+            .and_then(|tex_id| renderer.textures().get(tex_id).cloned())
+            .filter(|texture| texture.dimensions() == (self.width, self.height));
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return self.upload_image(display, renderer),
+        };
+        let region_frame = RawImage2d {
+            data: Cow::Owned(region_bytes(&self.image, region)),
+            width: region.width(),
+            height: region.height(),
+            format: ClientFormat::U8U8U8,
+        };
+        // The texture's rows are in the same order as the image's, so its "bottom" is the top of
+        // the image.
+        texture.write(
+            glium::Rect {
+                left: region.left,
+                bottom: region.top,
+                width: region.width(),
+                height: region.height(),
+            },
+            region_frame,
+        );
+        self.damage = MapDamage::Clean;
+    }
+}
+
+/// Downloads a single tile, returning its PNG bytes.
+fn fetch_tile(
+    client: &reqwest::Client,
+    template: &str,
+    (zoom, x_tile, y_tile): TileKey,
+) -> Result<Vec<u8>, GpsError> {
+    let mut resp = client
+        .get(&tile_url(template, zoom, x_tile, y_tile))
+        .send()?
+        .error_for_status()?;
+    let (bytes, received) = read_body(&mut resp)?;
+    debug!(
+        "GPS: tile {}/{}/{} took {} bytes for {} bytes of PNG",
+        zoom,
+        x_tile,
+        y_tile,
+        received,
+        bytes.len()
+    );
+    Ok(bytes)
+}
+
+/// Downloads the given tiles on up to `concurrency` threads sharing the client's connection pool.
+/// The results are in the same order as `keys`, whichever download finishes first.
+fn fetch_tiles(
+    client: &reqwest::Client,
+    template: &str,
+    keys: &[TileKey],
+    concurrency: usize,
+) -> Vec<TileDownload> {
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<TileDownload>>> = keys.iter().map(|_| Mutex::new(None)).collect();
+    let workers = concurrency.max(1).min(keys.len());
+    // Each worker takes the next tile nobody has started on until there are none left.
+    let scoped = crossbeam::scope(|scope| {
+        for _ in 0..workers {
+            let client = client.clone();
+            let (next, results) = (&next, &results);
+            scope.spawn(move |_| {
+                let mut ix = next.fetch_add(1, Ordering::Relaxed);
+                while let Some(key) = keys.get(ix) {
+                    let result = fetch_tile(&client, template, *key);
+                    if let Ok(mut slot) = results[ix].lock() {
+                        *slot = Some(result);
+                    }
+                    ix = next.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    if scoped.is_err() {
+        warn!("GPS: a tile download thread panicked");
+    }
+    results
+        .into_iter()
+        .map(|slot| {
+            slot.into_inner()
+                .ok()
+                .and_then(|result| result)
+                .unwrap_or(Err(GpsError::DownloadPanicked))
+        })
+        .collect()
+}
+
+/// Checks that a tile URL template contains the placeholders needed to address a single tile.
+fn check_tile_url_template(template: &str) -> Result<(), String> {
+    for placeholder in &["{z}", "{x}", "{y}"] {
+        if !template.contains(placeholder) {
+            return Err(format!(
+                "tile URL is missing the {} placeholder",
+                placeholder
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the placeholders of a tile URL template for a single tile. Neighboring tiles get
+/// different subdomains so the requests are spread across the servers.
+fn tile_url(template: &str, zoom: u32, x_tile: u32, y_tile: u32) -> String {
+    let subdomain = TILE_SUBDOMAINS[((x_tile + y_tile) as usize) % TILE_SUBDOMAINS.len()];
+    template
+        .replace("{s}", subdomain)
+        .replace("{z}", &zoom.to_string())
+        .replace("{x}", &x_tile.to_string())
+        .replace("{y}", &y_tile.to_string())
+}
+
+/// Picks the longest round distance (1, 2 or 5 times a power of ten meters) that fits within
+/// `max_pixels` at the given scale. Returns the distance in meters along with its length in
+/// pixels.
+fn scale_bar(meters_per_pixel: f32, max_pixels: f32) -> (f32, f32) {
+    let max_meters = meters_per_pixel * max_pixels;
+    let magnitude = 10f32.powf(max_meters.log10().floor());
+    let meters = [5.0, 2.0, 1.0]
+        .iter()
+        .map(|step| step * magnitude)
+        .find(|meters| *meters <= max_meters)
+        .unwrap_or(magnitude);
+    (meters, meters / meters_per_pixel)
+}
+
+/// Formats a distance for the scale bar's label.
+fn format_distance(meters: f32) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else {
+        format!("{} m", meters)
+    }
+}
+
+/// Checks that a latitude and longitude lie within the ranges a position on Earth can have.
+fn check_coords(lat: f32, lon: f32) -> Result<(), String> {
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(format!("latitude {} is outside [-90, 90]", lat));
+    }
+    if !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("longitude {} is outside [-180, 180]", lon));
+    }
+    Ok(())
+}
+
+/// Finds the coordinates of the place that best matches a place name.
+fn geocode(client: &reqwest::Client, query: &str) -> Result<(f32, f32), GpsError> {
+    let mut resp = client
+        .get(GEOCODE_URL)
+        .query(&[("q", query), ("format", "json"), ("limit", "1")])
+        .send()?
+        .error_for_status()?;
+    let (body, _) = read_body(&mut resp)?;
+    let coords = parse_geocode_response(&String::from_utf8(body)?)?
+        .ok_or_else(|| GpsError::NoPlaces(query.to_string()))?;
+    Ok(coords)
+}
+
+/// Which of the two coordinates of a position a value is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Axis {
+    Latitude,
+    Longitude,
+}
+
+impl Axis {
+    fn name(self) -> &'static str {
+        match self {
+            Axis::Latitude => "latitude",
+            Axis::Longitude => "longitude",
+        }
+    }
+
+    /// The largest value the coordinate can have in either direction.
+    fn limit(self) -> f32 {
+        match self {
+            Axis::Latitude => 90.0,
+            Axis::Longitude => 180.0,
+        }
+    }
+
+    /// The letters marking the positive and the negative hemisphere.
+    fn hemispheres(self) -> (char, char) {
+        match self {
+            Axis::Latitude => ('N', 'S'),
+            Axis::Longitude => ('E', 'W'),
+        }
+    }
+}
+
+/// Parses a latitude or longitude typed in either as decimal degrees, like `-48.8567`, or as
+/// degrees, minutes and seconds, like `48°51'24"N` or `48 51 24 N`. The hemisphere can be given
+/// by a sign or by a letter at either end, and minutes and seconds can be left out.
+fn parse_coordinate(text: &str, axis: Axis) -> Result<f32, String> {
+    let text = text.trim().to_uppercase();
+    let (positive, negative) = axis.hemispheres();
+    let mut body = text.as_str();
+    let mut sign = 1.0;
+    let letter = body
+        .chars()
+        .last()
+        .filter(char::is_ascii_alphabetic)
+        .or_else(|| body.chars().next().filter(char::is_ascii_alphabetic));
+    if let Some(letter) = letter {
+        if letter == negative {
+            sign = -1.0;
+        } else if letter != positive {
+            return Err(format!("{} isn't a hemisphere of {}", letter, axis.name()));
+        }
+        body = body.trim_matches(letter).trim();
+    }
+    if body.starts_with('-') {
+        if letter.is_some() {
+            return Err(format!("{:?} has both a sign and a hemisphere", text));
+        }
+        sign = -1.0;
+        body = &body[1..];
+    }
+    let separators = |c: char| c.is_whitespace() || "°'\"′″:".contains(c);
+    let parts = body
+        .split(separators)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse::<f32>()
+                .ok()
+                .filter(|value| *value >= 0.0)
+                .ok_or_else(|| format!("{:?} isn't a valid {}", text, axis.name()))
+        })
+        .collect::<Result<Vec<f32>, String>>()?;
+    let degrees = match parts[..] {
+        [degrees] => degrees,
+        [degrees, minutes] if minutes < 60.0 => degrees + minutes / 60.0,
+        [degrees, minutes, seconds] if minutes < 60.0 && seconds < 60.0 => {
+            degrees + minutes / 60.0 + seconds / 3600.0
+        }
+        _ => return Err(format!("{:?} isn't a valid {}", text, axis.name())),
+    };
+    let value = sign * degrees;
+    if !(-axis.limit()..=axis.limit()).contains(&value) {
+        return Err(format!(
+            "{} {} is outside [-{2}, {2}]",
+            axis.name(),
+            value,
+            axis.limit()
+        ));
+    }
+    Ok(value)
+}
+
+/// Shows a latitude or longitude in degrees, minutes and seconds to a tenth of a second, with
+/// the hemisphere as a letter.
+fn format_dms(value: f32, axis: Axis) -> String {
+    let (positive, negative) = axis.hemispheres();
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    // Working in tenths of a second keeps rounding from producing 60 seconds or minutes.
+    let tenths = (f64::from(value.abs()) * 36_000.0).round() as u64;
+    format!(
+        "{}°{:02}'{:02}.{}\"{}",
+        tenths / 36_000,
+        tenths / 600 % 60,
+        tenths / 10 % 60,
+        tenths % 10,
+        hemisphere
+    )
+}
+
+/// A single result of a Nominatim search. Nominatim sends the coordinates as strings.
+#[derive(Deserialize)]
+struct GeocodeResult {
+    lat: String,
+    lon: String,
+}
+
+/// Parses the coordinates of the first place in a Nominatim JSON response, if there is one.
+fn parse_geocode_response(body: &str) -> Result<Option<(f32, f32)>, GpsError> {
+    let results: Vec<GeocodeResult> = serde_json::from_str(body)?;
+    match results.first() {
+        Some(result) => Ok(Some((result.lat.parse()?, result.lon.parse()?))),
+        None => Ok(None),
+    }
+}
+
+/// Draws the box for searching for a place by name, returning whether a search was requested.
+fn search_controls(ui: &Ui, query: &mut ImString) -> bool {
+    let entered = ui
+        .input_text(im_str!("Place"), query)
+        .enter_returns_true(true)
+        .build();
+    ui.same_line(0.0);
+    ui.button(im_str!("Search"), [0.0, 0.0]) || entered
+}
+
+/// Draws the inputs for jumping the map to a coordinate, returning whether "Go" was pressed.
+fn goto_controls(ui: &Ui, lat: &mut ImString, lon: &mut ImString, zoom: &mut i32) -> bool {
+    ui.input_text(im_str!("Latitude"), lat).build();
+    ui.input_text(im_str!("Longitude"), lon).build();
+    ui.input_int(im_str!("Zoom Level"), zoom).build();
+    ui.button(im_str!("Go"), [0.0, 0.0])
+}
+
+/// Computes the great-circle distance in meters between two fixes using the haversine formula.
+fn distance_meters(from: &GpsData, to: &GpsData) -> f32 {
+    let from_lat = from.lat.to_radians();
+    let to_lat = to.lat.to_radians();
+    let dlat = to_lat - from_lat;
+    let dlon = (to.lon - from.lon).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + from_lat.cos() * to_lat.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Computes the average speed in meters per second along fixes given oldest first: the distance
+/// from fix to fix over the time from the first fix to the last. There's no speed with fewer than
+/// two fixes or when they were all received at the same time.
+fn average_speed(fixes: &[GpsData]) -> Option<f32> {
+    let elapsed = fixes.last()?.timestamp - fixes.first()?.timestamp;
+    if elapsed <= 0.0 {
+        return None;
+    }
+    let distance: f32 = fixes
+        .windows(2)
+        .map(|leg| distance_meters(&leg[0], &leg[1]))
+        .sum();
+    Some(distance / elapsed as f32)
+}
+
+/// Formats a speed in meters per second along with the same speed in kilometers per hour, or a
+/// placeholder for a track without a speed yet.
+fn format_speed(speed: Option<f32>) -> String {
+    match speed {
+        Some(speed) => format!("{:.1} m/s ({:.1} km/h)", speed, speed * 3.6),
+        None => "--".to_string(),
+    }
+}
+
+/// Computes the initial great-circle bearing from one fix to another, in degrees clockwise from
+/// north in the range [0, 360).
+fn bearing_degrees(from: &GpsData, to: &GpsData) -> f32 {
+    let from_lat = from.lat.to_radians();
+    let to_lat = to.lat.to_radians();
+    let dlon = (to.lon - from.lon).to_radians();
+    let y = dlon.sin() * to_lat.cos();
+    let x = from_lat.cos() * to_lat.sin() - from_lat.sin() * to_lat.cos() * dlon.cos();
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+/// Formats a heading for display along with the nearest of the eight compass points, or a
+/// placeholder for a track that hasn't moved yet.
+fn format_heading(heading: Option<f32>) -> String {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    match heading {
+        Some(heading) => {
+            let point = POINTS[((heading / 45.0).round() as usize) % POINTS.len()];
+            format!("{:.0}\u{b0} {}", heading, point)
+        }
+        None => "--".to_string(),
+    }
+}
+
+/// Picks the smallest of `GRATICULE_INTERVALS` that puts lines at least `min_spacing` pixels
+/// apart at the given scale, or the largest one if none does.
+fn graticule_interval(degrees_per_pixel: f32, min_spacing: f32) -> f32 {
+    GRATICULE_INTERVALS
+        .iter()
+        .cloned()
+        .find(|interval| interval / degrees_per_pixel >= min_spacing)
+        .unwrap_or(GRATICULE_INTERVALS[GRATICULE_INTERVALS.len() - 1])
+}
+
+/// Formats a latitude or longitude for a graticule label in degrees, minutes and seconds, leaving
+/// out the parts the interval doesn't need.
+fn format_graticule(value: f32, interval: f32, positive: char, negative: char) -> String {
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    let total_seconds = (value.abs() * 3600.0).round() as u32;
+    let degrees = total_seconds / 3600;
+    let minutes = total_seconds / 60 % 60;
+    let seconds = total_seconds % 60;
+    if interval >= 1.0 {
+        format!("{}\u{b0}{}", degrees, hemisphere)
+    } else if interval >= 1.0 / 60.0 {
+        format!("{}\u{b0}{:02}'{}", degrees, minutes, hemisphere)
+    } else {
+        format!(
+            "{}\u{b0}{:02}'{:02}\"{}",
+            degrees, minutes, seconds, hemisphere
+        )
+    }
+}
+
+/// Mixes `color` into a pixel of the map at `GRATICULE_ALPHA`.
+fn blend_pixel(pixel: &mut Rgb<u8>, color: Rgb<u8>) {
+    for channel in 0..3 {
+        let blended = f32::from(pixel[channel]) * (1.0 - GRATICULE_ALPHA)
+            + f32::from(color[channel]) * GRATICULE_ALPHA;
+        pixel[channel] = blended.round() as u8;
+    }
+}
+
+/// Whether a fix at `pixel` is more than `threshold` pixels from the `center` of the map, so that
+/// following it should re-center the map. Small movements around the center don't, which keeps a
+/// jittery fix from rebuilding the map on every update.
+fn needs_recenter(center: (i32, i32), pixel: (i32, i32), threshold: f32) -> bool {
+    let dx = (pixel.0 - center.0) as f32;
+    let dy = (pixel.1 - center.1) as f32;
+    dx.hypot(dy) > threshold
+}
+
+/// Draws a dot for each point of a track.
+fn draw_points(image: &mut RgbImage, points: &[(i32, i32)], radius: i32, color: Rgb<u8>) {
+    for point in points {
+        draw_filled_circle_mut(image, *point, radius, color);
+    }
+}
+
+/// Draws an anti-aliased line connecting each point of a track to the next one. Segments that
+/// leave the map are clipped to it.
+fn draw_lines(image: &mut RgbImage, points: &[(i32, i32)], color: Rgb<u8>) {
+    for segment in points.windows(2) {
+        draw_antialiased_line_segment_mut(image, segment[0], segment[1], color, interpolate);
+    }
+}
+
+/// Copies a region from one image to another of the same size.
+fn copy_region(from: &RgbImage, to: &mut RgbImage, region: PixelRegion) {
+    let row_bytes = from.width() as usize * 3;
+    let (start, end) = (region.left as usize * 3, region.right as usize * 3);
+    let to: &mut [u8] = to;
+    for y in region.top as usize..region.bottom as usize {
+        let row = y * row_bytes;
+        to[row + start..row + end].copy_from_slice(&from.as_raw()[row + start..row + end]);
+    }
+}
+
+/// Copies the pixels of a region out of an image, row by row.
+fn region_bytes(image: &RgbImage, region: PixelRegion) -> Vec<u8> {
+    let row_bytes = image.width() as usize * 3;
+    let (start, end) = (region.left as usize * 3, region.right as usize * 3);
+    let mut bytes = Vec::with_capacity(region.width() as usize * region.height() as usize * 3);
+    for y in region.top as usize..region.bottom as usize {
+        let row = y * row_bytes;
+        bytes.extend_from_slice(&image.as_raw()[row + start..row + end]);
+    }
+    bytes
+}
+
+/// Shows a combo box choosing how the tracks are drawn, returning whether the choice changed.
+fn track_style_combo(ui: &Ui, style: &mut TrackStyle) -> bool {
+    let labels = [im_str!("Dots"), im_str!("Lines"), im_str!("Lines and Dots")];
+    let mut style_item = TRACK_STYLES.iter().position(|s| s == style).unwrap_or(0);
+    let changed = imgui::ComboBox::new(im_str!("Track Style")).build_simple_string(
+        ui,
+        &mut style_item,
+        &labels,
+    );
+    if changed {
+        *style = TRACK_STYLES[style_item];
+    }
+    changed
+}
+
+/// Adds a visit at the given pixel to a density grid of the given size, spread over
+/// `HEATMAP_RADIUS` and weighted towards the center. Visits off the grid are skipped.
+fn add_visit(density: &mut [f32], width: u32, height: u32, (x, y): (i32, i32)) {
+    for dy in -HEATMAP_RADIUS..=HEATMAP_RADIUS {
+        for dx in -HEATMAP_RADIUS..=HEATMAP_RADIUS {
+            let (px, py) = (x + dx, y + dy);
+            if px < 0 || py < 0 || px as u32 >= width || py as u32 >= height {
+                continue;
+            }
+            let distance = ((dx * dx + dy * dy) as f32).sqrt();
+            let weight = 1.0 - distance / (HEATMAP_RADIUS + 1) as f32;
+            if weight > 0.0 {
+                density[(py as u32 * width + px as u32) as usize] += weight;
+            }
+        }
+    }
+}
+
+/// Blends a density grid over the map, graded from blue for the least visited spots to red for
+/// the most visited. The density is normalized on a log scale so that a single long stop doesn't
+/// wash out every other visit.
+fn draw_heatmap(image: &mut RgbImage, density: &[f32]) {
+    let max = density.iter().cloned().fold(0.0, f32::max);
+    if max <= 0.0 || density.len() != (image.width() * image.height()) as usize {
+        return;
+    }
+    let scale = max.ln_1p();
+    for (pixel, value) in image.pixels_mut().zip(density) {
+        if *value <= 0.0 {
+            continue;
+        }
+        let heat = value.ln_1p() / scale;
+        let color = heat_color(heat);
+        let alpha = HEATMAP_MIN_ALPHA + (HEATMAP_MAX_ALPHA - HEATMAP_MIN_ALPHA) * heat;
+        for channel in 0..3 {
+            let blended = pixel[channel] as f32 * (1.0 - alpha) + color[channel] * 255.0 * alpha;
+            pixel[channel] = blended.round() as u8;
+        }
+    }
+}
+
+/// Maps a heat from 0 to 1 onto a blue, green, yellow, red gradient.
+fn heat_color(heat: f32) -> [f32; 3] {
+    const STOPS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ];
+    let position = heat.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    [
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+    ]
+}
+
+/// Converts a color edited in imgui, with each component from 0 to 1, to an image color.
+fn rgb_from_floats(color: [f32; 3]) -> Rgb<u8> {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgb([channel(color[0]), channel(color[1]), channel(color[2])])
+}
+
+/// Returns the color a track is drawn in. The default track uses the configured point color.
+fn track_color(track: Option<TrackId>, default_color: [f32; 3]) -> [f32; 3] {
+    match track {
+        Some(id) => TRACK_COLORS[id as usize % TRACK_COLORS.len()],
+        None => default_color,
+    }
+}
+
+/// Returns the name a track is listed under in the legend and in exported GPX files.
+fn track_name(track: Option<TrackId>) -> String {
+    match track {
+        Some(id) => format!("Track {}", id),
+        None => "Default".to_string(),
+    }
+}
+
+/// Draws a hollow crosshair centered on the given pixel. The middle is left open so that whatever
+/// lies under it, like a track point, stays visible.
+fn draw_crosshair(image: &mut RgbImage, (x, y): (i32, i32), color: Rgb<u8>) {
+    let (x, y) = (x as f32, y as f32);
+    for &(dx, dy) in &[(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+        draw_line_segment_mut(
+            image,
+            (x + dx * 4.0, y + dy * 4.0),
+            (x + dx * 12.0, y + dy * 12.0),
+            color,
+        );
+    }
+    draw_hollow_circle_mut(image, (x as i32, y as i32), 8, color);
+}
+
+/// Draws a small flag planted at the given pixel.
+fn draw_flag(image: &mut RgbImage, (x, y): (i32, i32), color: Rgb<u8>) {
+    let pole_color = Rgb([0u8, 0u8, 0u8]);
+    draw_line_segment_mut(
+        image,
+        (x as f32, y as f32),
+        (x as f32, (y - 14) as f32),
+        pole_color,
+    );
+    draw_filled_rect_mut(image, Rect::at(x + 1, y - 14).of_size(9, 6), color);
+}
+
+/// Draws a filled arrowhead centered on the given pixel and pointing along `heading`, in degrees
+/// clockwise from north. The map's projection keeps angles, so north is straight up.
+fn draw_arrow(image: &mut RgbImage, (x, y): (i32, i32), heading: f32, color: Rgb<u8>) {
+    let heading = heading.to_radians();
+    let (dx, dy) = (heading.sin(), -heading.cos());
+    let (x, y) = (x as f32, y as f32);
+    let tip = (x + dx * 9.0, y + dy * 9.0);
+    let left = (x - dx * 5.0 - dy * 6.0, y - dy * 5.0 + dx * 6.0);
+    let right = (x - dx * 5.0 + dy * 6.0, y - dy * 5.0 - dx * 6.0);
+
+    // Fill the arrowhead by sweeping lines from the tip across its base.
+    let steps = 16;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        let base = (
+            left.0 + (right.0 - left.0) * t,
+            left.1 + (right.1 - left.1) * t,
+        );
+        draw_line_segment_mut(image, tip, base, color);
+    }
+}
+
+/// Darkens every pixel of the image by the given factor, where 0.0 leaves the image untouched and
+/// 1.0 turns it black. This is used to fade the map tiles so the drawn track stands out.
+fn dim_image(image: &mut RgbImage, dim: f32) {
+    let scale = 1.0 - dim.clamp(0.0, 1.0);
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut() {
+            *channel = (f32::from(*channel) * scale) as u8;
+        }
+    }
+}
+
+/// Blends every pixel of the image towards `background`, where an opacity of 1.0 leaves the
+/// image untouched and 0.0 replaces it with the background entirely.
+fn fade_to_background(image: &mut RgbImage, opacity: f32, background: Rgb<u8>) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    if opacity >= 1.0 {
+        return;
+    }
+    for pixel in image.pixels_mut() {
+        for (channel, background) in pixel.0.iter_mut().zip(background.0.iter()) {
+            let faded = f32::from(*channel) * opacity + f32::from(*background) * (1.0 - opacity);
+            *channel = faded.round() as u8;
+        }
+    }
+}
+
+/// Checks whether two fixes are within `epsilon` degrees of each other in both latitude and
+/// longitude.
+fn same_position(a: &GpsData, b: &GpsData, epsilon: f32) -> bool {
+    (a.lat - b.lat).abs() <= epsilon && (a.lon - b.lon).abs() <= epsilon
+}
+
+/// Serializes the tracks as a GPX 1.1 document with one named track, made of a single segment,
+/// per track.
+fn tracks_to_gpx(tracks: &BTreeMap<Option<TrackId>, Track>) -> String {
+    let mut gpx = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<gpx version=\"1.1\" creator=\"sensorview\" ",
+        "xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    ));
+    for (id, track) in tracks {
+        gpx.push_str(&format!(
+            "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+            track_name(*id)
+        ));
+        for fix in &track.fixes {
+            gpx.push_str(&format!(
+                "      <trkpt lat=\"{}\" lon=\"{}\"></trkpt>\n",
+                fix.lat, fix.lon
+            ));
+        }
+        gpx.push_str("    </trkseg>\n  </trk>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+/// Writes a fix as a single line of JSON.
+fn write_json_line<W: Write>(writer: &mut W, fix: &GpsData) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, fix).map_err(io::Error::other)?;
+    writer.write_all(b"\n")
+}
+
+/// Wraps RGB bytes in an image, failing rather than panicking if there aren't exactly enough
+/// bytes for the given dimensions.
+fn map_image_from_raw(width: u32, height: u32, bytes: Vec<u8>) -> Result<RgbImage, GpsError> {
+    let expected = width as usize * height as usize * 3;
+    if bytes.len() != expected {
+        return Err(GpsError::MapSizeMismatch {
+            width,
+            height,
+            expected,
+            actual: bytes.len(),
+        });
+    }
+    RgbImage::from_raw(width, height, bytes).ok_or(GpsError::MapTooLarge)
+}
+
+/// Builds the HTTP client used for every tile request made by a GPS window. The client is kept for
+/// the life of the window so connections to the tile server are reused. It asks for compressed
+/// responses but leaves decoding them to `read_body`, so the bytes actually received can be
+/// logged.
+fn tile_client() -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(TILE_USER_AGENT));
+    headers.insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static(TILE_ACCEPT_ENCODING),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .gzip(false)
+        .timeout(TILE_REQUEST_TIMEOUT)
+        .build()
+        .expect("Couldn't create the HTTP client")
+}
+
+/// Reads the whole body of a response and undoes its content encoding, returning the decoded
+/// body along with the number of bytes received.
+fn read_body(resp: &mut reqwest::Response) -> Result<(Vec<u8>, usize), GpsError> {
+    let encoding = resp
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let mut body = Vec::new();
+    resp.copy_to(&mut body)?;
+    let received = body.len();
+    Ok((decode_body(encoding.as_deref(), body)?, received))
+}
+
+/// Decodes a body sent with the given content encoding. HTTP's deflate is meant to be zlib
+/// wrapped, but some servers send bare deflate streams, so those are accepted too.
+fn decode_body(encoding: Option<&str>, body: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    match encoding {
+        None | Some("identity") => return Ok(body),
+        Some("gzip") | Some("x-gzip") => {
+            GzDecoder::new(&body[..]).read_to_end(&mut decoded)?;
+        }
+        Some("deflate") => {
+            if ZlibDecoder::new(&body[..])
+                .read_to_end(&mut decoded)
+                .is_err()
+            {
+                decoded.clear();
+                DeflateDecoder::new(&body[..]).read_to_end(&mut decoded)?;
+            }
+        }
+        Some(other) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported content encoding {:?}", other),
+            ))
+        }
+    }
+    Ok(decoded)
+}
+
+/// Decodes the PNG bytes of a map tile into RGB pixels. Tile servers don't all serve RGB tiles,
+/// so tiles with an alpha channel have it dropped.
+fn decode_tile(bytes: Vec<u8>) -> Result<OsmTile, GpsError> {
+    let decoder = PngDecoder::new(Cursor::new(bytes))?;
+    let (width, height) = decoder.dimensions();
+    let pixel_format = match decoder.color_type() {
+        ColorType::Rgb8 => PixelFormat::Rgb,
+        ColorType::Rgba8 => PixelFormat::Rgba,
+        other => return Err(GpsError::UnsupportedColorType(other)),
+    };
+    let mut data: Vec<u8> = vec![0; decoder.total_bytes() as usize];
+    decoder.read_image(&mut data)?;
+    Ok(OsmTile {
+        data: camera::to_rgb(data, width, height, pixel_format)?,
+        width,
+        height,
+    })
+}
+
+impl Renderable for GpsWindow {
+    /// Renders the data received from the gps sensor. This currently assumes RGB data format.
+    fn render(
+        &mut self,
+        ui: &Ui,
+        display: &Display,
+        renderer: &mut Renderer,
+        visible: bool,
+        all_paused: bool,
+    ) -> bool {
+        let mut opened = true;
+        self.throughput.sample(&self.stats);
+        let paused = self.paused || all_paused;
+
+        // If the world map couldn't be loaded, it's retried when the next fix arrives instead
+        // of on every frame.
+        if self.image.is_empty()
+            && self.tile_error.is_none()
+            && self.try_query_osm(self.query_lat, self.query_lon)
+        {
+            self.upload_image(display, renderer);
+        }
+
+        // The map only gets uploaded when it changes, so if the texture was reset the current
+        // image has to be uploaded again.
+        if self.texture_id.is_none() || self.poll_search() {
+            self.upload_image(display, renderer);
+        }
+
+        // A batching sender delivers several fixes at once, so take every fix that's waiting
+        // rather than one per frame, and redraw the track once for all of them. Glitched fixes
+        // are dropped rather than drawn, otherwise a single bad fix can drag the map across the
+        // world.
+        let received: Vec<GpsData> = self.receiver.try_iter().collect();
+        let fixes: Vec<GpsData> = received
+            .into_iter()
+            .filter(|fix| match self.validate_fix(fix) {
+                Ok(()) => true,
+                Err(reason) => {
+                    warn!("GPS: rejecting fix ({}, {}): {}", fix.lat, fix.lon, reason);
+                    false
+                }
+            })
+            .collect();
+        let mut latest = None;
+        let (mut trimmed, mut added) = (false, 0);
+        for gps_data in fixes {
+            self.log_fix(&gps_data);
+            // Until the first point comes in we only show the world map. Once it arrives, center
+            // the map on it. If loading the tiles failed previously, each new fix retries it.
+            self.zoom_to_first_fix();
+            if (!self.has_fix || self.tile_error.is_some())
+                && self.try_query_osm(gps_data.lat, gps_data.lon)
+            {
+                self.has_fix = true;
+            }
+            // Points are stored relative to the map's corner, so they can't be placed until a map
+            // around the first fix has loaded.
+            if !self.has_fix {
+                continue;
+            }
+            self.tracks
+                .entry(gps_data.track)
+                .or_default()
+                .record_recent(gps_data.clone(), self.speed_window);
+
+            // A stationary object keeps reporting the same position, which would pile up
+            // identical points and redraw the map for nothing. The fix still counts towards
+            // keeping the track live.
+            let stationary = self
+                .last_fix_of(gps_data.track)
+                .is_some_and(|last| same_position(last, &gps_data, self.stationary_epsilon));
+            if stationary {
+                self.last_fix = Some(Instant::now());
+                continue;
+            }
+
+            let pixel_coords = self.coords_to_pixel(&gps_data);
+            trimmed |= self.tracks.entry(gps_data.track).or_default().push(
+                gps_data.clone(),
+                pixel_coords,
+                self.max_points,
+                self.merge_radius,
+            );
+            added += 1;
+            latest = Some((gps_data, pixel_coords));
+        }
+        if let Some((gps_data, pixel_coords)) = latest {
+            self.last_fix = Some(Instant::now());
+            let was_live = mem::replace(&mut self.track_live, true);
+
+            // Once the track leaves the fetched tiles, or strays from the center while following
+            // it, center the map on the latest fix. Querying the map redraws the whole track
+            // against the new tiles; if that fails the track is drawn on the old tiles until the
+            // next fix retries. Otherwise only the new fix is drawn, unless a batch added several
+            // fixes, or dropping the oldest fixes or the tracks coming back to life changed the
+            // map elsewhere too.
+            let recenter = !self.in_bounds(pixel_coords)
+                || (self.follow
+                    && needs_recenter(
+                        self.center_pixel(),
+                        pixel_coords,
+                        self.tile_size() * FOLLOW_RECENTER_TILES,
+                    ));
+            let region = if recenter && self.try_query_osm(gps_data.lat, gps_data.lon) {
+                None
+            } else if trimmed || added > 1 || !was_live {
+                self.redraw_track();
+                None
             } else {
-                self.texture_id = Some(renderer.textures().insert(Rc::new(gl_texture)));
+                let region = self.draw_new_point(gps_data.track);
+                if region.is_none() {
+                    self.redraw_track();
+                }
+                region
+            };
+            self.damage = match region {
+                Some(region) => self.damage.add(region),
+                None => MapDamage::Full,
+            };
+        }
+
+        // Once fixes stop arriving, swap the current-position arrow for an end-of-track flag.
+        let live = self
+            .last_fix
+            .is_some_and(|last_fix| last_fix.elapsed() < TRACK_LIVE_TIMEOUT);
+        if live != self.track_live {
+            self.track_live = live;
+            if self.show_markers {
+                self.redraw_track();
+                self.damage = MapDamage::Full;
             }
         }
+        // Unpausing shows the fixes that arrived in the meantime.
+        if !paused {
+            self.upload_damage(display, renderer);
+        }
+
+        if !visible {
+            return true;
+        }
 
         // We call this each iteration of the GpsWindow, so we need to make sure we draw the
         // window even if we didn't receive camera data on this iteration. However, we currently
         // do not draw a window unless we've received our first sample from the camera.
+        let tile_error = &self.tile_error;
+        let goto_error = &self.goto_error;
+        let search_error = &self.search_error;
+        let searching = self.pending_search.is_some();
+        let show_tile_error = || {
+            if let Some(e) = tile_error {
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], format!("Failed to load tiles: {}", e));
+            }
+            if let Some(e) = goto_error {
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], format!("Can't go there: {}", e));
+            }
+            if let Some(e) = search_error {
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], format!("Search failed: {}", e));
+            }
+            if searching {
+                ui.text(im_str!("Searching..."));
+            }
+        };
+        let (scale_meters, _) = scale_bar(self.meters_per_pixel(), SCALE_BAR_MAX_PIXELS);
+        let center = format!(
+            "Center: {}, {}",
+            self.format_coordinate(self.query_lat, Axis::Latitude),
+            self.format_coordinate(self.query_lon, Axis::Longitude)
+        );
+        let cursor = self.cursor_coords.as_ref().map(|coords| {
+            format!(
+                "Cursor: {}, {}",
+                self.format_coordinate(coords.lat, Axis::Latitude),
+                self.format_coordinate(coords.lon, Axis::Longitude)
+            )
+        });
+        let stats = &self.stats;
+        let throughput = &self.throughput;
+        let stale_after = self.stale_after;
+        let mut go = false;
+        let mut search = false;
+        let search_query = &mut self.search_query;
+        let goto_lat = &mut self.goto_lat;
+        let goto_lon = &mut self.goto_lon;
+        let goto_zoom = &mut self.goto_zoom;
+        let title = window::window_title(&self.name, self.window_id);
+        let restartable = &mut self.restartable;
+        let spec = self.spec.as_ref();
+        let mut restarted = None;
         if let Some(tex_id) = self.texture_id {
-            let dims = [self.width as f32, self.height as f32];
-            Window::new(im_str!("GPS"))
+            let scale_factor = window::scale_factor(ui);
+            let dims = window::logical_size([self.width as f32, self.height as f32], scale_factor);
+            let zoom = self.zoom;
+            let has_fix = self.has_fix;
+            let mut new_zoom = None;
+            let mut export = false;
+            let mut clear = false;
+            let mut save = false;
+            let snapshot_status = &self.snapshot_status;
+            let paused = &mut self.paused;
+            let follow = &mut self.follow;
+            let dms_readouts = &mut self.dms_readouts;
+            let gpx_path = &mut self.gpx_path;
+            let gpx_status = &self.gpx_status;
+            let jsonl_path = &mut self.jsonl_path;
+            let jsonl_status = &self.jsonl_status;
+            let logging = self.jsonl.is_some();
+            let mut toggle_jsonl = false;
+            let point_radius = &mut self.point_radius;
+            let point_color = &mut self.point_color;
+            let heatmap = &mut self.heatmap;
+            let graticule = &mut self.show_graticule;
+            let track_style = &mut self.track_style;
+            let map_opacity = &mut self.map_opacity;
+            let track_only = &mut self.track_only;
+            let shown_at = &mut self.shown_at;
+            let mut restyled = false;
+            let mut hovered_pixel = None;
+            let speed_window = &mut self.speed_window;
+            let merge_radius = &mut self.merge_radius;
+            let mut remerged = false;
+            // The legend only helps once there's more than one track to tell apart. It carries
+            // each track's heading and speed, while a lone track's get a line of their own.
+            let heading = match self.tracks.values().next() {
+                Some(track) if self.tracks.len() == 1 => Some(format!(
+                    "Heading: {}  Speed: {}",
+                    format_heading(track.heading()),
+                    format_speed(track.speed())
+                )),
+                _ => None,
+            };
+            let legend: Vec<(String, [f32; 3])> = if self.tracks.len() > 1 {
+                self.tracks
+                    .iter()
+                    .map(|(id, track)| {
+                        let label = format!(
+                            "{} ({} fixes, heading {}, speed {})",
+                            track_name(*id),
+                            track.fixes.len(),
+                            format_heading(track.heading()),
+                            format_speed(track.speed())
+                        );
+                        (label, track_color(*id, *point_color))
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Window::new(&title)
+                .opened(&mut opened)
                 .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+                .place(self.placement.take())
                 .build(ui, || {
-                    Image::new(tex_id, dims).build(&ui);
+                    *shown_at = Some(window::current_placement(ui));
+                    let origin = ui.cursor_screen_pos();
+                    Image::new(tex_id, dims).build(ui);
+                    // The mouse is in logical pixels, while the map is drawn in physical ones.
+                    if ui.is_item_hovered() {
+                        let mouse = ui.io().mouse_pos;
+                        hovered_pixel = Some((
+                            (mouse[0] - origin[0]) * scale_factor,
+                            (mouse[1] - origin[1]) * scale_factor,
+                        ));
+                    }
+                    diagnostics::connection_status(ui, stats, stale_after);
+                    diagnostics::dropped_frames(ui, stats);
+                    diagnostics::throughput_graph(ui, throughput);
+                    ui.text(format!("Scale bar: {}", format_distance(scale_meters)));
+                    ui.same_line(0.0);
+                    ui.text(&center);
+                    if let Some(cursor) = &cursor {
+                        ui.same_line(0.0);
+                        ui.text(cursor);
+                    }
+                    if let Some(heading) = &heading {
+                        ui.text(heading);
+                    }
+                    for (label, color) in &legend {
+                        ui.text_colored([color[0], color[1], color[2], 1.0], label);
+                    }
+                    show_tile_error();
+                    // Zooming only makes sense once the map is centered on the track.
+                    if has_fix {
+                        if ui.button(im_str!("-"), [0.0, 0.0]) && zoom > MIN_GRID_ZOOM {
+                            new_zoom = Some(zoom - 1);
+                        }
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("+"), [0.0, 0.0]) && zoom < MAX_ZOOM {
+                            new_zoom = Some(zoom + 1);
+                        }
+                        ui.same_line(0.0);
+                        ui.text(format!("Zoom {}", zoom));
+                    }
+                    ui.checkbox(im_str!("Pause"), paused);
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("Follow"), follow);
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("Degrees, Minutes, Seconds"), dms_readouts);
+                    let mut window = *speed_window as i32;
+                    if Slider::new(
+                        im_str!("Speed Smoothing (fixes)"),
+                        2..=MAX_SPEED_WINDOW as i32,
+                    )
+                    .build(ui, &mut window)
+                    {
+                        *speed_window = window as usize;
+                    }
+                    restyled |= track_style_combo(ui, track_style);
+                    restyled |= Slider::new(im_str!("Point Size"), 1..=10).build(ui, point_radius);
+                    restyled |= ColorEdit::new(im_str!("Point Color"), point_color).build(ui);
+                    remerged =
+                        Slider::new(im_str!("Merge Fixes Within (px)"), 0..=MAX_MERGE_RADIUS)
+                            .build(ui, merge_radius);
+                    restyled |= ui.checkbox(im_str!("Heatmap"), heatmap);
+                    ui.same_line(0.0);
+                    restyled |= ui.checkbox(im_str!("Graticule"), graticule);
+                    restyled |= ui.checkbox(im_str!("Track Only"), track_only);
+                    if !*track_only {
+                        restyled |=
+                            Slider::new(im_str!("Map Opacity"), 0.0..=1.0).build(ui, map_opacity);
+                    }
+                    clear = ui.button(im_str!("Clear Track"), [0.0, 0.0]);
+                    ui.input_text(im_str!("GPX File"), gpx_path).build();
+                    ui.same_line(0.0);
+                    export = ui.button(im_str!("Export GPX"), [0.0, 0.0]);
+                    if let Some(status) = gpx_status {
+                        ui.text(status);
+                    }
+                    ui.input_text(im_str!("JSON Lines File"), jsonl_path)
+                        .build();
+                    ui.same_line(0.0);
+                    let jsonl_label = if logging {
+                        im_str!("Stop Logging")
+                    } else {
+                        im_str!("Log Fixes")
+                    };
+                    toggle_jsonl = ui.button(jsonl_label, [0.0, 0.0]);
+                    if let Some(status) = jsonl_status {
+                        ui.text(status);
+                    }
+                    save = ui.button(im_str!("Save Image"), [0.0, 0.0]);
+                    if let Some(status) = snapshot_status {
+                        ui.same_line(0.0);
+                        ui.text(status);
+                    }
+                    search = search_controls(ui, search_query);
+                    go = goto_controls(ui, goto_lat, goto_lon, goto_zoom);
+                    connection::connection_help(ui, spec);
+                    if let Some(sensor) = restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
+                });
+            // The cursor's coordinates are shown on the next frame, which isn't noticeable.
+            self.cursor_coords = hovered_pixel.map(|pixel| self.pixel_to_coords(pixel));
+            if let Some(zoom) = new_zoom {
+                self.set_zoom(zoom);
+                self.upload_image(display, renderer);
+            } else if clear {
+                self.clear_tracks();
+                self.upload_image(display, renderer);
+            } else if restyled || remerged {
+                if remerged {
+                    for track in self.tracks.values_mut() {
+                        track.remerge(self.merge_radius);
+                    }
+                }
+                self.redraw_track();
+                self.upload_image(display, renderer);
+            }
+            if export {
+                let path = self.gpx_path.to_string();
+                self.gpx_status = Some(match self.export_gpx(&path) {
+                    Ok(()) => format!("Exported {} points to {}", self.fix_count(), path),
+                    Err(e) => format!("Couldn't export {}: {}", path, e),
                 });
+            }
+            if toggle_jsonl {
+                self.toggle_jsonl();
+            }
+            if save {
+                self.save_image();
+            }
         } else {
-            Window::new(im_str!("GPS")).build(ui, || {
-                ui.text(im_str!("Waiting for GPS data..."));
-            });
+            let shown_at = &mut self.shown_at;
+            Window::new(&title)
+                .opened(&mut opened)
+                .place(self.placement.take())
+                .build(ui, || {
+                    *shown_at = Some(window::current_placement(ui));
+                    ui.text(im_str!("Waiting for GPS data..."));
+                    diagnostics::connection_status(ui, stats, stale_after);
+                    diagnostics::dropped_frames(ui, stats);
+                    diagnostics::throughput_graph(ui, throughput);
+                    show_tile_error();
+                    search = search_controls(ui, search_query);
+                    go = goto_controls(ui, goto_lat, goto_lon, goto_zoom);
+                    connection::connection_help(ui, spec);
+                    if let Some(sensor) = restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
+                });
+        }
+        if search && self.search() {
+            self.upload_image(display, renderer);
+        } else if go {
+            self.go_to_entered();
+            self.upload_image(display, renderer);
+        }
+        if let Some(address) = restarted {
+            if let Some(spec) = &mut self.spec {
+                spec.set_address(address);
+            }
+        }
+        opened
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reset_texture(&mut self) {
+        self.texture_id = None;
+    }
+
+    fn has_pending_data(&self) -> bool {
+        !self.receiver.is_empty()
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        self.spec.clone()
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        Some(self.stats.clone())
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        mem::take(&mut self.notifications)
+    }
+
+    fn capture(&self, path: &Path) -> Option<ImageResult<()>> {
+        if self.image.width() == 0 {
+            return None;
         }
+        Some(self.image.save(path))
+    }
+
+    fn set_placement(&mut self, placement: Placement) {
+        self.placement = Some(placement);
+    }
+
+    fn take_join_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        self.restartable
+            .as_mut()
+            .map_or_else(Vec::new, RestartableSensor::take_handles)
+    }
+
+    fn current_placement(&self) -> Option<Placement> {
+        self.shown_at
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
     }
 }
 
 pub struct GpsConfig {
+    name: ImString,
     gps_port: ImString,
+    /// Why the last attempt to create the sensor failed, if it did.
+    address_error: Option<String>,
+    format_list: Vec<ImString>,
+    format_item: usize,
+    tile_dim: f32,
+    tile_url_template: ImString,
+    grid_size: i32,
+    initial_zoom: i32,
+    tile_fetch_concurrency: i32,
+    tile_cache_mib: i32,
+    disk_cache: bool,
+    tile_max_age_days: i32,
+    capture_raw: bool,
+    capture_path: ImString,
+    buffer_kib: i32,
+    read_timeout_secs: f32,
+    max_connections: i32,
+    stale_after_secs: f32,
+    channel: ChannelConfig,
+    endianness: Endianness,
+    transport: Transport,
+    show_markers: bool,
+    max_jump_meters: f32,
+    max_points: i32,
+    tls: ServerTlsConfig,
 }
 
 impl GpsConfig {
     pub fn new() -> Self {
         let mut gps_port = ImString::new("8003");
-        gps_port.reserve_exact(10);
-        Self { gps_port }
+        gps_port.reserve_exact(256);
+        let format_list: Vec<ImString> = GpsFormat::iter()
+            .map(|format| {
+                let format_str: &str = format.as_ref();
+                ImString::new(format_str)
+            })
+            .collect();
+        let mut capture_path = ImString::new("gps_capture.bin");
+        capture_path.reserve_exact(256);
+        let mut tile_url_template = ImString::new(DEFAULT_TILE_URL);
+        tile_url_template.reserve_exact(256);
+        let mut name = ImString::new("GPS");
+        name.reserve_exact(64);
+        Self {
+            name,
+            gps_port,
+            address_error: None,
+            format_list,
+            format_item: 0,
+            tile_dim: 0.0,
+            tile_url_template,
+            grid_size: DEFAULT_GRID_SIZE as i32,
+            initial_zoom: DEFAULT_INITIAL_ZOOM as i32,
+            tile_fetch_concurrency: DEFAULT_TILE_FETCH_CONCURRENCY as i32,
+            tile_cache_mib: DEFAULT_TILE_CACHE_MIB,
+            disk_cache: true,
+            tile_max_age_days: DEFAULT_TILE_MAX_AGE_DAYS,
+            capture_raw: false,
+            capture_path,
+            buffer_kib: (DEFAULT_BUFFER_SIZE / 1024) as i32,
+            read_timeout_secs: 0.0,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS as i32,
+            stale_after_secs: diagnostics::DEFAULT_STALE_AFTER.as_secs_f32(),
+            channel: ChannelConfig::new(DEFAULT_CHANNEL_CAPACITY, DEFAULT_OVERFLOW_POLICY),
+            endianness: Endianness::Little,
+            transport: Transport::Tcp,
+            show_markers: true,
+            max_jump_meters: 0.0,
+            max_points: 0,
+            tls: ServerTlsConfig::new(),
+        }
     }
 }
 
 impl Modal for GpsConfig {
+    fn sensor_name(&self) -> &'static ImStr {
+        im_str!("GPS")
+    }
+
+    fn popup_name(&self) -> &'static ImStr {
+        im_str!("GPS Configuration")
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        self.gps_port = ImString::new(settings.gps_port.to_string());
+        self.gps_port.reserve_exact(256);
+        self.channel.capacity = settings.gps_channel_capacity as i32;
+    }
+
     fn render_modal(
         &mut self,
         ui: &Ui,
         join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
         sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        _shutdown: &Arc<AtomicBool>,
     ) {
-        ui.popup_modal(im_str!("GPS Configuration"))
+        ui.popup_modal(self.popup_name())
             .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
             .build(|| {
-                ui.input_text(im_str!("Listen Port"), &mut self.gps_port)
+                ui.input_text(im_str!("Window Name"), &mut self.name)
+                    .build();
+                ui.input_text(im_str!("Listen Port or Address"), &mut self.gps_port)
+                    .build();
+                if let Some(e) = &self.address_error {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], e);
+                }
+                let format_slices: Vec<&ImString> = self.format_list.iter().collect();
+                imgui::ComboBox::new(im_str!("Wire Format")).build_simple_string(
+                    ui,
+                    &mut self.format_item,
+                    &format_slices,
+                );
+                Slider::new(im_str!("Tile Dimming"), 0.0..=1.0).build(ui, &mut self.tile_dim);
+                ui.input_text(im_str!("Tile URL"), &mut self.tile_url_template)
+                    .build();
+                ui.input_int(im_str!("Tile Grid Size"), &mut self.grid_size)
+                    .build();
+                Slider::new(im_str!("Initial Zoom"), 0..=MAX_ZOOM as i32)
+                    .build(ui, &mut self.initial_zoom);
+                Slider::new(
+                    im_str!("Parallel Tile Downloads"),
+                    1..=MAX_TILE_FETCH_CONCURRENCY as i32,
+                )
+                .build(ui, &mut self.tile_fetch_concurrency);
+                ui.input_int(im_str!("Tile Cache (MiB)"), &mut self.tile_cache_mib)
+                    .build();
+                ui.checkbox(im_str!("Cache Tiles On Disk"), &mut self.disk_cache);
+                if self.disk_cache {
+                    ui.input_int(im_str!("Tile Max Age (days)"), &mut self.tile_max_age_days)
+                        .build();
+                }
+                ui.checkbox(im_str!("Draw Track Markers"), &mut self.show_markers);
+                ui.input_float(im_str!("Max Jump (m, 0 = off)"), &mut self.max_jump_meters)
+                    .build();
+                ui.input_int(im_str!("Max Points (0 = all)"), &mut self.max_points)
+                    .build();
+                ui.input_int(im_str!("Read Buffer (KiB)"), &mut self.buffer_kib)
+                    .build();
+                ui.input_float(
+                    im_str!("Read Timeout (s, 0 = off)"),
+                    &mut self.read_timeout_secs,
+                )
+                .build();
+                ui.input_int(im_str!("Max Connections"), &mut self.max_connections)
                     .build();
+                ui.input_float(im_str!("Stale After (s)"), &mut self.stale_after_secs)
+                    .build();
+                self.channel.render(ui);
+                byte_order_combo(ui, &mut self.endianness);
+                server::transport_combo(ui, &mut self.transport);
+                // Datagrams can't be read over TLS.
+                if self.transport == Transport::Tcp {
+                    self.tls.render(ui);
+                }
+                ui.checkbox(im_str!("Capture Raw Bytes"), &mut self.capture_raw);
+                if self.capture_raw {
+                    ui.input_text(im_str!("Capture File"), &mut self.capture_path)
+                        .build();
+                }
                 if ui.button(im_str!("Create Sensor Window"), [0.0, 0.0]) {
-                    let (gps_tx, gps_rx) = unbounded();
-                    let gps = Gps::new(gps_tx);
-                    join_handles.push(
-                        gps.start(
-                            format!("0.0.0.0:{}", self.gps_port.to_string())
-                                .parse()
-                                .expect("couldn't parse IP address"),
-                        ),
-                    );
-                    sensor_windows.push(Box::new(GpsWindow::new(gps_rx)));
+                    let address = match parse_listen_addr(self.gps_port.as_ref()) {
+                        Ok(address) => address,
+                        Err(e) => {
+                            self.address_error = Some(format!("Invalid listen address: {}", e));
+                            return;
+                        }
+                    };
+                    let tile_url_template = self.tile_url_template.to_string();
+                    if let Err(e) = check_tile_url_template(&tile_url_template) {
+                        self.address_error = Some(e);
+                        return;
+                    }
+                    let tls_files = match self.transport {
+                        Transport::Tcp => self.tls.files(),
+                        Transport::Udp => None,
+                    };
+                    let tls = match tls_files.as_ref().map(|files| files.load()).transpose() {
+                        Ok(tls) => tls,
+                        Err(e) => {
+                            self.address_error = Some(format!("Couldn't set up TLS: {}", e));
+                            return;
+                        }
+                    };
+                    self.address_error = None;
+                    let (gps_tx, gps_rx) = self.channel.channel();
+                    let mut gps = Gps::new(gps_tx);
+                    gps.format =
+                        GpsFormat::from_str(self.format_list[self.format_item].as_ref()).unwrap();
+                    gps.buffer_size = self.buffer_kib.max(1) as usize * 1024;
+                    gps.read_timeout = server::read_timeout_from_secs(self.read_timeout_secs);
+                    gps.max_connections = self.max_connections.max(1) as usize;
+                    gps.endianness = self.endianness;
+                    gps.transport = self.transport;
+                    gps.tls = tls;
+                    if self.capture_raw {
+                        gps.capture_path = Some(PathBuf::from(self.capture_path.to_string()));
+                    }
+                    let spec = SensorSpec::Gps {
+                        address,
+                        format: gps.format.as_ref().to_string(),
+                        endianness: gps.endianness,
+                        transport: gps.transport,
+                        tls: tls_files,
+                    };
+                    let stats = gps.stats.clone();
+                    let started = RestartableSensor::start(address, move |address, stop| {
+                        gps.clone().start(address, stop)
+                    });
+                    let restartable = match started {
+                        Ok((restartable, handle)) => {
+                            join_handles.push(handle);
+                            restartable
+                        }
+                        Err(e) => {
+                            self.address_error = Some(e.to_string());
+                            return;
+                        }
+                    };
+                    let mut gps_window = GpsWindow::new(gps_rx);
+                    gps_window.restartable = Some(restartable);
+                    gps_window.name = window::window_name(&self.name, "GPS");
+                    gps_window.spec = Some(spec);
+                    gps_window.stats = stats;
+                    gps_window.stale_after =
+                        diagnostics::stale_after_from_secs(self.stale_after_secs);
+                    gps_window.tile_dim = self.tile_dim;
+                    // Even sizes are rounded up so the grid has a center tile.
+                    gps_window.grid_size = self.grid_size.max(1) as u32 | 1;
+                    gps_window.initial_zoom = self.initial_zoom.max(0).min(MAX_ZOOM as i32) as u32;
+                    gps_window.tile_fetch_concurrency =
+                        self.tile_fetch_concurrency
+                            .max(1)
+                            .min(MAX_TILE_FETCH_CONCURRENCY as i32) as u32;
+                    gps_window.show_markers = self.show_markers;
+                    gps_window.max_jump_meters = self.max_jump_meters;
+                    gps_window.max_points = self.max_points.max(0) as usize;
+                    gps_window.tile_cache.max_bytes = mib_to_bytes(self.tile_cache_mib);
+                    gps_window.tile_disk_cache = if self.disk_cache {
+                        Some(TileDiskCache::new(
+                            TileDiskCache::dir_for(&tile_url_template),
+                            days_to_duration(self.tile_max_age_days),
+                        ))
+                    } else {
+                        None
+                    };
+                    gps_window.tile_url_template = tile_url_template;
+                    sensor_windows.push(Box::new(gps_window));
                     ui.close_current_popup();
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::channel;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    #[test]
+    fn batched_fixes_are_decoded_in_order() {
+        let (sender, receiver) = channel(8, OverflowPolicy::Block);
+        let mut gps = Gps::new(sender);
+        gps.format = GpsFormat::Batched;
+        gps.endianness = Endianness::Big;
+        let mut stream = 3u32.to_be_bytes().to_vec();
+        for value in [45.5f32, 7.25, 45.75, 7.5, -33.875, 151.25].iter() {
+            stream.extend_from_slice(&value.to_be_bytes());
+        }
+        let error = gps.handle_gps(&stream[..]).unwrap_err();
+        assert!(matches!(error, GpsError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof));
+
+        let fixes: Vec<(f32, f32)> = receiver.try_iter().map(|fix| (fix.lat, fix.lon)).collect();
+        assert_eq!(fixes, vec![(45.5, 7.25), (45.75, 7.5), (-33.875, 151.25)]);
+
+        let oversized = (MAX_BATCH_SIZE + 1).to_be_bytes();
+        let error = gps.handle_gps(&oversized[..]).unwrap_err();
+        assert!(matches!(error, GpsError::BatchTooLarge(count) if count == MAX_BATCH_SIZE + 1));
+    }
+
+    #[test]
+    fn geocode_responses_give_the_first_place() {
+        // Trimmed from a real Nominatim response for "Brandenburger Tor".
+        let body = r#"[{"place_id":128503513,"licence":"Data © OpenStreetMap contributors, ODbL 1.0. https://osm.org/copyright","osm_type":"way","osm_id":518071791,"lat":"52.5162699","lon":"13.3777034","class":"tourism","type":"attraction","place_rank":30,"importance":0.6,"addresstype":"tourism","name":"Brandenburger Tor","display_name":"Brandenburger Tor, Pariser Platz, Mitte, Berlin, 10117, Deutschland","boundingbox":["52.5161167","52.5164286","13.3775469","13.3778578"]},{"place_id":1,"lat":"0.5","lon":"0.5"}]"#;
+        let (lat, lon) = parse_geocode_response(body).unwrap().unwrap();
+        assert!((lat - 52.516_27).abs() < 1e-5);
+        assert!((lon - 13.377_703).abs() < 1e-5);
+
+        assert_eq!(parse_geocode_response("[]").unwrap(), None);
+        assert!(parse_geocode_response(r#"[{"lat":"north","lon":"0"}]"#).is_err());
+        assert!(parse_geocode_response("<html>").is_err());
+    }
+
+    #[test]
+    fn map_images_must_fill_their_dimensions() {
+        let image = map_image_from_raw(2, 2, vec![0; 12]).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert!(map_image_from_raw(2, 2, vec![0; 11]).is_err());
+        assert!(map_image_from_raw(2, 2, vec![0; 13]).is_err());
+    }
+
+    #[test]
+    fn windows_cache_tiles_like_the_config_modal_by_default() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let window = GpsWindow::new(receiver);
+        assert_eq!(window.tile_cache.max_bytes, 64 << 20);
+        let disk_cache = window.tile_disk_cache.expect("no disk cache");
+        assert_eq!(disk_cache.dir, TileDiskCache::default_dir());
+        assert_eq!(disk_cache.max_age, Duration::from_secs(7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn the_first_fix_keeps_a_configured_zoom() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = GpsWindow::new(receiver);
+        window.zoom = 12;
+        window.zoom_to_first_fix();
+        assert_eq!(window.zoom, 12);
+    }
+
+    #[test]
+    fn the_first_fix_moves_the_world_view_to_the_initial_zoom() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = GpsWindow::new(receiver);
+        window.initial_zoom = 14;
+        window.zoom_to_first_fix();
+        assert_eq!(window.zoom, 14);
+    }
+
+    #[test]
+    fn steady_fixes_after_a_relocation_are_accepted() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = GpsWindow::new(receiver);
+        window.max_jump_meters = 1000.0;
+        let fix = |lat, lon| GpsData {
+            lat,
+            lon,
+            track: None,
+            timestamp: 0.0,
+        };
+
+        assert!(window.validate_fix(&fix(45.0, 7.0)).is_ok());
+        assert!(window.validate_fix(&fix(46.0, 7.0)).is_err());
+        assert!(window.validate_fix(&fix(46.001, 7.0)).is_ok());
+        assert!(window.validate_fix(&fix(46.002, 7.0)).is_ok());
+    }
+
+    #[test]
+    fn jumps_are_measured_within_each_track() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = GpsWindow::new(receiver);
+        window.max_jump_meters = 1000.0;
+        let fix = |lat, track| GpsData {
+            lat,
+            lon: 7.0,
+            track,
+            timestamp: 0.0,
+        };
+
+        assert!(window.validate_fix(&fix(45.0, None)).is_ok());
+        assert!(window.validate_fix(&fix(46.0, Some(1))).is_ok());
+        assert!(window.validate_fix(&fix(45.001, None)).is_ok());
+        assert!(window.validate_fix(&fix(46.001, Some(1))).is_ok());
+        assert!(window.validate_fix(&fix(47.0, Some(1))).is_err());
+    }
+
+    #[test]
+    fn malformed_tiles_are_an_error() {
+        assert!(decode_tile(b"not a png".to_vec()).is_err());
+        assert!(decode_tile(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn tiles_on_disk_are_used_without_fetching() {
+        let dir = env::temp_dir().join(format!("sensorview-tiles-{}", std::process::id()));
+        let cache = TileDiskCache::new(dir.clone(), Duration::from_secs(60));
+        // Real tiles are 256x256, so a 2x2 tile can only have come from the cache.
+        let mut png = Vec::new();
+        image::png::PngEncoder::new(&mut png)
+            .encode(&[0; 2 * 2 * 3], 2, 2, image::ColorType::Rgb8)
+            .unwrap();
+        cache.insert((3, 4, 5), &png).unwrap();
+
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = GpsWindow::new(receiver);
+        window.zoom = 3;
+        window.tile_cache.max_bytes = 0;
+        window.tile_disk_cache = Some(cache);
+        for _ in 0..2 {
+            let tile = window
+                .query_tile(4, 5)
+                .expect("the tile should come from the cache");
+            assert_eq!((tile.width, tile.height), (2, 2));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tile_requests_identify_sensorview() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0; 1];
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                request.push(byte[0]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        tile_client()
+            .get(&format!("http://{}/0/0/0.png", addr))
+            .send()
+            .unwrap();
+        let request = server.join().unwrap().to_lowercase();
+        let expected = format!("user-agent: {}\r\n", TILE_USER_AGENT).to_lowercase();
+        assert!(request.contains(&expected), "{}", request);
+    }
+
+    #[test]
+    fn only_real_movement_counts_as_a_new_position() {
+        let start = GpsData {
+            lat: 45.0,
+            lon: 7.0,
+            track: None,
+            timestamp: 0.0,
+        };
+        let jitter = GpsData {
+            lat: 45.000_001,
+            lon: 6.999_999,
+            track: None,
+            timestamp: 0.0,
+        };
+        let moved = GpsData {
+            lat: 45.001,
+            lon: 7.0,
+            track: None,
+            timestamp: 0.0,
+        };
+        assert!(same_position(&start, &jitter, DEFAULT_STATIONARY_EPSILON));
+        assert!(!same_position(&start, &moved, DEFAULT_STATIONARY_EPSILON));
+    }
+
+    #[test]
+    fn gpx_lists_every_fix_as_a_track_point() {
+        let fix = |lat, lon, track| GpsData {
+            lat,
+            lon,
+            track,
+            timestamp: 0.0,
+        };
+        let mut tracks = BTreeMap::new();
+        tracks.insert(
+            None,
+            Track {
+                fixes: vec![fix(45.5, 7.25, None), fix(45.75, 7.5, None)],
+                ..Track::default()
+            },
+        );
+        tracks.insert(
+            Some(2),
+            Track {
+                fixes: vec![fix(-33.875, 151.25, Some(2))],
+                ..Track::default()
+            },
+        );
+        let gpx = tracks_to_gpx(&tracks);
+        assert!(gpx.contains("<gpx version=\"1.1\""));
+        assert!(gpx.contains("<trkpt lat=\"45.5\" lon=\"7.25\">"));
+        assert!(gpx.contains("<trkpt lat=\"45.75\" lon=\"7.5\">"));
+        assert!(gpx.contains("<trkpt lat=\"-33.875\" lon=\"151.25\">"));
+        assert_eq!(gpx.matches("<trkseg>").count(), 2);
+        assert!(
+            gpx.find("<name>Default</name>").unwrap() < gpx.find("<name>Track 2</name>").unwrap()
+        );
+    }
+
+    #[test]
+    fn compressed_bodies_are_decoded() {
+        use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+
+        let tile = b"not really a png, but it compresses all the same".to_vec();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tile).unwrap();
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&tile).unwrap();
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(&tile).unwrap();
+
+        assert_eq!(decode_body(None, tile.clone()).unwrap(), tile);
+        assert_eq!(decode_body(Some("identity"), tile.clone()).unwrap(), tile);
+        assert_eq!(
+            decode_body(Some("gzip"), gzip.finish().unwrap()).unwrap(),
+            tile
+        );
+        assert_eq!(
+            decode_body(Some("deflate"), zlib.finish().unwrap()).unwrap(),
+            tile
+        );
+        assert_eq!(
+            decode_body(Some("deflate"), deflate.finish().unwrap()).unwrap(),
+            tile
+        );
+        assert!(decode_body(Some("br"), tile.clone()).is_err());
+        assert!(decode_body(Some("gzip"), tile).is_err());
+    }
+
+    #[test]
+    fn coordinates_are_parsed_as_decimal_or_dms() {
+        let close = |text, axis, expected: f32| {
+            let value = parse_coordinate(text, axis).unwrap();
+            assert!(
+                (value - expected).abs() < 1e-5,
+                "{} parsed as {}",
+                text,
+                value
+            );
+        };
+        close("-48.8567", Axis::Latitude, -48.8567);
+        close("48°51'24\"N", Axis::Latitude, 48.856_667);
+        close("48 51 24 s", Axis::Latitude, -48.856_667);
+        close("W 2 21", Axis::Longitude, -2.35);
+
+        assert!(parse_coordinate("91", Axis::Latitude).is_err());
+        assert!(parse_coordinate("48 61", Axis::Latitude).is_err());
+        assert!(parse_coordinate("48N", Axis::Longitude).is_err());
+        assert!(parse_coordinate("-48S", Axis::Latitude).is_err());
+        assert!(parse_coordinate("north", Axis::Latitude).is_err());
+    }
+
+    #[test]
+    fn readouts_are_formatted_in_dms() {
+        assert_eq!(format_dms(48.856_667, Axis::Latitude), "48°51'24.0\"N");
+        assert_eq!(format_dms(-2.3522, Axis::Longitude), "2°21'07.9\"W");
+        assert_eq!(format_dms(0.0, Axis::Longitude), "0°00'00.0\"E");
+    }
+}
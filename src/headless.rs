@@ -0,0 +1,214 @@
+use crate::camera::{self, Camera, CameraData, FrameFraming, VideoFormat};
+use crate::channel::{self, OverflowPolicy};
+use crate::diagnostics::SensorStats;
+use crate::gps::{self, Gps, GpsData, GpsFormat};
+use crate::health::{self, HealthSensors};
+use crate::lidar::{self, DistanceUnit, Lidar, LidarData, LidarFormat};
+use crate::session::SensorSpec;
+use crossbeam::channel::Receiver;
+use image::ColorType;
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Starts the given sensors without opening a window and writes everything they receive to files
+/// in `output_dir`, until the process is stopped:
+///
+/// * camera frames as numbered PNG files in a `camera-PORT` directory,
+/// * LIDAR scans to `lidar-PORT.csv`, one row per return, with distances in meters if the LIDAR's
+///   distance unit is known,
+/// * GPS fixes to `gps-PORT.csv`, one row per fix.
+///
+/// The writers take the place of the sensor windows, so the sensors wait for them rather than
+/// dropping data. If `health_address` is set, the state of the sensors is served there, under the
+/// same names as their files.
+pub fn run(
+    sensors: &[SensorSpec],
+    output_dir: &Path,
+    health_address: Option<SocketAddr>,
+) -> io::Result<()> {
+    fs::create_dir_all(output_dir)?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let mut join_handles = Vec::new();
+    let mut stats = Vec::new();
+    for spec in sensors {
+        start_capture(spec, output_dir, &shutdown, &mut join_handles, &mut stats)?;
+    }
+    if join_handles.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "headless mode needs at least one camera, LIDAR or GPS to capture",
+        ));
+    }
+    info!("headless: capturing to {}", output_dir.display());
+    if let Some(address) = health_address {
+        let health_sensors = HealthSensors::default();
+        *health_sensors.lock().unwrap() = stats;
+        join_handles.push(health::start(address, health_sensors, shutdown.clone())?);
+    }
+
+    for handle in join_handles {
+        match handle.join() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => warn!("headless: capture stopped: {}", e),
+            Err(_) => warn!("headless: a capture thread panicked"),
+        }
+    }
+    Ok(())
+}
+
+/// Starts the sensor described by the spec along with a thread writing what it receives to a
+/// file, and adds the sensor's stats to `stats`.
+fn start_capture(
+    spec: &SensorSpec,
+    output_dir: &Path,
+    shutdown: &Arc<AtomicBool>,
+    join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
+    stats: &mut Vec<(String, Arc<SensorStats>)>,
+) -> io::Result<()> {
+    match spec {
+        SensorSpec::Camera {
+            address,
+            video_format,
+            framing,
+            endianness,
+            checksummed,
+            tls,
+            ..
+        } => {
+            let video_format = VideoFormat::from_str(video_format).unwrap_or(VideoFormat::MJPEG);
+            let (camera_tx, camera_rx) =
+                channel::channel(camera::DEFAULT_CHANNEL_CAPACITY, OverflowPolicy::Block);
+            let mut camera = Camera::new(camera_tx);
+            camera.endianness = *endianness;
+            camera.framing =
+                FrameFraming::from_str(framing).unwrap_or(FrameFraming::LengthPrefixed);
+            camera.checksummed = *checksummed;
+            camera.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let name = format!("camera-{}", address.port());
+            let dir = output_dir.join(&name);
+            fs::create_dir_all(&dir)?;
+            stats.push((name, camera.stats.clone()));
+            join_handles.push(camera.start(*address, video_format, shutdown.clone())?);
+            join_handles.push(thread::spawn(move || write_frames(camera_rx, dir)));
+        }
+        SensorSpec::Lidar {
+            address,
+            format,
+            endianness,
+            transport,
+            checksummed,
+            distance_unit,
+            tls,
+        } => {
+            let (lidar_tx, lidar_rx) =
+                channel::channel(lidar::DEFAULT_CHANNEL_CAPACITY, OverflowPolicy::Block);
+            let mut lidar = Lidar::new(lidar_tx);
+            lidar.format = LidarFormat::from_str(format).unwrap_or(LidarFormat::Basic);
+            lidar.endianness = *endianness;
+            lidar.transport = *transport;
+            lidar.checksummed = *checksummed;
+            lidar.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let name = format!("lidar-{}", address.port());
+            let file = File::create(output_dir.join(format!("{}.csv", name)))?;
+            stats.push((name, lidar.stats.clone()));
+            join_handles.push(lidar.start(*address, shutdown.clone())?);
+            let distance_unit = DistanceUnit::from_str(distance_unit).unwrap_or(DistanceUnit::Raw);
+            join_handles.push(thread::spawn(move || {
+                write_scans(lidar_rx, file, distance_unit)
+            }));
+        }
+        SensorSpec::Gps {
+            address,
+            format,
+            endianness,
+            transport,
+            tls,
+        } => {
+            let (gps_tx, gps_rx) =
+                channel::channel(gps::DEFAULT_CHANNEL_CAPACITY, OverflowPolicy::Block);
+            let mut gps = Gps::new(gps_tx);
+            gps.format = GpsFormat::from_str(format).unwrap_or(GpsFormat::Single);
+            gps.endianness = *endianness;
+            gps.transport = *transport;
+            gps.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let name = format!("gps-{}", address.port());
+            let file = File::create(output_dir.join(format!("{}.csv", name)))?;
+            stats.push((name, gps.stats.clone()));
+            join_handles.push(gps.start(*address, shutdown.clone())?);
+            join_handles.push(thread::spawn(move || write_fixes(gps_rx, file)));
+        }
+        // The controller sends data rather than receiving it, so there's nothing to capture.
+        SensorSpec::Controller { address, .. } => {
+            warn!("headless: ignoring the controller for {}", address);
+        }
+    }
+    Ok(())
+}
+
+/// Saves each frame as a numbered PNG file in `dir` until the camera stops.
+fn write_frames(receiver: Receiver<CameraData>, dir: PathBuf) -> io::Result<()> {
+    for (index, frame) in receiver.iter().enumerate() {
+        let frame = match frame.into_rgb() {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("headless: dropping camera frame: {}", e);
+                continue;
+            }
+        };
+        let path = dir.join(format!("frame-{:06}.png", index));
+        image::save_buffer(
+            &path,
+            &frame.image_bytes,
+            frame.width,
+            frame.height,
+            ColorType::Rgb8,
+        )
+        .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Writes a row for every return of every scan until the LIDAR stops. Rows are flushed after each
+/// scan so that nothing is lost when the process is killed. Returns without an intensity leave
+/// the intensity column empty. Distances are converted to meters unless the unit is `Raw`.
+fn write_scans(
+    receiver: Receiver<LidarData>,
+    file: File,
+    distance_unit: DistanceUnit,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "scan,angle,distance,intensity")?;
+    for (index, scan) in receiver.iter().enumerate() {
+        for (point, (angle, distance)) in scan.distances.iter().enumerate() {
+            let intensity = scan
+                .intensities
+                .get(point)
+                .map(|intensity| intensity.to_string())
+                .unwrap_or_default();
+            let distance = distance_unit.to_meters(*distance);
+            writeln!(writer, "{},{},{},{}", index, angle, distance, intensity)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes a row for every fix until the GPS stops. Fixes without a track id leave the track
+/// column empty.
+fn write_fixes(receiver: Receiver<GpsData>, file: File) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "lat,lon,track")?;
+    for fix in receiver.iter() {
+        let track = fix.track.map(|id| id.to_string()).unwrap_or_default();
+        writeln!(writer, "{},{},{}", fix.lat, fix.lon, track)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::diagnostics::{self, SensorStats};
+use crate::server;
+use crate::shutdown::{self, POLL_INTERVAL};
+use log::{info, warn};
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long the health endpoint waits for a client to send its request before answering anyway.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The sensors the health endpoint reports on, by name. The GUI refreshes the list every frame
+/// as windows come and go, while headless mode fills it in once at startup.
+pub type HealthSensors = Arc<Mutex<Vec<(String, Arc<SensorStats>)>>>;
+
+/// How a single sensor is doing, as reported by the health endpoint.
+#[derive(Debug, Serialize)]
+struct SensorHealth {
+    name: String,
+    /// One of "Listening", "Connected", "Stale" or "Error".
+    state: &'static str,
+    /// Seconds since the last frame or fix, or null if there hasn't been one.
+    last_update_secs: Option<f32>,
+    frames: u64,
+    dropped: u64,
+    bytes: u64,
+    connections: usize,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    sensors: Vec<SensorHealth>,
+}
+
+/// Describes the sensors as the JSON document served by the health endpoint.
+pub fn report(sensors: &[(String, Arc<SensorStats>)]) -> String {
+    let report = HealthReport {
+        sensors: sensors
+            .iter()
+            .map(|(name, stats)| SensorHealth {
+                name: name.clone(),
+                state: stats
+                    .connection_state(diagnostics::DEFAULT_STALE_AFTER)
+                    .label(),
+                last_update_secs: stats.since_last_update().map(|since| since.as_secs_f32()),
+                frames: stats.frames(),
+                dropped: stats.dropped(),
+                bytes: stats.bytes(),
+                connections: stats.connections(),
+                last_error: stats.last_error(),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&report).unwrap_or_else(|_| "{\"sensors\":[]}".to_string())
+}
+
+/// Starts answering every connection to `address` with an HTTP response holding the health
+/// report, until `shutdown` is set. The request itself is ignored, so both `curl` and a plain
+/// TCP check work. The listener is bound before returning so a port that's in use is reported
+/// right away.
+pub fn start(
+    address: SocketAddr,
+    sensors: HealthSensors,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<io::Result<()>>> {
+    let listener = server::bind(address)?;
+    // Accepting without blocking lets the thread notice the shutdown flag.
+    listener.set_nonblocking(true)?;
+    info!("health: serving on {}", address);
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let body = match sensors.lock() {
+                        Ok(sensors) => report(&sensors),
+                        Err(_) => continue,
+                    };
+                    if let Err(e) = respond(stream, &body) {
+                        warn!("health: couldn't answer {}: {}", peer, e);
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if shutdown::sleep(POLL_INTERVAL, &shutdown) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("health: couldn't accept a connection: {}", e);
+                    shutdown::sleep(POLL_INTERVAL, &shutdown);
+                }
+            }
+        }
+        Ok(())
+    }))
+}
+
+fn respond(mut stream: TcpStream, body: &str) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    // Whatever part of the request arrives in one read is enough, since it isn't looked at.
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request);
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
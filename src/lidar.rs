@@ -1,182 +1,2116 @@
-use crate::window::{Modal, Renderable};
-use byteorder::{LittleEndian, ReadBytesExt};
-use crossbeam::{unbounded, Receiver, Sender};
+use crate::camera;
+use crate::capture::CaptureReader;
+use crate::channel::{ChannelConfig, OverflowPolicy, SensorSender};
+use crate::connection;
+use crate::diagnostics::{self, SensorStats, ThroughputHistory};
+use crate::endian::{byte_order_combo, Endianness};
+use crate::framing::FrameSync;
+use crate::notifications::Notification;
+use crate::server::{self, RestartableSensor, Transport};
+use crate::session::SensorSpec;
+use crate::settings::Settings;
+use crate::shutdown::ReadDeadline;
+use crate::texture;
+use crate::tls::{self, ServerTlsConfig};
+use crate::window::{self, parse_listen_addr, Modal, Place, Placement, Renderable};
+use crossbeam::Receiver;
 use glium::Display;
-use glium::{
-    backend::Facade,
-    texture::{ClientFormat, RawImage2d},
-    Texture2d,
+use image::{ImageResult, Rgb, RgbImage};
+use imageproc::drawing::{
+    draw_filled_circle_mut, draw_filled_rect_mut, draw_hollow_circle_mut, draw_line_segment_mut,
 };
-use image::{Rgb, RgbImage};
-use imageproc::drawing::draw_filled_circle_mut;
+use imageproc::rect::Rect;
 use imgui::TextureId;
-use imgui::{self, im_str, ImString, Image, Ui, Window, WindowFlags};
+use imgui::{self, im_str, ImStr, ImString, Image, Slider, Ui, Window, WindowFlags};
 use imgui_glium_renderer::Renderer;
+use log::{info, warn};
+use rustls::ServerConfig;
 use std::borrow::Cow;
-use std::io;
-use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::rc::Rc;
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::io::{self, BufReader, Cursor, Read};
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter, EnumString};
+
+/// The default capacity of the buffered reader wrapped around each LIDAR connection.
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The default limit on the size of a single scan, checked against the scan's point count before
+/// any of it is read. Each point is an angle and a distance, 8 bytes in total.
+const DEFAULT_MAX_FRAME_BYTES: usize = 1024 * 1024;
+
+/// How many scans can wait for the LIDAR window. Scans arriving while it's full are dropped.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 8;
+pub const DEFAULT_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Drop;
+
+/// The default offset applied to every return's angle. The LIDAR this was written against has its
+/// zero point approximately 90 degrees off from forward.
+const DEFAULT_ANGLE_OFFSET: f32 = -90.0;
+
+/// The fraction of the plot's radius that the farthest return is scaled to, so points at the
+/// maximum range don't get clipped at the edge of the image.
+const PLOT_MARGIN: f32 = 0.9;
+
+/// The scale used when a scan has no returns to scale to.
+const DEFAULT_SCALE: f32 = 0.03;
+
+/// The number of range rings the grid aims to fit inside the plot.
+const TARGET_RINGS: f32 = 4.0;
+
+/// The angle between the spokes of the grid, in degrees.
+const SPOKE_SPACING_DEGREES: u32 = 30;
+
+/// The number of scans an accumulated return stays on screen by default.
+const DEFAULT_MAX_AGE: u32 = 20;
+
+/// How old the latest scan can get by default before the plot is dimmed to show it's stale.
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(1);
+
+/// The default shortest time between redraws of the plot. Scans arriving faster than this are
+/// still added to the plot, but only the latest state is drawn and uploaded.
+const DEFAULT_MIN_REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+
+/// The default width and height of the plot, in pixels.
+const DEFAULT_IMAGE_DIM: f32 = 400.0;
+
+/// The range the plot size can be set to. The plot is redrawn into a new image on every scan, so
+/// the upper bound keeps a stray slider drag from allocating hundreds of megabytes per frame.
+const MIN_IMAGE_DIM: f32 = 100.0;
+const MAX_IMAGE_DIM: f32 = 2000.0;
+
+/// The brightness of a return with zero intensity, relative to the strongest return of its scan.
+const MIN_INTENSITY_BRIGHTNESS: f32 = 0.2;
+
+/// The default percentile of return distances "Fit to Data" scales the plot to.
+const DEFAULT_FIT_PERCENTILE: f32 = 95.0;
+
+/// The default largest gap between neighboring returns of the same cluster, in meters, and the
+/// default fewest returns a cluster is shown for.
+const DEFAULT_CLUSTER_EPSILON_METERS: f32 = 0.2;
+const DEFAULT_CLUSTER_MIN_POINTS: usize = 5;
+
+/// The default size of the buckets angular binning keeps the nearest return of, in degrees.
+const DEFAULT_ANGULAR_BIN: f32 = 1.0;
+
+/// The size of the buckets of angles whose distances smoothing averages across scans, in degrees.
+const SMOOTHING_BIN: f32 = 0.5;
+
+/// The default and largest number of scans smoothing averages each distance over.
+const DEFAULT_SMOOTHING_SCANS: usize = 5;
+const MAX_SMOOTHING_SCANS: i32 = 30;
+
+/// The smallest circle drawn around a cluster, in pixels, so clusters of one spot stay visible.
+const MIN_CLUSTER_RADIUS: f32 = 4.0;
+
+/// The color of the circles drawn around clusters and their labels.
+const CLUSTER_COLOR: [u8; 3] = [255, 220, 0];
+
+/// The faint color masked sectors are shaded with.
+const MASK_COLOR: [u8; 3] = [45, 25, 25];
+
+/// The radius returns are drawn with by default, in pixels.
+const DEFAULT_POINT_RADIUS: u32 = 2;
+const MAX_POINT_RADIUS: i32 = 8;
+
+/// How close the mouse has to be to a return, in pixels of the plot, for the return to be
+/// labeled.
+const LABEL_PICK_RADIUS: f32 = 10.0;
+
+/// The color of the label and marker shown on the return under the mouse.
+const POINT_LABEL_COLOR: [f32; 3] = [0.4, 1.0, 1.0];
+
+/// The wire formats the LIDAR can send. Each scan starts with a u32 count of the returns that
+/// follow. `Basic` sends an angle and a distance per return while `Intensity` follows each
+/// distance with the strength of the return.
+#[derive(AsRefStr, EnumIter, EnumString, Clone, Copy, Debug)]
+pub enum LidarFormat {
+    Basic,
+    Intensity,
+}
+
+impl LidarFormat {
+    /// The number of bytes each return takes up on the wire.
+    fn point_bytes(self) -> usize {
+        match self {
+            LidarFormat::Basic => 8,
+            LidarFormat::Intensity => 12,
+        }
+    }
+}
+
+/// The unit the LIDAR reports distances in. Distances in a known unit are converted to meters
+/// before they're plotted, while `Raw` distances are plotted as they arrive and the range rings
+/// are left unlabeled.
+#[derive(AsRefStr, EnumIter, EnumString, Clone, Copy, Debug, PartialEq)]
+pub enum DistanceUnit {
+    Raw,
+    Millimeters,
+    Centimeters,
+    Meters,
+}
+
+impl DistanceUnit {
+    /// Converts a distance reported in this unit to meters, or leaves it alone for `Raw`.
+    pub fn to_meters(self, distance: f32) -> f32 {
+        match self {
+            DistanceUnit::Raw | DistanceUnit::Meters => distance,
+            DistanceUnit::Millimeters => distance / 1000.0,
+            DistanceUnit::Centimeters => distance / 100.0,
+        }
+    }
+
+    /// The default cluster distance in the units distances are plotted in. `Raw` distances are
+    /// taken to be in millimeters, the unit LIDARs usually report.
+    fn default_cluster_epsilon(self) -> f32 {
+        match self {
+            DistanceUnit::Raw => DEFAULT_CLUSTER_EPSILON_METERS * 1000.0,
+            _ => DEFAULT_CLUSTER_EPSILON_METERS,
+        }
+    }
+
+    /// The suffix plotted distances are labeled with.
+    fn suffix(self) -> &'static str {
+        match self {
+            DistanceUnit::Raw => "",
+            _ => " m",
+        }
+    }
+}
 
 pub struct LidarData {
-    distances: Vec<(f32, f32)>,
+    pub distances: Vec<(f32, f32)>,
+    /// The intensity of each return, in the same order as `distances`. Empty if the LIDAR
+    /// doesn't report intensities.
+    pub intensities: Vec<f32>,
+    /// When the scan was assembled from the stream.
+    pub captured_at: Instant,
+}
+
+impl LidarData {
+    /// Creates a scan captured just now.
+    pub fn new(distances: Vec<(f32, f32)>) -> Self {
+        Self {
+            distances,
+            intensities: Vec::new(),
+            captured_at: Instant::now(),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Lidar {
-    sender: Sender<LidarData>,
+    sender: SensorSender<LidarData>,
+    pub stats: Arc<SensorStats>,
+    pub format: LidarFormat,
+    pub capture_path: Option<PathBuf>,
+    pub buffer_size: usize,
+    /// How long a connection can go without sending anything before it's dropped, if at all.
+    pub read_timeout: Option<Duration>,
+    /// The most devices that can be connected at once. Further connections are refused.
+    pub max_connections: usize,
+    pub endianness: Endianness,
+    pub max_frame_bytes: usize,
+    pub transport: Transport,
+    /// Whether each scan is wrapped in a checksummed frame, as read by `FrameSync`, so that
+    /// corrupted scans are skipped rather than plotted.
+    pub checksummed: bool,
+    /// If set, TCP connections are only read over TLS with this configuration.
+    pub tls: Option<Arc<ServerConfig>>,
+    /// How long the points of a scan can take to arrive once its count has, if limited. Scans
+    /// that take longer are discarded. Checksummed frames skip to the next intact frame anyway,
+    /// so this only applies to unframed scans over TCP.
+    pub scan_timeout: Option<Duration>,
 }
 
 impl Lidar {
-    pub fn new(sender: Sender<LidarData>) -> Self {
-        Self { sender }
+    pub fn new(sender: SensorSender<LidarData>) -> Self {
+        Self {
+            sender,
+            stats: Arc::new(SensorStats::new()),
+            format: LidarFormat::Basic,
+            capture_path: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            read_timeout: None,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS,
+            endianness: Endianness::Little,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            transport: Transport::Tcp,
+            checksummed: false,
+            tls: None,
+            scan_timeout: None,
+        }
     }
 
-    /// Starts a TCP listener to receive data from the LIDAR. This supports multiple connections,
-    /// though multiple connections aren't handled correctly at the moment.
-    pub fn start(mut self, ip: SocketAddr) -> JoinHandle<io::Result<()>> {
-        thread::spawn(move || {
-            let listener = TcpListener::bind(&ip).unwrap();
-            for stream in listener.incoming() {
-                self.handle_lidar_stream(stream?)?;
+    /// Starts listening for data from the LIDAR over the configured transport. Over TCP each
+    /// connection is handled on its own thread, so multiple devices can stream to the same
+    /// listener at once. Over UDP every datagram is decoded as it arrives. Fails if the port can't
+    /// be bound.
+    pub fn start(
+        self,
+        ip: SocketAddr,
+        shutdown: Arc<AtomicBool>,
+    ) -> io::Result<JoinHandle<io::Result<()>>> {
+        let stats = self.stats.clone();
+        match self.transport {
+            Transport::Tcp => {
+                let listener = server::bind(ip)?;
+                info!("LIDAR: listening on {} with format {:?}", ip, self.format);
+                Ok(thread::spawn(move || {
+                    let buffer_size = self.buffer_size;
+                    let read_timeout = self.read_timeout;
+                    let max_connections = self.max_connections;
+                    server::serve(
+                        "LIDAR",
+                        &listener,
+                        &shutdown,
+                        &stats,
+                        read_timeout,
+                        max_connections,
+                        move |stream| {
+                            let deadline = stream.get_ref().deadline();
+                            let stream = tls::accept(stream, self.tls.as_ref());
+                            let stream = BufReader::with_capacity(buffer_size, stream);
+                            self.receive(stream, Some(deadline))
+                        },
+                    )
+                }))
             }
-            Ok(())
-        })
+            Transport::Udp => {
+                let socket = server::bind_udp(ip)?;
+                info!(
+                    "LIDAR: receiving datagrams on {} with format {:?}",
+                    ip, self.format
+                );
+                if self.tls.is_some() {
+                    warn!("LIDAR: TLS only applies over TCP, so datagrams are read in plaintext");
+                }
+                Ok(thread::spawn(move || {
+                    server::serve_udp("LIDAR", &socket, &shutdown, &stats, |datagram| {
+                        self.receive(datagram, None)
+                    })
+                }))
+            }
+        }
+    }
+
+    /// Decodes the scans arriving on a connection or in a datagram, copying the raw bytes to the
+    /// capture file first if there is one. `deadline` is where the scan timeout is enforced on
+    /// a connection.
+    fn receive<R: Read + 'static>(
+        &self,
+        stream: R,
+        deadline: Option<ReadDeadline>,
+    ) -> io::Result<()> {
+        let stream: Box<dyn Read> = match self.capture_path {
+            Some(ref path) => Box::new(CaptureReader::new(stream, path)?),
+            None => Box::new(stream),
+        };
+        self.clone().handle_lidar_stream(stream, deadline)
     }
 
-    pub fn handle_lidar_stream(&mut self, mut stream: TcpStream) -> io::Result<()> {
+    /// Decodes scans from the stream until it ends. Checksummed scans that fail their checksum are
+    /// skipped, as are ones whose points don't fill their frame exactly. With a scan timeout and a
+    /// deadline to enforce it on, unframed scans whose points stall are discarded, and the next
+    /// bytes to arrive are read as the count of a new scan.
+    pub fn handle_lidar_stream<R: Read>(
+        &mut self,
+        mut stream: R,
+        deadline: Option<ReadDeadline>,
+    ) -> io::Result<()> {
+        let mut sync = FrameSync::new("LIDAR");
         loop {
-            let mut scan = Vec::new();
-            let scan_size = stream.read_u32::<LittleEndian>()?;
-            for _ in 0..scan_size {
-                let angle = stream.read_f32::<LittleEndian>()?;
-                let distance = stream.read_f32::<LittleEndian>()?;
-                scan.push((angle, distance));
-            }
-            let lidar_data = LidarData {
-                distances: scan.to_vec(),
+            let lidar_data = if self.checksummed {
+                let frame = sync.next_frame(&mut stream, self.endianness, self.max_frame_bytes)?;
+                let mut frame = Cursor::new(frame);
+                match self.read_scan(&mut frame, None) {
+                    Ok(Some(scan)) if frame.position() == frame.get_ref().len() as u64 => scan,
+                    Ok(_) | Err(_) => {
+                        warn!("LIDAR: skipping a frame that doesn't hold a whole scan");
+                        continue;
+                    }
+                }
+            } else {
+                match self.read_scan(&mut stream, deadline.as_ref())? {
+                    Some(scan) => scan,
+                    None => continue,
+                }
             };
-            self.sender.send(lidar_data).map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::ConnectionAborted,
-                    "camera channel disconnected",
-                )
-            })?;
+            self.sender.send(lidar_data, &self.stats)?;
         }
     }
+
+    /// Reads a single scan: a u32 count n followed by n points. If there's a scan timeout, it's
+    /// enforced on `deadline` from when the count arrives, and a scan that doesn't arrive in time
+    /// is discarded, returning `None`. The bytes of the scan that did arrive are discarded with
+    /// it, wherever the stall fell, so the next bytes to arrive are read as a new count.
+    fn read_scan<R: Read>(
+        &self,
+        stream: &mut R,
+        deadline: Option<&ReadDeadline>,
+    ) -> io::Result<Option<LidarData>> {
+        let scan_size = self.endianness.read_u32(stream)?;
+        if scan_size as usize * self.format.point_bytes() > self.max_frame_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "scan of {} points exceeds the maximum of {} bytes",
+                    scan_size, self.max_frame_bytes
+                ),
+            ));
+        }
+        let (deadline, timeout) = match (deadline, self.scan_timeout) {
+            (Some(deadline), Some(timeout)) => (deadline, timeout),
+            _ => return self.read_points(stream, scan_size).map(Some),
+        };
+        deadline.set(Some(Instant::now() + timeout));
+        let scan = self.read_points(stream, scan_size);
+        // The deadline only runs while the points are read, so it having passed means the scan
+        // stalled, rather than the connection going idle between scans.
+        let stalled = deadline.passed();
+        deadline.set(None);
+        match scan {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut && stalled => {
+                warn!(
+                    "LIDAR: discarding a scan of {} points that didn't arrive within {:?}",
+                    scan_size, timeout
+                );
+                self.stats.record_error(format!(
+                    "discarded a scan that didn't arrive within {:?}",
+                    timeout
+                ));
+                Ok(None)
+            }
+            scan => scan.map(Some),
+        }
+    }
+
+    /// Reads the points of a scan of the given size. The scan's bytes are read into a buffer
+    /// before any point is decoded, so a scan cut off partway through a value leaves nothing of
+    /// itself behind in the stream to be mistaken for the start of the next scan.
+    fn read_points<R: Read>(&self, stream: &mut R, scan_size: u32) -> io::Result<LidarData> {
+        let mut bytes = vec![0; scan_size as usize * self.format.point_bytes()];
+        stream.read_exact(&mut bytes)?;
+        let mut points = Cursor::new(bytes);
+        let mut scan = Vec::with_capacity(scan_size as usize);
+        let mut intensities = Vec::new();
+        for _ in 0..scan_size {
+            let angle = self.endianness.read_f32(&mut points)?;
+            let distance = self.endianness.read_f32(&mut points)?;
+            scan.push((angle, distance));
+            if let LidarFormat::Intensity = self.format {
+                intensities.push(self.endianness.read_f32(&mut points)?);
+            }
+        }
+        let mut lidar_data = LidarData::new(scan);
+        lidar_data.intensities = intensities;
+        Ok(lidar_data)
+    }
+}
+
+/// How a scan is plotted in the LIDAR window.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LidarViewMode {
+    /// A top-down view with the LIDAR in the center.
+    Polar,
+    /// A strip chart with the angle along the x-axis and the distance along the y-axis.
+    AngleDistance,
+}
+
+/// How each return is drawn on the plot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointShape {
+    /// A filled circle of the point radius.
+    Circle,
+    /// A single pixel, whatever the point radius, so dense scans don't merge into blobs.
+    Pixel,
+    /// A plus sign whose arms are the point radius long.
+    Cross,
+}
+
+const POINT_SHAPES: [PointShape; 3] = [PointShape::Circle, PointShape::Pixel, PointShape::Cross];
+
+/// A sector of the LIDAR's field of view whose returns are hidden, such as one blocked by the
+/// vehicle it's mounted on. The sector runs from `start` to `end` in the direction the LIDAR's
+/// angles grow, in degrees from 0 to 360, and wraps through 0 when `end` is less than `start`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AngleMask {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl AngleMask {
+    /// Whether an angle in degrees falls inside the sector. Both edges count as inside.
+    pub fn contains(&self, degrees: f32) -> bool {
+        let degrees = degrees.rem_euclid(360.0);
+        if self.start <= self.end {
+            degrees >= self.start && degrees <= self.end
+        } else {
+            degrees >= self.start || degrees <= self.end
+        }
+    }
+
+    /// The size of the sector in degrees.
+    fn sweep(&self) -> f32 {
+        (self.end - self.start).rem_euclid(360.0)
+    }
+}
+
+/// Parses a comma separated list of masked sectors, each written as `start-end` in degrees, like
+/// "90-135, 350-10". An empty list masks nothing.
+pub fn parse_angle_masks(text: &str) -> Result<Vec<AngleMask>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let mut bounds = range
+                .splitn(2, '-')
+                .map(|bound| bound.trim().parse::<f32>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(start)), Some(Ok(end))) if start.is_finite() && end.is_finite() => {
+                    Ok(AngleMask {
+                        start: start.rem_euclid(360.0),
+                        end: end.rem_euclid(360.0),
+                    })
+                }
+                _ => Err(format!("{:?} isn't a range of degrees like 90-135", range)),
+            }
+        })
+        .collect()
+}
+
+/// A single return along with the number of scans since it was received.
+struct LidarPoint {
+    angle: f32,
+    distance: f32,
+    /// The return's intensity relative to the strongest return of its scan, from 0 to 1. Returns
+    /// without an intensity are drawn at full brightness.
+    intensity: f32,
+    age: u32,
+}
+
+/// A return as drawn on the current plot, in pixels from the top left corner of the plot, along
+/// with the angle in degrees and the distance it's labeled with.
+struct PlottedPoint {
+    position: (f32, f32),
+    degrees: f32,
+    distance: f32,
+}
+
+/// A cluster found in the polar plot, in pixels from the top left corner of the plot.
+struct ClusterMarker {
+    center: (f32, f32),
+    radius: f32,
+    count: usize,
 }
 
 pub struct LidarWindow {
+    /// The name shown in the window's title bar and in the list of active sensors.
+    pub name: String,
+    /// Keeps the window apart from other windows with the same name.
+    window_id: usize,
+    /// Where the main window has asked this window to move on its next frame.
+    placement: Option<Placement>,
+    /// Where the window was drawn on its last frame.
+    shown_at: Option<Placement>,
     texture_id: Option<TextureId>,
     receiver: Receiver<LidarData>,
-    lidar_data: Vec<(f32, f32)>,
+    lidar_data: Vec<LidarPoint>,
+    pub angle_offset: f32,
+    /// Whether the LIDAR's angles grow clockwise rather than counterclockwise.
+    pub clockwise: bool,
+    /// Whether the plot is mirrored left to right, for LIDARs mounted upside down.
+    pub mirror_x: bool,
+    /// Whether returns from previous scans stay on screen, fading out, instead of only showing the
+    /// latest scan.
+    pub accumulate: bool,
+    /// The number of scans an accumulated return is kept for before it's dropped.
+    pub max_age: u32,
+    pub view_mode: LidarViewMode,
+    /// Whether the LIDAR reports angles in degrees rather than radians.
+    pub angles_in_degrees: bool,
+    /// The unit the LIDAR reports distances in, set through `set_distance_unit`.
+    distance_unit: DistanceUnit,
+    pub spec: Option<SensorSpec>,
+    /// Lets the sensor feeding the window be restarted from it, when the window started the
+    /// sensor itself.
+    pub restartable: Option<RestartableSensor>,
+    pub stats: Arc<SensorStats>,
+    /// The bytes per second received recently, for the throughput graph.
+    throughput: ThroughputHistory,
+    /// Whether the plot on screen is frozen. Scans that arrive while paused are thrown away.
+    pub paused: bool,
+    /// The plot is dimmed, and the LIDAR shown as stale, once the latest scan is older than this.
+    pub stale_after: Duration,
+    /// The width and height of the plot, in pixels.
+    pub image_dim: f32,
+    /// The size the current texture was drawn at.
+    drawn_dim: f32,
+    /// The plot the current texture was drawn from, kept for saving it as an image.
+    plot: RgbImage,
+    snapshot_status: Option<String>,
+    ring_spacing: f32,
+    /// When the scan on screen was captured.
+    last_capture: Option<Instant>,
+    /// The plot is redrawn at most once per this interval, however fast scans arrive.
+    pub min_redraw_interval: Duration,
+    /// When the plot was last drawn and uploaded.
+    last_redraw: Option<Instant>,
+    /// Whether scans have been added since the plot was last drawn.
+    redraw_pending: bool,
+    /// Whether nearby returns are grouped into clusters, which the polar plot circles and labels
+    /// with their size.
+    pub show_clusters: bool,
+    /// The largest distance between neighboring returns of the same cluster.
+    pub cluster_epsilon: f32,
+    /// Groups with fewer returns than this aren't shown as clusters.
+    pub cluster_min_points: usize,
+    /// The clusters drawn on the current plot.
+    clusters: Vec<ClusterMarker>,
+    /// Whether the return nearest the mouse is labeled with its angle and distance.
+    pub show_point_labels: bool,
+    /// Where each return was drawn on the current plot, for finding the one under the mouse.
+    plotted: Vec<PlottedPoint>,
+    /// Only every this many returns are drawn, to keep very dense scans responsive. 1 draws every
+    /// return.
+    pub decimation: usize,
+    /// If set, the returns are split into buckets of this many degrees and only the nearest
+    /// return of each bucket is drawn.
+    pub angular_bin: Option<f32>,
+    /// If set, each return's distance is averaged with the distances at the same angle in up to
+    /// this many of the latest scans, so a static scene doesn't jitter from scan to scan.
+    pub smoothing_scans: Option<usize>,
+    smoother: ScanSmoother,
+    /// Whether the plot is scaled to `fit_percentile` of the return distances rather than the
+    /// farthest return, so a few spurious far returns don't shrink everything else.
+    pub fit_to_data: bool,
+    pub fit_percentile: f32,
+    /// Sectors whose returns aren't drawn. They're shaded on the plot so it's clear what's
+    /// hidden.
+    pub angle_masks: Vec<AngleMask>,
+    pub point_shape: PointShape,
+    /// The size of each return in pixels. Returns are drawn as single pixels at a radius of 0,
+    /// whatever the shape.
+    pub point_radius: u32,
+    /// Whether the last plot couldn't be uploaded, so only the first of a run of failures raises
+    /// a notification.
+    texture_failing: bool,
+    notifications: Vec<Notification>,
 }
 
 impl LidarWindow {
     pub fn new(receiver: Receiver<LidarData>) -> Self {
         Self {
+            name: "LIDAR".to_string(),
+            window_id: window::next_window_id(),
+            placement: None,
+            shown_at: None,
             texture_id: None,
             receiver,
             lidar_data: Vec::new(),
+            angle_offset: DEFAULT_ANGLE_OFFSET,
+            clockwise: false,
+            mirror_x: false,
+            accumulate: false,
+            max_age: DEFAULT_MAX_AGE,
+            view_mode: LidarViewMode::Polar,
+            angles_in_degrees: false,
+            distance_unit: DistanceUnit::Raw,
+            spec: None,
+            restartable: None,
+            stats: Arc::new(SensorStats::new()),
+            throughput: ThroughputHistory::new(),
+            paused: false,
+            stale_after: DEFAULT_STALE_AFTER,
+            image_dim: DEFAULT_IMAGE_DIM,
+            drawn_dim: DEFAULT_IMAGE_DIM,
+            plot: RgbImage::new(0, 0),
+            snapshot_status: None,
+            ring_spacing: 0.0,
+            last_capture: None,
+            min_redraw_interval: DEFAULT_MIN_REDRAW_INTERVAL,
+            last_redraw: None,
+            redraw_pending: false,
+            show_clusters: false,
+            cluster_epsilon: DistanceUnit::Raw.default_cluster_epsilon(),
+            cluster_min_points: DEFAULT_CLUSTER_MIN_POINTS,
+            clusters: Vec::new(),
+            show_point_labels: false,
+            plotted: Vec::new(),
+            decimation: 1,
+            angular_bin: None,
+            smoothing_scans: None,
+            smoother: ScanSmoother::new(),
+            fit_to_data: false,
+            fit_percentile: DEFAULT_FIT_PERCENTILE,
+            angle_masks: Vec::new(),
+            point_shape: PointShape::Circle,
+            point_radius: DEFAULT_POINT_RADIUS,
+            texture_failing: false,
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Sets the unit the LIDAR reports distances in, along with the cluster distance, which is
+    /// in the unit distances are plotted in.
+    pub fn set_distance_unit(&mut self, distance_unit: DistanceUnit) {
+        self.distance_unit = distance_unit;
+        self.cluster_epsilon = distance_unit.default_cluster_epsilon();
+    }
+
+    /// Plots the returns as seen from above, with the LIDAR in the center of the image.
+    fn draw_polar(&mut self, image: &mut RgbImage, image_dim: f32) {
+        let scale = plot_scale(&self.lidar_data, image_dim, self.scale_percentile());
+        self.ring_spacing = ring_spacing(scale, image_dim);
+        self.draw_polar_masks(image, image_dim);
+        draw_grid(image, scale, self.ring_spacing);
+
+        // Draw a green dot in the center of the LIDAR display to represent the LIDAR.
+        let center_color = Rgb([0u8, 255u8, 0u8]);
+        let center = (image_dim / 2.0) as i32;
+        draw_filled_circle_mut(image, (center, center), 2, center_color);
+
+        // Older returns fade towards black. They come first in the list, so the latest scan is
+        // drawn on top.
+        let points = self.drawn_points();
+        let mut positions = Vec::with_capacity(points.len());
+        let mut plotted = Vec::with_capacity(points.len());
+        for point in points {
+            let position = project(
+                angle_to_radians(point.angle, self.angles_in_degrees),
+                point.distance,
+                self.angle_offset,
+                self.clockwise,
+                self.mirror_x,
+            );
+            let x = scale * position.0 + image_dim / 2.0;
+            let y = image_dim / 2.0 - position.1 * scale;
+            let color = self.point_color(point);
+            draw_point(
+                image,
+                (x as i32, y as i32),
+                self.point_shape,
+                self.point_radius,
+                color,
+            );
+            positions.push(position);
+            plotted.push(PlottedPoint {
+                position: (x, y),
+                degrees: point_degrees(point.angle, self.angles_in_degrees),
+                distance: point.distance,
+            });
+        }
+        self.plotted = plotted;
+
+        // Clusters are circled on top of the returns, which stay visible underneath.
+        if self.show_clusters {
+            let color = Rgb(CLUSTER_COLOR);
+            for cluster in cluster_points(&positions, self.cluster_epsilon, self.cluster_min_points)
+            {
+                let count = cluster.len() as f32;
+                let centroid = cluster.iter().fold((0.0, 0.0), |sum, index| {
+                    let (x, y) = positions[*index];
+                    (sum.0 + x / count, sum.1 + y / count)
+                });
+                let radius = cluster
+                    .iter()
+                    .map(|index| {
+                        let (x, y) = positions[*index];
+                        (x - centroid.0).hypot(y - centroid.1)
+                    })
+                    .fold(0.0, f32::max);
+                let marker = ClusterMarker {
+                    center: (
+                        scale * centroid.0 + image_dim / 2.0,
+                        image_dim / 2.0 - centroid.1 * scale,
+                    ),
+                    radius: (radius * scale).max(MIN_CLUSTER_RADIUS),
+                    count: cluster.len(),
+                };
+                draw_hollow_circle_mut(
+                    image,
+                    (marker.center.0 as i32, marker.center.1 as i32),
+                    marker.radius as i32,
+                    color,
+                );
+                self.clusters.push(marker);
+            }
+        }
+    }
+
+    /// Shades the masked sectors of the polar plot by sweeping lines out from the center, close
+    /// enough together that they fill the sector out to the edge of the plot.
+    fn draw_polar_masks(&self, image: &mut RgbImage, image_dim: f32) {
+        let radius = image_dim / 2.0;
+        for mask in &self.angle_masks {
+            let steps = (mask.sweep().to_radians() * radius).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let degrees = mask.start + mask.sweep() * step as f32 / steps as f32;
+                let direction = project(
+                    degrees.to_radians(),
+                    radius,
+                    self.angle_offset,
+                    self.clockwise,
+                    self.mirror_x,
+                );
+                let end = (radius + direction.0, radius - direction.1);
+                draw_line_segment_mut(image, (radius, radius), end, Rgb(MASK_COLOR));
+            }
+        }
+    }
+
+    /// Plots the returns as a strip chart, with the angle from 0 to 360 degrees along the x-axis
+    /// and the distance growing upwards from the bottom of the image.
+    fn draw_angle_distance(&mut self, image: &mut RgbImage, image_dim: f32) {
+        let scale = plot_scale(&self.lidar_data, image_dim, self.scale_percentile()) * 2.0;
+        // Masked sectors that wrap through 0 are shaded at both ends of the chart.
+        for mask in &self.angle_masks {
+            let ranges = if mask.start <= mask.end {
+                vec![(mask.start, mask.end)]
+            } else {
+                vec![(mask.start, 360.0), (0.0, mask.end)]
+            };
+            for (start, end) in ranges {
+                let x = (start / 360.0 * image_dim) as i32;
+                let width = ((end - start) / 360.0 * image_dim).ceil().max(1.0) as u32;
+                draw_filled_rect_mut(
+                    image,
+                    Rect::at(x, 0).of_size(width, image_dim as u32),
+                    Rgb(MASK_COLOR),
+                );
+            }
+        }
+        let points = self.drawn_points();
+        let mut plotted = Vec::with_capacity(points.len());
+        for point in points {
+            let degrees = point_degrees(point.angle, self.angles_in_degrees);
+            let x = degrees / 360.0 * image_dim;
+            let y = image_dim - point.distance * scale;
+            let color = self.point_color(point);
+            draw_point(
+                image,
+                (x as i32, y as i32),
+                self.point_shape,
+                self.point_radius,
+                color,
+            );
+            plotted.push(PlottedPoint {
+                position: (x, y),
+                degrees,
+                distance: point.distance,
+            });
+        }
+        self.plotted = plotted;
+    }
+
+    /// The returns to draw, after binning and decimation and leaving out the masked sectors. The
+    /// plot is still scaled to every return, so thinning them out doesn't change the scale.
+    fn drawn_points(&self) -> Vec<&LidarPoint> {
+        let mut points = thin_points(
+            &self.lidar_data,
+            self.decimation,
+            self.angular_bin,
+            self.angles_in_degrees,
+        );
+        if !self.angle_masks.is_empty() {
+            points.retain(|point| {
+                let degrees = angle_to_radians(point.angle, self.angles_in_degrees).to_degrees();
+                !self.angle_masks.iter().any(|mask| mask.contains(degrees))
+            });
+        }
+        points
+    }
+
+    /// The percentile of return distances the plot is scaled to, or `None` to scale it to the
+    /// farthest return.
+    fn scale_percentile(&self) -> Option<f32> {
+        if self.fit_to_data {
+            Some(self.fit_percentile)
+        } else {
+            None
+        }
+    }
+
+    /// The plot size to draw at, kept within the allowed range.
+    fn plot_dim(&self) -> f32 {
+        self.image_dim.clamp(MIN_IMAGE_DIM, MAX_IMAGE_DIM).round()
+    }
+
+    /// Draws the returns at the current plot size and uploads them as the window's texture.
+    fn upload_plot(&mut self, display: &Display, renderer: &mut Renderer) {
+        let image_dim = self.plot_dim();
+        let mut image = RgbImage::new(image_dim as u32, image_dim as u32);
+        self.clusters.clear();
+        match self.view_mode {
+            LidarViewMode::Polar => self.draw_polar(&mut image, image_dim),
+            LidarViewMode::AngleDistance => self.draw_angle_distance(&mut image, image_dim),
+        }
+        let uploaded = texture::upload_rgb(
+            display,
+            renderer,
+            &mut self.texture_id,
+            Cow::Borrowed(&image.as_raw()[..]),
+            image.width(),
+            image.height(),
+        );
+        self.notifications.extend(texture::report_upload(
+            uploaded,
+            &mut self.texture_failing,
+            &self.name,
+        ));
+        self.drawn_dim = image_dim;
+        self.plot = image;
+    }
+
+    /// Saves the plot on screen to a timestamped PNG file, remembering how it went so it can be
+    /// shown in the window.
+    fn save_plot(&mut self) {
+        let path = camera::snapshot_path("lidar");
+        self.snapshot_status = Some(match self.plot.save(&path) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(e) => format!("Couldn't save image: {}", e),
+        });
+    }
+
+    /// Colors a return by its age and intensity. Weak returns are dimmed but never drawn fully
+    /// black, so they stay visible.
+    fn point_color(&self, point: &LidarPoint) -> Rgb<u8> {
+        let fade = 1.0 - point.age as f32 / self.max_age.max(1) as f32;
+        let brightness =
+            MIN_INTENSITY_BRIGHTNESS + (1.0 - MIN_INTENSITY_BRIGHTNESS) * point.intensity;
+        Rgb([(255.0 * fade * brightness) as u8, 0u8, 0u8])
+    }
+
+    /// Adds a new scan to the displayed returns. In live mode the scan replaces the previous one,
+    /// otherwise the previous returns age by one scan and the ones past `max_age` are dropped.
+    fn add_scan(&mut self, mut scan: LidarData) {
+        if let Some(scans) = self.smoothing_scans {
+            self.smoother
+                .smooth(&mut scan.distances, scans, self.angles_in_degrees);
+        }
+        if self.accumulate {
+            age_points(&mut self.lidar_data, self.max_age);
+        } else {
+            self.lidar_data.clear();
+        }
+        let intensities = relative_intensities(&scan.intensities, scan.distances.len());
+        let distance_unit = self.distance_unit;
+        self.lidar_data
+            .extend(scan.distances.into_iter().zip(intensities).map(
+                |((angle, distance), intensity)| LidarPoint {
+                    angle,
+                    distance: distance_unit.to_meters(distance),
+                    intensity,
+                    age: 0,
+                },
+            ));
+    }
+}
+
+/// Averages each return's distance with the distances in the same bucket of angles in the
+/// previous scans. Moving objects still show up, just a few scans late, while the distances to
+/// static structure settle on their average.
+struct ScanSmoother {
+    /// The mean distance in each bucket of `SMOOTHING_BIN` degrees of the latest scans, oldest
+    /// first.
+    history: VecDeque<HashMap<i64, f32>>,
+}
+
+impl ScanSmoother {
+    fn new() -> Self {
+        Self {
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Forgets the previous scans, so smoothing starts over from the next scan.
+    fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Replaces each distance of a scan with its average over this scan and up to `scans - 1`
+    /// previous ones, then remembers the scan. Returns without a distance, reported as 0, and
+    /// invalid ones are left alone and don't count towards the average.
+    fn smooth(&mut self, distances: &mut [(f32, f32)], scans: usize, angles_in_degrees: bool) {
+        let bucket =
+            |angle: f32| (point_degrees(angle, angles_in_degrees) / SMOOTHING_BIN).floor() as i64;
+        let valid = |distance: f32| distance.is_finite() && distance > 0.0;
+        let mut sums: HashMap<i64, (f32, usize)> = HashMap::new();
+        for &(angle, distance) in distances.iter().filter(|(_, distance)| valid(*distance)) {
+            let sum = sums.entry(bucket(angle)).or_insert((0.0, 0));
+            sum.0 += distance;
+            sum.1 += 1;
+        }
+        while !self.history.is_empty() && self.history.len() >= scans {
+            self.history.pop_front();
+        }
+        for (angle, distance) in distances
+            .iter_mut()
+            .filter(|(_, distance)| valid(*distance))
+        {
+            let bucket = bucket(*angle);
+            let (sum, count) = self
+                .history
+                .iter()
+                .filter_map(|scan| scan.get(&bucket))
+                .fold((*distance, 1), |(sum, count), mean| (sum + mean, count + 1));
+            *distance = sum / count as f32;
+        }
+        self.history.push_back(
+            sums.into_iter()
+                .map(|(bucket, (sum, count))| (bucket, sum / count as f32))
+                .collect(),
+        );
+    }
+}
+
+/// Scales the intensities of a scan's returns relative to its strongest return. A scan without
+/// intensities, or without any positive ones, gets full intensity for each of its `len` returns.
+fn relative_intensities(intensities: &[f32], len: usize) -> Vec<f32> {
+    let max = intensities.iter().cloned().fold(0.0, f32::max);
+    if intensities.len() != len || max <= 0.0 {
+        return vec![1.0; len];
+    }
+    intensities
+        .iter()
+        .map(|intensity| (intensity / max).max(0.0))
+        .collect()
+}
+
+/// Groups points that are chained together by gaps of at most `epsilon`, returning the indices of
+/// the points in each group of at least `min_points`. Points are bucketed into a grid of
+/// `epsilon` sized cells so only the neighboring cells have to be searched for each point.
+/// Points that aren't finite, like a return with an infinite distance, never join a cluster.
+fn cluster_points(points: &[(f32, f32)], epsilon: f32, min_points: usize) -> Vec<Vec<usize>> {
+    if epsilon <= 0.0 || !epsilon.is_finite() {
+        return Vec::new();
+    }
+    let cell = |(x, y): (f32, f32)| ((x / epsilon).floor() as i64, (y / epsilon).floor() as i64);
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    let mut visited = vec![false; points.len()];
+    for (index, &(x, y)) in points.iter().enumerate() {
+        if x.is_finite() && y.is_finite() {
+            grid.entry(cell((x, y))).or_default().push(index);
+        } else {
+            visited[index] = true;
+        }
+    }
+
+    let mut clusters = Vec::new();
+    for start in 0..points.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut cluster = vec![start];
+        let mut next = 0;
+        while next < cluster.len() {
+            let (x, y) = points[cluster[next]];
+            let (cell_x, cell_y) = cell((x, y));
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    // Points far enough out land in the outermost cells, which have no neighbors
+                    // past them.
+                    let neighbor_cell = (cell_x.saturating_add(dx), cell_y.saturating_add(dy));
+                    let neighbors = match grid.get(&neighbor_cell) {
+                        Some(neighbors) => neighbors,
+                        None => continue,
+                    };
+                    for &neighbor in neighbors {
+                        let (nx, ny) = points[neighbor];
+                        if !visited[neighbor] && (nx - x).hypot(ny - y) <= epsilon {
+                            visited[neighbor] = true;
+                            cluster.push(neighbor);
+                        }
+                    }
+                }
+            }
+            next += 1;
+        }
+        if cluster.len() >= min_points.max(1) {
+            clusters.push(cluster);
         }
     }
+    clusters
+}
+
+/// Thins out the returns to draw. With `angular_bin` set, only the nearest return in each bucket
+/// of that many degrees is kept; then only every `decimation`th of the remaining returns is kept.
+/// The returns stay in their original order, so the latest scan is still drawn on top.
+fn thin_points(
+    points: &[LidarPoint],
+    decimation: usize,
+    angular_bin: Option<f32>,
+    angles_in_degrees: bool,
+) -> Vec<&LidarPoint> {
+    let mut kept: Vec<&LidarPoint> = match angular_bin.filter(|bin| *bin > 0.0) {
+        Some(bin) => {
+            let mut nearest: HashMap<i64, usize> = HashMap::new();
+            for (index, point) in points.iter().enumerate() {
+                let degrees = angle_to_radians(point.angle, angles_in_degrees)
+                    .to_degrees()
+                    .rem_euclid(360.0);
+                let bucket = (degrees / bin).floor() as i64;
+                let entry = nearest.entry(bucket).or_insert(index);
+                if point.distance < points[*entry].distance {
+                    *entry = index;
+                }
+            }
+            let mut indices: Vec<usize> = nearest.values().copied().collect();
+            indices.sort_unstable();
+            indices.into_iter().map(|index| &points[index]).collect()
+        }
+        None => points.iter().collect(),
+    };
+    if decimation > 1 {
+        kept = kept.into_iter().step_by(decimation).collect();
+    }
+    kept
+}
+
+/// Converts an angle reported by the LIDAR to radians.
+fn angle_to_radians(angle: f32, in_degrees: bool) -> f32 {
+    if in_degrees {
+        angle.to_radians()
+    } else {
+        angle
+    }
+}
+
+/// Converts an angle reported by the LIDAR to degrees between 0 and 360.
+fn point_degrees(angle: f32, in_degrees: bool) -> f32 {
+    angle_to_radians(angle, in_degrees)
+        .to_degrees()
+        .rem_euclid(360.0)
+}
+
+/// Finds the plotted return closest to the given pixel of the plot, as long as it's within
+/// `max_distance` pixels of it.
+fn nearest_point(
+    points: &[PlottedPoint],
+    (x, y): (f32, f32),
+    max_distance: f32,
+) -> Option<&PlottedPoint> {
+    points
+        .iter()
+        .map(|point| ((point.position.0 - x).hypot(point.position.1 - y), point))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .map(|(_, point)| point)
+}
+
+/// Projects a return onto the plane of the polar plot, with x growing to the right and y upwards.
+/// The zero point of the LIDAR rarely lines up with the forward direction of whatever it's
+/// mounted on, so the offset, in degrees counterclockwise, rotates the plot until "up" matches
+/// the physical forward direction. Clockwise angles are flipped before the offset is added, and
+/// mirroring flips the result left to right.
+fn project(
+    angle: f32,
+    distance: f32,
+    offset_degrees: f32,
+    clockwise: bool,
+    mirror_x: bool,
+) -> (f32, f32) {
+    let angle = if clockwise { -angle } else { angle } + offset_degrees.to_radians();
+    let x = distance * angle.cos();
+    let y = distance * angle.sin();
+    if mirror_x {
+        (-x, y)
+    } else {
+        (x, y)
+    }
+}
+
+/// Ages every point by one scan and drops the points that have reached `max_age`.
+fn age_points(points: &mut Vec<LidarPoint>, max_age: u32) {
+    for point in points.iter_mut() {
+        point.age += 1;
+    }
+    points.retain(|point| point.age < max_age);
+}
+
+/// Calculates the scale that places the farthest return of a scan near the edge of the plot. With
+/// a percentile given, the return at that percentile of the distances is placed there instead,
+/// and the returns beyond it fall off the plot.
+fn plot_scale(points: &[LidarPoint], image_dim: f32, percentile: Option<f32>) -> f32 {
+    let distances: Vec<f32> = points.iter().map(|point| point.distance).collect();
+    let max_distance = match percentile {
+        Some(percentile) => distance_percentile(distances, percentile),
+        None => distances.into_iter().fold(0.0, f32::max),
+    };
+    if max_distance > 0.0 {
+        image_dim / 2.0 * PLOT_MARGIN / max_distance
+    } else {
+        DEFAULT_SCALE
+    }
+}
+
+/// Finds the distance at the given percentile, from 0 to 100, using the nearest rank. Returns 0
+/// for an empty scan.
+fn distance_percentile(mut distances: Vec<f32>, percentile: f32) -> f32 {
+    distances.retain(|distance| distance.is_finite());
+    if distances.is_empty() {
+        return 0.0;
+    }
+    distances.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * distances.len() as f32).ceil() as usize;
+    distances[rank.max(1) - 1]
+}
+
+/// Picks a round distance between range rings, 1, 2 or 5 times a power of ten, so that about
+/// `TARGET_RINGS` rings fit within the plot at the given scale.
+fn ring_spacing(scale: f32, image_dim: f32) -> f32 {
+    let max_range = image_dim / 2.0 / scale;
+    let rough = max_range / TARGET_RINGS;
+    let magnitude = 10f32.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|step| step * magnitude)
+        .find(|spacing| *spacing >= rough)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Draws concentric range rings `ring_spacing` apart and spokes every `SPOKE_SPACING_DEGREES`
+/// around the center of the image.
+fn draw_grid(image: &mut RgbImage, scale: f32, ring_spacing: f32) {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 || ring_spacing <= 0.0 || !ring_spacing.is_finite() {
+        return;
+    }
+    let color = Rgb([70u8, 70u8, 70u8]);
+    let center = (width as f32 / 2.0, height as f32 / 2.0);
+    let radius = center.0.min(center.1);
+
+    let mut ring = 1.0;
+    while ring * ring_spacing * scale <= radius {
+        let ring_radius = (ring * ring_spacing * scale) as i32;
+        draw_hollow_circle_mut(
+            image,
+            (center.0 as i32, center.1 as i32),
+            ring_radius,
+            color,
+        );
+        ring += 1.0;
+    }
+
+    for degrees in (0..360).step_by(SPOKE_SPACING_DEGREES as usize) {
+        let angle = (degrees as f32).to_radians();
+        let end = (
+            center.0 + radius * angle.cos(),
+            center.1 - radius * angle.sin(),
+        );
+        draw_line_segment_mut(image, center, end, color);
+    }
+}
+
+/// Draws a single return centered on the given pixel. Returns partly off the image are clipped.
+fn draw_point(
+    image: &mut RgbImage,
+    (x, y): (i32, i32),
+    shape: PointShape,
+    radius: u32,
+    color: Rgb<u8>,
+) {
+    match shape {
+        PointShape::Circle if radius > 0 => {
+            draw_filled_circle_mut(image, (x, y), radius as i32, color)
+        }
+        PointShape::Cross if radius > 0 => {
+            let (x, y, radius) = (x as f32, y as f32, radius as f32);
+            draw_line_segment_mut(image, (x - radius, y), (x + radius, y), color);
+            draw_line_segment_mut(image, (x, y - radius), (x, y + radius), color);
+        }
+        _ => {
+            let (width, height) = image.dimensions();
+            if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                image.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+}
+
+/// Shows a combo box choosing how returns are drawn, returning whether the choice changed.
+fn point_shape_combo(ui: &Ui, shape: &mut PointShape) -> bool {
+    let labels = [im_str!("Circle"), im_str!("Pixel"), im_str!("Cross")];
+    let mut shape_item = POINT_SHAPES.iter().position(|s| s == shape).unwrap_or(0);
+    let changed = imgui::ComboBox::new(im_str!("Point Shape")).build_simple_string(
+        ui,
+        &mut shape_item,
+        &labels,
+    );
+    if changed {
+        *shape = POINT_SHAPES[shape_item];
+    }
+    changed
 }
 
 impl Renderable for LidarWindow {
-    fn render(&mut self, ui: &Ui, display: &Display, renderer: &mut Renderer) {
-        // TODO: right now the scale factor is static to make it work. In the future, we should
-        // figure out a better way to handle the scale better.
-        let scale = 0.03;
-        let image_dim = 400.0;
-        if let Ok(lidar_data) = self.receiver.try_recv() {
-            self.lidar_data = lidar_data.distances;
-            let mut image = RgbImage::new(image_dim as u32, image_dim as u32);
-            let color = Rgb([255u8, 0u8, 0u8]);
-
-            // Draw a green dot in the center of the LIDAR display to represent the LIDAR.
-            let center_color = Rgb([0u8, 255u8, 0u8]);
-            let center = (image_dim / 2.0) as i32;
-            draw_filled_circle_mut(&mut image, (center, center), 2, center_color);
-
-            for (angle, distance) in self.lidar_data.iter() {
-                // TODO: the zero point of the LIDAR is not what I expected, so the mount point is
-                // off by approximately 90 degrees. This corrects that error to make the LIDAR plot
-                // significantly more useable. This should probably be configurable.
-                let angle = (angle - 90.0) % 360.0;
-                let x = scale * distance * angle.cos() + image_dim / 2.0;
-                let y = image_dim / 2.0 - (distance * angle.sin()) * scale;
-                draw_filled_circle_mut(&mut image, (x as i32, y as i32), 2, color);
-            }
-            let image_frame = Some(RawImage2d {
-                data: Cow::Owned(image.into_vec()),
-                width: image_dim as u32,
-                height: image_dim as u32,
-                format: ClientFormat::U8U8U8,
-            })
-            .unwrap();
-            let gl_texture = Texture2d::new(display.get_context(), image_frame)
-                .expect("Couldn't create new texture");
-            if let Some(tex_id) = self.texture_id {
-                renderer.textures().replace(tex_id, Rc::new(gl_texture));
-            } else {
-                self.texture_id = Some(renderer.textures().insert(Rc::new(gl_texture)));
+    fn render(
+        &mut self,
+        ui: &Ui,
+        display: &Display,
+        renderer: &mut Renderer,
+        visible: bool,
+        all_paused: bool,
+    ) -> bool {
+        let mut opened = true;
+        self.throughput.sample(&self.stats);
+
+        // Every pending scan is taken from the channel, even while paused so the LIDAR doesn't
+        // back up, but the plot is only redrawn once `min_redraw_interval` has passed since the
+        // last redraw. Scans that arrive in between are added to the plot all the same.
+        let paused = self.paused || all_paused;
+        let scans: Vec<LidarData> = self.receiver.try_iter().collect();
+        for lidar_data in scans {
+            if !paused {
+                self.last_capture = Some(lidar_data.captured_at);
+                self.add_scan(lidar_data);
+                self.redraw_pending = true;
             }
         }
+        let redraw_due = self
+            .last_redraw
+            .is_none_or(|last_redraw| last_redraw.elapsed() >= self.min_redraw_interval);
+        if self.redraw_pending && !paused && redraw_due {
+            self.upload_plot(display, renderer);
+            self.last_redraw = Some(Instant::now());
+            self.redraw_pending = false;
+        } else if self.texture_id.is_some() && self.plot_dim() != self.drawn_dim {
+            // Resizing the plot redraws the current returns right away rather than waiting for
+            // the next scan.
+            self.upload_plot(display, renderer);
+        }
+
+        if !visible {
+            return true;
+        }
 
         // We call this each iteration of the LidarWindow, so we need to make sure we draw the
         // window even if we didn't receive LIDAR data on this iteration. However, we currently
         // do not draw a window unless we've received our first sample from the LIDAR.
+        let title = window::window_title(&self.name, self.window_id);
+        let mut restarted = None;
         if let Some(tex_id) = self.texture_id {
-            let image_dims = [image_dim, image_dim];
-            Window::new(im_str!("LIDAR"))
+            let image_dims =
+                window::logical_size([self.drawn_dim, self.drawn_dim], window::scale_factor(ui));
+            let image_dim = &mut self.image_dim;
+            let accumulate = &mut self.accumulate;
+            let view_mode = &mut self.view_mode;
+            let angles_in_degrees = &mut self.angles_in_degrees;
+            let angle_offset = &mut self.angle_offset;
+            let clockwise = &mut self.clockwise;
+            let mirror_x = &mut self.mirror_x;
+            let paused = &mut self.paused;
+            let ring_spacing = self.ring_spacing;
+            let unit_suffix = self.distance_unit.suffix();
+            let clusters = &self.clusters;
+            let plotted = &self.plotted;
+            let show_point_labels = &mut self.show_point_labels;
+            let scale_factor = window::scale_factor(ui);
+            let show_clusters = &mut self.show_clusters;
+            let cluster_epsilon = &mut self.cluster_epsilon;
+            let mut cluster_min_points = self.cluster_min_points as i32;
+            let mut decimation = self.decimation as i32;
+            let mut bin_angles = self.angular_bin.is_some();
+            let mut bin_degrees = self.angular_bin.unwrap_or(DEFAULT_ANGULAR_BIN);
+            let mut smooth = self.smoothing_scans.is_some();
+            let mut smoothing_scans =
+                self.smoothing_scans.unwrap_or(DEFAULT_SMOOTHING_SCANS) as i32;
+            let mut redraw = false;
+            let mut save = false;
+            let snapshot_status = &self.snapshot_status;
+            let fit_to_data = &mut self.fit_to_data;
+            let fit_percentile = &mut self.fit_percentile;
+            let point_shape = &mut self.point_shape;
+            let mut point_radius = self.point_radius as i32;
+            let scan_age = self
+                .last_capture
+                .map(|captured_at| captured_at.elapsed())
+                .unwrap_or_default();
+            // A paused plot is old on purpose, so it isn't dimmed.
+            let stale = !*paused && !all_paused && scan_age > self.stale_after;
+            let stats = &self.stats;
+            let throughput = &self.throughput;
+            let stale_after = self.stale_after;
+            let restartable = &mut self.restartable;
+            let spec = self.spec.as_ref();
+            let shown_at = &mut self.shown_at;
+            Window::new(&title)
+                .opened(&mut opened)
                 .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+                .place(self.placement.take())
                 .build(ui, || {
-                    Image::new(tex_id, image_dims).build(&ui);
+                    *shown_at = Some(window::current_placement(ui));
+                    // A stale plot is dimmed so it isn't mistaken for what the LIDAR sees now.
+                    let tint = if stale {
+                        [0.4, 0.4, 0.4, 1.0]
+                    } else {
+                        [1.0, 1.0, 1.0, 1.0]
+                    };
+                    let origin = ui.cursor_screen_pos();
+                    Image::new(tex_id, image_dims).tint_col(tint).build(ui);
+                    // The cluster sizes are drawn by imgui, since the plot has no fonts of its
+                    // own, just outside the top right of each cluster's circle.
+                    let draw_list = ui.get_window_draw_list();
+                    let label_color = [
+                        CLUSTER_COLOR[0] as f32 / 255.0,
+                        CLUSTER_COLOR[1] as f32 / 255.0,
+                        CLUSTER_COLOR[2] as f32 / 255.0,
+                    ];
+                    for cluster in clusters {
+                        let offset = cluster.radius * FRAC_1_SQRT_2;
+                        draw_list.add_text(
+                            [
+                                origin[0] + cluster.center.0 + offset,
+                                origin[1] + cluster.center.1 - offset - ui.current_font_size(),
+                            ],
+                            label_color,
+                            cluster.count.to_string(),
+                        );
+                    }
+                    // The mouse is in logical pixels, while the plot is drawn in physical ones.
+                    if *show_point_labels && ui.is_item_hovered() {
+                        let mouse = ui.io().mouse_pos;
+                        let pixel = (
+                            (mouse[0] - origin[0]) * scale_factor,
+                            (mouse[1] - origin[1]) * scale_factor,
+                        );
+                        if let Some(point) = nearest_point(plotted, pixel, LABEL_PICK_RADIUS) {
+                            let center = [
+                                origin[0] + point.position.0 / scale_factor,
+                                origin[1] + point.position.1 / scale_factor,
+                            ];
+                            draw_list
+                                .add_circle(center, LABEL_PICK_RADIUS / 2.0, POINT_LABEL_COLOR)
+                                .build();
+                            draw_list.add_text(
+                                [
+                                    center[0] + LABEL_PICK_RADIUS,
+                                    center[1] - ui.current_font_size(),
+                                ],
+                                POINT_LABEL_COLOR,
+                                format!(
+                                    "{:.1} degrees, {:.2}{}",
+                                    point.degrees, point.distance, unit_suffix
+                                ),
+                            );
+                        }
+                    }
+                    diagnostics::connection_status(ui, stats, stale_after);
+                    diagnostics::dropped_frames(ui, stats);
+                    diagnostics::throughput_graph(ui, throughput);
+                    let age_text = format!("Last scan {:.2}s ago", scan_age.as_secs_f32());
+                    if stale {
+                        ui.text_colored([1.0, 0.2, 0.2, 1.0], age_text);
+                    } else {
+                        ui.text(age_text);
+                    }
+                    if *view_mode == LidarViewMode::Polar {
+                        ui.text(format!("Range rings every {}{}", ring_spacing, unit_suffix));
+                    }
+                    let mode = if *accumulate {
+                        im_str!("Mode: Accumulate")
+                    } else {
+                        im_str!("Mode: Live")
+                    };
+                    if ui.button(mode, [0.0, 0.0]) {
+                        *accumulate = !*accumulate;
+                    }
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("Pause"), paused);
+                    ui.same_line(0.0);
+                    let view = match *view_mode {
+                        LidarViewMode::Polar => im_str!("View: Polar"),
+                        LidarViewMode::AngleDistance => im_str!("View: Angle/Distance"),
+                    };
+                    if ui.button(view, [0.0, 0.0]) {
+                        *view_mode = match *view_mode {
+                            LidarViewMode::Polar => LidarViewMode::AngleDistance,
+                            LidarViewMode::AngleDistance => LidarViewMode::Polar,
+                        };
+                    }
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("Angles in Degrees"), angles_in_degrees);
+                    ui.checkbox(im_str!("Label Return Under Mouse"), show_point_labels);
+                    Slider::new(im_str!("Plot Size"), MIN_IMAGE_DIM..=MAX_IMAGE_DIM)
+                        .build(ui, image_dim);
+                    let fit = if *fit_to_data {
+                        im_str!("Auto Scale")
+                    } else {
+                        im_str!("Fit to Data")
+                    };
+                    if ui.button(fit, [0.0, 0.0]) {
+                        *fit_to_data = !*fit_to_data;
+                        redraw = true;
+                    }
+                    if *fit_to_data {
+                        ui.same_line(0.0);
+                        redraw |= Slider::new(im_str!("Percentile"), 50.0..=100.0)
+                            .build(ui, fit_percentile);
+                    }
+                    if *view_mode == LidarViewMode::Polar {
+                        redraw |= ui
+                            .input_float(im_str!("Angle Offset"), angle_offset)
+                            .build();
+                        redraw |= ui.checkbox(im_str!("Clockwise Angles"), clockwise);
+                        ui.same_line(0.0);
+                        redraw |= ui.checkbox(im_str!("Mirror X"), mirror_x);
+                    }
+                    save = ui.button(im_str!("Save Image"), [0.0, 0.0]);
+                    if let Some(status) = snapshot_status {
+                        ui.same_line(0.0);
+                        ui.text(status);
+                    }
+                    redraw |= Slider::new(im_str!("Draw Every Nth Return"), 1..=20)
+                        .build(ui, &mut decimation);
+                    redraw |= ui.checkbox(im_str!("Angular Binning"), &mut bin_angles);
+                    if bin_angles {
+                        redraw |= Slider::new(im_str!("Bin Size (degrees)"), 0.1..=10.0)
+                            .build(ui, &mut bin_degrees);
+                    }
+                    ui.checkbox(im_str!("Smooth Over Scans"), &mut smooth);
+                    if smooth {
+                        Slider::new(im_str!("Scans to Average"), 2..=MAX_SMOOTHING_SCANS)
+                            .build(ui, &mut smoothing_scans);
+                    }
+                    redraw |= point_shape_combo(ui, point_shape);
+                    if *point_shape != PointShape::Pixel {
+                        redraw |= Slider::new(im_str!("Point Radius"), 0..=MAX_POINT_RADIUS)
+                            .build(ui, &mut point_radius);
+                    }
+                    redraw |= ui.checkbox(im_str!("Show Clusters"), show_clusters);
+                    if *show_clusters {
+                        redraw |= ui
+                            .input_float(im_str!("Cluster Distance"), cluster_epsilon)
+                            .build();
+                        redraw |= Slider::new(im_str!("Min Points"), 1..=50)
+                            .build(ui, &mut cluster_min_points);
+                    }
+                    connection::connection_help(ui, spec);
+                    if let Some(sensor) = restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
                 });
+            self.cluster_min_points = cluster_min_points.max(1) as usize;
+            self.decimation = decimation.max(1) as usize;
+            self.point_radius = point_radius.max(0) as u32;
+            self.angular_bin = if bin_angles {
+                Some(bin_degrees.max(0.1))
+            } else {
+                None
+            };
+            // Smoothing only applies to scans as they arrive. Turning it off forgets the scans it
+            // had seen, so turning it back on doesn't average in an old view of the scene.
+            self.smoothing_scans = if smooth {
+                Some(smoothing_scans.max(2) as usize)
+            } else {
+                self.smoother.clear();
+                None
+            };
+            // Thinning and clustering settings are applied to the returns on screen right away,
+            // even while paused.
+            if redraw {
+                self.upload_plot(display, renderer);
+            }
+            if save {
+                self.save_plot();
+            }
         } else {
-            Window::new(im_str!("LIDAR")).build(ui, || {
-                ui.text(im_str!("Waiting for LIDAR data..."));
-            });
+            let stats = &self.stats;
+            let throughput = &self.throughput;
+            let stale_after = self.stale_after;
+            let restartable = &mut self.restartable;
+            let spec = self.spec.as_ref();
+            let shown_at = &mut self.shown_at;
+            Window::new(&title)
+                .opened(&mut opened)
+                .place(self.placement.take())
+                .build(ui, || {
+                    *shown_at = Some(window::current_placement(ui));
+                    ui.text(im_str!("Waiting for LIDAR data..."));
+                    diagnostics::connection_status(ui, stats, stale_after);
+                    diagnostics::dropped_frames(ui, stats);
+                    diagnostics::throughput_graph(ui, throughput);
+                    connection::connection_help(ui, spec);
+                    if let Some(sensor) = restartable {
+                        restarted = server::restart_controls(ui, sensor);
+                    }
+                });
         }
+        if let Some(address) = restarted {
+            if let Some(spec) = &mut self.spec {
+                spec.set_address(address);
+            }
+        }
+        opened
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reset_texture(&mut self) {
+        self.texture_id = None;
+    }
+
+    fn has_pending_data(&self) -> bool {
+        !self.receiver.is_empty() || (self.redraw_pending && !self.paused)
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        self.spec.clone()
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        Some(self.stats.clone())
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        mem::take(&mut self.notifications)
+    }
+
+    fn capture(&self, path: &Path) -> Option<ImageResult<()>> {
+        if self.plot.width() == 0 {
+            return None;
+        }
+        Some(self.plot.save(path))
+    }
+
+    fn set_placement(&mut self, placement: Placement) {
+        self.placement = Some(placement);
+    }
+
+    fn take_join_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        self.restartable
+            .as_mut()
+            .map_or_else(Vec::new, RestartableSensor::take_handles)
+    }
+
+    fn current_placement(&self) -> Option<Placement> {
+        self.shown_at
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
     }
 }
 
 pub struct LidarConfig {
+    name: ImString,
     lidar_port: ImString,
+    /// Why the last attempt to create the sensor failed, if it did.
+    address_error: Option<String>,
+    format_list: Vec<ImString>,
+    format_item: usize,
+    checksummed: bool,
+    unit_list: Vec<ImString>,
+    unit_item: usize,
+    capture_raw: bool,
+    capture_path: ImString,
+    buffer_kib: i32,
+    read_timeout_secs: f32,
+    scan_timeout_secs: f32,
+    max_connections: i32,
+    stale_after_secs: f32,
+    min_redraw_ms: i32,
+    channel: ChannelConfig,
+    endianness: Endianness,
+    transport: Transport,
+    angle_offset: f32,
+    clockwise: bool,
+    mirror_x: bool,
+    angle_masks: ImString,
+    /// Why the masked sectors couldn't be parsed, if they couldn't.
+    mask_error: Option<String>,
+    tls: ServerTlsConfig,
 }
 
 impl LidarConfig {
     pub fn new() -> Self {
         let mut lidar_port = ImString::new("8002");
-        lidar_port.reserve_exact(10);
-        Self { lidar_port }
+        lidar_port.reserve_exact(256);
+        let format_list: Vec<ImString> = LidarFormat::iter()
+            .map(|format| {
+                let format_str: &str = format.as_ref();
+                ImString::new(format_str)
+            })
+            .collect();
+        let unit_list: Vec<ImString> = DistanceUnit::iter()
+            .map(|unit| {
+                let unit_str: &str = unit.as_ref();
+                ImString::new(unit_str)
+            })
+            .collect();
+        let mut capture_path = ImString::new("lidar_capture.bin");
+        capture_path.reserve_exact(256);
+        let mut angle_masks = ImString::new("");
+        angle_masks.reserve_exact(256);
+        let mut name = ImString::new("LIDAR");
+        name.reserve_exact(64);
+        Self {
+            name,
+            lidar_port,
+            address_error: None,
+            format_list,
+            format_item: 0,
+            checksummed: false,
+            unit_list,
+            unit_item: 0,
+            capture_raw: false,
+            capture_path,
+            buffer_kib: (DEFAULT_BUFFER_SIZE / 1024) as i32,
+            read_timeout_secs: 0.0,
+            scan_timeout_secs: 0.0,
+            max_connections: server::DEFAULT_MAX_CONNECTIONS as i32,
+            stale_after_secs: DEFAULT_STALE_AFTER.as_secs_f32(),
+            min_redraw_ms: DEFAULT_MIN_REDRAW_INTERVAL.as_millis() as i32,
+            channel: ChannelConfig::new(DEFAULT_CHANNEL_CAPACITY, DEFAULT_OVERFLOW_POLICY),
+            endianness: Endianness::Little,
+            transport: Transport::Tcp,
+            angle_offset: DEFAULT_ANGLE_OFFSET,
+            clockwise: false,
+            mirror_x: false,
+            angle_masks,
+            mask_error: None,
+            tls: ServerTlsConfig::new(),
+        }
     }
 }
 
 impl Modal for LidarConfig {
+    fn sensor_name(&self) -> &'static ImStr {
+        im_str!("LIDAR")
+    }
+
+    fn popup_name(&self) -> &'static ImStr {
+        im_str!("LIDAR Configuration")
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        self.lidar_port = ImString::new(settings.lidar_port.to_string());
+        self.lidar_port.reserve_exact(256);
+        self.channel.capacity = settings.lidar_channel_capacity as i32;
+    }
+
     fn render_modal(
         &mut self,
         ui: &Ui,
         join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
         sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        _shutdown: &Arc<AtomicBool>,
     ) {
-        ui.popup_modal(im_str!("LIDAR Configuration"))
+        ui.popup_modal(self.popup_name())
             .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
             .build(|| {
-                ui.input_text(im_str!("Listen Port"), &mut self.lidar_port)
+                ui.input_text(im_str!("Window Name"), &mut self.name)
+                    .build();
+                ui.input_text(im_str!("Listen Port or Address"), &mut self.lidar_port)
                     .build();
+                if let Some(e) = &self.address_error {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], e);
+                }
+                let format_slices: Vec<&ImString> = self.format_list.iter().collect();
+                imgui::ComboBox::new(im_str!("Wire Format")).build_simple_string(
+                    ui,
+                    &mut self.format_item,
+                    &format_slices,
+                );
+                let unit_slices: Vec<&ImString> = self.unit_list.iter().collect();
+                imgui::ComboBox::new(im_str!("Distance Unit")).build_simple_string(
+                    ui,
+                    &mut self.unit_item,
+                    &unit_slices,
+                );
+                ui.input_int(im_str!("Read Buffer (KiB)"), &mut self.buffer_kib)
+                    .build();
+                ui.input_float(
+                    im_str!("Read Timeout (s, 0 = off)"),
+                    &mut self.read_timeout_secs,
+                )
+                .build();
+                ui.input_int(im_str!("Max Connections"), &mut self.max_connections)
+                    .build();
+                ui.input_float(im_str!("Stale After (s)"), &mut self.stale_after_secs)
+                    .build();
+                ui.input_int(im_str!("Min Redraw Interval (ms)"), &mut self.min_redraw_ms)
+                    .build();
+                self.channel.render(ui);
+                byte_order_combo(ui, &mut self.endianness);
+                ui.checkbox(im_str!("Checksummed Frames (CRC32)"), &mut self.checksummed);
+                // Checksummed frames resynchronize on their own.
+                if !self.checksummed {
+                    ui.input_float(
+                        im_str!("Scan Timeout (s, 0 = off)"),
+                        &mut self.scan_timeout_secs,
+                    )
+                    .build();
+                }
+                server::transport_combo(ui, &mut self.transport);
+                // Datagrams can't be read over TLS.
+                if self.transport == Transport::Tcp {
+                    self.tls.render(ui);
+                }
+                ui.input_float(im_str!("Angle Offset"), &mut self.angle_offset)
+                    .build();
+                ui.checkbox(im_str!("Clockwise Angles"), &mut self.clockwise);
+                ui.checkbox(im_str!("Mirror X"), &mut self.mirror_x);
+                ui.input_text(im_str!("Masked Sectors (deg)"), &mut self.angle_masks)
+                    .build();
+                ui.text_disabled(im_str!("e.g. 90-135, 350-10"));
+                if let Some(e) = &self.mask_error {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], e);
+                }
+                ui.checkbox(im_str!("Capture Raw Bytes"), &mut self.capture_raw);
+                if self.capture_raw {
+                    ui.input_text(im_str!("Capture File"), &mut self.capture_path)
+                        .build();
+                }
                 if ui.button(im_str!("Create Sensor Window"), [0.0, 0.0]) {
-                    let (lidar_tx, lidar_rx) = unbounded();
-                    let lidar = Lidar::new(lidar_tx);
-                    join_handles.push(
-                        lidar.start(
-                            format!("0.0.0.0:{}", self.lidar_port.to_string())
-                                .parse()
-                                .expect("couldn't parse IP address"),
-                        ),
-                    );
-                    sensor_windows.push(Box::new(LidarWindow::new(lidar_rx)));
+                    let angle_masks = match parse_angle_masks(self.angle_masks.to_str()) {
+                        Ok(angle_masks) => angle_masks,
+                        Err(e) => {
+                            self.mask_error = Some(format!("Invalid masked sectors: {}", e));
+                            return;
+                        }
+                    };
+                    self.mask_error = None;
+                    let address = match parse_listen_addr(self.lidar_port.as_ref()) {
+                        Ok(address) => address,
+                        Err(e) => {
+                            self.address_error = Some(format!("Invalid listen address: {}", e));
+                            return;
+                        }
+                    };
+                    let tls_files = match self.transport {
+                        Transport::Tcp => self.tls.files(),
+                        Transport::Udp => None,
+                    };
+                    let tls = match tls_files.as_ref().map(|files| files.load()).transpose() {
+                        Ok(tls) => tls,
+                        Err(e) => {
+                            self.address_error = Some(format!("Couldn't set up TLS: {}", e));
+                            return;
+                        }
+                    };
+                    self.address_error = None;
+                    let (lidar_tx, lidar_rx) = self.channel.channel();
+                    let mut lidar = Lidar::new(lidar_tx);
+                    lidar.format =
+                        LidarFormat::from_str(self.format_list[self.format_item].as_ref()).unwrap();
+                    lidar.buffer_size = self.buffer_kib.max(1) as usize * 1024;
+                    lidar.read_timeout = server::read_timeout_from_secs(self.read_timeout_secs);
+                    lidar.max_connections = self.max_connections.max(1) as usize;
+                    lidar.endianness = self.endianness;
+                    lidar.checksummed = self.checksummed;
+                    lidar.scan_timeout = server::read_timeout_from_secs(self.scan_timeout_secs);
+                    lidar.transport = self.transport;
+                    lidar.tls = tls;
+                    if self.capture_raw {
+                        lidar.capture_path = Some(PathBuf::from(self.capture_path.to_string()));
+                    }
+                    let stats = lidar.stats.clone();
+                    let format = lidar.format.as_ref().to_string();
+                    let started = RestartableSensor::start(address, move |address, stop| {
+                        lidar.clone().start(address, stop)
+                    });
+                    let restartable = match started {
+                        Ok((restartable, handle)) => {
+                            join_handles.push(handle);
+                            restartable
+                        }
+                        Err(e) => {
+                            self.address_error = Some(e.to_string());
+                            return;
+                        }
+                    };
+                    let mut lidar_window = LidarWindow::new(lidar_rx);
+                    lidar_window.restartable = Some(restartable);
+                    lidar_window.name = window::window_name(&self.name, "LIDAR");
+                    lidar_window.angle_offset = self.angle_offset;
+                    lidar_window.clockwise = self.clockwise;
+                    lidar_window.mirror_x = self.mirror_x;
+                    lidar_window.angle_masks = angle_masks;
+                    let distance_unit = DistanceUnit::iter()
+                        .nth(self.unit_item)
+                        .unwrap_or(DistanceUnit::Raw);
+                    lidar_window.set_distance_unit(distance_unit);
+                    lidar_window.stale_after =
+                        diagnostics::stale_after_from_secs(self.stale_after_secs);
+                    lidar_window.min_redraw_interval =
+                        Duration::from_millis(self.min_redraw_ms.max(0) as u64);
+                    lidar_window.stats = stats;
+                    lidar_window.spec = Some(SensorSpec::Lidar {
+                        address,
+                        format,
+                        endianness: self.endianness,
+                        transport: self.transport,
+                        checksummed: self.checksummed,
+                        distance_unit: distance_unit.as_ref().to_string(),
+                        tls: tls_files,
+                    });
+                    sensor_windows.push(Box::new(lidar_window));
                     ui.close_current_popup();
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::channel;
+    use crate::shutdown::ShutdownReader;
+
+    /// Encodes a scan in the `Basic` format, little endian.
+    fn basic_scan(points: &[(f32, f32)]) -> Vec<u8> {
+        let mut bytes = (points.len() as u32).to_le_bytes().to_vec();
+        for (angle, distance) in points {
+            bytes.extend_from_slice(&angle.to_le_bytes());
+            bytes.extend_from_slice(&distance.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// A connection that delivers `before`, then has nothing to read for `stall`, then delivers
+    /// `after` and closes.
+    struct StallingStream {
+        before: Cursor<Vec<u8>>,
+        stall: Duration,
+        stall_ends: Option<Instant>,
+        after: Cursor<Vec<u8>>,
+    }
+
+    impl Read for StallingStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.before.position() < self.before.get_ref().len() as u64 {
+                return self.before.read(buf);
+            }
+            let stall = self.stall;
+            let stall_ends = *self
+                .stall_ends
+                .get_or_insert_with(|| Instant::now() + stall);
+            if Instant::now() < stall_ends {
+                thread::sleep(Duration::from_millis(5));
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.after.read(buf)
+        }
+    }
+
+    fn point(angle: f32, distance: f32, age: u32) -> LidarPoint {
+        LidarPoint {
+            angle,
+            distance,
+            intensity: 1.0,
+            age,
+        }
+    }
+
+    #[test]
+    fn the_farthest_return_stays_inside_the_plot() {
+        let image_dim = 400.0;
+        let scan = [
+            point(0.0, 1200.0, 0),
+            point(90.0, 5000.0, 0),
+            point(180.0, 40.0, 0),
+        ];
+        let scale = plot_scale(&scan, image_dim, None);
+        assert!(5000.0 * scale < image_dim / 2.0);
+        assert!(5000.0 * scale > image_dim / 2.0 * 0.8);
+    }
+
+    #[test]
+    fn fitting_to_a_percentile_ignores_outliers() {
+        let mut distances: Vec<f32> = (1..=99).map(|distance| distance as f32).collect();
+        distances.push(1e6);
+        distances.push(f32::INFINITY);
+        assert_eq!(distance_percentile(distances.clone(), 95.0), 95.0);
+        assert_eq!(distance_percentile(distances.clone(), 0.0), 1.0);
+        assert_eq!(distance_percentile(distances, 100.0), 1e6);
+        assert_eq!(distance_percentile(Vec::new(), 95.0), 0.0);
+
+        let scan: Vec<LidarPoint> = (1..=20)
+            .map(|distance| point(0.0, distance as f32 * 10.0, 0))
+            .chain(std::iter::once(point(0.0, 10_000.0, 0)))
+            .collect();
+        let image_dim = 400.0;
+        let scale = plot_scale(&scan, image_dim, Some(95.0));
+        assert!(200.0 * scale <= image_dim / 2.0);
+        assert!(10_000.0 * scale > image_dim / 2.0);
+    }
+
+    #[test]
+    fn points_chained_within_the_cluster_distance_are_grouped() {
+        let points = [
+            // A chain whose ends are further apart than the cluster distance.
+            (0.0, 0.0),
+            (0.8, 0.0),
+            (1.6, 0.0),
+            // A pair across the cells around the origin.
+            (-5.0, -5.0),
+            (-5.5, -5.2),
+            // A point on its own.
+            (10.0, 10.0),
+        ];
+        let sorted = |clusters: Vec<Vec<usize>>| -> Vec<Vec<usize>> {
+            clusters
+                .into_iter()
+                .map(|mut cluster| {
+                    cluster.sort();
+                    cluster
+                })
+                .collect()
+        };
+        assert_eq!(
+            sorted(cluster_points(&points, 1.0, 2)),
+            vec![vec![0, 1, 2], vec![3, 4]]
+        );
+        assert_eq!(sorted(cluster_points(&points, 1.0, 3)), vec![vec![0, 1, 2]]);
+        assert_eq!(
+            sorted(cluster_points(&points, 1.0, 1)),
+            vec![vec![0, 1, 2], vec![3, 4], vec![5]]
+        );
+        assert!(cluster_points(&points, 0.0, 1).is_empty());
+    }
+
+    #[test]
+    fn points_that_are_not_finite_are_left_out_of_clusters() {
+        // The positions of returns at an infinite distance, next to a pair of real returns.
+        let angle: f32 = 0.3;
+        let infinite = (f32::INFINITY * angle.cos(), f32::INFINITY * angle.sin());
+        let points = [
+            (0.0, 0.0),
+            infinite,
+            (0.5, 0.0),
+            (f32::NAN, 0.0),
+            (f32::MAX, f32::MAX),
+            (f32::MAX, f32::MAX),
+            infinite,
+        ];
+        let mut clusters = cluster_points(&points, 1.0, 2);
+        for cluster in clusters.iter_mut() {
+            cluster.sort();
+        }
+        assert_eq!(clusters, vec![vec![0, 2], vec![4, 5]]);
+    }
+
+    #[test]
+    fn scans_without_returns_keep_the_default_scale() {
+        assert_eq!(plot_scale(&[], 400.0, None), DEFAULT_SCALE);
+        let scan = [point(0.0, 0.0, 0), point(90.0, 0.0, 0)];
+        assert_eq!(plot_scale(&scan, 400.0, None), DEFAULT_SCALE);
+        assert_eq!(plot_scale(&scan, 400.0, Some(95.0)), DEFAULT_SCALE);
+    }
+
+    #[test]
+    fn returns_are_dropped_once_they_reach_the_max_age() {
+        let mut points = vec![
+            point(0.0, 1.0, 0),
+            point(90.0, 1.0, 3),
+            point(180.0, 1.0, 4),
+        ];
+        age_points(&mut points, 5);
+        let ages: Vec<u32> = points.iter().map(|point| point.age).collect();
+        assert_eq!(ages, vec![1, 4]);
+    }
+
+    #[test]
+    fn oversized_scans_are_refused_before_reading() {
+        let (sender, _receiver) = channel(4, OverflowPolicy::Block);
+        let mut lidar = Lidar::new(sender);
+        let stream = u32::MAX.to_le_bytes();
+        let error = lidar.handle_lidar_stream(&stream[..], None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn intensity_scans_carry_a_strength_per_return() {
+        let (sender, receiver) = channel(4, OverflowPolicy::Block);
+        let mut lidar = Lidar::new(sender);
+        lidar.format = LidarFormat::Intensity;
+        let mut stream = 2u32.to_le_bytes().to_vec();
+        for value in [0.5f32, 1.0, 0.25, 90.0, 2.0, 0.75].iter() {
+            stream.extend_from_slice(&value.to_le_bytes());
+        }
+        let error = lidar.handle_lidar_stream(&stream[..], None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let scan = receiver.try_recv().expect("the scan should have been read");
+        assert_eq!(scan.distances, vec![(0.5, 1.0), (90.0, 2.0)]);
+        assert_eq!(scan.intensities, vec![0.25, 0.75]);
+    }
+
+    #[test]
+    fn distances_are_converted_to_meters() {
+        assert_eq!(DistanceUnit::Millimeters.to_meters(1500.0), 1.5);
+        assert_eq!(DistanceUnit::Centimeters.to_meters(150.0), 1.5);
+        assert_eq!(DistanceUnit::Meters.to_meters(1.5), 1.5);
+        assert_eq!(DistanceUnit::Raw.to_meters(1500.0), 1500.0);
+    }
+
+    #[test]
+    fn the_cluster_distance_follows_the_distance_unit() {
+        let (_, receiver) = crossbeam::channel::unbounded();
+        let mut window = LidarWindow::new(receiver);
+        assert_eq!(window.cluster_epsilon, 200.0);
+        window.set_distance_unit(DistanceUnit::Millimeters);
+        assert_eq!(window.cluster_epsilon, 0.2);
+        window.set_distance_unit(DistanceUnit::Raw);
+        assert_eq!(window.cluster_epsilon, 200.0);
+    }
+
+    #[test]
+    fn a_truncated_scan_followed_by_a_complete_one_delivers_the_complete_one() {
+        let (sender, receiver) = channel(4, OverflowPolicy::Block);
+        let mut lidar = Lidar::new(sender);
+        lidar.scan_timeout = Some(Duration::from_millis(50));
+        // The count, a whole point and the next point's angle and half its distance.
+        let mut truncated = basic_scan(&[(1.0, 100.0), (2.0, 200.0)]);
+        truncated.truncate(4 + 8 + 4 + 2);
+        let stream = StallingStream {
+            before: Cursor::new(truncated),
+            stall: Duration::from_millis(200),
+            stall_ends: None,
+            after: Cursor::new(basic_scan(&[(3.0, 300.0), (4.0, 400.0)])),
+        };
+        let stream = ShutdownReader::new(stream, Arc::new(AtomicBool::new(false)));
+        let deadline = stream.deadline();
+
+        let ended = lidar.handle_lidar_stream(stream, Some(deadline));
+        assert_eq!(ended.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let scans: Vec<LidarData> = receiver.try_iter().collect();
+        assert_eq!(scans.len(), 1);
+        assert_eq!(scans[0].distances, vec![(3.0, 300.0), (4.0, 400.0)]);
+    }
+
+    #[test]
+    fn smoothing_noisy_scans_of_a_constant_distance_converges_on_it() {
+        const DISTANCE: f32 = 10.0;
+        const SCANS: usize = 30;
+        let mut smoother = ScanSmoother::new();
+        // A fixed pseudo-random sequence of noise from -0.5 to 0.5.
+        let mut seed: u32 = 12_345;
+        for scan in 0..SCANS * 3 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = (seed >> 16) as f32 / 65536.0 - 0.5;
+            let mut distances = [(90.0, DISTANCE + noise)];
+            smoother.smooth(&mut distances, SCANS, true);
+            let smoothed = distances[0].1;
+            if scan >= SCANS {
+                assert!(
+                    (smoothed - DISTANCE).abs() < 0.15,
+                    "scan {} smoothed to {}",
+                    scan,
+                    smoothed
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::diagnostics::SensorStats;
+use crate::notifications::Notification;
+use crate::session::SensorSpec;
+use crate::window::Renderable;
+use glium::Display;
+use image::ImageResult;
+use imgui::{self, im_str, Condition, ImString, Ui, Window};
+use imgui_glium_renderer::Renderer;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+/// The most log records kept for the log window. Older records are dropped first.
+const MAX_RECORDS: usize = 1000;
+
+/// A log record as kept for the log window.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// The most recent log records, shared between the logger and the log window.
+pub type LogBuffer = Arc<Mutex<VecDeque<LogRecord>>>;
+
+/// A logger that writes to the console like `env_logger` does, and also keeps the records it lets
+/// through in a buffer the log window reads, for when there's no console to look at.
+struct BufferLogger {
+    inner: env_logger::Logger,
+    buffer: LogBuffer,
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        if let Ok(mut buffer) = self.buffer.lock() {
+            if buffer.len() >= MAX_RECORDS {
+                buffer.pop_front();
+            }
+            buffer.push_back(LogRecord {
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger, which logs sensor connections and errors unless RUST_LOG asks for
+/// something else, and returns the buffer it keeps records in.
+pub fn init() -> LogBuffer {
+    let inner =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let max_level = inner.filter();
+    let buffer = LogBuffer::default();
+    let logger = BufferLogger {
+        inner,
+        buffer: buffer.clone(),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+    buffer
+}
+
+/// The least severe level of record shown in the log window.
+#[derive(AsRefStr, EnumIter, Clone, Copy, Debug, PartialEq)]
+enum LevelChoice {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelChoice {
+    fn filter(self) -> LevelFilter {
+        match self {
+            LevelChoice::Error => LevelFilter::Error,
+            LevelChoice::Warn => LevelFilter::Warn,
+            LevelChoice::Info => LevelFilter::Info,
+            LevelChoice::Debug => LevelFilter::Debug,
+            LevelChoice::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// The color a record of the given level is shown in.
+fn level_color(level: Level) -> [f32; 4] {
+    match level {
+        Level::Error => [1.0, 0.2, 0.2, 1.0],
+        Level::Warn => [1.0, 0.8, 0.2, 1.0],
+        Level::Info => [1.0, 1.0, 1.0, 1.0],
+        Level::Debug | Level::Trace => [0.6, 0.6, 0.6, 1.0],
+    }
+}
+
+/// Shows the most recent log records, newest first so new warnings show up without scrolling.
+pub struct LogWindow {
+    buffer: LogBuffer,
+    level_list: Vec<ImString>,
+    level_item: usize,
+}
+
+impl LogWindow {
+    pub fn new(buffer: LogBuffer) -> Self {
+        let level_list: Vec<ImString> = LevelChoice::iter()
+            .map(|level| {
+                let level_str: &str = level.as_ref();
+                ImString::new(level_str)
+            })
+            .collect();
+        Self {
+            buffer,
+            level_list,
+            level_item: LevelChoice::iter()
+                .position(|level| level == LevelChoice::Info)
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl Renderable for LogWindow {
+    fn render(
+        &mut self,
+        ui: &Ui,
+        _display: &Display,
+        _renderer: &mut Renderer,
+        visible: bool,
+        _all_paused: bool,
+    ) -> bool {
+        if !visible {
+            return true;
+        }
+        let mut opened = true;
+        let buffer = &self.buffer;
+        let level_list = &self.level_list;
+        let level_item = &mut self.level_item;
+        Window::new(im_str!("Log"))
+            .opened(&mut opened)
+            .size([600.0, 300.0], Condition::FirstUseEver)
+            .build(ui, || {
+                let level_slices: Vec<&ImString> = level_list.iter().collect();
+                imgui::ComboBox::new(im_str!("Level")).build_simple_string(
+                    ui,
+                    level_item,
+                    &level_slices,
+                );
+                let filter = LevelChoice::iter()
+                    .nth(*level_item)
+                    .unwrap_or(LevelChoice::Info)
+                    .filter();
+                ui.same_line(0.0);
+                let clear = ui.button(im_str!("Clear"), [0.0, 0.0]);
+                ui.separator();
+
+                let mut buffer = match buffer.lock() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                if clear {
+                    buffer.clear();
+                }
+                for record in buffer.iter().rev().filter(|record| record.level <= filter) {
+                    ui.text_colored(
+                        level_color(record.level),
+                        format!("[{}] {}: {}", record.level, record.target, record.message),
+                    );
+                }
+            });
+        opened
+    }
+
+    fn name(&self) -> &str {
+        "Log"
+    }
+
+    fn reset_texture(&mut self) {}
+
+    fn has_pending_data(&self) -> bool {
+        false
+    }
+
+    fn sensor_spec(&self) -> Option<SensorSpec> {
+        None
+    }
+
+    fn stats(&self) -> Option<Arc<SensorStats>> {
+        None
+    }
+
+    fn take_notifications(&mut self) -> Vec<Notification> {
+        Vec::new()
+    }
+
+    fn capture(&self, _path: &Path) -> Option<ImageResult<()>> {
+        None
+    }
+}
@@ -1,14 +1,64 @@
 mod camera;
+mod capture;
+mod channel;
+mod cli;
+mod clipboard;
+mod connection;
 mod controller;
+mod detached;
+mod diagnostics;
+mod endian;
+mod framing;
 mod gps;
+mod headless;
+mod health;
 mod lidar;
+mod logs;
+mod notifications;
+mod playback;
+mod server;
+mod session;
+mod settings;
+mod shutdown;
+mod texture;
+mod tls;
 mod window;
+mod yuv;
 
+use std::env;
 use std::io;
+use std::path::Path;
+use std::process;
 use window::SensorWindow;
 
 fn main() -> io::Result<()> {
-    let window = SensorWindow::new();
-    window.render();
+    let log_buffer = logs::init();
+    let args = match cli::parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.headless {
+        let output_dir = args.output_dir.as_deref().unwrap_or_else(|| Path::new("."));
+        return headless::run(&args.sensors, output_dir, args.health_address);
+    }
+    let window = match SensorWindow::new(args.sensors, args.health_address, log_buffer) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if args.selftest {
+        window.selftest();
+    } else {
+        window.render();
+    }
     Ok(())
 }
@@ -0,0 +1,137 @@
+use imgui::{im_str, Condition, Ui, Window, WindowFlags};
+use std::time::{Duration, Instant};
+
+/// How long a notification stays on screen unless another duration is given.
+const DEFAULT_TTL: Duration = Duration::from_secs(5);
+
+/// The distance in pixels between the notifications and the corner of the main window.
+const CORNER_MARGIN: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn color(self) -> [f32; 4] {
+        match self {
+            Level::Info => [1.0, 1.0, 1.0, 1.0],
+            Level::Warn => [1.0, 0.8, 0.2, 1.0],
+            Level::Error => [1.0, 0.2, 0.2, 1.0],
+        }
+    }
+}
+
+/// A message for the user that doesn't belong to any one window, like a sensor that couldn't be
+/// started or a window running into trouble.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn info<S: Into<String>>(message: S) -> Self {
+        Self {
+            level: Level::Info,
+            message: message.into(),
+        }
+    }
+
+    pub fn warn<S: Into<String>>(message: S) -> Self {
+        Self {
+            level: Level::Warn,
+            message: message.into(),
+        }
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+        }
+    }
+}
+
+/// The notifications currently on screen, drawn as toasts in the top right corner of the main
+/// window until they expire.
+pub struct Notifications {
+    toasts: Vec<(Notification, Instant)>,
+    /// How long notifications pushed with `push` stay on screen.
+    pub ttl: Duration,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self {
+            toasts: Vec::new(),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Shows a notification for the default duration.
+    pub fn push(&mut self, notification: Notification) {
+        self.push_with_ttl(notification, self.ttl);
+    }
+
+    /// Shows a notification for the given duration.
+    pub fn push_with_ttl(&mut self, notification: Notification, ttl: Duration) {
+        self.toasts.push((notification, Instant::now() + ttl));
+    }
+
+    /// Drops the notifications that have expired by `now`.
+    pub fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|(_, expires_at)| *expires_at > now);
+    }
+
+    /// Draws the notifications that haven't expired yet, newest at the bottom.
+    pub fn render(&mut self, ui: &Ui) {
+        self.expire(Instant::now());
+        if self.toasts.is_empty() {
+            return;
+        }
+        let display_size = ui.io().display_size;
+        let toasts = &self.toasts;
+        Window::new(im_str!("Notifications"))
+            .position(
+                [display_size[0] - CORNER_MARGIN, CORNER_MARGIN],
+                Condition::Always,
+            )
+            .position_pivot([1.0, 0.0])
+            .flags(
+                WindowFlags::NO_TITLE_BAR
+                    | WindowFlags::ALWAYS_AUTO_RESIZE
+                    | WindowFlags::NO_MOVE
+                    | WindowFlags::NO_SAVED_SETTINGS
+                    | WindowFlags::NO_FOCUS_ON_APPEARING
+                    | WindowFlags::NO_NAV,
+            )
+            .build(ui, || {
+                for (notification, _) in toasts {
+                    ui.text_colored(notification.level.color(), &notification.message);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_expire_after_their_ttl() {
+        let mut notifications = Notifications::new();
+        notifications.push_with_ttl(Notification::info("short"), Duration::from_secs(1));
+        notifications.push_with_ttl(Notification::error("long"), Duration::from_secs(60));
+
+        notifications.expire(Instant::now() + Duration::from_secs(2));
+        assert_eq!(notifications.toasts.len(), 1);
+        assert_eq!(notifications.toasts[0].0.message, "long");
+        assert_eq!(notifications.toasts[0].0.level, Level::Error);
+
+        notifications.expire(Instant::now() + Duration::from_secs(61));
+        assert!(notifications.toasts.is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::camera::{self, decode_jpeg, CameraData, CameraWindow};
+use crate::channel::{self, OverflowPolicy, SensorSender};
+use crate::diagnostics::SensorStats;
+use crate::shutdown::POLL_INTERVAL;
+use crate::window::{Modal, Renderable};
+use byteorder::{LittleEndian, ReadBytesExt};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use imgui::{im_str, ImStr, ImString, Slider, Ui, WindowFlags};
+use log::{info, warn};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The default rate recordings are played back at, in frames per second.
+const DEFAULT_FRAME_RATE: f32 = 30.0;
+
+/// The slowest and fastest playback can go, as multiples of the frame rate.
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.0;
+
+/// What the playback controls in the camera window ask the playback thread to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackCommand {
+    /// Plays back this many times faster than the frame rate.
+    SetSpeed(f32),
+    SetPaused(bool),
+    /// Pauses and shows the frame this many frames after the current one, or before it if
+    /// negative.
+    Step(isize),
+    /// Shows the frame with the given index and carries on playing from there unless paused.
+    Seek(usize),
+}
+
+/// Which frame playback is on and whether it's paused, written by the playback thread and read
+/// by the controls.
+#[derive(Default)]
+pub struct PlaybackPosition {
+    frame: AtomicUsize,
+    frame_count: AtomicUsize,
+    paused: AtomicBool,
+}
+
+/// Plays back a recorded MJPEG file, using the same length-prefixed framing as the camera
+/// protocol, into a camera window without needing a live connection.
+pub struct FilePlayback {
+    sender: SensorSender<CameraData>,
+    pub stats: Arc<SensorStats>,
+    pub frame_rate: f32,
+    /// Whether playback starts over from the beginning once the end of the file is reached.
+    pub looping: bool,
+    commands: Receiver<PlaybackCommand>,
+    position: Arc<PlaybackPosition>,
+}
+
+impl FilePlayback {
+    /// Creates the playback along with the controls that drive it, which go in the camera window
+    /// the playback is shown in.
+    pub fn new(sender: SensorSender<CameraData>) -> (Self, PlaybackControls) {
+        let (command_tx, command_rx) = unbounded();
+        let position = Arc::new(PlaybackPosition::default());
+        let playback = Self {
+            sender,
+            stats: Arc::new(SensorStats::new()),
+            frame_rate: DEFAULT_FRAME_RATE,
+            looping: false,
+            commands: command_rx,
+            position: position.clone(),
+        };
+        let controls = PlaybackControls {
+            commands: command_tx,
+            position,
+            speed: 1.0,
+        };
+        (playback, controls)
+    }
+
+    /// Starts playing back the file at `path` on a new thread. The file is indexed first so that
+    /// the controls can seek and step backwards. Playback pauses on the last frame rather than
+    /// stopping unless it's looping, and stops once the controls are gone with their window.
+    pub fn start(self, path: PathBuf, shutdown: Arc<AtomicBool>) -> JoinHandle<io::Result<()>> {
+        info!("playback: playing back {}", path.display());
+        thread::spawn(move || {
+            let mut file = BufReader::new(File::open(&path)?);
+            let offsets = index_frames(&mut file)?;
+            if offsets.is_empty() {
+                warn!("playback: {} has no frames", path.display());
+                return Ok(());
+            }
+            self.position
+                .frame_count
+                .store(offsets.len(), Ordering::Relaxed);
+            let last = offsets.len() - 1;
+            let frame_interval = Duration::from_secs_f32(1.0 / self.frame_rate.max(0.1));
+            let mut speed = 1.0;
+            let mut paused = false;
+            let mut current = 0;
+            let mut next = 0;
+            // Set when the controls ask for a frame, which is shown even while paused.
+            let mut requested = true;
+            while !shutdown.load(Ordering::Relaxed) {
+                if next > last {
+                    if self.looping {
+                        next = 0;
+                    } else {
+                        paused = true;
+                    }
+                }
+                self.position.paused.store(paused, Ordering::Relaxed);
+                if (!paused || requested) && next <= last {
+                    file.seek(SeekFrom::Start(offsets[next]))?;
+                    if let Some(bytes) = read_frame(&mut file)? {
+                        match decode_jpeg(bytes) {
+                            Ok(camera_data) => self.sender.send(camera_data, &self.stats)?,
+                            Err(e) => warn!("playback: error decoding the image: {:?}", e),
+                        }
+                    }
+                    self.position.frame.store(next, Ordering::Relaxed);
+                    current = next;
+                    next += 1;
+                    requested = false;
+                }
+
+                let wait = if paused {
+                    POLL_INTERVAL
+                } else {
+                    frame_interval.div_f32(speed)
+                };
+                let command = match self.wait_for_command(wait, &shutdown) {
+                    Ok(Some(command)) => command,
+                    Ok(None) => continue,
+                    Err(()) => return Ok(()),
+                };
+                match command {
+                    PlaybackCommand::SetSpeed(new_speed) => {
+                        speed = new_speed.clamp(MIN_SPEED, MAX_SPEED)
+                    }
+                    PlaybackCommand::SetPaused(new_paused) => {
+                        paused = new_paused;
+                        // Playing again after the end of the file starts over.
+                        if !paused && next > last {
+                            next = 0;
+                        }
+                    }
+                    PlaybackCommand::Step(frames) => {
+                        paused = true;
+                        next = ((current as isize + frames).max(0) as usize).min(last);
+                        requested = true;
+                    }
+                    PlaybackCommand::Seek(frame) => {
+                        next = frame.min(last);
+                        requested = true;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Waits up to `timeout` for a command from the controls, returning early if one arrives.
+    /// Fails once the controls have been dropped or the application is shutting down.
+    fn wait_for_command(
+        &self,
+        timeout: Duration,
+        shutdown: &AtomicBool,
+    ) -> Result<Option<PlaybackCommand>, ()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                return Err(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                // A command could have arrived right as the wait ran out.
+                return match self.commands.try_recv() {
+                    Ok(command) => Ok(Some(command)),
+                    Err(TryRecvError::Empty) => Ok(None),
+                    Err(TryRecvError::Disconnected) => Err(()),
+                };
+            }
+            match self
+                .commands
+                .recv_timeout((deadline - now).min(POLL_INTERVAL))
+            {
+                Ok(command) => return Ok(Some(command)),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Err(()),
+            }
+        }
+    }
+}
+
+/// Reads through the whole file for where each frame starts. A frame cut short by the end of the
+/// file, as happens when a recording is interrupted, is left out.
+fn index_frames<R: Read>(reader: &mut R) -> io::Result<Vec<u64>> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    loop {
+        let size = match reader.read_u32::<LittleEndian>() {
+            Ok(size) => u64::from(size),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(offsets),
+            Err(e) => return Err(e),
+        };
+        if io::copy(&mut reader.by_ref().take(size), &mut io::sink())? < size {
+            warn!("playback: the last frame of the recording is incomplete");
+            return Ok(offsets);
+        }
+        offsets.push(offset);
+        offset += 4 + size;
+    }
+}
+
+/// The speed, pause, step and seek controls shown in the camera window of a playback.
+pub struct PlaybackControls {
+    commands: Sender<PlaybackCommand>,
+    position: Arc<PlaybackPosition>,
+    speed: f32,
+}
+
+impl PlaybackControls {
+    pub fn render(&mut self, ui: &Ui) {
+        // The thread only goes away along with the window, so failed sends can be ignored.
+        if Slider::new(im_str!("Speed"), MIN_SPEED..=MAX_SPEED)
+            .display_format(im_str!("%.2fx"))
+            .build(ui, &mut self.speed)
+        {
+            let _ = self.commands.send(PlaybackCommand::SetSpeed(self.speed));
+        }
+        let paused = self.position.paused.load(Ordering::Relaxed);
+        let pause_label = if paused {
+            im_str!("Play")
+        } else {
+            im_str!("Pause Playback")
+        };
+        if ui.button(pause_label, [0.0, 0.0]) {
+            let _ = self.commands.send(PlaybackCommand::SetPaused(!paused));
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("Step Back"), [0.0, 0.0]) {
+            let _ = self.commands.send(PlaybackCommand::Step(-1));
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("Step Forward"), [0.0, 0.0]) {
+            let _ = self.commands.send(PlaybackCommand::Step(1));
+        }
+
+        let frame_count = self.position.frame_count.load(Ordering::Relaxed);
+        if frame_count > 0 {
+            let mut frame = self.position.frame.load(Ordering::Relaxed) as i32;
+            if Slider::new(im_str!("Frame"), 0..=frame_count as i32 - 1).build(ui, &mut frame) {
+                let _ = self
+                    .commands
+                    .send(PlaybackCommand::Seek(frame.max(0) as usize));
+            }
+        }
+    }
+}
+
+/// Reads the next length-prefixed frame, returning `None` at the end of the file.
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let size = match reader.read_u32::<LittleEndian>() {
+        Ok(size) => size as usize,
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut bytes = vec![0; size];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+pub struct PlaybackConfig {
+    file_path: ImString,
+    frame_rate: f32,
+    looping: bool,
+}
+
+impl PlaybackConfig {
+    pub fn new() -> Self {
+        let mut file_path = ImString::new("camera_recording.mjpeg");
+        file_path.reserve_exact(256);
+        Self {
+            file_path,
+            frame_rate: DEFAULT_FRAME_RATE,
+            looping: false,
+        }
+    }
+}
+
+impl Modal for PlaybackConfig {
+    fn sensor_name(&self) -> &'static ImStr {
+        im_str!("Playback")
+    }
+
+    fn popup_name(&self) -> &'static ImStr {
+        im_str!("Playback Configuration")
+    }
+
+    fn render_modal(
+        &mut self,
+        ui: &Ui,
+        join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
+        sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        shutdown: &Arc<AtomicBool>,
+    ) {
+        ui.popup_modal(self.popup_name())
+            .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+            .build(|| {
+                ui.input_text(im_str!("Recording File"), &mut self.file_path)
+                    .build();
+                ui.input_float(im_str!("Frame Rate"), &mut self.frame_rate)
+                    .build();
+                ui.checkbox(im_str!("Loop"), &mut self.looping);
+
+                if ui.button(im_str!("Play"), [0.0, 0.0]) {
+                    // Playback is paced already, so waiting for the window doesn't slow it down
+                    // unless the UI can't keep up with the frame rate.
+                    let (camera_tx, camera_rx) =
+                        channel::channel(camera::DEFAULT_CHANNEL_CAPACITY, OverflowPolicy::Block);
+                    let (mut playback, controls) = FilePlayback::new(camera_tx);
+                    playback.frame_rate = self.frame_rate;
+                    playback.looping = self.looping;
+                    let mut camera_window = CameraWindow::new(camera_rx);
+                    camera_window.stats = playback.stats.clone();
+                    camera_window.playback = Some(controls);
+                    join_handles.push(
+                        playback.start(PathBuf::from(self.file_path.to_string()), shutdown.clone()),
+                    );
+                    sensor_windows.push(Box::new(camera_window));
+                    ui.close_current_popup();
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use image::jpeg::JpegEncoder;
+    use image::ColorType;
+    use std::io::Write;
+
+    /// Writes a recording with a solid gray frame of each of the given sizes, returning its path.
+    fn write_recording(name: &str, frames: &[(u32, u32)]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("sensorview-{}-{}.mjpeg", name, std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        for &(width, height) in frames.iter() {
+            let mut jpeg = Vec::new();
+            JpegEncoder::new(&mut jpeg)
+                .encode(
+                    &vec![90; (width * height * 3) as usize],
+                    width,
+                    height,
+                    ColorType::Rgb8,
+                )
+                .unwrap();
+            file.write_u32::<LittleEndian>(jpeg.len() as u32).unwrap();
+            file.write_all(&jpeg).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn frames_are_played_back_in_order() {
+        let frames = [(8, 8), (16, 4)];
+        let path = write_recording("in-order", &frames);
+
+        let (sender, receiver) = channel::channel(frames.len(), OverflowPolicy::Block);
+        let (mut playback, controls) = FilePlayback::new(sender);
+        playback.frame_rate = 1000.0;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = playback.start(path.clone(), shutdown);
+        let timeout = Duration::from_secs(2);
+        let played: Vec<(u32, u32)> = (0..frames.len())
+            .map(|_| receiver.recv_timeout(timeout).unwrap())
+            .map(|frame| (frame.width, frame.height))
+            .collect();
+        assert_eq!(played, frames.to_vec());
+
+        // Playback pauses on the last frame and only stops once its controls are gone.
+        drop(controls);
+        handle.join().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_controls_seek_and_step_through_the_recording() {
+        let frames = [(8, 8), (16, 4), (4, 16)];
+        let path = write_recording("controls", &frames);
+
+        let (sender, receiver) = channel::channel(frames.len(), OverflowPolicy::Block);
+        let (mut playback, controls) = FilePlayback::new(sender);
+        // Slow enough that only the commands move playback along during the test.
+        playback.frame_rate = 0.1;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = playback.start(path.clone(), shutdown);
+        let timeout = Duration::from_secs(2);
+        let next_size = || {
+            let frame = receiver.recv_timeout(timeout).unwrap();
+            (frame.width, frame.height)
+        };
+        assert_eq!(next_size(), (8, 8));
+
+        controls.commands.send(PlaybackCommand::Seek(2)).unwrap();
+        assert_eq!(next_size(), (4, 16));
+        controls.commands.send(PlaybackCommand::Step(-1)).unwrap();
+        assert_eq!(next_size(), (16, 4));
+        controls.commands.send(PlaybackCommand::Step(-5)).unwrap();
+        assert_eq!(next_size(), (8, 8));
+        assert_eq!(controls.position.frame.load(Ordering::Relaxed), 0);
+        assert_eq!(controls.position.frame_count.load(Ordering::Relaxed), 3);
+
+        drop(controls);
+        handle.join().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+use crate::diagnostics::{SensorStats, StatsReader};
+use crate::shutdown::{self, ShutdownReader, POLL_INTERVAL};
+use crate::window::parse_listen_addr;
+use imgui::{im_str, ImStr, ImString, Ui};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor};
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The most connections a single sensor listener serves at once unless it's configured otherwise.
+/// Further connections are refused until one of the existing connections closes.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 8;
+
+/// The longest the listener waits before accepting again after accepting a connection failed.
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The largest datagram a UDP sensor can receive, which is the most a UDP datagram can carry.
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+/// How long a restarted sensor keeps trying to bind its address while the listener it replaces
+/// shuts down.
+const RESTART_BIND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a sensor's data reaches sensorview. Over `Tcp` devices connect and stream messages, while
+/// over `Udp` each datagram carries whole messages and there's no connection.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// The transports in the order they're listed in the config modals.
+const TRANSPORTS: [Transport; 2] = [Transport::Tcp, Transport::Udp];
+
+/// Shows a combo box for picking the transport in a sensor's config modal.
+pub fn transport_combo(ui: &Ui, transport: &mut Transport) {
+    let labels: [&ImStr; 2] = [im_str!("TCP"), im_str!("UDP")];
+    let mut item = TRANSPORTS
+        .iter()
+        .position(|choice| choice == transport)
+        .unwrap_or(0);
+    if imgui::ComboBox::new(im_str!("Transport")).build_simple_string(ui, &mut item, &labels) {
+        *transport = TRANSPORTS[item];
+    }
+}
+
+/// The longest read timeout a config modal can set, in seconds. Longer values, which
+/// `Duration::from_secs_f32` would panic on if they're infinite or huge, are cut down to it.
+const MAX_READ_TIMEOUT_SECS: f32 = 3600.0;
+
+/// Converts the read timeout typed into a config modal, in seconds, into the timeout passed to
+/// `serve`. Zero or less turns the timeout off.
+pub fn read_timeout_from_secs(secs: f32) -> Option<Duration> {
+    if secs > 0.0 {
+        Some(Duration::from_secs_f32(secs.min(MAX_READ_TIMEOUT_SECS)))
+    } else {
+        None
+    }
+}
+
+/// Binds the listener for a sensor. Binding is done before the sensor thread is spawned so that
+/// the config modal can tell the user right away if the port can't be used.
+pub fn bind(address: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(address).map_err(|e| match e.kind() {
+        io::ErrorKind::AddrInUse => {
+            io::Error::new(e.kind(), format!("Port {} already in use", address.port()))
+        }
+        _ => e,
+    })
+}
+
+/// Binds the socket for a sensor receiving datagrams, for the same reasons as `bind`.
+pub fn bind_udp(address: SocketAddr) -> io::Result<UdpSocket> {
+    UdpSocket::bind(address).map_err(|e| match e.kind() {
+        io::ErrorKind::AddrInUse => {
+            io::Error::new(e.kind(), format!("Port {} already in use", address.port()))
+        }
+        _ => e,
+    })
+}
+
+/// Receives datagrams on the socket until `shutdown` is set, handing each one to `handler` as a
+/// reader over its bytes. Each datagram is expected to hold whole messages, so the handler running
+/// out of bytes just means the datagram is done; a datagram cut short loses the message it ends
+/// in. `stats` counts the received bytes, and the sensor counts as connected from the first
+/// datagram on.
+///
+/// As with `serve`, a handler failing with `ConnectionAborted` means the sensor window is gone,
+/// so the socket stops receiving. Any other error only drops the rest of that datagram.
+pub fn serve_udp<F>(
+    sensor: &'static str,
+    socket: &UdpSocket,
+    shutdown: &Arc<AtomicBool>,
+    stats: &Arc<SensorStats>,
+    handler: F,
+) -> io::Result<()>
+where
+    F: Fn(Cursor<Vec<u8>>) -> io::Result<()>,
+{
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+    let mut last_peer = None;
+    while !shutdown.load(Ordering::Relaxed) {
+        let (size, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(e) => {
+                warn!("{}: couldn't receive a datagram: {}", sensor, e);
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        if last_peer != Some(peer) {
+            info!("{}: receiving datagrams from {}", sensor, peer);
+            stats.set_peer(peer);
+            if last_peer.is_none() {
+                stats.connection_opened();
+            }
+            last_peer = Some(peer);
+        }
+        stats.add_bytes(size);
+        match handler(Cursor::new(buffer[..size].to_vec())) {
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => {
+                info!("{}: stopped receiving datagrams: {}", sensor, e);
+                break;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            Err(e) => warn!("{}: dropping a datagram from {}: {}", sensor, peer, e),
+            Ok(()) => (),
+        }
+    }
+    if last_peer.is_some() {
+        stats.connection_closed();
+    }
+    Ok(())
+}
+
+/// Accepts connections on the listener until `shutdown` is set, handling each connection on its
+/// own thread so that several devices can stream to the same sensor at once. `sensor` names the
+/// kind of sensor in log messages, and `stats` counts the open connections and received bytes and
+/// remembers why the last connection failed.
+/// Connections that send nothing for `read_timeout`, if set, are closed so the device can connect
+/// again. Connections beyond the first `max_connections` are closed right away and recorded in
+/// `stats`, so the window can tell the user which device was turned away.
+///
+/// A handler failing with `ConnectionAborted` means the sensor window is gone, so the listener
+/// stops accepting connections. Any other error, including the device disconnecting, only closes
+/// that one connection and the device can simply connect again.
+pub fn serve<F>(
+    sensor: &'static str,
+    listener: &TcpListener,
+    shutdown: &Arc<AtomicBool>,
+    stats: &Arc<SensorStats>,
+    read_timeout: Option<Duration>,
+    max_connections: usize,
+    handler: F,
+) -> io::Result<()>
+where
+    F: Fn(StatsReader<ShutdownReader<TcpStream>>) -> io::Result<()> + Send + Sync + 'static,
+{
+    stats.set_max_connections(max_connections);
+    let handler = Arc::new(handler);
+    let closed = Arc::new(AtomicBool::new(false));
+    let active = Arc::new(AtomicUsize::new(0));
+    let mut workers = Vec::new();
+
+    while let Some(stream) = accept(sensor, listener, || {
+        shutdown.load(Ordering::Relaxed) || closed.load(Ordering::Relaxed)
+    })? {
+        // Only the workers still running need joining at shutdown, so the handles of closed
+        // connections don't pile up on a long-running listener.
+        prune_finished(&mut workers);
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer,
+            // The device already hung up again, so there's nothing to serve.
+            Err(_) => continue,
+        };
+        if active.load(Ordering::SeqCst) >= max_connections {
+            warn!(
+                "{}: refusing connection from {}, already serving {} connections",
+                sensor, peer, max_connections
+            );
+            stats.record_refused(peer);
+            continue;
+        }
+        info!("{}: accepted connection from {}", sensor, peer);
+
+        active.fetch_add(1, Ordering::SeqCst);
+        let handler = handler.clone();
+        let closed = closed.clone();
+        let active = active.clone();
+        let shutdown = shutdown.clone();
+        let stats = stats.clone();
+        workers.push(thread::spawn(move || {
+            stats.connection_opened();
+            stats.set_peer(peer);
+            let mut stream = ShutdownReader::new(stream, shutdown);
+            stream.idle_timeout = read_timeout;
+            let stream = StatsReader::new(stream, stats.clone());
+            match handler(stream) {
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => {
+                    info!("{}: stopped serving {}: {}", sensor, peer, e);
+                    closed.store(true, Ordering::Relaxed);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    warn!("{}: dropping the connection from {}: {}", sensor, peer, e);
+                    stats.record_error(e.to_string());
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => warn!(
+                    "{}: {} disconnected, waiting for a new connection",
+                    sensor, peer
+                ),
+                Err(e) => {
+                    error!("{}: connection from {} closed: {}", sensor, peer, e);
+                    stats.record_error(e.to_string());
+                }
+                Ok(()) => info!("{}: connection from {} closed", sensor, peer),
+            }
+            stats.connection_closed();
+            active.fetch_sub(1, Ordering::SeqCst);
+        }));
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+/// Drops the handles of workers whose threads have already finished.
+fn prune_finished(workers: &mut Vec<JoinHandle<()>>) {
+    workers.retain(|worker| !worker.is_finished());
+}
+
+/// Waits for the next connection on a listener, returning `None` once `stop` returns true. The
+/// listener is switched to non-blocking mode so that it can be polled. Accepted streams are
+/// blocking, but time out regularly so `ShutdownReader` can check for shutdown.
+///
+/// Failing to accept a connection, e.g. because the process is out of file descriptors, doesn't
+/// stop the listener. It backs off and tries again instead.
+fn accept(
+    sensor: &str,
+    listener: &TcpListener,
+    stop: impl Fn() -> bool,
+) -> io::Result<Option<TcpStream>> {
+    listener.set_nonblocking(true)?;
+    let mut backoff = POLL_INTERVAL;
+    loop {
+        if stop() {
+            return Ok(None);
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                return Ok(Some(stream));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                warn!(
+                    "{}: couldn't accept a connection, retrying in {:?}: {}",
+                    sensor, backoff, e
+                );
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Starts a sensor thread listening on the given address, stopping once the given flag is set.
+type StartSensor =
+    dyn Fn(SocketAddr, Arc<AtomicBool>) -> io::Result<JoinHandle<io::Result<()>>> + Send + Sync;
+
+/// A sensor its window can restart, for when the sensor is stuck or should listen on another
+/// address. The sensor thread is stopped by a flag of its own rather than the application's
+/// shutdown flag. That flag is set when this is dropped, so closing the window or the
+/// application still stops the thread.
+pub struct RestartableSensor {
+    start: Arc<StartSensor>,
+    stop: Arc<AtomicBool>,
+    /// The address typed into the window to restart the sensor on.
+    address_input: ImString,
+    /// How the last restart went, shown in the window.
+    status: Arc<Mutex<Option<String>>>,
+    /// The threads started by restarts that haven't been handed to the main loop yet, which
+    /// joins them on shutdown along with the other sensor threads.
+    restarts: Vec<JoinHandle<io::Result<()>>>,
+}
+
+impl RestartableSensor {
+    /// Starts the sensor by calling `start`, which is called again to start a new sensor thread
+    /// on every restart. Fails if the first start does.
+    pub fn start<F>(address: SocketAddr, start: F) -> io::Result<(Self, JoinHandle<io::Result<()>>)>
+    where
+        F: Fn(SocketAddr, Arc<AtomicBool>) -> io::Result<JoinHandle<io::Result<()>>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = start(address, stop.clone())?;
+        let mut address_input = ImString::new(address.to_string());
+        address_input.reserve_exact(256);
+        let sensor = Self {
+            start: Arc::new(start),
+            stop,
+            address_input,
+            status: Arc::default(),
+            restarts: Vec::new(),
+        };
+        Ok((sensor, handle))
+    }
+
+    /// Stops the sensor thread and starts a new one listening on `address`. The old listener can
+    /// take a moment to let go of its port, so the new one is started on a thread of its own,
+    /// which retries binding for up to `RESTART_BIND_TIMEOUT` and then waits for the new sensor
+    /// thread to stop.
+    pub fn restart(&mut self, address: SocketAddr) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.set_status(format!("Restarting on {}", address));
+        info!("restarting the sensor on {}", address);
+
+        let start = self.start.clone();
+        let stop = self.stop.clone();
+        let status = self.status.clone();
+        let handle = thread::spawn(move || {
+            let started = Instant::now();
+            let handle = loop {
+                match start(address, stop.clone()) {
+                    Ok(handle) => break handle,
+                    Err(ref e)
+                        if e.kind() == io::ErrorKind::AddrInUse
+                            && started.elapsed() < RESTART_BIND_TIMEOUT =>
+                    {
+                        // Restarted again, or closed, before the port came free.
+                        if shutdown::sleep(POLL_INTERVAL, &stop) {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) => {
+                        warn!("couldn't restart the sensor on {}: {}", address, e);
+                        if let Ok(mut status) = status.lock() {
+                            *status = Some(format!("Restart failed: {}", e));
+                        }
+                        return Err(e);
+                    }
+                }
+            };
+            if let Ok(mut status) = status.lock() {
+                *status = Some(format!("Restarted on {}", address));
+            }
+            handle.join().unwrap_or(Ok(()))
+        });
+        self.restarts.push(handle);
+    }
+
+    /// Takes the threads started by restarts since this was last called.
+    pub fn take_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        mem::take(&mut self.restarts)
+    }
+
+    fn set_status(&self, message: String) {
+        if let Ok(mut status) = self.status.lock() {
+            *status = Some(message);
+        }
+    }
+}
+
+impl Drop for RestartableSensor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Shows the address a sensor listens on, which can be edited, with a button restarting the
+/// sensor there, followed by how the last restart went. Returns the address the sensor was
+/// restarted on, if it was restarted.
+pub fn restart_controls(ui: &Ui, sensor: &mut RestartableSensor) -> Option<SocketAddr> {
+    ui.input_text(
+        im_str!("Listen Address##restart"),
+        &mut sensor.address_input,
+    )
+    .build();
+    ui.same_line(0.0);
+    let mut restarted = None;
+    if ui.button(im_str!("Restart"), [0.0, 0.0]) {
+        match parse_listen_addr(sensor.address_input.to_str()) {
+            Ok(address) => {
+                sensor.restart(address);
+                restarted = Some(address);
+            }
+            Err(e) => sensor.set_status(format!("Invalid address: {}", e)),
+        }
+    }
+    if let Some(status) = sensor.status.lock().ok().and_then(|status| status.clone()) {
+        ui.text(status);
+    }
+    restarted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+
+    #[test]
+    fn connections_are_served_at_the_same_time() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let server_shutdown = shutdown.clone();
+        let stats = Arc::new(SensorStats::new());
+        let server = thread::spawn(move || {
+            serve(
+                "test",
+                &listener,
+                &server_shutdown,
+                &stats,
+                None,
+                DEFAULT_MAX_CONNECTIONS,
+                move |mut stream| {
+                    let mut byte = [0; 1];
+                    loop {
+                        stream.read_exact(&mut byte)?;
+                        sender.send(byte[0]).unwrap();
+                    }
+                },
+            )
+        });
+
+        // The first connection stays open while the second one sends, which used to block the
+        // second connection until the first one closed.
+        let mut first = TcpStream::connect(addr).unwrap();
+        let mut second = TcpStream::connect(addr).unwrap();
+        first.write_all(&[1]).unwrap();
+        second.write_all(&[2]).unwrap();
+        let timeout = Duration::from_secs(2);
+        let mut received = vec![
+            receiver.recv_timeout(timeout).unwrap(),
+            receiver.recv_timeout(timeout).unwrap(),
+        ];
+        received.sort_unstable();
+        assert_eq!(received, vec![1, 2]);
+
+        shutdown.store(true, Ordering::Relaxed);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn finished_workers_are_pruned() {
+        let (release, wait) = mpsc::channel::<()>();
+        let mut workers = vec![
+            thread::spawn(|| ()),
+            thread::spawn(move || {
+                let _ = wait.recv();
+            }),
+        ];
+        while !workers[0].is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        prune_finished(&mut workers);
+        assert_eq!(workers.len(), 1);
+
+        release.send(()).unwrap();
+        workers.pop().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn binding_a_port_in_use_names_the_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let error = bind(address).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(
+            error.to_string(),
+            format!("Port {} already in use", address.port())
+        );
+    }
+
+    #[test]
+    fn devices_can_reconnect_after_disconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let server_shutdown = shutdown.clone();
+        let stats = Arc::new(SensorStats::new());
+        let server = thread::spawn(move || {
+            serve(
+                "test",
+                &listener,
+                &server_shutdown,
+                &stats,
+                None,
+                DEFAULT_MAX_CONNECTIONS,
+                move |mut stream| {
+                    let mut byte = [0; 1];
+                    loop {
+                        stream.read_exact(&mut byte)?;
+                        sender.send(byte[0]).unwrap();
+                    }
+                },
+            )
+        });
+
+        let timeout = Duration::from_secs(2);
+        let mut first = TcpStream::connect(addr).unwrap();
+        first.write_all(&[1]).unwrap();
+        assert_eq!(receiver.recv_timeout(timeout), Ok(1));
+        drop(first);
+
+        let mut second = TcpStream::connect(addr).unwrap();
+        second.write_all(&[2]).unwrap();
+        assert_eq!(receiver.recv_timeout(timeout), Ok(2));
+
+        shutdown.store(true, Ordering::Relaxed);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn read_timeouts_are_clamped() {
+        assert_eq!(read_timeout_from_secs(0.0), None);
+        assert_eq!(read_timeout_from_secs(-1.0), None);
+        assert_eq!(read_timeout_from_secs(f32::NAN), None);
+        assert_eq!(
+            read_timeout_from_secs(1.5),
+            Some(Duration::from_millis(1500))
+        );
+        let max = Some(Duration::from_secs(3600));
+        assert_eq!(read_timeout_from_secs(f32::INFINITY), max);
+        assert_eq!(read_timeout_from_secs(1e30), max);
+    }
+
+    #[test]
+    fn each_datagram_is_handed_to_the_handler() {
+        let socket = bind_udp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let server_shutdown = shutdown.clone();
+        let stats = Arc::new(SensorStats::new());
+        let server_stats = stats.clone();
+        let server = thread::spawn(move || {
+            serve_udp(
+                "test",
+                &socket,
+                &server_shutdown,
+                &server_stats,
+                move |mut datagram| {
+                    let mut byte = [0; 1];
+                    loop {
+                        datagram.read_exact(&mut byte)?;
+                        sender.send(byte[0]).unwrap();
+                    }
+                },
+            )
+        });
+
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device.send_to(&[1, 2], addr).unwrap();
+        device.send_to(&[3], addr).unwrap();
+        let timeout = Duration::from_secs(2);
+        let received: Vec<u8> = (0..3)
+            .map(|_| receiver.recv_timeout(timeout).unwrap())
+            .collect();
+        assert_eq!(received, vec![1, 2, 3]);
+        assert_eq!(stats.connections(), 1);
+
+        shutdown.store(true, Ordering::Relaxed);
+        server.join().unwrap().unwrap();
+        assert_eq!(stats.connections(), 0);
+    }
+
+    #[test]
+    fn restarted_sensors_hand_over_their_threads() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let address = "127.0.0.1:0".parse().unwrap();
+        let (mut sensor, handle) = RestartableSensor::start(address, move |address, stop| {
+            started_tx.send(address).unwrap();
+            Ok(thread::spawn(move || {
+                shutdown::sleep(Duration::from_secs(60), &stop);
+                Ok(())
+            }))
+        })
+        .unwrap();
+        assert!(sensor.take_handles().is_empty());
+
+        let new_address = "127.0.0.1:1".parse().unwrap();
+        sensor.restart(new_address);
+        let timeout = Duration::from_secs(2);
+        assert_eq!(started_rx.recv_timeout(timeout), Ok(address));
+        assert_eq!(started_rx.recv_timeout(timeout), Ok(new_address));
+        let restarts = sensor.take_handles();
+        assert_eq!(restarts.len(), 1);
+        assert!(sensor.take_handles().is_empty());
+
+        // Dropping the sensor stops both the first and the restarted sensor thread.
+        drop(sensor);
+        handle.join().unwrap().unwrap();
+        for restart in restarts {
+            restart.join().unwrap().unwrap();
+        }
+    }
+}
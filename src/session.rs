@@ -0,0 +1,508 @@
+use crate::camera::{self, Camera, CameraWindow, FrameFraming, VideoFormat};
+use crate::channel;
+use crate::controller::{Controller, ControllerWindow};
+use crate::endian::Endianness;
+use crate::gps::{self, Gps, GpsFormat, GpsWindow};
+use crate::lidar::{self, DistanceUnit, Lidar, LidarFormat, LidarWindow};
+use crate::server::{RestartableSensor, Transport};
+use crate::settings::Settings;
+use crate::tls::{ClientTlsFiles, ServerTlsFiles};
+use crate::window::{Placement, Renderable, Theme};
+use crossbeam::channel::unbounded;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long opening a project keeps trying to start a sensor whose port is still held by the
+/// sensor it replaces, which was only just told to stop.
+const PROJECT_BIND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Describes a sensor well enough to start it again on the next launch. Sessions saved before
+/// byte orders were configurable don't have one, so those sensors get little-endian. Likewise
+/// LIDARs saved before they had a wire format get the basic format, and cameras saved before
+/// their framing was configurable get length-prefixed frames. Sensors saved before UDP was
+/// supported use TCP, and ones saved before TLS was supported use plaintext.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SensorSpec {
+    Camera {
+        address: SocketAddr,
+        video_format: String,
+        #[serde(default)]
+        framing: String,
+        #[serde(default)]
+        endianness: Endianness,
+        /// Whether frames are wrapped in checksummed frames, as read by `FrameSync`.
+        #[serde(default)]
+        checksummed: bool,
+        #[serde(default)]
+        tls: Option<ServerTlsFiles>,
+        /// Whether H264 and JPEG frames are converted to RGB on the GPU rather than the CPU.
+        #[serde(default)]
+        gpu_colors: bool,
+    },
+    Lidar {
+        address: SocketAddr,
+        #[serde(default)]
+        format: String,
+        #[serde(default)]
+        endianness: Endianness,
+        #[serde(default)]
+        transport: Transport,
+        /// Whether each scan is wrapped in a checksummed frame, as read by `FrameSync`.
+        #[serde(default)]
+        checksummed: bool,
+        /// The unit the LIDAR reports distances in, raw if it's missing or unknown.
+        #[serde(default)]
+        distance_unit: String,
+        #[serde(default)]
+        tls: Option<ServerTlsFiles>,
+    },
+    Gps {
+        address: SocketAddr,
+        format: String,
+        #[serde(default)]
+        endianness: Endianness,
+        #[serde(default)]
+        transport: Transport,
+        #[serde(default)]
+        tls: Option<ServerTlsFiles>,
+    },
+    Controller {
+        address: SocketAddr,
+        #[serde(default)]
+        endianness: Endianness,
+        #[serde(default)]
+        tls: Option<ClientTlsFiles>,
+    },
+}
+
+impl SensorSpec {
+    /// Moves the sensor to another address, e.g. after its window restarted it on another port.
+    pub fn set_address(&mut self, new_address: SocketAddr) {
+        match self {
+            SensorSpec::Camera { address, .. }
+            | SensorSpec::Lidar { address, .. }
+            | SensorSpec::Gps { address, .. }
+            | SensorSpec::Controller { address, .. } => *address = new_address,
+        }
+    }
+}
+
+/// The state remembered between runs of sensorview.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Whether the sensors from the previous run should be started again on launch.
+    pub restore_on_launch: bool,
+    pub sensors: Vec<SensorSpec>,
+    /// Sessions saved before themes were added don't have one, so they get the default theme.
+    #[serde(default)]
+    pub theme: Theme,
+    /// The color behind the windows, as RGB from 0 to 1. Without one the theme's color is used.
+    #[serde(default)]
+    pub background: Option<[f32; 3]>,
+    /// The positions and sizes of the windows inside the main window, in imgui's ini format.
+    #[serde(default)]
+    pub layout: String,
+    /// The sensors whose windows were popped out into their own OS windows.
+    #[serde(default)]
+    pub detached: Vec<DetachedLayout>,
+}
+
+/// Where a sensor window popped out into its own OS window was, so it's popped out to the same
+/// place when the session is restored. Positions and sizes are in logical pixels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DetachedLayout {
+    /// The index of the sensor in the session's `sensors`.
+    pub sensor: usize,
+    /// The position of the OS window, if the platform reports one.
+    pub position: Option<[f64; 2]>,
+    pub size: [f64; 2],
+}
+
+impl Session {
+    /// Loads the session saved by the previous run. A missing or unreadable session file just
+    /// means there's nothing to restore, so this falls back to an empty session.
+    pub fn load() -> Self {
+        File::open(session_path())
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = session_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::other)
+    }
+}
+
+/// A sensor saved in a project, along with its window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectSensor {
+    pub spec: SensorSpec,
+    pub name: String,
+    pub visible: bool,
+    /// Where the window was, if it had been drawn in the main window.
+    pub placement: Option<Placement>,
+}
+
+/// A setup saved to a file of the user's choosing, so several setups can be kept and switched
+/// between: the sensors and their windows, the theme and background, and the preferences.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Project {
+    pub sensors: Vec<ProjectSensor>,
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub background: Option<[f32; 3]>,
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+impl Project {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::other)
+    }
+
+    /// The project's sensors, to be started with `PendingSensors::spawn`.
+    pub fn pending_sensors(&self) -> PendingSensors {
+        PendingSensors {
+            sensors: self.sensors.clone(),
+            settings: self.settings.clone(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// The sensors of a project that are still to be started. The sensors the project replaces were
+/// only just stopped, so a sensor whose port is still in use is kept to be tried again on a later
+/// frame, for up to `PROJECT_BIND_TIMEOUT`, rather than holding up the UI while the port is freed.
+pub struct PendingSensors {
+    sensors: Vec<ProjectSensor>,
+    settings: Settings,
+    started: Instant,
+}
+
+impl PendingSensors {
+    /// Whether every sensor has been started or given up on.
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty()
+    }
+
+    /// Starts the sensors still to be started and puts their windows back where they were,
+    /// returning the sensors that couldn't be started along with why. Sensors whose ports are in
+    /// use are kept for the next call until `PROJECT_BIND_TIMEOUT` has passed.
+    pub fn spawn(
+        &mut self,
+        join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
+        sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        sensor_visibility: &mut Vec<bool>,
+        shutdown: &Arc<AtomicBool>,
+    ) -> Vec<(SensorSpec, io::Error)> {
+        let retrying = self.started.elapsed() < PROJECT_BIND_TIMEOUT;
+        let mut failed = Vec::new();
+        let mut in_use = Vec::new();
+        for sensor in self.sensors.drain(..) {
+            let result = spawn_sensor(
+                &sensor.spec,
+                &self.settings,
+                join_handles,
+                sensor_windows,
+                shutdown,
+            );
+            match result {
+                Ok(()) => {
+                    if let Some(window) = sensor_windows.last_mut() {
+                        window.set_name(sensor.name.clone());
+                        if let Some(placement) = sensor.placement {
+                            window.set_placement(placement);
+                        }
+                    }
+                    sensor_visibility.push(sensor.visible);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::AddrInUse && retrying => {
+                    in_use.push(sensor)
+                }
+                Err(e) => failed.push((sensor.spec, e)),
+            }
+        }
+        self.sensors = in_use;
+        failed
+    }
+}
+
+/// Returns the per-user directory that sensorview stores its configuration in.
+pub fn config_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("sensorview")
+}
+
+fn session_path() -> PathBuf {
+    config_dir().join("session.json")
+}
+
+/// Starts the sensor described by the spec with its default settings and creates its window.
+/// The sensor's channel gets the capacity set in the preferences.
+pub fn spawn_sensor(
+    spec: &SensorSpec,
+    settings: &Settings,
+    join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
+    sensor_windows: &mut Vec<Box<dyn Renderable>>,
+    shutdown: &Arc<AtomicBool>,
+) -> io::Result<()> {
+    match spec {
+        SensorSpec::Camera {
+            address,
+            video_format,
+            framing,
+            endianness,
+            checksummed,
+            tls,
+            gpu_colors,
+        } => {
+            let video_format = VideoFormat::from_str(video_format).unwrap_or(VideoFormat::MJPEG);
+            let (camera_tx, camera_rx) = channel::channel(
+                settings.camera_channel_capacity,
+                camera::DEFAULT_OVERFLOW_POLICY,
+            );
+            let mut camera = Camera::new(camera_tx);
+            camera.endianness = *endianness;
+            camera.framing =
+                FrameFraming::from_str(framing).unwrap_or(FrameFraming::LengthPrefixed);
+            camera.checksummed = *checksummed;
+            camera.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            camera.yuv_frames = *gpu_colors;
+            let mut camera_window = CameraWindow::new(camera_rx);
+            camera_window.health = camera.health.clone();
+            camera_window.stats = camera.stats.clone();
+            camera_window.spec = Some(spec.clone());
+            let (restartable, handle) =
+                RestartableSensor::start(*address, move |address, stop| {
+                    camera.clone().start(address, video_format, stop)
+                })?;
+            camera_window.restartable = Some(restartable);
+            join_handles.push(handle);
+            sensor_windows.push(Box::new(camera_window));
+        }
+        SensorSpec::Lidar {
+            address,
+            format,
+            endianness,
+            transport,
+            checksummed,
+            distance_unit,
+            tls,
+        } => {
+            let (lidar_tx, lidar_rx) = channel::channel(
+                settings.lidar_channel_capacity,
+                lidar::DEFAULT_OVERFLOW_POLICY,
+            );
+            let mut lidar = Lidar::new(lidar_tx);
+            lidar.format = LidarFormat::from_str(format).unwrap_or(LidarFormat::Basic);
+            lidar.endianness = *endianness;
+            lidar.transport = *transport;
+            lidar.checksummed = *checksummed;
+            lidar.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let mut lidar_window = LidarWindow::new(lidar_rx);
+            lidar_window.set_distance_unit(
+                DistanceUnit::from_str(distance_unit).unwrap_or(DistanceUnit::Raw),
+            );
+            lidar_window.stats = lidar.stats.clone();
+            let (restartable, handle) =
+                RestartableSensor::start(*address, move |address, stop| {
+                    lidar.clone().start(address, stop)
+                })?;
+            lidar_window.restartable = Some(restartable);
+            join_handles.push(handle);
+            lidar_window.spec = Some(spec.clone());
+            sensor_windows.push(Box::new(lidar_window));
+        }
+        SensorSpec::Gps {
+            address,
+            format,
+            endianness,
+            transport,
+            tls,
+        } => {
+            let (gps_tx, gps_rx) =
+                channel::channel(settings.gps_channel_capacity, gps::DEFAULT_OVERFLOW_POLICY);
+            let mut gps = Gps::new(gps_tx);
+            gps.format = GpsFormat::from_str(format).unwrap_or(GpsFormat::Single);
+            gps.endianness = *endianness;
+            gps.transport = *transport;
+            gps.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let mut gps_window = GpsWindow::new(gps_rx);
+            gps_window.stats = gps.stats.clone();
+            let (restartable, handle) =
+                RestartableSensor::start(*address, move |address, stop| {
+                    gps.clone().start(address, stop)
+                })?;
+            gps_window.restartable = Some(restartable);
+            join_handles.push(handle);
+            gps_window.spec = Some(spec.clone());
+            sensor_windows.push(Box::new(gps_window));
+        }
+        SensorSpec::Controller {
+            address,
+            endianness,
+            tls,
+        } => {
+            let (ui_tx, ui_rx) = unbounded();
+            let (status_tx, status_rx) = unbounded();
+            let mut controller = Controller::new();
+            controller.endianness = *endianness;
+            controller.tls = tls.as_ref().map(|files| files.load()).transpose()?;
+            let mut controller_window = ControllerWindow::new(ui_rx, status_rx);
+            controller_window.stats = controller.stats.clone();
+            join_handles.push(controller.start(*address, ui_tx, status_tx, shutdown.clone()));
+            controller_window.spec = Some(spec.clone());
+            sensor_windows.push(Box::new(controller_window));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_round_trip_through_json() {
+        let session = Session {
+            restore_on_launch: true,
+            sensors: vec![
+                SensorSpec::Camera {
+                    address: "0.0.0.0:8001".parse().unwrap(),
+                    video_format: "MJPEG".to_string(),
+                    framing: "SoiEoiMarkers".to_string(),
+                    endianness: Endianness::Little,
+                    checksummed: false,
+                    tls: None,
+                    gpu_colors: false,
+                },
+                SensorSpec::Lidar {
+                    address: "0.0.0.0:8002".parse().unwrap(),
+                    format: "Intensity".to_string(),
+                    transport: Transport::Tcp,
+                    endianness: Endianness::Big,
+                    checksummed: true,
+                    distance_unit: "Millimeters".to_string(),
+                    tls: None,
+                },
+                SensorSpec::Gps {
+                    address: "127.0.0.1:8003".parse().unwrap(),
+                    format: "Batched".to_string(),
+                    transport: Transport::Udp,
+                    endianness: Endianness::Little,
+                    tls: None,
+                },
+                SensorSpec::Controller {
+                    address: "192.168.1.20:8004".parse().unwrap(),
+                    endianness: Endianness::Big,
+                    tls: None,
+                },
+            ],
+            theme: Theme::Light,
+            background: Some([0.1, 0.2, 0.3]),
+            layout: "[Window][LIDAR]\nPos=60,60\nSize=400,400\n".to_string(),
+            detached: vec![DetachedLayout {
+                sensor: 2,
+                position: Some([1920.0, 0.0]),
+                size: [800.0, 600.0],
+            }],
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: Session = serde_json::from_str(&json).unwrap();
+        assert!(restored.restore_on_launch);
+        assert_eq!(restored.sensors, session.sensors);
+        assert_eq!(restored.theme, Theme::Light);
+        assert_eq!(restored.background, Some([0.1, 0.2, 0.3]));
+        assert_eq!(restored.layout, session.layout);
+        assert_eq!(restored.detached, session.detached);
+    }
+
+    #[test]
+    fn projects_round_trip_through_a_file() {
+        let project = Project {
+            sensors: vec![
+                ProjectSensor {
+                    spec: SensorSpec::Camera {
+                        address: "127.0.0.1:8001".parse().unwrap(),
+                        video_format: "H264".to_string(),
+                        framing: "LengthPrefixed".to_string(),
+                        endianness: Endianness::Big,
+                        checksummed: true,
+                        tls: None,
+                        gpu_colors: false,
+                    },
+                    name: "Front".to_string(),
+                    visible: false,
+                    placement: Some(Placement {
+                        position: [10.0, 20.0],
+                        size: [640.0, 480.0],
+                    }),
+                },
+                ProjectSensor {
+                    spec: SensorSpec::Lidar {
+                        address: "0.0.0.0:8002".parse().unwrap(),
+                        format: "Intensity".to_string(),
+                        endianness: Endianness::Little,
+                        transport: Transport::Udp,
+                        checksummed: false,
+                        distance_unit: "Millimeters".to_string(),
+                        tls: None,
+                    },
+                    name: "Roof".to_string(),
+                    visible: true,
+                    placement: None,
+                },
+            ],
+            theme: Theme::Light,
+            background: Some([0.1, 0.2, 0.3]),
+            settings: Settings::default(),
+        };
+        let path = env::temp_dir().join(format!("sensorview-project-{}.json", std::process::id()));
+        project.save(&path).unwrap();
+        let loaded = Project::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.theme, Theme::Light);
+        assert_eq!(loaded.background, Some([0.1, 0.2, 0.3]));
+        assert_eq!(loaded.sensors[0].name, "Front");
+        assert!(!loaded.sensors[0].visible);
+        assert_eq!(loaded.sensors[0].placement, project.sensors[0].placement);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&project).unwrap()
+        );
+    }
+
+    #[test]
+    fn sessions_saved_without_a_layout_still_load() {
+        let json = r#"{"restore_on_launch":false,"sensors":[]}"#;
+        let restored: Session = serde_json::from_str(json).unwrap();
+        assert!(restored.layout.is_empty());
+        assert!(restored.detached.is_empty());
+    }
+}
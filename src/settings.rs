@@ -0,0 +1,251 @@
+use crate::camera;
+use crate::gps;
+use crate::lidar;
+use crate::session;
+use crate::texture;
+use imgui::{im_str, Condition, ImStr, Ui, Window};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The highest frame rate the preferences accept as a target.
+const MAX_TARGET_FPS: i32 = 1000;
+
+/// The smallest texture size limit the preferences accept, other than no limit.
+const MIN_TEXTURE_SIZE: i32 = 64;
+
+/// Preferences that apply to the whole application rather than to a single sensor. Settings files
+/// written by older versions may be missing fields, which get their default values.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The size of the UI font in logical pixels. The font atlas is built once at startup, so a
+    /// new size takes effect on the next launch.
+    pub font_size: f32,
+    /// The shortest time between frames while nothing is happening, in milliseconds.
+    pub idle_frame_interval_ms: u64,
+    /// Whether frames wait for the monitor to refresh. The OpenGL context is created once at
+    /// startup, so a change takes effect on the next launch.
+    pub vsync: bool,
+    /// The most frames drawn per second with vsync off, or 0 for no limit.
+    pub target_fps: u32,
+    /// The width and height in pixels of the largest texture the windows upload, or 0 for as
+    /// large as the GPU supports. Larger images are scaled down to fit.
+    pub max_texture_size: u32,
+    /// The capacities new sensors' channels start out with.
+    pub camera_channel_capacity: usize,
+    pub lidar_channel_capacity: usize,
+    pub gps_channel_capacity: usize,
+    /// The ports the config modals suggest for new sensors.
+    pub camera_port: u16,
+    pub lidar_port: u16,
+    pub gps_port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_size: 13.0,
+            idle_frame_interval_ms: 50,
+            vsync: true,
+            target_fps: 60,
+            max_texture_size: 0,
+            camera_channel_capacity: camera::DEFAULT_CHANNEL_CAPACITY,
+            lidar_channel_capacity: lidar::DEFAULT_CHANNEL_CAPACITY,
+            gps_channel_capacity: gps::DEFAULT_CHANNEL_CAPACITY,
+            camera_port: 8001,
+            lidar_port: 8002,
+            gps_port: 8003,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the saved settings. A missing or unreadable settings file falls back to the
+    /// defaults, as does a file that fails to parse.
+    pub fn load() -> Self {
+        File::open(settings_path())
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = settings_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::other)
+    }
+
+    /// The shortest time between frames while nothing is happening, i.e. no sensor has sent new
+    /// data and there's no user input.
+    pub fn idle_frame_interval(&self) -> Duration {
+        Duration::from_millis(self.idle_frame_interval_ms)
+    }
+
+    /// The shortest time between frames at any time. With vsync on, the refresh rate already
+    /// paces the frames, so only the target frame rate of vsync being off is enforced here.
+    pub fn frame_interval(&self) -> Duration {
+        if self.vsync || self.target_fps == 0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(1.0 / f64::from(self.target_fps))
+        }
+    }
+}
+
+fn settings_path() -> PathBuf {
+    session::config_dir().join("settings.json")
+}
+
+/// Edits the settings in place. The main window picks up changes on the next frame, so most of
+/// them apply right away; the settings are only written to disk when saved.
+pub struct PreferencesWindow {
+    /// The result of the last save, shown below the buttons.
+    status: Option<String>,
+}
+
+impl PreferencesWindow {
+    pub fn new() -> Self {
+        Self { status: None }
+    }
+
+    /// Draws the window, returning false once the user has closed it.
+    pub fn render(&mut self, ui: &Ui, settings: &mut Settings) -> bool {
+        let mut opened = true;
+        let status = &mut self.status;
+        Window::new(im_str!("Preferences"))
+            .opened(&mut opened)
+            .size([360.0, 320.0], Condition::FirstUseEver)
+            .build(ui, || {
+                ui.input_float(im_str!("Font Size"), &mut settings.font_size)
+                    .step(1.0)
+                    .build();
+                // `f32::max` maps a NaN typed in to the minimum, which `clamp` wouldn't.
+                #[allow(clippy::manual_clamp)]
+                {
+                    settings.font_size = settings.font_size.max(6.0).min(48.0);
+                }
+                ui.text_disabled(im_str!("The font size applies on the next launch."));
+
+                let mut idle_ms = settings.idle_frame_interval_ms as i32;
+                if ui
+                    .input_int(im_str!("Idle Frame Interval (ms)"), &mut idle_ms)
+                    .build()
+                {
+                    settings.idle_frame_interval_ms = idle_ms.clamp(0, 1000) as u64;
+                }
+                ui.checkbox(im_str!("VSync"), &mut settings.vsync);
+                ui.text_disabled(im_str!("VSync applies on the next launch."));
+                if !settings.vsync {
+                    let mut fps = settings.target_fps as i32;
+                    if ui
+                        .input_int(im_str!("Target FPS (0 = unlimited)"), &mut fps)
+                        .build()
+                    {
+                        settings.target_fps = fps.clamp(0, MAX_TARGET_FPS) as u32;
+                    }
+                }
+                let mut texture_size = settings.max_texture_size as i32;
+                if ui
+                    .input_int(
+                        im_str!("Max Texture Size (0 = GPU limit)"),
+                        &mut texture_size,
+                    )
+                    .build()
+                {
+                    settings.max_texture_size = match texture_size {
+                        size if size <= 0 => 0,
+                        size => size.max(MIN_TEXTURE_SIZE) as u32,
+                    };
+                }
+                ui.text_disabled(&im_str!(
+                    "The GPU supports up to {}.",
+                    texture::gpu_max_size()
+                ));
+
+                ui.separator();
+                ui.text(im_str!("Channel capacities for new sensors:"));
+                capacity_input(
+                    ui,
+                    im_str!("Camera##capacity"),
+                    &mut settings.camera_channel_capacity,
+                );
+                capacity_input(
+                    ui,
+                    im_str!("LIDAR##capacity"),
+                    &mut settings.lidar_channel_capacity,
+                );
+                capacity_input(
+                    ui,
+                    im_str!("GPS##capacity"),
+                    &mut settings.gps_channel_capacity,
+                );
+
+                ui.separator();
+                ui.text(im_str!("Default ports for new sensors:"));
+                port_input(ui, im_str!("Camera##port"), &mut settings.camera_port);
+                port_input(ui, im_str!("LIDAR##port"), &mut settings.lidar_port);
+                port_input(ui, im_str!("GPS##port"), &mut settings.gps_port);
+
+                ui.separator();
+                if ui.button(im_str!("Save"), [0.0, 0.0]) {
+                    *status = Some(match settings.save() {
+                        Ok(()) => format!("Saved to {}", settings_path().display()),
+                        Err(e) => format!("Couldn't save the settings: {}", e),
+                    });
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Restore Defaults"), [0.0, 0.0]) {
+                    *settings = Settings::default();
+                }
+                if let Some(status) = status {
+                    ui.text_wrapped(&im_str!("{}", status));
+                }
+            });
+        opened
+    }
+}
+
+fn capacity_input(ui: &Ui, label: &ImStr, capacity: &mut usize) {
+    let mut value = *capacity as i32;
+    if ui.input_int(label, &mut value).build() {
+        *capacity = value.max(1) as usize;
+    }
+}
+
+fn port_input(ui: &Ui, label: &ImStr, port: &mut u16) {
+    let mut value = i32::from(*port);
+    if ui.input_int(label, &mut value).build() {
+        *port = value.max(1).min(i32::from(u16::MAX)) as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_target_frame_rate_only_applies_without_vsync() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.frame_interval(), Duration::from_secs(0));
+
+        settings.vsync = false;
+        settings.target_fps = 50;
+        assert_eq!(settings.frame_interval(), Duration::from_millis(20));
+        settings.target_fps = 0;
+        assert_eq!(settings.frame_interval(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn settings_saved_before_vsync_was_configurable_keep_it_on() {
+        let settings: Settings = serde_json::from_str(r#"{"font_size":16.0}"#).unwrap();
+        assert_eq!(settings.font_size, 16.0);
+        assert!(settings.vsync);
+        assert_eq!(settings.target_fps, Settings::default().target_fps);
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often blocked sensor threads wake up to check whether they should shut down.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps for the given duration, waking up early if `shutdown` is set. Returns whether the
+/// thread should shut down.
+pub fn sleep(duration: Duration, shutdown: &AtomicBool) -> bool {
+    let mut remaining = duration;
+    while remaining > Duration::from_secs(0) {
+        if shutdown.load(Ordering::Relaxed) {
+            return true;
+        }
+        let step = remaining.min(POLL_INTERVAL);
+        thread::sleep(step);
+        remaining -= step;
+    }
+    shutdown.load(Ordering::Relaxed)
+}
+
+/// A time after which reads from a `ShutdownReader` give up with `TimedOut`, so a decoder can
+/// bound how long something spread over several reads, like a whole scan, takes to arrive. Clones
+/// share the deadline, so the decoder can move it while the reader is wrapped in others.
+#[derive(Clone, Default)]
+pub struct ReadDeadline(Arc<Mutex<Option<Instant>>>);
+
+impl ReadDeadline {
+    /// Sets the deadline, or clears it with `None`.
+    pub fn set(&self, deadline: Option<Instant>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = deadline;
+        }
+    }
+
+    /// Whether the deadline is set and has passed.
+    pub fn passed(&self) -> bool {
+        self.0
+            .lock()
+            .is_ok_and(|deadline| deadline.is_some_and(|d| Instant::now() >= d))
+    }
+}
+
+/// A reader over a stream with a read timeout that retries timed out reads until `shutdown` is
+/// set. A timed out read hasn't consumed any bytes, so retrying it is invisible to the decoders
+/// reading from this, even in the middle of a frame.
+///
+/// If `idle_timeout` is set, reads give up with `TimedOut` once nothing has arrived for that
+/// long, so a peer that vanished without closing the connection doesn't hold it open forever.
+/// Reads likewise give up once the reader's `ReadDeadline` has passed.
+pub struct ShutdownReader<R> {
+    inner: R,
+    shutdown: Arc<AtomicBool>,
+    pub idle_timeout: Option<Duration>,
+    last_data: Instant,
+    deadline: ReadDeadline,
+}
+
+impl<R: Read> ShutdownReader<R> {
+    pub fn new(inner: R, shutdown: Arc<AtomicBool>) -> Self {
+        Self {
+            inner,
+            shutdown,
+            idle_timeout: None,
+            last_data: Instant::now(),
+            deadline: ReadDeadline::default(),
+        }
+    }
+
+    /// The deadline reads give up at, which is unset until a decoder sets it.
+    pub fn deadline(&self) -> ReadDeadline {
+        self.deadline.clone()
+    }
+}
+
+impl<R: Read> Read for ShutdownReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Checked before every read, not only once a read times out, so that a sensor being
+            // restarted stops even while its device keeps streaming.
+            if self.shutdown.load(Ordering::Relaxed) {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "sensorview is shutting down",
+                ));
+            }
+            if self.deadline.passed() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the read deadline passed",
+                ));
+            }
+            match self.inner.read(buf) {
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if let Some(timeout) = self.idle_timeout {
+                        if self.last_data.elapsed() >= timeout {
+                            return Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                format!("nothing received for {:?}", timeout),
+                            ));
+                        }
+                    }
+                }
+                result => {
+                    self.last_data = Instant::now();
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+/// Writes go straight through, so a TLS session can answer its peer over the same connection.
+impl<R: Write> Write for ShutdownReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+
+    /// Runs `f` on a new thread, flips the shutdown flag and returns what the thread returned, or
+    /// `None` if it didn't finish in time.
+    fn run_until_shutdown<T, F>(f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Arc<AtomicBool>) -> T + Send + 'static,
+    {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (done_tx, done_rx) = mpsc::channel();
+        let thread_shutdown = shutdown.clone();
+        let handle = thread::spawn(move || done_tx.send(f(thread_shutdown)).unwrap());
+        thread::sleep(POLL_INTERVAL);
+        shutdown.store(true, Ordering::Relaxed);
+        let result = done_rx.recv_timeout(Duration::from_secs(2)).ok();
+        if result.is_some() {
+            handle.join().unwrap();
+        }
+        result
+    }
+
+    #[test]
+    fn blocked_reads_end_on_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+        let error = run_until_shutdown(move |shutdown| {
+            let mut reader = ShutdownReader::new(stream, shutdown);
+            reader.read(&mut [0; 1]).unwrap_err().kind()
+        });
+        assert_eq!(
+            error.expect("the read didn't end"),
+            io::ErrorKind::ConnectionAborted
+        );
+    }
+
+    #[test]
+    fn sleeps_end_early_on_shutdown() {
+        let shut_down = run_until_shutdown(|shutdown| sleep(Duration::from_secs(60), &shutdown));
+        assert_eq!(shut_down, Some(true));
+        assert!(!sleep(Duration::from_millis(10), &AtomicBool::new(false)));
+    }
+}
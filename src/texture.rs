@@ -0,0 +1,177 @@
+use crate::notifications::Notification;
+use crate::window;
+use glium::texture::TextureCreationError;
+use glium::Display;
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use imgui::TextureId;
+use imgui_glium_renderer::Renderer;
+use log::{info, warn};
+use std::borrow::Cow;
+use std::mem;
+use std::sync::atomic::{AtomicU32, Ordering};
+use thiserror::Error;
+
+/// The largest texture every OpenGL 3 driver has to support, assumed until the GPU is asked.
+const GUARANTEED_MAX_TEXTURE_SIZE: u32 = 1024;
+
+/// The `glGetIntegerv` parameter for the largest texture the GPU supports.
+const GL_MAX_TEXTURE_SIZE: u32 = 0x0D33;
+
+/// The width and height in pixels of the largest texture the GPU supports.
+static GPU_MAX_SIZE: AtomicU32 = AtomicU32::new(GUARANTEED_MAX_TEXTURE_SIZE);
+
+/// The width and height in pixels of the largest texture the preferences allow, or 0 to go up to
+/// what the GPU supports.
+static CONFIGURED_MAX_SIZE: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("the image is {width}x{height} but holds {len} bytes")]
+    Size { width: u32, height: u32, len: usize },
+    #[error("couldn't create a {width}x{height} texture: {error:?}")]
+    Create {
+        width: u32,
+        height: u32,
+        error: TextureCreationError,
+    },
+}
+
+/// Asks the GPU for the largest texture it supports, which every upload is kept within. The
+/// display's context has to be current, as it is right after it's created.
+pub fn query_gpu_limit(display: &Display) {
+    let get_integer = display.gl_window().get_proc_address("glGetIntegerv");
+    if get_integer.is_null() {
+        warn!(
+            "texture: couldn't ask the GPU for its texture size limit, assuming {}",
+            GUARANTEED_MAX_TEXTURE_SIZE
+        );
+        return;
+    }
+    let mut size = 0;
+    // glGetIntegerv only writes the single integer the parameter asks for.
+    unsafe {
+        let get_integer: extern "system" fn(u32, *mut i32) = mem::transmute(get_integer);
+        get_integer(GL_MAX_TEXTURE_SIZE, &mut size);
+    }
+    if size > 0 {
+        GPU_MAX_SIZE.store(size as u32, Ordering::Relaxed);
+        info!("texture: the GPU supports textures up to {0}x{0}", size);
+    }
+}
+
+/// Sets the texture size limit from the preferences, 0 meaning the GPU's own limit. A limit above
+/// the GPU's has no effect.
+pub fn set_configured_limit(max_size: u32) {
+    CONFIGURED_MAX_SIZE.store(max_size, Ordering::Relaxed);
+}
+
+/// The width and height in pixels of the largest texture the GPU supports.
+pub fn gpu_max_size() -> u32 {
+    GPU_MAX_SIZE.load(Ordering::Relaxed)
+}
+
+/// The width and height in pixels of the largest texture that gets uploaded.
+pub fn max_size() -> u32 {
+    let gpu = gpu_max_size();
+    match CONFIGURED_MAX_SIZE.load(Ordering::Relaxed) {
+        0 => gpu,
+        configured => configured.min(gpu),
+    }
+}
+
+/// Uploads an RGB image as a window's texture, replacing the texture the window had if there was
+/// one. An image larger than `max_size` is scaled down to fit, and imgui stretches the smaller
+/// texture back over the image's size when it's drawn. Returns the size of the texture.
+pub fn upload_rgb(
+    display: &Display,
+    renderer: &mut Renderer,
+    texture_id: &mut Option<TextureId>,
+    bytes: Cow<[u8]>,
+    width: u32,
+    height: u32,
+) -> Result<(u32, u32), TextureError> {
+    let len = bytes.len();
+    if len != width as usize * height as usize * 3 {
+        return Err(TextureError::Size { width, height, len });
+    }
+    let (bytes, width, height) = downscale_rgb(bytes, width, height, max_size());
+    window::upload_rgb_texture(display, renderer, texture_id, bytes, width, height)?;
+    Ok((width, height))
+}
+
+/// Scales an RGB image down so that neither side is longer than `max_dim`, keeping its aspect
+/// ratio. Images that already fit are passed through untouched.
+pub fn downscale_rgb(
+    bytes: Cow<[u8]>,
+    width: u32,
+    height: u32,
+    max_dim: u32,
+) -> (Cow<[u8]>, u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_dim {
+        return (bytes, width, height);
+    }
+    let scale = max_dim as f64 / longest as f64;
+    let new_width = ((width as f64 * scale).round() as u32).max(1).min(max_dim);
+    let new_height = ((height as f64 * scale).round() as u32).max(1).min(max_dim);
+    let image = match RgbImage::from_raw(width, height, bytes.to_vec()) {
+        Some(image) => image,
+        None => return (bytes, width, height),
+    };
+    let resized = imageops::resize(&image, new_width, new_height, FilterType::Triangle);
+    (Cow::Owned(resized.into_raw()), new_width, new_height)
+}
+
+/// Logs an upload that failed and raises a notification for it, unless the window's previous
+/// upload failed too, so a window whose every frame fails doesn't raise one per frame. `failing`
+/// remembers how the window's last upload went.
+pub fn report_upload<T>(
+    result: Result<T, TextureError>,
+    failing: &mut bool,
+    window_name: &str,
+) -> Option<Notification> {
+    let was_failing = mem::replace(failing, result.is_err());
+    let e = result.err()?;
+    warn!("{}: couldn't upload the image: {}", window_name, e);
+    if was_failing {
+        return None;
+    }
+    Some(Notification::error(format!(
+        "{}: couldn't show the image: {}",
+        window_name, e
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_images_are_scaled_down_to_the_limit() {
+        let limit = gpu_max_size();
+        let (width, height) = (limit * 2, 4);
+        let bytes = vec![90; (width * height * 3) as usize];
+        let (scaled, scaled_width, scaled_height) =
+            downscale_rgb(Cow::Borrowed(&bytes[..]), width, height, max_size());
+        assert_eq!((scaled_width, scaled_height), (limit, 2));
+        assert_eq!(scaled.len(), (limit * 2 * 3) as usize);
+    }
+
+    #[test]
+    fn upload_failures_only_notify_once_per_run() {
+        let mut failing = false;
+        let failure = || {
+            Err::<(), _>(TextureError::Size {
+                width: 2,
+                height: 2,
+                len: 3,
+            })
+        };
+        assert!(report_upload(failure(), &mut failing, "Camera").is_some());
+        assert!(report_upload(failure(), &mut failing, "Camera").is_none());
+        assert!(report_upload(Ok(()), &mut failing, "Camera").is_none());
+        assert!(!failing);
+        assert!(report_upload(failure(), &mut failing, "Camera").is_some());
+    }
+}
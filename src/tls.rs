@@ -0,0 +1,428 @@
+use imgui::{im_str, ImString, Ui};
+use rustls::internal::pemfile;
+use rustls::{
+    ClientConfig, ClientSession, NoClientAuth, PrivateKey, ServerConfig, ServerSession, Session,
+    StreamOwned,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use webpki::DNSNameRef;
+
+/// How long connecting to a receiver over TLS waits for the handshake to finish.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The PEM files a sensor listening over TLS identifies itself with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerTlsFiles {
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+}
+
+impl ServerTlsFiles {
+    /// Reads the certificate chain and the private key, which can be either PKCS#8 or RSA.
+    pub fn load(&self) -> io::Result<Arc<ServerConfig>> {
+        let certificates = pemfile::certs(&mut open(&self.certificate)?)
+            .map_err(|_| invalid_file(&self.certificate, "isn't a PEM certificate"))?;
+        if certificates.is_empty() {
+            return Err(invalid_file(&self.certificate, "holds no certificates"));
+        }
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(certificates, read_private_key(&self.private_key)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Arc::new(config))
+    }
+}
+
+/// What the controller needs to check the receiver it connects to over TLS: the certificate of
+/// the authority that signed the receiver's certificate, and the name the receiver's certificate
+/// was issued for.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientTlsFiles {
+    pub ca_certificate: PathBuf,
+    pub server_name: String,
+}
+
+impl ClientTlsFiles {
+    pub fn load(&self) -> io::Result<ClientTls> {
+        if DNSNameRef::try_from_ascii_str(&self.server_name).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} isn't a valid server name", self.server_name),
+            ));
+        }
+        let mut config = ClientConfig::new();
+        let (added, _) = config
+            .root_store
+            .add_pem_file(&mut open(&self.ca_certificate)?)
+            .map_err(|_| invalid_file(&self.ca_certificate, "isn't a PEM certificate"))?;
+        if added == 0 {
+            return Err(invalid_file(
+                &self.ca_certificate,
+                "holds no usable certificates",
+            ));
+        }
+        Ok(ClientTls {
+            config: Arc::new(config),
+            server_name: self.server_name.clone(),
+        })
+    }
+}
+
+/// A loaded client configuration, ready to connect with.
+#[derive(Clone)]
+pub struct ClientTls {
+    config: Arc<ClientConfig>,
+    server_name: String,
+}
+
+fn open(path: &Path) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn invalid_file(path: &Path, problem: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} {}", path.display(), problem),
+    )
+}
+
+fn read_private_key(path: &Path) -> io::Result<PrivateKey> {
+    let not_a_key = || invalid_file(path, "isn't a PEM private key");
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(path)?).map_err(|_| not_a_key())?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(path)?).map_err(|_| not_a_key())?;
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_file(path, "holds no PKCS#8 or RSA private keys"))
+}
+
+/// Wraps an accepted connection in TLS if the sensor has a configuration for it, so the decoders
+/// read the decrypted bytes either way. The handshake happens during the first read.
+pub fn accept<S: Read + Write + 'static>(
+    stream: S,
+    config: Option<&Arc<ServerConfig>>,
+) -> Box<dyn Read> {
+    match config {
+        Some(config) => Box::new(ServerStream {
+            session: ServerSession::new(config),
+            socket: stream,
+        }),
+        None => Box::new(stream),
+    }
+}
+
+/// A TLS connection accepted by a sensor.
+struct ServerStream<S> {
+    session: ServerSession,
+    socket: S,
+}
+
+impl<S: Read + Write> Read for ServerStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.session.is_handshaking() || self.session.wants_write() {
+            self.session.complete_io(&mut self.socket)?;
+        }
+        // A single call may only read part of a record, so this keeps reading until there's
+        // plaintext or the connection ended. A call that only wrote, e.g. the session tickets
+        // sent after the handshake, reads nothing without the connection having ended.
+        while self.session.wants_read() {
+            if self.session.complete_io(&mut self.socket)? == (0, 0) {
+                break;
+            }
+        }
+        // rustls reports the device closing the session as `ConnectionAborted`, which
+        // `server::serve` takes to mean the sensor window is gone, so it's passed on as the end of
+        // the stream instead. Errors from the socket itself, like shutting down, are left alone.
+        match self.session.read(buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// A connection to a receiver, either plain or over TLS. A TLS session can't be split into a
+/// read and a write half like a socket can, so clones share the session. A read waits for data
+/// on the socket before taking the session, so a reader waiting for data doesn't hold up writes.
+pub enum ClientStream {
+    Plain(TcpStream),
+    Tls {
+        stream: Arc<Mutex<StreamOwned<ClientSession, TcpStream>>>,
+        socket: TcpStream,
+    },
+}
+
+impl ClientStream {
+    /// Connects to the receiver, finishing the TLS handshake before returning if `tls` is set.
+    pub fn connect(address: SocketAddr, tls: Option<&ClientTls>) -> io::Result<Self> {
+        let socket = TcpStream::connect(address)?;
+        let tls = match tls {
+            Some(tls) => tls,
+            None => return Ok(ClientStream::Plain(socket)),
+        };
+        let server_name = DNSNameRef::try_from_ascii_str(&tls.server_name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid server name"))?;
+        let mut session = ClientSession::new(&tls.config, server_name);
+        socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        while session.is_handshaking() {
+            match session.complete_io(&mut &socket) {
+                Ok(_) => (),
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "the TLS handshake timed out",
+                    ));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        socket.set_read_timeout(None)?;
+        Ok(ClientStream::Tls {
+            socket: socket.try_clone()?,
+            stream: Arc::new(Mutex::new(StreamOwned::new(session, socket))),
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            ClientStream::Plain(socket) => ClientStream::Plain(socket.try_clone()?),
+            ClientStream::Tls { stream, socket } => ClientStream::Tls {
+                stream: stream.clone(),
+                socket: socket.try_clone()?,
+            },
+        })
+    }
+
+    fn socket(&self) -> &TcpStream {
+        match self {
+            ClientStream::Plain(socket) | ClientStream::Tls { socket, .. } => socket,
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket().set_read_timeout(timeout)
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.socket().shutdown(how)
+    }
+}
+
+fn lock_session(
+    stream: &Mutex<StreamOwned<ClientSession, TcpStream>>,
+) -> io::Result<MutexGuard<'_, StreamOwned<ClientSession, TcpStream>>> {
+    stream
+        .lock()
+        .map_err(|_| io::Error::other("the TLS session was poisoned"))
+}
+
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(socket) => socket.read(buf),
+            ClientStream::Tls { stream, socket } => {
+                // The session may already hold decrypted bytes that arrived along with earlier
+                // ones, in which case there's nothing more to wait for on the socket.
+                let size = lock_session(stream)?.sess.read(buf)?;
+                if size > 0 {
+                    return Ok(size);
+                }
+                socket.peek(&mut [0])?;
+                lock_session(stream)?.read(buf)
+            }
+        }
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(socket) => socket.write(buf),
+            ClientStream::Tls { stream, .. } => lock_session(stream)?.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientStream::Plain(socket) => socket.flush(),
+            ClientStream::Tls { stream, .. } => lock_session(stream)?.flush(),
+        }
+    }
+}
+
+/// The TLS settings in the config modal of a sensor that listens for connections.
+pub struct ServerTlsConfig {
+    enabled: bool,
+    certificate: ImString,
+    private_key: ImString,
+}
+
+impl ServerTlsConfig {
+    pub fn new() -> Self {
+        let mut certificate = ImString::new("sensorview.crt");
+        certificate.reserve_exact(256);
+        let mut private_key = ImString::new("sensorview.key");
+        private_key.reserve_exact(256);
+        Self {
+            enabled: false,
+            certificate,
+            private_key,
+        }
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        ui.checkbox(im_str!("Require TLS"), &mut self.enabled);
+        if self.enabled {
+            ui.input_text(im_str!("Certificate (PEM)"), &mut self.certificate)
+                .build();
+            ui.input_text(im_str!("Private Key (PEM)"), &mut self.private_key)
+                .build();
+        }
+    }
+
+    /// The files to listen with, or `None` to listen in plaintext.
+    pub fn files(&self) -> Option<ServerTlsFiles> {
+        if !self.enabled {
+            return None;
+        }
+        Some(ServerTlsFiles {
+            certificate: PathBuf::from(self.certificate.to_str()),
+            private_key: PathBuf::from(self.private_key.to_str()),
+        })
+    }
+}
+
+/// The TLS settings in the controller's config modal.
+pub struct ClientTlsConfig {
+    enabled: bool,
+    ca_certificate: ImString,
+    server_name: ImString,
+}
+
+impl ClientTlsConfig {
+    pub fn new() -> Self {
+        let mut ca_certificate = ImString::new("ca.crt");
+        ca_certificate.reserve_exact(256);
+        let mut server_name = ImString::new("localhost");
+        server_name.reserve_exact(256);
+        Self {
+            enabled: false,
+            ca_certificate,
+            server_name,
+        }
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        ui.checkbox(im_str!("Connect With TLS"), &mut self.enabled);
+        if self.enabled {
+            ui.input_text(im_str!("CA Certificate (PEM)"), &mut self.ca_certificate)
+                .build();
+            ui.input_text(im_str!("Server Name"), &mut self.server_name)
+                .build();
+        }
+    }
+
+    /// The files to connect with, or `None` to connect in plaintext.
+    pub fn files(&self) -> Option<ClientTlsFiles> {
+        if !self.enabled {
+            return None;
+        }
+        Some(ClientTlsFiles {
+            ca_certificate: PathBuf::from(self.ca_certificate.to_str()),
+            server_name: self.server_name.to_str().trim().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endian::Endianness;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// A certificate for `localhost` signed by a test authority, valid until 2126.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/tls")
+            .join(name)
+    }
+
+    fn server_config() -> Arc<ServerConfig> {
+        ServerTlsFiles {
+            certificate: fixture("server.crt"),
+            private_key: fixture("server.key"),
+        }
+        .load()
+        .unwrap()
+    }
+
+    fn client_tls(server_name: &str) -> ClientTls {
+        ClientTlsFiles {
+            ca_certificate: fixture("ca.crt"),
+            server_name: server_name.to_string(),
+        }
+        .load()
+        .unwrap()
+    }
+
+    #[test]
+    fn framed_messages_arrive_over_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = server_config();
+        let (sender, receiver) = mpsc::channel();
+        let server = thread::spawn(move || -> io::Result<()> {
+            let (stream, _) = listener.accept()?;
+            let mut stream = accept(stream, Some(&config));
+            let size = Endianness::Little.read_u32(&mut stream)? as usize;
+            let mut message = vec![0; size];
+            stream.read_exact(&mut message)?;
+            sender.send(message).unwrap();
+            Ok(())
+        });
+
+        let mut client = ClientStream::connect(address, Some(&client_tls("localhost"))).unwrap();
+        assert!(matches!(client, ClientStream::Tls { .. }));
+        let message = b"hello over tls";
+        Endianness::Little
+            .write_u32(&mut client, message.len() as u32)
+            .unwrap();
+        client.write_all(message).unwrap();
+        client.flush().unwrap();
+
+        let received = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(received, message);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn servers_with_another_name_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = server_config();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // The handshake fails on the client's side, which closes the connection.
+            let _ = accept(stream, Some(&config)).read(&mut [0; 1]);
+        });
+
+        let error = ClientStream::connect(address, Some(&client_tls("sensorview.example")))
+            .err()
+            .expect("the handshake should fail");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        server.join().unwrap();
+    }
+}
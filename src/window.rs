@@ -1,28 +1,261 @@
-use crate::camera::CameraConfig;
+use crate::camera::{self, CameraConfig};
+use crate::clipboard;
 use crate::controller::ControllerConfig;
+use crate::detached::DetachedWindow;
+use crate::diagnostics::{DiagnosticsWindow, SensorStats};
 use crate::gps::GpsConfig;
-use crate::lidar::LidarConfig;
+use crate::health::{self, HealthSensors};
+use crate::lidar::{LidarConfig, LidarData, LidarWindow};
+use crate::logs::{LogBuffer, LogWindow};
+use crate::notifications::{Notification, Notifications};
+use crate::playback::PlaybackConfig;
+use crate::session::{
+    self, DetachedLayout, PendingSensors, Project, ProjectSensor, SensorSpec, Session,
+};
+use crate::settings::{PreferencesWindow, Settings};
+use crate::texture::{self, TextureError};
+use crossbeam::channel::unbounded;
+use glium::backend::glutin::DisplayCreationError;
+use glium::backend::Facade;
+use glium::glutin::dpi::LogicalSize;
 use glium::glutin::{self, Event, WindowEvent};
-use glium::{Display, Surface};
-use imgui::{self, im_str, Context, FontConfig, FontSource, Ui, Window};
-use imgui_glium_renderer::Renderer;
+use glium::texture::{ClientFormat, RawImage2d};
+use glium::{Display, Surface, Texture2d};
+use image::ImageResult;
+use imgui::{
+    self, im_str, ColorEdit, Condition, Context, FontConfig, FontSource, ImStr, ImString, MenuItem,
+    Style, TextureId, Textures, Ui, Window, WindowFlags,
+};
+use imgui_glium_renderer::{Renderer, RendererError};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use std::io;
-use std::thread::JoinHandle;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
 
 /// A trait for sensor windows so that eventually the main window can simply keep a list of all
 /// active sensor windows and update them without having to care about the types of sensors.
 pub trait Renderable {
-    fn render(&mut self, ui: &Ui, display: &Display, renderer: &mut Renderer);
+    /// Draws the window, returning false once the user has closed it. Closed windows are dropped
+    /// along with their receiver, which stops the sensor thread feeding them. Hidden windows
+    /// still take the data waiting in their receiver so they're current once shown again, but
+    /// skip drawing. While `all_paused` is set by the main window's Pause All toggle, windows
+    /// likewise keep taking their data but leave what they show as it is.
+    fn render(
+        &mut self,
+        ui: &Ui,
+        display: &Display,
+        renderer: &mut Renderer,
+        visible: bool,
+        all_paused: bool,
+    ) -> bool;
+
+    /// A short, human-readable name for the window used when listing active sensors.
+    fn name(&self) -> &str;
+
+    /// Forgets the window's texture so it gets uploaded again on the next render. Texture ids
+    /// belong to a single renderer, so this is needed when the window moves between OS windows.
+    fn reset_texture(&mut self);
+
+    /// Whether the sensor has sent data the window hasn't taken yet. The main loop slows down
+    /// while no window has pending data and there's no user input.
+    fn has_pending_data(&self) -> bool;
+
+    /// Describes the sensor feeding this window so it can be started again on the next launch.
+    /// Windows that weren't created from a sensor configuration return `None`.
+    fn sensor_spec(&self) -> Option<SensorSpec>;
+
+    /// The stats of the sensor feeding this window, shown in the diagnostics window.
+    fn stats(&self) -> Option<Arc<SensorStats>>;
+
+    /// Takes the notifications the window has raised since this was last called, which the main
+    /// window shows as toasts.
+    fn take_notifications(&mut self) -> Vec<Notification>;
+
+    /// Saves what the window shows to a PNG file at `path`, for capturing every window at once.
+    /// Windows without an image, or that haven't received anything to show yet, return `None`.
+    fn capture(&self, path: &Path) -> Option<ImageResult<()>>;
+
+    /// Moves and resizes the window on its next frame, after which the user can move it again.
+    /// Windows that size themselves to their contents only take the position.
+    fn set_placement(&mut self, _placement: Placement) {}
+
+    /// Takes the sensor threads the window has started since this was last called, such as by
+    /// restarting its sensor, so the main window can wait for them on shutdown.
+    fn take_join_handles(&mut self) -> Vec<JoinHandle<io::Result<()>>> {
+        Vec::new()
+    }
+
+    /// Where the window was as of the last frame it was drawn, which projects save so the window
+    /// can be put back there. Windows that haven't been drawn yet return `None`.
+    fn current_placement(&self) -> Option<Placement> {
+        None
+    }
+
+    /// Renames the window, e.g. to the name it had when a project was saved. Windows with a fixed
+    /// name ignore this.
+    fn set_name(&mut self, _name: String) {}
 }
 
 pub trait Modal {
+    /// The name the sensor is listed under in the main window.
+    fn sensor_name(&self) -> &'static ImStr;
+
+    /// The id of the configuration popup, which `render_modal` draws once it's opened.
+    fn popup_name(&self) -> &'static ImStr;
+
     fn render_modal(
         &mut self,
         ui: &Ui,
         join_handles: &mut Vec<JoinHandle<io::Result<()>>>,
         sensor_windows: &mut Vec<Box<dyn Renderable>>,
+        shutdown: &Arc<AtomicBool>,
     );
+
+    /// Picks up changed preferences, such as the default port and channel capacity for new
+    /// sensors. Sensors without either can ignore this.
+    fn apply_settings(&mut self, _settings: &Settings) {}
+}
+
+/// Wraps owned or borrowed RGB bytes in an image that can be uploaded as a texture. A zero-sized
+/// image can't be turned into a texture, so `None` is returned for one and callers keep their
+/// previous texture.
+pub fn rgb_image<'a>(
+    bytes: impl Into<Cow<'a, [u8]>>,
+    width: u32,
+    height: u32,
+) -> Option<RawImage2d<'a, u8>> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(RawImage2d {
+        data: bytes.into(),
+        width,
+        height,
+        format: ClientFormat::U8U8U8,
+    })
+}
+
+/// Uploads RGB bytes as a window's texture, replacing the texture the window had if there was one.
+/// A zero-sized image can't be turned into a texture, so for one the window keeps the texture it
+/// had. Images have to fit the GPU's texture size limit, which `texture::upload_rgb` takes care of.
+pub fn upload_rgb_texture(
+    display: &Display,
+    renderer: &mut Renderer,
+    texture_id: &mut Option<TextureId>,
+    bytes: Cow<[u8]>,
+    width: u32,
+    height: u32,
+) -> Result<(), TextureError> {
+    let image_frame = match rgb_image(bytes, width, height) {
+        Some(image_frame) => image_frame,
+        None => return Ok(()),
+    };
+    let gl_texture = Texture2d::new(display.get_context(), image_frame).map_err(|error| {
+        TextureError::Create {
+            width,
+            height,
+            error,
+        }
+    })?;
+    store_texture(renderer.textures(), texture_id, Rc::new(gl_texture));
+    Ok(())
+}
+
+/// Stores a window's texture under the id the window already has, or under a new id the first
+/// time, so imgui keeps drawing the window's image from the same id.
+pub fn store_texture<T>(
+    textures: &mut Textures<T>,
+    texture_id: &mut Option<TextureId>,
+    texture: T,
+) {
+    match texture_id {
+        Some(tex_id) => {
+            textures.replace(*tex_id, texture);
+        }
+        None => *texture_id = Some(textures.insert(texture)),
+    }
+}
+
+/// Creates the configuration modal of every sensor type, in the order they're listed in the main
+/// window. The main window only deals with them through `Modal`, so a new sensor type only needs
+/// its modal added here.
+fn config_windows() -> Vec<Box<dyn Modal>> {
+    vec![
+        Box::new(CameraConfig::new()),
+        Box::new(LidarConfig::new()),
+        Box::new(GpsConfig::new()),
+        Box::new(ControllerConfig::new()),
+        Box::new(PlaybackConfig::new()),
+    ]
+}
+
+/// The id the next sensor window gets. imgui identifies windows by their title, so the id keeps
+/// windows with the same name apart.
+static NEXT_WINDOW_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Hands out an id that no other sensor window of this run has.
+pub fn next_window_id() -> usize {
+    NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The imgui title of a sensor window: its name, followed by its id, which imgui uses to tell the
+/// window apart from others but doesn't show.
+pub fn window_title(name: &str, id: usize) -> ImString {
+    im_str!("{}##sensor{}", name, id)
+}
+
+/// The name a config modal gives the window it creates: what the user typed, or the sensor's
+/// usual name if they left it blank.
+pub fn window_name(input: &ImStr, default: &str) -> String {
+    match input.to_str().trim() {
+        "" => default.to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Why the main window couldn't be opened.
+pub enum StartupError {
+    /// There's no display to open a window on, or its driver couldn't make an OpenGL context.
+    Display(DisplayCreationError),
+    /// The UI renderer couldn't be set up on the OpenGL context, along with the version string
+    /// the driver reported.
+    Renderer(RendererError, String),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupError::Display(e) => write!(
+                f,
+                "Couldn't create the window: {}\n\
+                 sensorview needs a display and an OpenGL 2.1 or newer driver. Check that DISPLAY \
+                 or WAYLAND_DISPLAY is set and that a graphics driver is installed, or use \
+                 --headless to capture the sensors without a window.",
+                e
+            ),
+            StartupError::Renderer(e, version) => write!(
+                f,
+                "Couldn't initialize the renderer: {}\n\
+                 The OpenGL driver reports version {}. Updating the graphics driver, or trying \
+                 LIBGL_ALWAYS_SOFTWARE=1 for software rendering, may help.",
+                e, version
+            ),
+        }
+    }
 }
 
 pub struct SensorWindow {
@@ -32,31 +265,359 @@ pub struct SensorWindow {
     platform: WinitPlatform,
     renderer: Renderer,
     sensor_windows: Vec<Box<dyn Renderable>>,
+    /// Whether each of the sensor windows is shown, in the same order as `sensor_windows`.
+    sensor_visibility: Vec<bool>,
     join_handles: Vec<JoinHandle<io::Result<()>>>,
     config_windows: Vec<Box<dyn Modal>>,
+    /// The sensors given on the command line.
+    initial_sensors: Vec<SensorSpec>,
+    /// The restored sensor windows that were popped out in the last session, by their index in
+    /// `sensor_windows`. They're popped out again on the first frame.
+    restored_detached: Vec<(usize, DetachedLayout)>,
+    session: Session,
+    /// The global preferences, edited in the preferences window.
+    settings: Settings,
+    notifications: Notifications,
+    /// The recent log records, shown in the log window.
+    log_buffer: LogBuffer,
+    /// Set when the application exits to tell the sensor threads to stop.
+    shutdown: Arc<AtomicBool>,
+    /// Whether every sensor window is paused by the Pause All toggle.
+    paused: bool,
+    /// Where to serve the health report, if anywhere.
+    health_address: Option<SocketAddr>,
+    /// The sensors the health endpoint reports on, once it's running.
+    health_sensors: Option<HealthSensors>,
+}
+
+/// Moves the windows inside the main window to where they were in the saved session.
+fn restore_layout(imgui: &mut Context, session: &Session) {
+    imgui.load_ini_settings(&session.layout);
+}
+
+/// Records where the windows inside the main window are, to be saved with the session.
+fn save_layout(imgui: &mut Context, session: &mut Session) {
+    session.layout.clear();
+    imgui.save_ini_settings(&mut session.layout);
+}
+
+/// Parses what was typed into a config modal into the address a sensor listens on. A bare port
+/// listens on every IPv4 interface, while anything else is resolved with `resolve_addr`, so
+/// `[::]:8001` listens on IPv6 and `localhost:8001` only on the loopback interface.
+pub fn parse_listen_addr(address: &str) -> io::Result<SocketAddr> {
+    let address = address.trim();
+    match address.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from(([0, 0, 0, 0], port))),
+        Err(_) => resolve_addr(address),
+    }
+}
+
+/// Resolves an address like `192.168.1.10:8001`, `[fe80::1]:8001` or `robot.local:8001`. IPv6
+/// addresses need their brackets to be told apart from the port. A hostname that resolves to
+/// several addresses gives the first one.
+pub fn resolve_addr(address: &str) -> io::Result<SocketAddr> {
+    let address = address.trim();
+    // Numeric addresses are what's usually typed, and they don't need the resolver.
+    if let Ok(parsed) = address.parse() {
+        return Ok(parsed);
+    }
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} didn't resolve to any address", address),
+        )
+    })
+}
+
+/// The number of physical pixels per logical pixel in the window being drawn. imgui lays windows
+/// out in logical pixels, and the factor changes when a window moves to a monitor with a different
+/// DPI, so it's read again every frame rather than kept from startup.
+pub fn scale_factor(ui: &Ui) -> f32 {
+    let factor = ui.io().display_framebuffer_scale[0];
+    if factor > 0.0 {
+        factor
+    } else {
+        1.0
+    }
+}
+
+/// The logical size to show an image of the given size in pixels at, so that each pixel of the
+/// image covers one physical pixel rather than being stretched on HiDPI displays.
+pub fn logical_size(pixels: [f32; 2], scale_factor: f32) -> [f32; 2] {
+    [pixels[0] / scale_factor, pixels[1] / scale_factor]
+}
+
+/// The space left between the windows arranged by the Dashboard button, and around them, in
+/// logical pixels.
+const DASHBOARD_GAP: f32 = 8.0;
+
+/// Where a window should be and how big, in logical pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+/// Splits the area at `origin` of the given size into `count` equal cells, `gap` apart and
+/// filled row by row, with as many columns as rows or one more. The cells don't overlap and stay
+/// inside the area, unless it's too small to give each cell a pixel.
+pub fn grid_layout(count: usize, origin: [f32; 2], size: [f32; 2], gap: f32) -> Vec<Placement> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let columns = (count as f32).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    let cell_width = ((size[0] - gap * (columns + 1) as f32) / columns as f32).max(1.0);
+    let cell_height = ((size[1] - gap * (rows + 1) as f32) / rows as f32).max(1.0);
+    (0..count)
+        .map(|ix| {
+            let (row, column) = (ix / columns, ix % columns);
+            Placement {
+                position: [
+                    origin[0] + gap + column as f32 * (cell_width + gap),
+                    origin[1] + gap + row as f32 * (cell_height + gap),
+                ],
+                size: [cell_width, cell_height],
+            }
+        })
+        .collect()
+}
+
+/// Where the window being built is and how big it is.
+pub fn current_placement(ui: &Ui) -> Placement {
+    Placement {
+        position: ui.window_pos(),
+        size: ui.window_size(),
+    }
+}
+
+/// Applies a placement asked for with `Renderable::set_placement` to a window being built.
+pub trait Place {
+    /// Moves and sizes the window if there's a placement. This has to come after any other size
+    /// given to the builder, which would otherwise replace it.
+    fn place(self, placement: Option<Placement>) -> Self;
+}
+
+impl<'a> Place for Window<'a> {
+    fn place(self, placement: Option<Placement>) -> Self {
+        match placement {
+            Some(placement) => self
+                .position(placement.position, Condition::Always)
+                .size(placement.size, Condition::Always),
+            None => self,
+        }
+    }
+}
+
+/// What the project popup does once it's confirmed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProjectAction {
+    Save,
+    Open,
+}
+
+/// Describes the sensors running in the main window and in detached windows, along with the look
+/// of the main window and the preferences, as a project.
+fn current_project(
+    sensor_windows: &[Box<dyn Renderable>],
+    sensor_visibility: &[bool],
+    detached_windows: &[DetachedWindow],
+    session: &Session,
+    settings: &Settings,
+) -> Project {
+    let attached = sensor_windows
+        .iter()
+        .zip(sensor_visibility)
+        .map(|(win, visible)| (win.as_ref(), *visible));
+    let detached = detached_windows.iter().map(|win| (win.renderable(), true));
+    let sensors = attached
+        .chain(detached)
+        .filter_map(|(win, visible)| {
+            win.sensor_spec().map(|spec| ProjectSensor {
+                spec,
+                name: win.name().to_string(),
+                visible,
+                placement: win.current_placement(),
+            })
+        })
+        .collect();
+    Project {
+        sensors,
+        theme: session.theme,
+        background: session.background,
+        settings: settings.clone(),
+    }
+}
+
+/// Describes a sensor in a notification, e.g. "GPS on 0.0.0.0:8003".
+fn spec_label(spec: &SensorSpec) -> String {
+    match spec {
+        SensorSpec::Camera { address, .. } => format!("camera on {}", address),
+        SensorSpec::Lidar { address, .. } => format!("LIDAR on {}", address),
+        SensorSpec::Gps { address, .. } => format!("GPS on {}", address),
+        SensorSpec::Controller { address, .. } => format!("controller for {}", address),
+    }
+}
+
+/// Saves the image of every window that has one to a new timestamped directory, named after the
+/// window, e.g. camera.png and lidar.png. Further windows of the same kind are numbered, e.g.
+/// camera-2.png. Returns notifications saying how it went.
+fn capture_all(
+    sensor_windows: &[Box<dyn Renderable>],
+    detached_windows: &[DetachedWindow],
+) -> Vec<Notification> {
+    let dir = PathBuf::from(camera::timestamped_name("capture"));
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return vec![Notification::error(format!(
+            "Couldn't create {}: {}",
+            dir.display(),
+            e
+        ))];
+    }
+    let windows = sensor_windows
+        .iter()
+        .map(|win| win.as_ref())
+        .chain(detached_windows.iter().map(|win| win.renderable()));
+    let mut notifications = Vec::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut saved = 0;
+    for window in windows {
+        // Window names are chosen by the user, so anything that isn't safe in a file name is
+        // replaced.
+        let stem: String = window
+            .name()
+            .to_lowercase()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let count = counts.get(&stem).cloned().unwrap_or(0) + 1;
+        let file_name = if count == 1 {
+            format!("{}.png", stem)
+        } else {
+            format!("{}-{}.png", stem, count)
+        };
+        let path = dir.join(file_name);
+        match window.capture(&path) {
+            Some(Ok(())) => saved += 1,
+            Some(Err(e)) => notifications.push(Notification::warn(format!(
+                "Couldn't save {}: {}",
+                path.display(),
+                e
+            ))),
+            None => continue,
+        }
+        counts.insert(stem, count);
+    }
+    if saved > 0 {
+        notifications.push(Notification::info(format!(
+            "Captured {} windows to {}",
+            saved,
+            dir.display()
+        )));
+    } else {
+        // Don't leave an empty directory behind when nothing had an image yet.
+        let _ = fs::remove_dir(&dir);
+        notifications.push(Notification::warn(
+            "No windows have an image to capture yet".to_string(),
+        ));
+    }
+    notifications
+}
+
+/// The color schemes the UI can be drawn with.
+#[derive(AsRefStr, EnumIter, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    Classic,
+}
+
+impl Theme {
+    /// Switches the colors of the imgui style over to this theme.
+    fn apply(self, style: &mut Style) {
+        match self {
+            Theme::Dark => style.use_dark_colors(),
+            Theme::Light => style.use_light_colors(),
+            Theme::Classic => style.use_classic_colors(),
+        };
+    }
+
+    /// The color the background behind the imgui windows is cleared to.
+    fn clear_color(self) -> (f32, f32, f32, f32) {
+        match self {
+            Theme::Dark => (0.211, 0.223, 0.243, 1.0),
+            Theme::Light => (0.862, 0.862, 0.862, 1.0),
+            Theme::Classic => (0.114, 0.114, 0.152, 1.0),
+        }
+    }
+}
+
+/// How long to sleep after a frame that took `elapsed` so that frames start no closer together
+/// than `interval`. A frame that ran over gets no sleep at all.
+fn frame_sleep(interval: Duration, elapsed: Duration) -> Duration {
+    interval
+        .checked_sub(elapsed)
+        .unwrap_or_else(|| Duration::from_secs(0))
+}
+
+/// The fraction of the primary monitor that the main window covers when it's first opened.
+const SCREEN_FRACTION: f64 = 0.9;
+
+/// Picks the size of the main window from the primary monitor's resolution. Some platforms can't
+/// report the monitor's size and return zero, in which case this falls back to 1280x720.
+fn initial_size(events_loop: &glutin::EventsLoop) -> LogicalSize {
+    let monitor = events_loop.get_primary_monitor();
+    let screen = monitor
+        .get_dimensions()
+        .to_logical(monitor.get_hidpi_factor());
+    if screen.width < 1.0 || screen.height < 1.0 {
+        return LogicalSize::new(1280f64, 720f64);
+    }
+    LogicalSize::new(
+        screen.width * SCREEN_FRACTION,
+        screen.height * SCREEN_FRACTION,
+    )
 }
 
 impl SensorWindow {
-    /// Initializes a blank window for displaying multiple sensor windows.
-    pub fn new() -> Self {
+    /// Initializes a window for displaying multiple sensor windows. The given sensors are started
+    /// once rendering begins, in addition to any restored from the last session, as is the health
+    /// endpoint if `health_address` is set. Fails when there's no display or its OpenGL driver
+    /// can't run the UI.
+    pub fn new(
+        sensors: Vec<SensorSpec>,
+        health_address: Option<SocketAddr>,
+        log_buffer: LogBuffer,
+    ) -> Result<Self, StartupError> {
         let events_loop = glutin::EventsLoop::new();
-        let context = glutin::ContextBuilder::new().with_vsync(true);
+        let settings = Settings::load();
+        let context = glutin::ContextBuilder::new().with_vsync(settings.vsync);
 
-        // TODO: Query screen resolution so the window size isn't hardcoded.
-        let builder = glutin::WindowBuilder::new()
-            .with_dimensions(glutin::dpi::LogicalSize::new(1920f64, 1080f64));
+        let builder = glutin::WindowBuilder::new().with_dimensions(initial_size(&events_loop));
         let display =
-            Display::new(builder, context, &events_loop).expect("Could not create display.");
+            Display::new(builder, context, &events_loop).map_err(StartupError::Display)?;
+        texture::query_gpu_limit(&display);
         let mut imgui = Context::create();
+        // The layout is kept in the session file rather than in imgui's own ini file.
         imgui.set_ini_filename(None);
+        let session = Session::load();
+        restore_layout(&mut imgui, &session);
+        clipboard::install(&mut imgui);
         let mut platform = WinitPlatform::init(&mut imgui);
         {
             let gl_window = display.gl_window();
             let window = gl_window.window();
-            platform.attach_window(imgui.io_mut(), &window, HiDpiMode::Rounded);
+            platform.attach_window(imgui.io_mut(), window, HiDpiMode::Rounded);
         }
         let hidpi_factor = platform.hidpi_factor();
-        let font_size = (13.0 * hidpi_factor) as f32;
+        let font_size = (f64::from(settings.font_size) * hidpi_factor) as f32;
         imgui.fonts().add_font(&[
             FontSource::DefaultFontData {
                 config: Some(FontConfig {
@@ -76,30 +637,119 @@ impl SensorWindow {
 
         imgui.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
 
-        let renderer = Renderer::init(&mut imgui, &display).expect("Failed to initialize renderer");
+        let renderer = Renderer::init(&mut imgui, &display).map_err(|e| {
+            StartupError::Renderer(e, display.get_opengl_version_string().to_owned())
+        })?;
 
-        let config_windows: Vec<Box<dyn Modal>> = vec![
-            Box::new(CameraConfig::new()),
-            Box::new(LidarConfig::new()),
-            Box::new(GpsConfig::new()),
-            Box::new(ControllerConfig::new()),
-        ];
-
-        Self {
+        Ok(Self {
             events_loop,
             display,
             imgui,
             platform,
             renderer,
             sensor_windows: Vec::new(),
+            sensor_visibility: Vec::new(),
             join_handles: Vec::new(),
-            config_windows,
-        }
+            config_windows: config_windows(),
+            initial_sensors: sensors,
+            restored_detached: Vec::new(),
+            session,
+            settings,
+            notifications: Notifications::new(),
+            log_buffer,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            paused: false,
+            health_address,
+            health_sensors: None,
+        })
     }
 
     /// Starts the rendering loop for the window. This will check for any new data received from
     /// the sensors and update any windows with new information.
-    pub fn render(self) {
+    pub fn render(mut self) {
+        if self.session.restore_on_launch {
+            for (sensor, spec) in self.session.sensors.iter().enumerate() {
+                let window_ix = self.sensor_windows.len();
+                if let Err(e) = session::spawn_sensor(
+                    spec,
+                    &self.settings,
+                    &mut self.join_handles,
+                    &mut self.sensor_windows,
+                    &self.shutdown,
+                ) {
+                    warn!("Couldn't restore {:?}: {}", spec, e);
+                    self.notifications.push(Notification::warn(format!(
+                        "Couldn't restore {}: {}",
+                        spec_label(spec),
+                        e
+                    )));
+                    continue;
+                }
+                if let Some(layout) = self.session.detached.iter().find(|l| l.sensor == sensor) {
+                    self.restored_detached.push((window_ix, layout.clone()));
+                }
+            }
+            if !self.sensor_windows.is_empty() {
+                self.notifications.push(Notification::info(format!(
+                    "Restored {} sensors from the last session",
+                    self.sensor_windows.len()
+                )));
+            }
+        }
+        for spec in &self.initial_sensors {
+            if let Err(e) = session::spawn_sensor(
+                spec,
+                &self.settings,
+                &mut self.join_handles,
+                &mut self.sensor_windows,
+                &self.shutdown,
+            ) {
+                error!("Couldn't start {:?}: {}", spec, e);
+                self.notifications.push(Notification::error(format!(
+                    "Couldn't start {}: {}",
+                    spec_label(spec),
+                    e
+                )));
+            }
+        }
+        if let Some(address) = self.health_address {
+            let health_sensors = HealthSensors::default();
+            match health::start(address, health_sensors.clone(), self.shutdown.clone()) {
+                Ok(handle) => {
+                    self.join_handles.push(handle);
+                    self.health_sensors = Some(health_sensors);
+                }
+                Err(e) => {
+                    error!("Couldn't serve the health report on {}: {}", address, e);
+                    self.notifications.push(Notification::error(format!(
+                        "Couldn't serve the health report on {}: {}",
+                        address, e
+                    )));
+                }
+            }
+        }
+        self.render_frames(None);
+    }
+
+    /// Renders a single frame containing a LIDAR window fed with a synthetic scan, then returns.
+    /// This exercises the display, imgui and texture upload paths so a broken build can be caught
+    /// without having to launch the UI and connect sensors by hand.
+    pub fn selftest(mut self) {
+        let (lidar_tx, lidar_rx) = unbounded();
+        let distances = (0..360)
+            .map(|degrees| ((degrees as f32).to_radians(), 2000.0))
+            .collect();
+        lidar_tx
+            .send(LidarData::new(distances))
+            .expect("Couldn't send synthetic LIDAR data");
+        self.sensor_windows
+            .push(Box::new(LidarWindow::new(lidar_rx)));
+        self.render_frames(Some(1));
+    }
+
+    /// Runs the rendering loop until the window is closed or, if given, until `frame_limit`
+    /// frames have been drawn.
+    fn render_frames(self, frame_limit: Option<u32>) {
         let SensorWindow {
             mut events_loop,
             mut platform,
@@ -107,85 +757,615 @@ impl SensorWindow {
             mut imgui,
             mut renderer,
             mut sensor_windows,
+            mut sensor_visibility,
             mut join_handles,
             mut config_windows,
+            restored_detached,
+            mut session,
+            mut settings,
+            mut notifications,
+            log_buffer,
+            shutdown,
+            mut paused,
+            health_sensors,
             ..
         } = self;
         let gl_window = display.gl_window();
         let window = gl_window.window();
         let mut run = true;
         let mut selected_sensor = 0i32;
+        let mut frames = 0;
+        let mut detached_windows: Vec<DetachedWindow> = Vec::new();
+        // The sensor windows to pop out into their own OS windows at the end of the frame, by
+        // their index in `sensor_windows`, along with where to put them if they're being restored.
+        let mut detach_requests: Vec<(usize, Option<DetachedLayout>)> = restored_detached
+            .into_iter()
+            .map(|(ix, layout)| (ix, Some(layout)))
+            .collect();
+        let theme_list: Vec<ImString> = Theme::iter()
+            .map(|theme| {
+                let theme_str: &str = theme.as_ref();
+                ImString::new(theme_str)
+            })
+            .collect();
+        let mut applied_theme = None;
+        let mut diagnostics: Option<DiagnosticsWindow> = None;
+        let mut log_window: Option<LogWindow> = None;
+        let mut preferences: Option<PreferencesWindow> = None;
+        let mut applied_settings = None;
+        let mut dashboard_origin = None;
+        let mut project_path = ImString::new("sensorview_project.json");
+        project_path.reserve_exact(256);
+        let mut project_dialog = None;
+        let mut project_confirmed = None;
+        // The sensors of the project last opened that are still waiting for their ports.
+        let mut pending_sensors: Option<PendingSensors> = None;
 
         while run {
+            let frame_start = Instant::now();
+            let mut had_input = false;
+            let mut capture_requested = false;
+
             // Handle any close events for the window.
             events_loop.poll_events(|event| {
-                platform.handle_event(imgui.io_mut(), &window, &event);
+                platform.handle_event(imgui.io_mut(), window, &event);
+                for detached in &mut detached_windows {
+                    detached.handle_event(&event);
+                }
 
-                if let Event::WindowEvent { event, .. } = event {
-                    if let WindowEvent::CloseRequested = event {
+                if let Event::WindowEvent { event, window_id } = event {
+                    had_input = true;
+                    if window_id == window.id() && matches!(event, WindowEvent::CloseRequested) {
                         run = false;
                     }
                 }
             });
 
+            let busy = had_input
+                || sensor_windows.iter().any(|win| win.has_pending_data())
+                || detached_windows.iter().any(|win| win.has_pending_data());
+
+            // The style can't be changed while a frame is being built, so a theme picked during a
+            // frame is applied at the start of the next one.
+            if applied_theme != Some(session.theme) {
+                session.theme.apply(imgui.style_mut());
+                applied_theme = Some(session.theme);
+            }
+            // Likewise the config modals and the texture size limit pick up changed preferences
+            // before the frame is built.
+            if applied_settings.as_ref() != Some(&settings) {
+                texture::set_configured_limit(settings.max_texture_size);
+                config_windows
+                    .iter_mut()
+                    .for_each(|win| win.apply_settings(&settings));
+                applied_settings = Some(settings.clone());
+            }
+
             let io = imgui.io_mut();
             platform
-                .prepare_frame(io, &window)
+                .prepare_frame(io, window)
                 .expect("Failed to start frame.");
             let ui = imgui.frame();
 
-            Window::new(im_str!("SensorView")).build(&ui, || {
-                ui.text(im_str!("Create new:"));
-                ui.list_box(
-                    im_str!(""),
-                    &mut selected_sensor,
-                    &[
-                        im_str!("Camera"),
-                        im_str!("LIDAR"),
-                        im_str!("GPS"),
-                        im_str!("Controller"),
-                    ],
-                    10,
-                );
-                config_windows
-                    .iter_mut()
-                    .for_each(|win| win.render_modal(&ui, &mut join_handles, &mut sensor_windows));
-                if ui.button(im_str!("Configure sensor..."), [0.0, 0.0]) {
-                    match selected_sensor {
-                        0 => {
-                            ui.open_popup(im_str!("Camera Configuration"));
-                        }
-                        1 => {
-                            ui.open_popup(im_str!("LIDAR Configuration"));
+            Window::new(im_str!("SensorView"))
+                .flags(WindowFlags::MENU_BAR)
+                .build(&ui, || {
+                    let mut project_request = None;
+                    ui.menu_bar(|| {
+                        ui.menu(im_str!("File"), true, || {
+                            if MenuItem::new(im_str!("Save Project As...")).build(&ui) {
+                                project_request = Some(ProjectAction::Save);
+                            }
+                            if MenuItem::new(im_str!("Open Project...")).build(&ui) {
+                                project_request = Some(ProjectAction::Open);
+                            }
+                        });
+                        ui.menu(im_str!("Windows"), true, || {
+                            if sensor_windows.is_empty() {
+                                ui.text(im_str!("No active sensors"));
+                            }
+                            for (ix, sensor_window) in sensor_windows.iter().enumerate() {
+                                MenuItem::new(&im_str!("{}##{}", sensor_window.name(), ix))
+                                    .build_with_ref(&ui, &mut sensor_visibility[ix]);
+                            }
+                        });
+                    });
+                    // Popups opened from a menu would belong to the menu, so the project popup is
+                    // opened here instead.
+                    if let Some(action) = project_request {
+                        project_dialog = Some(action);
+                        ui.open_popup(im_str!("Project"));
+                    }
+                    ui.popup_modal(im_str!("Project"))
+                        .flags(WindowFlags::ALWAYS_AUTO_RESIZE)
+                        .build(|| {
+                            ui.input_text(im_str!("Project File"), &mut project_path)
+                                .build();
+                            let confirm = match project_dialog {
+                                Some(ProjectAction::Open) => im_str!("Open"),
+                                _ => im_str!("Save"),
+                            };
+                            if ui.button(confirm, [0.0, 0.0]) {
+                                project_confirmed = project_dialog.take();
+                                ui.close_current_popup();
+                            }
+                            ui.same_line(0.0);
+                            if ui.button(im_str!("Cancel"), [0.0, 0.0]) {
+                                project_dialog = None;
+                                ui.close_current_popup();
+                            }
+                        });
+
+                    ui.checkbox(im_str!("Pause All"), &mut paused);
+                    ui.separator();
+                    ui.text(im_str!("Create new:"));
+                    let sensor_names: Vec<&ImStr> =
+                        config_windows.iter().map(|win| win.sensor_name()).collect();
+                    ui.list_box(im_str!(""), &mut selected_sensor, &sensor_names, 10);
+                    config_windows.iter_mut().for_each(|win| {
+                        win.render_modal(&ui, &mut join_handles, &mut sensor_windows, &shutdown)
+                    });
+                    if ui.button(im_str!("Configure sensor..."), [0.0, 0.0]) {
+                        if let Some(config) = config_windows.get(selected_sensor as usize) {
+                            ui.open_popup(config.popup_name());
                         }
-                        2 => {
-                            ui.open_popup(im_str!("GPS Configuration"));
+                    }
+
+                    // List the active sensor windows so the user can reorder them. Windows are
+                    // submitted to imgui in this order, so moving a window down the list draws it
+                    // after (and on top of) the windows above it.
+                    ui.separator();
+                    ui.text(im_str!("Active sensors:"));
+                    let mut swap = None;
+                    for (ix, sensor_window) in sensor_windows.iter().enumerate() {
+                        ui.text(sensor_window.name());
+                        ui.same_line(0.0);
+                        if ui.small_button(&im_str!("Up##{}", ix)) && ix > 0 {
+                            swap = Some((ix - 1, ix));
                         }
-                        3 => {
-                            ui.open_popup(im_str!("Controller Configuration"));
+                        ui.same_line(0.0);
+                        if ui.small_button(&im_str!("Down##{}", ix))
+                            && ix + 1 < sensor_windows.len()
+                        {
+                            swap = Some((ix, ix + 1));
                         }
-                        _ => {
-                            ui.text("Not supported yet");
+                        ui.same_line(0.0);
+                        if ui.small_button(&im_str!("Detach##{}", ix)) {
+                            detach_requests.push((ix, None));
                         }
                     }
+                    if let Some((a, b)) = swap {
+                        sensor_windows.swap(a, b);
+                        sensor_visibility.swap(a, b);
+                    }
+                    if ui.button(im_str!("Diagnostics"), [0.0, 0.0]) && diagnostics.is_none() {
+                        diagnostics = Some(DiagnosticsWindow::new());
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Log"), [0.0, 0.0]) && log_window.is_none() {
+                        log_window = Some(LogWindow::new(log_buffer.clone()));
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Preferences"), [0.0, 0.0]) && preferences.is_none() {
+                        preferences = Some(PreferencesWindow::new());
+                    }
+                    ui.same_line(0.0);
+                    capture_requested = ui.button(im_str!("Capture All"), [0.0, 0.0]);
+                    ui.same_line(0.0);
+                    // The sensor windows are arranged to the right of this window, so it isn't
+                    // covered.
+                    if ui.button(im_str!("Dashboard"), [0.0, 0.0]) {
+                        let (position, size) = (ui.window_pos(), ui.window_size());
+                        dashboard_origin = Some([position[0] + size[0], 0.0]);
+                    }
+
+                    ui.separator();
+                    ui.checkbox(
+                        im_str!("Restore last session on launch"),
+                        &mut session.restore_on_launch,
+                    );
+
+                    let theme_slices: Vec<&ImString> = theme_list.iter().collect();
+                    let mut theme_item = Theme::iter()
+                        .position(|theme| theme == session.theme)
+                        .unwrap_or(0);
+                    if imgui::ComboBox::new(im_str!("Theme")).build_simple_string(
+                        &ui,
+                        &mut theme_item,
+                        &theme_slices,
+                    ) {
+                        session.theme = Theme::iter().nth(theme_item).unwrap_or_default();
+                    }
+
+                    let (red, green, blue, _) = session.theme.clear_color();
+                    let mut background = session.background.unwrap_or([red, green, blue]);
+                    if ColorEdit::new(im_str!("Background"), &mut background).build(&ui) {
+                        session.background = Some(background);
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Use Theme Background"), [0.0, 0.0]) {
+                        session.background = None;
+                    }
+                });
+
+            // Iterate over all created sensor windows and update them, dropping any the user has
+            // closed. The config modals only ever add windows at the end of the list, so windows
+            // they just created start out shown.
+            sensor_visibility.resize(sensor_windows.len(), true);
+            if let Some(origin) = dashboard_origin.take() {
+                let display_size = ui.io().display_size;
+                let shown: Vec<usize> = (0..sensor_windows.len())
+                    .filter(|&ix| sensor_visibility[ix])
+                    .collect();
+                let placements = grid_layout(
+                    shown.len(),
+                    origin,
+                    [display_size[0] - origin[0], display_size[1]],
+                    DASHBOARD_GAP,
+                );
+                for (ix, placement) in shown.into_iter().zip(placements) {
+                    sensor_windows[ix].set_placement(placement);
                 }
-            });
+            }
+            let mut closed = Vec::new();
+            for (ix, sensor_window) in sensor_windows.iter_mut().enumerate() {
+                if !sensor_window.render(
+                    &ui,
+                    &display,
+                    &mut renderer,
+                    sensor_visibility[ix],
+                    paused,
+                ) {
+                    closed.push(ix);
+                }
+                join_handles.extend(sensor_window.take_join_handles());
+            }
+            for ix in closed.into_iter().rev() {
+                sensor_windows.remove(ix);
+                sensor_visibility.remove(ix);
+            }
 
-            // Iterate over all created sensor windows and update them.
-            for sensor_window in &mut sensor_windows {
-                sensor_window.render(&ui, &display, &mut renderer);
+            // Capturing after the windows have taken this frame's data keeps the captures as close
+            // together in time as they can be.
+            if capture_requested {
+                for notification in capture_all(&sensor_windows, &detached_windows) {
+                    notifications.push(notification);
+                }
+            }
+
+            // The sensor threads can only record the connections they refuse, so it's up to the
+            // main window to tell the user about them.
+            for (name, stats) in sensor_windows
+                .iter()
+                .filter_map(|win| win.stats().map(|stats| (win.name().to_string(), stats)))
+                .chain(detached_windows.iter().filter_map(|win| win.stats()))
+            {
+                for peer in stats.take_refused() {
+                    notifications.push(Notification::warn(format!(
+                        "{}: refused a connection from {}, already serving {} connections",
+                        name,
+                        peer,
+                        stats.max_connections()
+                    )));
+                }
+            }
+            for notification in sensor_windows
+                .iter_mut()
+                .flat_map(|win| win.take_notifications())
+                .chain(
+                    detached_windows
+                        .iter_mut()
+                        .flat_map(|win| win.take_notifications()),
+                )
+            {
+                notifications.push(notification);
+            }
+            notifications.render(&ui);
+
+            if let Some(health_sensors) = &health_sensors {
+                if let Ok(mut health_sensors) = health_sensors.lock() {
+                    *health_sensors = sensor_windows
+                        .iter()
+                        .filter_map(|win| win.stats().map(|stats| (win.name().to_string(), stats)))
+                        .chain(detached_windows.iter().filter_map(|win| win.stats()))
+                        .collect();
+                }
+            }
+
+            if let Some(window) = &mut diagnostics {
+                window.sensors = sensor_windows
+                    .iter()
+                    .filter_map(|win| win.stats().map(|stats| (win.name().to_string(), stats)))
+                    .chain(detached_windows.iter().filter_map(|win| win.stats()))
+                    .collect();
+                if !window.render(&ui, &display, &mut renderer, true, paused) {
+                    diagnostics = None;
+                }
+            }
+            if let Some(window) = &mut log_window {
+                if !window.render(&ui, &display, &mut renderer, true, paused) {
+                    log_window = None;
+                }
+            }
+            if let Some(window) = &mut preferences {
+                if !window.render(&ui, &mut settings) {
+                    preferences = None;
+                }
             }
 
             // Once all the sensor windows are created and update them, we can now draw them to
             // the screen and start another iteration.
             let mut target = display.draw();
-            target.clear_color_srgb(0.211, 0.223, 0.243, 1.0);
-            platform.prepare_render(&ui, &window);
+            let (red, green, blue, alpha) = match session.background {
+                Some([red, green, blue]) => (red, green, blue, 1.0),
+                None => session.theme.clear_color(),
+            };
+            target.clear_color_srgb(red, green, blue, alpha);
+            platform.prepare_render(&ui, window);
             let draw_data = ui.render();
             renderer
                 .render(&mut target, draw_data)
                 .expect("Couldn't render");
             target.finish().expect("Failed to swap buffers");
+
+            if let Some(action) = project_confirmed.take() {
+                let project_file = PathBuf::from(project_path.to_str());
+                match action {
+                    ProjectAction::Save => {
+                        let project = current_project(
+                            &sensor_windows,
+                            &sensor_visibility,
+                            &detached_windows,
+                            &session,
+                            &settings,
+                        );
+                        notifications.push(match project.save(&project_file) {
+                            Ok(()) => Notification::info(format!(
+                                "Saved the project to {}",
+                                project_file.display()
+                            )),
+                            Err(e) => Notification::error(format!(
+                                "Couldn't save the project to {}: {}",
+                                project_file.display(),
+                                e
+                            )),
+                        });
+                    }
+                    // Dropping the current windows stops their sensors, so the project's sensors
+                    // can take over their ports.
+                    ProjectAction::Open => match Project::load(&project_file) {
+                        Ok(project) => {
+                            sensor_windows.clear();
+                            sensor_visibility.clear();
+                            detached_windows.clear();
+                            detach_requests.clear();
+                            session.theme = project.theme;
+                            session.background = project.background;
+                            settings = project.settings.clone();
+                            pending_sensors = Some(project.pending_sensors());
+                            notifications.push(Notification::info(format!(
+                                "Opened {}",
+                                project_file.display()
+                            )));
+                        }
+                        Err(e) => notifications.push(Notification::error(format!(
+                            "Couldn't open {}: {}",
+                            project_file.display(),
+                            e
+                        ))),
+                    },
+                }
+            }
+
+            // The project's sensors are started between frames, those whose ports the sensors they
+            // replace still hold being tried again on the next frame.
+            if let Some(pending) = &mut pending_sensors {
+                for (spec, e) in pending.spawn(
+                    &mut join_handles,
+                    &mut sensor_windows,
+                    &mut sensor_visibility,
+                    &shutdown,
+                ) {
+                    error!("Couldn't start {:?}: {}", spec, e);
+                    notifications.push(Notification::error(format!(
+                        "Couldn't start {}: {}",
+                        spec_label(&spec),
+                        e
+                    )));
+                }
+                if pending.is_empty() {
+                    pending_sensors = None;
+                }
+            }
+
+            // Detached windows each have their own imgui context, so the main context has to be
+            // suspended while they're created and drawn. Closing a detached window docks its
+            // sensor window back into the main window.
+            if !detach_requests.is_empty() || !detached_windows.is_empty() {
+                let suspended = imgui.suspend();
+                // Removing the highest index first keeps the other indices valid.
+                detach_requests.sort_by_key(|&(ix, _)| Reverse(ix));
+                for (ix, layout) in detach_requests.drain(..) {
+                    let renderable = sensor_windows.remove(ix);
+                    sensor_visibility.remove(ix);
+                    detached_windows.push(DetachedWindow::new(
+                        &events_loop,
+                        renderable,
+                        layout.as_ref(),
+                    ));
+                }
+                for detached in &mut detached_windows {
+                    detached.render_frame(paused);
+                    join_handles.extend(detached.take_join_handles());
+                }
+                let mut ix = 0;
+                while ix < detached_windows.len() {
+                    if detached_windows[ix].removed {
+                        detached_windows.remove(ix);
+                    } else if detached_windows[ix].closed {
+                        sensor_windows.push(detached_windows.remove(ix).into_renderable());
+                        sensor_visibility.push(true);
+                    } else {
+                        ix += 1;
+                    }
+                }
+                imgui = match suspended.activate() {
+                    Ok(imgui) => imgui,
+                    Err(_) => panic!("Couldn't reactivate the main imgui context"),
+                };
+            }
+
+            frames += 1;
+            if frame_limit.is_some_and(|limit| frames >= limit) {
+                run = false;
+            }
+
+            let mut frame_interval = settings.frame_interval();
+            if !busy {
+                frame_interval = frame_interval.max(settings.idle_frame_interval());
+            }
+            let sleep = frame_sleep(frame_interval, frame_start.elapsed());
+            if sleep > Duration::from_secs(0) {
+                thread::sleep(sleep);
+            }
+        }
+
+        // Remember which sensors were running so they can be started again on the next launch.
+        // Bounded runs like the self-test shouldn't clobber the user's session.
+        if frame_limit.is_none() {
+            session.sensors = sensor_windows
+                .iter()
+                .filter_map(|win| win.sensor_spec())
+                .collect();
+            session.detached.clear();
+            for detached in &detached_windows {
+                if let Some(spec) = detached.sensor_spec() {
+                    session
+                        .detached
+                        .push(detached.layout(session.sensors.len()));
+                    session.sensors.push(spec);
+                }
+            }
+            save_layout(&mut imgui, &mut session);
+            if let Err(e) = session.save() {
+                error!("Couldn't save the session: {}", e);
+            }
         }
+
+        // Tell the sensor threads to stop and wait for them so their ports are released before
+        // returning. Dropping the windows first disconnects their channels, which wakes up any
+        // sensor thread blocked on sending to a full channel.
+        drop(sensor_windows);
+        drop(detached_windows);
+        shutdown.store(true, Ordering::Relaxed);
+        for handle in join_handles {
+            match handle.join() {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => error!("Sensor thread stopped with an error: {}", e),
+                Err(_) => error!("Sensor thread panicked"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_sized_images_are_not_uploaded() {
+        assert!(rgb_image(Vec::new(), 0, 0).is_none());
+        assert!(rgb_image(Vec::new(), 0, 4).is_none());
+        assert!(rgb_image(Vec::new(), 4, 0).is_none());
+
+        let image = rgb_image(vec![0; 2 * 3 * 3], 2, 3).expect("a 2x3 image should be uploaded");
+        assert_eq!((image.width, image.height), (2, 3));
+    }
+
+    #[test]
+    fn textures_are_inserted_once_then_replaced() {
+        let mut textures = Textures::new();
+        let mut texture_id = None;
+        store_texture(&mut textures, &mut texture_id, "first");
+        let first_id = texture_id.expect("the first upload should insert a texture");
+        assert_eq!(textures.get(first_id), Some(&"first"));
+
+        store_texture(&mut textures, &mut texture_id, "second");
+        assert_eq!(texture_id, Some(first_id));
+        assert_eq!(textures.get(first_id), Some(&"second"));
+    }
+
+    #[test]
+    fn every_sensor_is_configured_through_a_modal() {
+        let configs = config_windows();
+        let sensor_names: Vec<&str> = configs
+            .iter()
+            .map(|config| config.sensor_name().to_str())
+            .collect();
+        assert!(sensor_names.contains(&"Camera"));
+        let mut popup_names: Vec<&str> = configs
+            .iter()
+            .map(|config| config.popup_name().to_str())
+            .collect();
+        popup_names.sort_unstable();
+        popup_names.dedup();
+        assert_eq!(popup_names.len(), configs.len());
+    }
+
+    #[test]
+    fn listen_addresses_come_from_the_port() {
+        assert_eq!(
+            parse_listen_addr("8001").unwrap(),
+            SocketAddr::from(([0, 0, 0, 0], 8001))
+        );
+        assert_eq!(
+            parse_listen_addr(" 8003 ").unwrap(),
+            SocketAddr::from(([0, 0, 0, 0], 8003))
+        );
+        assert!(parse_listen_addr("").is_err());
+        assert!(parse_listen_addr("not a port").is_err());
+        assert!(parse_listen_addr("70000").is_err());
+    }
+
+    #[test]
+    fn listen_addresses_can_be_ipv6() {
+        assert_eq!(
+            parse_listen_addr("[::]:8001").unwrap(),
+            SocketAddr::from(([0u16; 8], 8001))
+        );
+        assert_eq!(
+            resolve_addr(" [fe80::1]:8002 ").unwrap(),
+            "[fe80::1]:8002".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_addr("192.168.1.10:8003").unwrap(),
+            SocketAddr::from(([192, 168, 1, 10], 8003))
+        );
+        assert!(resolve_addr("localhost").is_err());
+    }
+
+    #[test]
+    fn window_layouts_are_restored_and_saved_with_the_session() {
+        let mut imgui = Context::create();
+        imgui.set_ini_filename(None);
+        imgui.io_mut().display_size = [1280.0, 720.0];
+        imgui.fonts().build_rgba32_texture();
+
+        let mut session = Session {
+            layout: "[Window][Lidar]\nPos=120,80\nSize=300,200\nCollapsed=0\n\n".to_owned(),
+            ..Session::default()
+        };
+        restore_layout(&mut imgui, &session);
+
+        let mut position = [0.0; 2];
+        let ui = imgui.frame();
+        Window::new(im_str!("Lidar")).build(&ui, || position = ui.window_pos());
+        ui.render();
+        assert_eq!(position, [120.0, 80.0]);
+
+        save_layout(&mut imgui, &mut session);
+        assert!(session.layout.contains("[Window][Lidar]"));
+        assert!(session.layout.contains("Pos=120,80"));
+        assert_eq!(session.layout.matches("[Window][Lidar]").count(), 1);
     }
 }
@@ -0,0 +1,425 @@
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{
+    ClientFormat, MipmapsOption, RawImage2d, TextureCreationError, UncompressedFloatFormat,
+};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{
+    implement_vertex, uniform, Display, DrawError, Program, ProgramCreationError, Rect, Surface,
+    Texture2d, VertexBuffer,
+};
+use std::borrow::Cow;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// How much each of the chroma components, centered on 0, adds to the red, green and blue of a
+/// pixel, as in the full range BT.601 conversion JPEG and H264 decoders use.
+const CR_TO_RED: f32 = 1.402;
+const CB_TO_GREEN: f32 = -0.344_136;
+const CR_TO_GREEN: f32 = -0.714_136;
+const CB_TO_BLUE: f32 = 1.772;
+
+const VERTEX_SHADER: &str = "
+    #version 140
+    in vec2 position;
+    out vec2 v_tex_coords;
+    void main() {
+        v_tex_coords = position * 0.5 + 0.5;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+";
+
+/// Converts a pixel from the planes to RGB the same way `ycbcr_to_rgb` does. The planes hold
+/// values from 0 to 1 rather than 0 to 255, so the chroma is centered on 0.5. Each chroma
+/// component is picked out of its plane's channels by a mask, so the same shader reads planes of
+/// their own and a single texture of interleaved components.
+fn fragment_shader() -> String {
+    format!(
+        "
+        #version 140
+        uniform sampler2D y_plane;
+        uniform sampler2D u_plane;
+        uniform sampler2D v_plane;
+        uniform vec3 u_channel;
+        uniform vec3 v_channel;
+        in vec2 v_tex_coords;
+        out vec4 color;
+        void main() {{
+            float y = texture(y_plane, v_tex_coords).r;
+            float cb = dot(texture(u_plane, v_tex_coords).rgb, u_channel) - 0.5;
+            float cr = dot(texture(v_plane, v_tex_coords).rgb, v_channel) - 0.5;
+            color = vec4(
+                y + {:.6} * cr,
+                y + {:.6} * cb + {:.6} * cr,
+                y + {:.6} * cb,
+                1.0
+            );
+        }}
+        ",
+        CR_TO_RED, CB_TO_GREEN, CR_TO_GREEN, CB_TO_BLUE
+    )
+}
+
+/// Converts a single pixel from YCbCr to RGB. This is what the shader does on the GPU, and it's
+/// used for frames that are converted on the CPU instead.
+pub fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+    let y = f32::from(y);
+    let cb = f32::from(cb) - 128.0;
+    let cr = f32::from(cr) - 128.0;
+    let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        channel(y + CR_TO_RED * cr),
+        channel(y + CB_TO_GREEN * cb + CR_TO_GREEN * cr),
+        channel(y + CB_TO_BLUE * cb),
+    ]
+}
+
+/// How the luma and chroma of a frame are laid out in its bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum YuvLayout {
+    /// A full size luma plane followed by the two chroma planes at half the width and height, as
+    /// H264 decodes to.
+    I420,
+    /// A Y, Cb and Cr byte for each pixel, as JPEG decodes to before its color transform.
+    Interleaved,
+}
+
+/// The width and height of the chroma planes of an I420 frame, which are half the frame's,
+/// rounded up.
+pub fn chroma_size(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+/// The number of bytes an I420 frame of the given size takes up: a full size luma plane followed
+/// by the two chroma planes.
+pub fn i420_bytes(width: u32, height: u32) -> usize {
+    let (chroma_width, chroma_height) = chroma_size(width, height);
+    width as usize * height as usize + 2 * chroma_width as usize * chroma_height as usize
+}
+
+/// Splits an I420 frame into its Y, U and V planes. The frame has to be `i420_bytes` long.
+pub fn i420_planes(bytes: &[u8], width: u32, height: u32) -> (&[u8], &[u8], &[u8]) {
+    let luma = width as usize * height as usize;
+    let (chroma_width, chroma_height) = chroma_size(width, height);
+    let chroma = chroma_width as usize * chroma_height as usize;
+    let (y, chroma_planes) = bytes.split_at(luma);
+    let (u, v) = chroma_planes.split_at(chroma);
+    (y, u, &v[..chroma])
+}
+
+/// Converts an I420 frame to RGB on the CPU. The frame has to be `i420_bytes` long.
+pub fn i420_to_rgb(bytes: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (y_plane, u_plane, v_plane) = i420_planes(bytes, width, height);
+    let (chroma_width, _) = chroma_size(width, height);
+    let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in 0..height as usize {
+        for column in 0..width as usize {
+            let chroma = (row / 2) * chroma_width as usize + column / 2;
+            rgb.extend_from_slice(&ycbcr_to_rgb(
+                y_plane[row * width as usize + column],
+                u_plane[chroma],
+                v_plane[chroma],
+            ));
+        }
+    }
+    rgb
+}
+
+/// Converts a frame of interleaved YCbCr pixels to RGB on the CPU, in place.
+pub fn interleaved_to_rgb(bytes: &mut [u8]) {
+    for pixel in bytes.chunks_exact_mut(3) {
+        let rgb = ycbcr_to_rgb(pixel[0], pixel[1], pixel[2]);
+        pixel.copy_from_slice(&rgb);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum YuvError {
+    #[error("couldn't compile the color conversion shader: {0:?}")]
+    Program(ProgramCreationError),
+    #[error("couldn't create the quad the conversion is drawn on: {0:?}")]
+    Quad(glium::vertex::BufferCreationError),
+    #[error("couldn't create a {width}x{height} texture: {error:?}")]
+    Texture {
+        width: u32,
+        height: u32,
+        error: TextureCreationError,
+    },
+    #[error("couldn't convert the frame: {0:?}")]
+    Draw(DrawError),
+}
+
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+implement_vertex!(Vertex, position);
+
+/// The textures frames of one layout and size are converted with: the planes the frame is
+/// uploaded to, and the RGB texture they're drawn onto, which the window shows.
+struct FrameTextures {
+    layout: YuvLayout,
+    width: u32,
+    height: u32,
+    /// The Y, U and V planes for I420, or the single texture of interleaved pixels.
+    planes: Vec<Texture2d>,
+    rgb: Rc<Texture2d>,
+}
+
+impl FrameTextures {
+    fn new(
+        display: &Display,
+        layout: YuvLayout,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, YuvError> {
+        let planes = match layout {
+            YuvLayout::I420 => {
+                let (chroma_width, chroma_height) = chroma_size(width, height);
+                vec![
+                    empty_texture(display, UncompressedFloatFormat::U8, width, height)?,
+                    empty_texture(
+                        display,
+                        UncompressedFloatFormat::U8,
+                        chroma_width,
+                        chroma_height,
+                    )?,
+                    empty_texture(
+                        display,
+                        UncompressedFloatFormat::U8,
+                        chroma_width,
+                        chroma_height,
+                    )?,
+                ]
+            }
+            YuvLayout::Interleaved => vec![empty_texture(
+                display,
+                UncompressedFloatFormat::U8U8U8,
+                width,
+                height,
+            )?],
+        };
+        let rgb = empty_texture(display, UncompressedFloatFormat::U8U8U8U8, width, height)?;
+        Ok(Self {
+            layout,
+            width,
+            height,
+            planes,
+            rgb: Rc::new(rgb),
+        })
+    }
+
+    fn fits(&self, layout: YuvLayout, width: u32, height: u32) -> bool {
+        self.layout == layout && self.width == width && self.height == height
+    }
+
+    /// Writes a frame into the planes. The frame has to hold as many bytes as its layout and
+    /// size call for.
+    fn upload(&self, bytes: &[u8]) {
+        match self.layout {
+            YuvLayout::I420 => {
+                let (y, u, v) = i420_planes(bytes, self.width, self.height);
+                for (plane, data) in self.planes.iter().zip([y, u, v].iter()) {
+                    write_plane(plane, data, ClientFormat::U8);
+                }
+            }
+            YuvLayout::Interleaved => write_plane(&self.planes[0], bytes, ClientFormat::U8U8U8),
+        }
+    }
+}
+
+/// Converts frames of YCbCr pixels to RGB textures on the GPU, by uploading the frame's planes as
+/// textures and drawing them through a shader onto an RGB texture the size of the frame. The
+/// textures are kept for the next frame as long as its layout and size stay the same.
+pub struct YuvConverter {
+    program: Program,
+    quad: VertexBuffer<Vertex>,
+    textures: Option<FrameTextures>,
+}
+
+impl YuvConverter {
+    pub fn new(display: &Display) -> Result<Self, YuvError> {
+        let program = Program::from_source(display, VERTEX_SHADER, &fragment_shader(), None)
+            .map_err(YuvError::Program)?;
+        let corners = [
+            Vertex {
+                position: [-1.0, -1.0],
+            },
+            Vertex {
+                position: [1.0, -1.0],
+            },
+            Vertex {
+                position: [-1.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, 1.0],
+            },
+        ];
+        let quad = VertexBuffer::new(display, &corners).map_err(YuvError::Quad)?;
+        Ok(Self {
+            program,
+            quad,
+            textures: None,
+        })
+    }
+
+    /// Converts a frame, which has to hold as many bytes as its layout and size call for, to an
+    /// RGB texture. The rows come out in the same order as the frame's, as they would uploading
+    /// it as RGB. The texture is drawn over by the next frame of the same layout and size, so
+    /// handing it to the renderer again is enough to show that one too.
+    pub fn convert(
+        &mut self,
+        display: &Display,
+        layout: YuvLayout,
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Rc<Texture2d>, YuvError> {
+        let textures = match self.textures.take() {
+            Some(textures) if textures.fits(layout, width, height) => textures,
+            _ => FrameTextures::new(display, layout, width, height)?,
+        };
+        textures.upload(bytes);
+        let (y, u, v, u_channel, v_channel) = match layout {
+            YuvLayout::I420 => (
+                &textures.planes[0],
+                &textures.planes[1],
+                &textures.planes[2],
+                [1.0f32, 0.0, 0.0],
+                [1.0f32, 0.0, 0.0],
+            ),
+            YuvLayout::Interleaved => (
+                &textures.planes[0],
+                &textures.planes[0],
+                &textures.planes[0],
+                [0.0f32, 1.0, 0.0],
+                [0.0f32, 0.0, 1.0],
+            ),
+        };
+        let uniforms = uniform! {
+            y_plane: sampled(y),
+            u_plane: sampled(u),
+            v_plane: sampled(v),
+            u_channel: u_channel,
+            v_channel: v_channel,
+        };
+        textures
+            .rgb
+            .as_surface()
+            .draw(
+                &self.quad,
+                NoIndices(PrimitiveType::TriangleStrip),
+                &self.program,
+                &uniforms,
+                &Default::default(),
+            )
+            .map_err(YuvError::Draw)?;
+        let rgb = textures.rgb.clone();
+        self.textures = Some(textures);
+        Ok(rgb)
+    }
+}
+
+fn empty_texture(
+    display: &Display,
+    format: UncompressedFloatFormat,
+    width: u32,
+    height: u32,
+) -> Result<Texture2d, YuvError> {
+    Texture2d::empty_with_format(display, format, MipmapsOption::NoMipmap, width, height).map_err(
+        |error| YuvError::Texture {
+            width,
+            height,
+            error,
+        },
+    )
+}
+
+/// Samples a plane linearly. The planes have no mipmaps to minify with, and the chroma at the
+/// edges mustn't wrap around to the other side of the frame.
+fn sampled(plane: &Texture2d) -> Sampler<'_, Texture2d> {
+    Sampler::new(plane)
+        .minify_filter(MinifySamplerFilter::Linear)
+        .magnify_filter(MagnifySamplerFilter::Linear)
+        .wrap_function(SamplerWrapFunction::Clamp)
+}
+
+/// Replaces the whole of a plane's texture with the given bytes.
+fn write_plane(plane: &Texture2d, data: &[u8], format: ClientFormat) {
+    let rect = Rect {
+        left: 0,
+        bottom: 0,
+        width: plane.width(),
+        height: plane.height(),
+    };
+    let image = RawImage2d {
+        data: Cow::Borrowed(data),
+        width: rect.width,
+        height: rect.height,
+        format,
+    };
+    plane.write(rect, image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fixed-point conversion libjpeg uses, which JPEG decoders that convert to RGB
+    /// themselves follow.
+    fn reference_ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+        const SCALE: i32 = 1 << 16;
+        let fixed = |factor: f64| (factor * f64::from(SCALE)).round() as i32;
+        let y = i32::from(y) * SCALE + SCALE / 2;
+        let cb = i32::from(cb) - 128;
+        let cr = i32::from(cr) - 128;
+        let channel = |value: i32| (value >> 16).clamp(0, 255) as u8;
+        [
+            channel(y + fixed(1.402) * cr),
+            channel(y - fixed(0.344_136) * cb - fixed(0.714_136) * cr),
+            channel(y + fixed(1.772) * cb),
+        ]
+    }
+
+    #[test]
+    fn ycbcr_to_rgb_matches_the_reference_conversion() {
+        for y in (0..=255).step_by(15) {
+            for cb in (0..=255).step_by(15) {
+                for cr in (0..=255).step_by(15) {
+                    let actual = ycbcr_to_rgb(y, cb, cr);
+                    let expected = reference_ycbcr_to_rgb(y, cb, cr);
+                    for (a, e) in actual.iter().zip(expected.iter()) {
+                        assert!(
+                            (i32::from(*a) - i32::from(*e)).abs() <= 1,
+                            "YCbCr {:?} gave {:?}, expected {:?}",
+                            (y, cb, cr),
+                            actual,
+                            expected
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neutral_chroma_is_gray() {
+        for y in 0..=255 {
+            assert_eq!(ycbcr_to_rgb(y, 128, 128), [y, y, y]);
+        }
+    }
+
+    #[test]
+    fn i420_chroma_covers_two_by_two_pixels() {
+        // A 3x3 frame has 2x2 chroma planes, the last column and row covering one pixel each.
+        let mut frame = vec![100; 9];
+        frame.extend_from_slice(&[128, 128, 128, 255]);
+        frame.extend_from_slice(&[128, 128, 128, 128]);
+        assert_eq!(frame.len(), i420_bytes(3, 3));
+        let rgb = i420_to_rgb(&frame, 3, 3);
+        let pixel = |x: usize, y: usize| &rgb[(y * 3 + x) * 3..(y * 3 + x) * 3 + 3];
+        assert_eq!(pixel(0, 0), &[100, 100, 100]);
+        assert_eq!(pixel(1, 1), &[100, 100, 100]);
+        assert_eq!(pixel(2, 2), &ycbcr_to_rgb(100, 255, 128)[..]);
+    }
+}